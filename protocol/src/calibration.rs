@@ -0,0 +1,150 @@
+//! Stick and trigger calibration
+
+use crate::{msg, Message};
+use alloc::string::String;
+use serde::{Deserialize, Serialize};
+
+/// Measured ranges of one controller's analog inputs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationData {
+    pub left_stick: StickCalibration,
+    pub right_stick: StickCalibration,
+    pub left_trigger_max: u8,
+    pub right_trigger_max: u8,
+}
+
+impl Default for CalibrationData {
+    fn default() -> Self {
+        Self {
+            left_stick: StickCalibration::default(),
+            right_stick: StickCalibration::default(),
+            left_trigger_max: 255,
+            right_trigger_max: 255,
+        }
+    }
+}
+
+impl CalibrationData {
+    /// Trigger level in 0.0 to 1.0, full at the measured peak
+    pub fn normalize_trigger(value: u8, max: u8) -> f32 {
+        if max == 0 {
+            return 0.0;
+        }
+        (value as f32 / max as f32).min(1.0)
+    }
+}
+
+/// Rest position and per-axis extremes of a stick
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StickCalibration {
+    pub center_x: i16,
+    pub center_y: i16,
+    pub min_x: i16,
+    pub max_x: i16,
+    pub min_y: i16,
+    pub max_y: i16,
+}
+
+impl Default for StickCalibration {
+    fn default() -> Self {
+        Self {
+            center_x: 0,
+            center_y: 0,
+            min_x: -32767,
+            max_x: 32767,
+            min_y: -32767,
+            max_y: 32767,
+        }
+    }
+}
+
+impl StickCalibration {
+    /// A stick at `center` with no measured travel yet
+    pub fn collapsed(center_x: i16, center_y: i16) -> Self {
+        Self {
+            center_x,
+            center_y,
+            min_x: center_x,
+            max_x: center_x,
+            min_y: center_y,
+            max_y: center_y,
+        }
+    }
+
+    /// Whether every direction travels at least `travel` from the center
+    pub fn has_travel(&self, travel: i32) -> bool {
+        self.max_x as i32 - self.center_x as i32 >= travel
+            && self.center_x as i32 - self.min_x as i32 >= travel
+            && self.max_y as i32 - self.center_y as i32 >= travel
+            && self.center_y as i32 - self.min_y as i32 >= travel
+    }
+
+    /// Position in -1.0 to 1.0 on each axis, zero at the center
+    pub fn normalize(&self, x: i16, y: i16) -> (f32, f32) {
+        (
+            normalize_axis(x, self.center_x, self.min_x, self.max_x),
+            normalize_axis(y, self.center_y, self.min_y, self.max_y),
+        )
+    }
+}
+
+fn normalize_axis(value: i16, center: i16, min: i16, max: i16) -> f32 {
+    let offset = value as f32 - center as f32;
+    let span = if offset >= 0.0 {
+        max as f32 - center as f32
+    } else {
+        center as f32 - min as f32
+    };
+    if span <= 0.0 {
+        return 0.0;
+    }
+    (offset / span).clamp(-1.0, 1.0)
+}
+
+/// Step of a calibration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalibrationPhase {
+    /// Sticks left at rest while their centers are measured
+    Center,
+    /// Sticks rolled around their edges and triggers pressed fully
+    Range,
+}
+
+/// Calibration step asked for by a client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalibrationRequest {
+    /// Name the client, applying any calibration stored for it
+    Identify {
+        client_id: String,
+    },
+    /// Begin measuring, starting with the stick centers
+    Start,
+    /// Finish the current phase
+    Next,
+    Cancel,
+}
+
+impl Message for CalibrationRequest {
+    const TYPE: u32 = msg::CALIBRATION;
+}
+
+/// Reply to a [`CalibrationRequest`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalibrationResponse {
+    Identified {
+        calibration: Option<CalibrationData>,
+    },
+    Phase(CalibrationPhase),
+    Complete(CalibrationData),
+    Cancelled,
+    Failed {
+        reason: String,
+    },
+}
+
+impl Message for CalibrationResponse {
+    const TYPE: u32 = msg::CALIBRATION_RESPONSE;
+}
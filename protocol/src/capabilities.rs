@@ -0,0 +1,49 @@
+//! What each side of a session supports
+
+use crate::{msg, Message};
+use serde::{Deserialize, Serialize};
+
+/// Bits of [`Capabilities::features`]
+pub mod features {
+    /// Forward error correction on the video stream
+    pub const FEC: u32 = 1 << 0;
+    /// Encrypted stream
+    pub const ENCRYPTION: u32 = 1 << 1;
+    /// H.265 video
+    pub const HEVC: u32 = 1 << 2;
+    /// Rumble commands
+    pub const RUMBLE: u32 = 1 << 3;
+    /// More than one controller per client
+    pub const MULTI_CONTROLLER: u32 = 1 << 4;
+    /// Gyroscope and accelerometer input
+    pub const MOTION: u32 = 1 << 5;
+    /// Touchscreen input and the virtual control overlay
+    pub const TOUCH: u32 = 1 << 6;
+    /// Frame input tags and clock sync for latency measurement
+    pub const LATENCY_TAGS: u32 = 1 << 7;
+}
+
+/// Features and stream limits of one side
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Bitset of [`features`]
+    pub features: u32,
+    pub max_width: u16,
+    pub max_height: u16,
+    pub max_fps: u8,
+}
+
+impl Capabilities {
+    pub fn supports(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+
+    /// Features both sides support
+    pub fn common_features(&self, other: &Capabilities) -> u32 {
+        self.features & other.features
+    }
+}
+
+impl Message for Capabilities {
+    const TYPE: u32 = msg::CAPABILITIES;
+}
@@ -0,0 +1,26 @@
+//! Emulation control
+
+use crate::{msg, Message};
+use serde::{Deserialize, Serialize};
+
+/// Emulation control commands accepted from clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmulationCommand {
+    Pause,
+    Resume,
+    Reset,
+    FrameAdvance,
+    SetSpeed {
+        percent: u16,
+    },
+    Screenshot,
+    ToggleFullscreen,
+    /// Save to Dolphin's selected state slot, outside the save-state catalog
+    SaveSelectedSlot,
+    LoadSelectedSlot,
+}
+
+impl Message for EmulationCommand {
+    const TYPE: u32 = msg::EMULATION_CONTROL;
+}
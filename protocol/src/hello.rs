@@ -0,0 +1,153 @@
+//! Version and feature negotiation
+//!
+//! The client's first control message is a [`Hello`]. The server answers it
+//! with a [`HelloReply`] that either settles the protocol version and the
+//! features both sides use for the session, or rejects the client with a
+//! [`Rejection`] saying which side needs updating. Unlike other messages,
+//! hellos decode whatever version byte they carry: their layouts are frozen
+//! since version 2 framed messages, so peers of any two versions from then
+//! on can tell each other why they can't talk.
+
+use crate::capabilities::Capabilities;
+use crate::{decode_fields, msg, Message, ProtocolError, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use alloc::string::String;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Versions and capabilities of one side
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    /// Newest protocol version the sender speaks
+    pub protocol_version: u8,
+    /// Oldest protocol version the sender still speaks
+    pub min_protocol_version: u8,
+    /// Release of the sender, shown to users when versions don't match
+    pub build_version: String,
+    pub capabilities: Capabilities,
+}
+
+impl Hello {
+    /// Hello for this build of the protocol
+    pub fn new(build_version: &str, capabilities: Capabilities) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            build_version: build_version.into(),
+            capabilities,
+        }
+    }
+
+    /// Answer a client's hello as the server
+    ///
+    /// The session uses the newest version both sides speak and the
+    /// features both support. `required_features` are ones the server won't
+    /// stream without, such as encryption when it is configured.
+    pub fn answer(&self, client: &Hello, required_features: u32) -> HelloReply {
+        let reason = if client.protocol_version < self.min_protocol_version {
+            Some(RejectReason::ClientTooOld)
+        } else if client.min_protocol_version > self.protocol_version {
+            Some(RejectReason::ServerTooOld)
+        } else if !client.capabilities.supports(required_features) {
+            Some(RejectReason::MissingFeatures(
+                required_features & !client.capabilities.features,
+            ))
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => HelloReply::Rejected(Rejection {
+                reason,
+                server_protocol_version: self.protocol_version,
+                server_min_protocol_version: self.min_protocol_version,
+                server_build: self.build_version.clone(),
+            }),
+            None => HelloReply::Accepted(Agreement {
+                protocol_version: self.protocol_version.min(client.protocol_version),
+                features: self.capabilities.common_features(&client.capabilities),
+                server_build: self.build_version.clone(),
+            }),
+        }
+    }
+}
+
+impl Message for Hello {
+    const TYPE: u32 = msg::HELLO;
+
+    fn decode_payload(payload: &[u8]) -> Result<Self, ProtocolError> {
+        decode_fields(payload)
+    }
+}
+
+/// Server answer to a [`Hello`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HelloReply {
+    Accepted(Agreement),
+    Rejected(Rejection),
+}
+
+impl Message for HelloReply {
+    const TYPE: u32 = msg::HELLO_REPLY;
+
+    fn decode_payload(payload: &[u8]) -> Result<Self, ProtocolError> {
+        decode_fields(payload)
+    }
+}
+
+/// What a session was settled on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Agreement {
+    pub protocol_version: u8,
+    /// Bitset of [`features`](crate::capabilities::features) both sides
+    /// support; the others stay off for the session
+    pub features: u32,
+    pub server_build: String,
+}
+
+impl Agreement {
+    pub fn has(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+}
+
+/// Why the server turned a client away
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    /// The client only speaks versions older than the server supports
+    ClientTooOld,
+    /// The client only speaks versions newer than the server's
+    ServerTooOld,
+    /// The client lacks features the server requires, as a bitset
+    MissingFeatures(u32),
+}
+
+/// A refused [`Hello`], with what the server speaks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rejection {
+    pub reason: RejectReason,
+    pub server_protocol_version: u8,
+    pub server_min_protocol_version: u8,
+    pub server_build: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reason {
+            RejectReason::ClientTooOld => write!(
+                f,
+                "{} needs protocol version {} or newer; update the Switch client",
+                self.server_build, self.server_min_protocol_version
+            ),
+            RejectReason::ServerTooOld => write!(
+                f,
+                "{} only speaks protocol version {}; update the server",
+                self.server_build, self.server_protocol_version
+            ),
+            RejectReason::MissingFeatures(features) => write!(
+                f,
+                "{} requires features {:#x} this client lacks",
+                self.server_build, features
+            ),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Controller input
+
+use crate::{msg, Message};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Bits of [`InputPacket::button_flags`], in Moonlight's gamepad layout
+///
+/// ZL and ZR travel as the analog triggers.
+pub mod buttons {
+    pub const UP: u16 = 0x0001;
+    pub const DOWN: u16 = 0x0002;
+    pub const LEFT: u16 = 0x0004;
+    pub const RIGHT: u16 = 0x0008;
+    /// Moonlight's Start
+    pub const PLUS: u16 = 0x0010;
+    /// Moonlight's Back
+    pub const MINUS: u16 = 0x0020;
+    pub const L_STICK: u16 = 0x0040;
+    pub const R_STICK: u16 = 0x0080;
+    pub const L: u16 = 0x0100;
+    pub const R: u16 = 0x0200;
+    pub const A: u16 = 0x1000;
+    pub const B: u16 = 0x2000;
+    pub const X: u16 = 0x4000;
+    pub const Y: u16 = 0x8000;
+}
+
+/// Input of one of the client's controllers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputPacket {
+    pub packet_type: u8,
+    /// Which of the client's local controllers this is, 0 for the first
+    pub controller_index: u8,
+    /// Per-controller packet number, for clients that order their input
+    pub sequence: Option<u32>,
+    /// Sent periodically with the full state, superseding anything older
+    pub snapshot: bool,
+    pub button_flags: u16,
+    pub left_trigger: u8,
+    pub right_trigger: u8,
+    pub left_stick_x: i16,
+    pub left_stick_y: i16,
+    pub right_stick_x: i16,
+    pub right_stick_y: i16,
+    /// Client clock in microseconds when the packet was sent
+    pub timestamp: u64,
+
+    // Extended data for Switch-specific features
+    pub gyro_x: Option<f32>,
+    pub gyro_y: Option<f32>,
+    pub gyro_z: Option<f32>,
+    pub accel_x: Option<f32>,
+    pub accel_y: Option<f32>,
+    pub accel_z: Option<f32>,
+    pub touch_points: Option<Vec<TouchPoint>>,
+}
+
+/// An idle first controller: nothing pressed, sticks centred, no motion
+/// or touches
+impl Default for InputPacket {
+    fn default() -> Self {
+        Self {
+            packet_type: msg::INPUT as u8,
+            controller_index: 0,
+            sequence: None,
+            snapshot: false,
+            button_flags: 0,
+            left_trigger: 0,
+            right_trigger: 0,
+            left_stick_x: 0,
+            left_stick_y: 0,
+            right_stick_x: 0,
+            right_stick_y: 0,
+            timestamp: 0,
+            gyro_x: None,
+            gyro_y: None,
+            gyro_z: None,
+            accel_x: None,
+            accel_y: None,
+            accel_z: None,
+            touch_points: None,
+        }
+    }
+}
+
+impl InputPacket {
+    /// Whether sending this after `other` would tell the server nothing new
+    ///
+    /// Touches always count as new.
+    pub fn same_state(&self, other: &InputPacket) -> bool {
+        self.controller_index == other.controller_index
+            && self.button_flags == other.button_flags
+            && self.left_trigger == other.left_trigger
+            && self.right_trigger == other.right_trigger
+            && self.left_stick_x == other.left_stick_x
+            && self.left_stick_y == other.left_stick_y
+            && self.right_stick_x == other.right_stick_x
+            && self.right_stick_y == other.right_stick_y
+            && self.gyro_x == other.gyro_x
+            && self.gyro_y == other.gyro_y
+            && self.gyro_z == other.gyro_z
+            && self.accel_x == other.accel_x
+            && self.accel_y == other.accel_y
+            && self.accel_z == other.accel_z
+            && self.touch_points.is_none()
+            && other.touch_points.is_none()
+    }
+}
+
+impl Message for InputPacket {
+    const TYPE: u32 = msg::INPUT;
+}
+
+/// Wii Remote extension plugged into the emulated remote
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WiiExtension {
+    #[default]
+    None,
+    Nunchuk,
+    Classic,
+}
+
+impl WiiExtension {
+    /// Position in Dolphin's attachment list
+    pub fn attachment_index(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Nunchuk => 1,
+            Self::Classic => 2,
+        }
+    }
+}
+
+impl Message for WiiExtension {
+    const TYPE: u32 = msg::WII_EXTENSION;
+}
+
+/// Touch on the client's screen, in screen pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TouchPoint {
+    pub x: u16,
+    pub y: u16,
+    pub pressure: u8,
+}
@@ -0,0 +1,24 @@
+//! Keyboard and mouse input
+
+use crate::{msg, Message};
+use serde::{Deserialize, Serialize};
+
+/// One keyboard or mouse event
+///
+/// Keys are Windows virtual-key codes as Moonlight sends them; mouse
+/// buttons are numbered 1 (left), 2 (middle), 3 (right), 4 and 5, and
+/// motion is in counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyboardMouse {
+    KeyDown { key: u32 },
+    KeyUp { key: u32 },
+    MouseMove { x: i32, y: i32 },
+    MouseDown { button: u8 },
+    MouseUp { button: u8 },
+    MouseWheel { delta: i32 },
+}
+
+impl Message for KeyboardMouse {
+    const TYPE: u32 = msg::KEYBOARD_MOUSE;
+}
@@ -0,0 +1,111 @@
+//! Input-to-photon latency measurement
+//!
+//! Clients stamp every numbered [`InputPacket`](crate::input::InputPacket)
+//! with their clock. Video frames carry a [`FrameInputTag`] naming the last
+//! input the server got into Dolphin before the frame, and a [`ClockSync`]
+//! exchange gives the client the offset between the two clocks. Each
+//! measurement goes back to the server as a [`LatencyReport`].
+
+use crate::{msg, Message};
+use serde::{Deserialize, Serialize};
+
+/// RTP header extension profile of a [`FrameInputTag`], "DP"
+pub const FRAME_TAG_PROFILE: u16 = 0x4450;
+
+/// Names the last input a video frame shows, sent as an RTP header
+/// extension of the frame's packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameInputTag {
+    /// Sequence number of the client's first controller's input
+    pub input_sequence: u32,
+    /// When the input reached Dolphin, on the server's clock
+    pub applied_at_us: u64,
+    /// Between the input reaching Dolphin and the frame being sent
+    pub hold_us: u32,
+}
+
+impl FrameInputTag {
+    /// Encode as an RTP header extension
+    ///
+    /// Layout, big-endian like the rest of the RTP header:
+    /// [`FRAME_TAG_PROFILE`], length in 32-bit words (4), then the `u32`
+    /// input sequence, `u32` hold time and `u64` applied time in
+    /// microseconds.
+    pub fn rtp_extension(&self) -> [u8; 20] {
+        let mut extension = [0; 20];
+        extension[..2].copy_from_slice(&FRAME_TAG_PROFILE.to_be_bytes());
+        extension[2..4].copy_from_slice(&4u16.to_be_bytes());
+        extension[4..8].copy_from_slice(&self.input_sequence.to_be_bytes());
+        extension[8..12].copy_from_slice(&self.hold_us.to_be_bytes());
+        extension[12..].copy_from_slice(&self.applied_at_us.to_be_bytes());
+        extension
+    }
+
+    /// Parse the data of an RTP header extension, `None` for other profiles
+    pub fn parse(profile: u16, extension: &[u8]) -> Option<Self> {
+        if profile != FRAME_TAG_PROFILE || extension.len() < 16 {
+            return None;
+        }
+        let word = |at: usize| {
+            u32::from_be_bytes([
+                extension[at],
+                extension[at + 1],
+                extension[at + 2],
+                extension[at + 3],
+            ])
+        };
+        Some(Self {
+            input_sequence: word(0),
+            hold_us: word(4),
+            applied_at_us: (u64::from(word(8)) << 32) | u64::from(word(12)),
+        })
+    }
+}
+
+/// Client clock reading asking for the server's
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClockSync {
+    pub client_time_us: u64,
+}
+
+impl Message for ClockSync {
+    const TYPE: u32 = msg::CLOCK_SYNC;
+}
+
+/// Server clock readings answering a [`ClockSync`]
+///
+/// The client takes the offset between the clocks as the average of
+/// `server_receive - client` and `server_send - its own receive time`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClockSyncReply {
+    pub client_time_us: u64,
+    pub server_receive_us: u64,
+    pub server_send_us: u64,
+}
+
+impl Message for ClockSyncReply {
+    const TYPE: u32 = msg::CLOCK_SYNC_REPLY;
+}
+
+/// One input-to-photon measurement by a client
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyReport {
+    pub input_to_photon_us: u32,
+    /// Input stamped until it reached Dolphin, by the synced clocks
+    pub uplink_us: u32,
+    /// The frame's [`FrameInputTag::hold_us`]
+    pub frame_us: u32,
+}
+
+impl LatencyReport {
+    /// What remains for sending, decoding and showing the frame
+    pub fn downlink_us(&self) -> u32 {
+        self.input_to_photon_us
+            .saturating_sub(self.uplink_us)
+            .saturating_sub(self.frame_us)
+    }
+}
+
+impl Message for LatencyReport {
+    const TYPE: u32 = msg::LATENCY_REPORT;
+}
@@ -0,0 +1,189 @@
+//! Wire protocol shared by the dpstream server and Switch client
+//!
+//! Every control channel message starts with its type from [`msg`] and the
+//! length of the rest, both as little-endian `u32`s, so readers can cut the
+//! byte stream into messages with a [`FrameReader`]. The [`Message`] types
+//! here follow the header with the [`PROTOCOL_VERSION`] byte and their
+//! fields in postcard encoding. Decoders ignore bytes past the fields they
+//! know, so fields are only ever appended and older peers keep reading newer
+//! messages; any other change to a message bumps the version. The files in `tests/golden` pin the bytes of
+//! each message, and both the server and the client test against them.
+//! [`rtcp`] feedback on the stream socket keeps the standard RTCP layouts.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod calibration;
+pub mod capabilities;
+pub mod emulation;
+pub mod hello;
+pub mod input;
+pub mod keyboard;
+pub mod latency;
+pub mod library;
+pub mod msg;
+pub mod rtcp;
+pub mod rumble;
+pub mod savestate;
+pub mod slots;
+pub mod stats;
+pub mod touch;
+
+use alloc::vec::Vec;
+use core::fmt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Version byte following the header of every [`Message`]
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Oldest version this build still speaks, see [`hello`]
+pub const MIN_PROTOCOL_VERSION: u8 = 2;
+
+/// Bytes of the type and length header starting every message
+pub const HEADER_LEN: usize = 8;
+
+/// Longest message body a reader accepts, version byte included
+pub const MAX_BODY_LEN: usize = 1 << 20;
+
+/// Why a message could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// Shorter than the header and version byte, or than the header says
+    Truncated,
+    /// The header announces a body longer than [`MAX_BODY_LEN`]
+    Oversized(u32),
+    /// The type header names another message
+    WrongType { expected: u32, found: u32 },
+    /// Sent by a peer speaking a protocol version this side does not
+    UnsupportedVersion(u8),
+    /// The fields did not decode
+    Malformed,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "message truncated"),
+            Self::WrongType { expected, found } => {
+                write!(f, "expected message {expected:#04x}, got {found:#04x}")
+            }
+            Self::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "protocol version {version} not supported (this side speaks {PROTOCOL_VERSION})"
+                )
+            }
+            Self::Oversized(len) => {
+                write!(f, "message body of {len} bytes exceeds {MAX_BODY_LEN}")
+            }
+            Self::Malformed => write!(f, "malformed message"),
+        }
+    }
+}
+
+impl core::error::Error for ProtocolError {}
+
+/// Message type of a control channel message, if it has a header
+pub fn message_type(message: &[u8]) -> Option<u32> {
+    let header = message.get(..4)?;
+    Some(u32::from_le_bytes([
+        header[0], header[1], header[2], header[3],
+    ]))
+}
+
+/// Body of the message starting `message`, past its header
+fn message_body(message: &[u8]) -> Result<&[u8], ProtocolError> {
+    let len = message.get(4..HEADER_LEN).ok_or(ProtocolError::Truncated)?;
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]);
+    if len as usize > MAX_BODY_LEN {
+        return Err(ProtocolError::Oversized(len));
+    }
+    message
+        .get(HEADER_LEN..HEADER_LEN + len as usize)
+        .ok_or(ProtocolError::Truncated)
+}
+
+/// Cuts the bytes read from a control channel into whole messages
+///
+/// Reads can end anywhere in a message or take in several, so readers
+/// [`extend`](Self::extend) the reader with each and take the messages
+/// completed so far with [`next_message`](Self::next_message).
+#[derive(Debug, Default)]
+pub struct FrameReader {
+    buffer: Vec<u8>,
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in bytes read from the channel
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The oldest message that arrived whole, header included
+    ///
+    /// An oversized header leaves the channel unreadable, as there's no
+    /// telling where the next message starts.
+    pub fn next_message(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let len = match message_body(&self.buffer) {
+            Ok(body) => HEADER_LEN + body.len(),
+            Err(ProtocolError::Truncated) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let rest = self.buffer.split_off(len);
+        Ok(Some(core::mem::replace(&mut self.buffer, rest)))
+    }
+}
+
+/// A control channel message with a versioned postcard encoding
+pub trait Message: Serialize + DeserializeOwned {
+    /// Type header, one of the [`msg`] constants
+    const TYPE: u32;
+
+    /// Encode with header and version byte
+    fn encode(&self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(32);
+        message.extend_from_slice(&Self::TYPE.to_le_bytes());
+        message.extend_from_slice(&[0; 4]);
+        message.push(PROTOCOL_VERSION);
+        let mut message =
+            postcard::to_extend(self, message).expect("protocol messages always serialize");
+        let len = (message.len() - HEADER_LEN) as u32;
+        message[4..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+        message
+    }
+
+    /// Decode a whole message, header included
+    fn decode(message: &[u8]) -> Result<Self, ProtocolError> {
+        let found = message_type(message).ok_or(ProtocolError::Truncated)?;
+        if found != Self::TYPE {
+            return Err(ProtocolError::WrongType {
+                expected: Self::TYPE,
+                found,
+            });
+        }
+        Self::decode_payload(message_body(message)?)
+    }
+
+    /// Decode what follows the header
+    fn decode_payload(payload: &[u8]) -> Result<Self, ProtocolError> {
+        let version = *payload.first().ok_or(ProtocolError::Truncated)?;
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+            return Err(ProtocolError::UnsupportedVersion(version));
+        }
+        decode_fields(payload)
+    }
+}
+
+/// Decode the fields after the version byte, whatever the version
+fn decode_fields<M: DeserializeOwned>(payload: &[u8]) -> Result<M, ProtocolError> {
+    let fields = payload.get(1..).ok_or(ProtocolError::Truncated)?;
+    postcard::take_from_bytes(fields)
+        .map(|(message, _newer_fields)| message)
+        .map_err(|_| ProtocolError::Malformed)
+}
@@ -0,0 +1,45 @@
+//! Game library listing and launch requests
+
+use crate::{msg, Message};
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Game from the server's library, as shown in the game picker
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameInfo {
+    /// 1-based position in the server's app list, used to launch it
+    pub app_id: u32,
+    pub game_id: String,
+    pub title: String,
+    pub disc_number: u8,
+}
+
+/// Game library request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryRequest {
+    List,
+    /// Start a game for `user`, whose saves it plays with
+    Launch {
+        app_id: u32,
+        user: String,
+    },
+}
+
+impl Message for LibraryRequest {
+    const TYPE: u32 = msg::LIBRARY;
+}
+
+/// Reply to a [`LibraryRequest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryResponse {
+    Games(Vec<GameInfo>),
+    Launched { game_id: String },
+    Failed { reason: String },
+}
+
+impl Message for LibraryResponse {
+    const TYPE: u32 = msg::LIBRARY_RESPONSE;
+}
@@ -0,0 +1,86 @@
+//! Control channel message types
+//!
+//! Every message but [`KEEPALIVE`] is a postcard [`Message`](crate::Message)
+//! of this crate.
+
+/// Keepalive, no payload
+pub const KEEPALIVE: u32 = 0x0A;
+
+/// Controller input, postcard [`InputPacket`](crate::input::InputPacket)
+pub const INPUT: u32 = 0x0C;
+
+/// Emulation command, postcard
+/// [`EmulationCommand`](crate::emulation::EmulationCommand)
+pub const EMULATION_CONTROL: u32 = 0x10;
+
+/// Save state request, postcard
+/// [`StateRequest`](crate::savestate::StateRequest)
+pub const SAVE_STATE: u32 = 0x11;
+
+/// Reply to [`SAVE_STATE`], postcard
+/// [`StateResponse`](crate::savestate::StateResponse)
+pub const SAVE_STATE_RESPONSE: u32 = 0x12;
+
+/// Wii Remote extension, postcard
+/// [`WiiExtension`](crate::input::WiiExtension)
+pub const WII_EXTENSION: u32 = 0x13;
+
+/// Server-to-client rumble, postcard [`Rumble`](crate::rumble::Rumble)
+pub const RUMBLE: u32 = 0x14;
+
+/// Stick and trigger calibration step, postcard
+/// [`CalibrationRequest`](crate::calibration::CalibrationRequest)
+pub const CALIBRATION: u32 = 0x15;
+
+/// Reply to [`CALIBRATION`], postcard
+/// [`CalibrationResponse`](crate::calibration::CalibrationResponse)
+pub const CALIBRATION_RESPONSE: u32 = 0x16;
+
+/// Server-to-client player slot layout, postcard
+/// [`PlayerSlots`](crate::slots::PlayerSlots)
+pub const PLAYER_SLOTS: u32 = 0x17;
+
+/// Player slot request, postcard
+/// [`SlotRequest`](crate::slots::SlotRequest)
+pub const SLOT_REQUEST: u32 = 0x18;
+
+/// One keyboard or mouse event, postcard
+/// [`KeyboardMouse`](crate::keyboard::KeyboardMouse)
+pub const KEYBOARD_MOUSE: u32 = 0x19;
+
+/// Server-to-client touchscreen overlay, postcard
+/// [`TouchOverlay`](crate::touch::TouchOverlay)
+pub const TOUCH_OVERLAY: u32 = 0x1A;
+
+/// Client clock reading, postcard [`ClockSync`](crate::latency::ClockSync)
+pub const CLOCK_SYNC: u32 = 0x1B;
+
+/// Reply to [`CLOCK_SYNC`], postcard
+/// [`ClockSyncReply`](crate::latency::ClockSyncReply)
+pub const CLOCK_SYNC_REPLY: u32 = 0x1C;
+
+/// Input-to-photon measurement, postcard
+/// [`LatencyReport`](crate::latency::LatencyReport)
+pub const LATENCY_REPORT: u32 = 0x1D;
+
+/// Periodic receive statistics, postcard
+/// [`ClientStats`](crate::stats::ClientStats)
+pub const CLIENT_STATS: u32 = 0x1E;
+
+/// What one side supports, postcard
+/// [`Capabilities`](crate::capabilities::Capabilities)
+pub const CAPABILITIES: u32 = 0x1F;
+
+/// First client message, postcard [`Hello`](crate::hello::Hello)
+pub const HELLO: u32 = 0x20;
+
+/// Reply to [`HELLO`], postcard [`HelloReply`](crate::hello::HelloReply)
+pub const HELLO_REPLY: u32 = 0x21;
+
+/// Game library request, postcard
+/// [`LibraryRequest`](crate::library::LibraryRequest)
+pub const LIBRARY: u32 = 0x22;
+
+/// Reply to [`LIBRARY`], postcard
+/// [`LibraryResponse`](crate::library::LibraryResponse)
+pub const LIBRARY_RESPONSE: u32 = 0x23;
@@ -0,0 +1,274 @@
+//! RTCP feedback on the stream socket
+//!
+//! RTCP shares the stream socket with RTP, told apart by the packet type
+//! byte as in RFC 5761. The server sends sender reports; clients answer with
+//! receiver reports, generic NACKs for packets they want resent (RFC 4585)
+//! and picture loss indications once resending can't help. Unlike the
+//! control channel [`Message`](crate::Message)s these keep the standard
+//! big-endian RTCP layouts.
+
+use crate::ProtocolError;
+use alloc::vec::Vec;
+
+/// Sender report packet type
+pub const PT_SR: u8 = 200;
+
+/// Receiver report packet type
+pub const PT_RR: u8 = 201;
+
+/// Transport-layer feedback packet type, carrying NACKs
+pub const PT_RTPFB: u8 = 205;
+
+/// Payload-specific feedback packet type, carrying PLIs
+pub const PT_PSFB: u8 = 206;
+
+/// Feedback message type of a generic NACK
+const FMT_NACK: u8 = 1;
+
+/// Feedback message type of a picture loss indication
+const FMT_PLI: u8 = 1;
+
+/// Length of a report block
+const REPORT_BLOCK_LEN: usize = 24;
+
+/// Whether a datagram on the stream socket is RTCP rather than RTP
+pub fn is_rtcp(datagram: &[u8]) -> bool {
+    datagram.len() >= 8 && (192..=223).contains(&datagram[1])
+}
+
+/// Middle 32 bits of a 32.32 NTP timestamp, as echoed in report blocks
+pub fn compact_ntp(ntp: u64) -> u32 {
+    (ntp >> 16) as u32
+}
+
+/// Reception statistics for one source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReportBlock {
+    /// Source the statistics are about
+    pub ssrc: u32,
+    /// Share of packets lost since the last report, in 1/256ths
+    pub fraction_lost: u8,
+    /// Packets lost since the start, 24 bits on the wire
+    pub cumulative_lost: u32,
+    /// Highest sequence number received, with wraparounds in the high bits
+    pub highest_sequence: u32,
+    /// Interarrival jitter, in RTP timestamp units
+    pub jitter: u32,
+    /// [`compact_ntp`] of the last sender report, 0 before any
+    pub last_sr: u32,
+    /// Time since that sender report, in 1/65536 seconds
+    pub delay_since_last_sr: u32,
+}
+
+/// Sender report, with what the sender sent and its clocks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SenderReport {
+    pub ssrc: u32,
+    /// Wallclock when sent, 32.32 fixed point seconds
+    pub ntp_timestamp: u64,
+    /// RTP clock at the same instant
+    pub rtp_timestamp: u32,
+    pub packet_count: u32,
+    pub octet_count: u32,
+    pub reports: Vec<ReportBlock>,
+}
+
+/// Receiver report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReceiverReport {
+    pub ssrc: u32,
+    pub reports: Vec<ReportBlock>,
+}
+
+/// Generic NACK asking for packets to be resent
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Nack {
+    pub sender_ssrc: u32,
+    pub media_ssrc: u32,
+    /// Sequence numbers of the missing packets, in ascending order
+    pub lost: Vec<u16>,
+}
+
+/// Picture loss indication, asking for a picture to decode from again
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PictureLoss {
+    pub sender_ssrc: u32,
+    pub media_ssrc: u32,
+}
+
+/// One packet of a compound RTCP datagram
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RtcpPacket {
+    SenderReport(SenderReport),
+    ReceiverReport(ReceiverReport),
+    Nack(Nack),
+    PictureLoss(PictureLoss),
+}
+
+impl RtcpPacket {
+    /// Append the packet to a datagram
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        match self {
+            Self::SenderReport(sr) => {
+                header(out, sr.reports.len() as u8, PT_SR);
+                put(out, sr.ssrc);
+                put(out, (sr.ntp_timestamp >> 32) as u32);
+                put(out, sr.ntp_timestamp as u32);
+                put(out, sr.rtp_timestamp);
+                put(out, sr.packet_count);
+                put(out, sr.octet_count);
+                sr.reports.iter().for_each(|block| put_block(out, block));
+            }
+            Self::ReceiverReport(rr) => {
+                header(out, rr.reports.len() as u8, PT_RR);
+                put(out, rr.ssrc);
+                rr.reports.iter().for_each(|block| put_block(out, block));
+            }
+            Self::Nack(nack) => {
+                header(out, FMT_NACK, PT_RTPFB);
+                put(out, nack.sender_ssrc);
+                put(out, nack.media_ssrc);
+                let mut lost = nack.lost.iter().copied().peekable();
+                while let Some(pid) = lost.next() {
+                    let mut bitmask = 0u16;
+                    while let Some(&next) = lost.peek() {
+                        let offset = next.wrapping_sub(pid);
+                        if offset == 0 || offset > 16 {
+                            break;
+                        }
+                        bitmask |= 1 << (offset - 1);
+                        lost.next();
+                    }
+                    put(out, u32::from(pid) << 16 | u32::from(bitmask));
+                }
+            }
+            Self::PictureLoss(pli) => {
+                header(out, FMT_PLI, PT_PSFB);
+                put(out, pli.sender_ssrc);
+                put(out, pli.media_ssrc);
+            }
+        }
+
+        let words = ((out.len() - start) / 4 - 1) as u16;
+        out[start + 2..start + 4].copy_from_slice(&words.to_be_bytes());
+    }
+}
+
+/// Encode packets into one compound datagram, reports first
+pub fn encode_compound(packets: &[RtcpPacket]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(64);
+    packets
+        .iter()
+        .for_each(|packet| packet.encode_into(&mut out));
+    out
+}
+
+/// Decode a compound datagram, skipping packet types this side doesn't use
+pub fn decode_compound(mut datagram: &[u8]) -> Result<Vec<RtcpPacket>, ProtocolError> {
+    let mut packets = Vec::new();
+    while !datagram.is_empty() {
+        let head = datagram.get(..4).ok_or(ProtocolError::Truncated)?;
+        if head[0] >> 6 != 2 {
+            return Err(ProtocolError::Malformed);
+        }
+        let count = head[0] & 0x1F;
+        let packet_type = head[1];
+        let len = (usize::from(u16::from_be_bytes([head[2], head[3]])) + 1) * 4;
+        let packet = datagram.get(..len).ok_or(ProtocolError::Truncated)?;
+        datagram = &datagram[len..];
+
+        let body = &packet[4..];
+        match (packet_type, count) {
+            (PT_SR, _) => {
+                let blocks = blocks(body.get(24..).ok_or(ProtocolError::Truncated)?, count)?;
+                packets.push(RtcpPacket::SenderReport(SenderReport {
+                    ssrc: word(body, 0)?,
+                    ntp_timestamp: u64::from(word(body, 1)?) << 32 | u64::from(word(body, 2)?),
+                    rtp_timestamp: word(body, 3)?,
+                    packet_count: word(body, 4)?,
+                    octet_count: word(body, 5)?,
+                    reports: blocks,
+                }));
+            }
+            (PT_RR, _) => {
+                let blocks = blocks(body.get(4..).ok_or(ProtocolError::Truncated)?, count)?;
+                packets.push(RtcpPacket::ReceiverReport(ReceiverReport {
+                    ssrc: word(body, 0)?,
+                    reports: blocks,
+                }));
+            }
+            (PT_RTPFB, FMT_NACK) => {
+                let mut lost = Vec::new();
+                for fci in 2..body.len() / 4 {
+                    let fci = word(body, fci)?;
+                    let pid = (fci >> 16) as u16;
+                    lost.push(pid);
+                    lost.extend(
+                        (0..16)
+                            .filter(|bit| fci & (1 << bit) != 0)
+                            .map(|bit| pid.wrapping_add(bit + 1)),
+                    );
+                }
+                packets.push(RtcpPacket::Nack(Nack {
+                    sender_ssrc: word(body, 0)?,
+                    media_ssrc: word(body, 1)?,
+                    lost,
+                }));
+            }
+            (PT_PSFB, FMT_PLI) => packets.push(RtcpPacket::PictureLoss(PictureLoss {
+                sender_ssrc: word(body, 0)?,
+                media_ssrc: word(body, 1)?,
+            })),
+            _ => {}
+        }
+    }
+    Ok(packets)
+}
+
+fn header(out: &mut Vec<u8>, count: u8, packet_type: u8) {
+    out.extend_from_slice(&[0x80 | (count & 0x1F), packet_type, 0, 0]);
+}
+
+fn put(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_block(out: &mut Vec<u8>, block: &ReportBlock) {
+    put(out, block.ssrc);
+    put(
+        out,
+        u32::from(block.fraction_lost) << 24 | block.cumulative_lost.min(0xFF_FFFF),
+    );
+    put(out, block.highest_sequence);
+    put(out, block.jitter);
+    put(out, block.last_sr);
+    put(out, block.delay_since_last_sr);
+}
+
+fn word(body: &[u8], index: usize) -> Result<u32, ProtocolError> {
+    let bytes = body
+        .get(index * 4..index * 4 + 4)
+        .ok_or(ProtocolError::Truncated)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn blocks(data: &[u8], count: u8) -> Result<Vec<ReportBlock>, ProtocolError> {
+    (0..usize::from(count))
+        .map(|block| {
+            let data = data
+                .get(block * REPORT_BLOCK_LEN..(block + 1) * REPORT_BLOCK_LEN)
+                .ok_or(ProtocolError::Truncated)?;
+            let lost = word(data, 1)?;
+            Ok(ReportBlock {
+                ssrc: word(data, 0)?,
+                fraction_lost: (lost >> 24) as u8,
+                cumulative_lost: lost & 0xFF_FFFF,
+                highest_sequence: word(data, 2)?,
+                jitter: word(data, 3)?,
+                last_sr: word(data, 4)?,
+                delay_since_last_sr: word(data, 5)?,
+            })
+        })
+        .collect()
+}
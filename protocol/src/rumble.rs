@@ -0,0 +1,20 @@
+//! Controller rumble
+
+use crate::{msg, Message};
+use serde::{Deserialize, Serialize};
+
+/// Motor levels for one of the client's controllers, scaled by the
+/// mapping's vibration strength
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rumble {
+    /// Local controller on the client that should rumble
+    pub controller: u8,
+    pub low: u16,
+    pub high: u16,
+    /// Time from the emulator's request to the server sending it
+    pub latency_us: u32,
+}
+
+impl Message for Rumble {
+    const TYPE: u32 = msg::RUMBLE;
+}
@@ -0,0 +1,75 @@
+//! Save-state requests and replies
+
+use crate::{msg, Message};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// A save-state slot, either numbered like Dolphin's or named by the player
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaveSlot {
+    Numbered(u8),
+    Named(String),
+}
+
+impl fmt::Display for SaveSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveSlot::Numbered(n) => write!(f, "{n}"),
+            SaveSlot::Named(name) => write!(f, "'{name}'"),
+        }
+    }
+}
+
+/// Catalog metadata for one stored state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateEntry {
+    pub user: String,
+    pub game_id: String,
+    pub slot: SaveSlot,
+    pub created_secs: u64,
+    pub size: u64,
+    pub has_thumbnail: bool,
+}
+
+/// Save-state operations clients can request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateOp {
+    Save { slot: SaveSlot },
+    Load { slot: SaveSlot },
+    List,
+    Delete { slot: SaveSlot },
+    Export { slot: SaveSlot },
+}
+
+/// Save-state request; `game_id` defaults to the running game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateRequest {
+    pub user: String,
+    #[serde(default)]
+    pub game_id: Option<String>,
+    pub op: StateOp,
+}
+
+impl Message for StateRequest {
+    const TYPE: u32 = msg::SAVE_STATE;
+}
+
+/// Reply to a [`StateRequest`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateResponse {
+    Saved(StateEntry),
+    Loaded(StateEntry),
+    Listed(Vec<StateEntry>),
+    Deleted,
+    Exported { path: String },
+    Failed { reason: String },
+}
+
+impl Message for StateResponse {
+    const TYPE: u32 = msg::SAVE_STATE_RESPONSE;
+}
@@ -0,0 +1,62 @@
+//! Player slots
+
+use crate::{msg, Message};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Player slots of a session
+pub const MAX_PLAYERS: u8 = 4;
+
+/// Slot layout as told to one client, sent whenever it changes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerSlots {
+    /// The receiving client's slot per controller number, `None` for
+    /// unseated controllers; the first is `None` while spectating
+    pub slots: Vec<Option<u8>>,
+    pub occupied: Vec<u8>,
+    /// Slots held for reconnecting clients
+    pub reserved: Vec<u8>,
+    pub spectators: u32,
+}
+
+impl PlayerSlots {
+    /// Slot of the client's first controller, `None` while spectating
+    pub fn slot(&self) -> Option<u8> {
+        self.slot_of(0)
+    }
+
+    /// Slot of one of the client's controllers
+    pub fn slot_of(&self, controller: u8) -> Option<u8> {
+        self.slots.get(usize::from(controller)).copied().flatten()
+    }
+
+    /// Lowest slot nobody plays in or holds
+    pub fn first_free(&self) -> Option<u8> {
+        (1..=MAX_PLAYERS)
+            .find(|slot| !self.occupied.contains(slot) && !self.reserved.contains(slot))
+    }
+}
+
+impl Message for PlayerSlots {
+    const TYPE: u32 = msg::PLAYER_SLOTS;
+}
+
+/// Slot change a client asks for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotRequest {
+    /// Move a controller to a free slot
+    Claim { slot: u8, controller: u8 },
+    /// Exchange a controller's slot with whoever plays in `slot`
+    Swap { slot: u8, controller: u8 },
+    /// Give up every slot and spectate
+    Release,
+    /// Give the first controller's slot to the longest-waiting spectator
+    HandOver,
+    /// A local controller went away; free its slot
+    Detach { controller: u8 },
+}
+
+impl Message for SlotRequest {
+    const TYPE: u32 = msg::SLOT_REQUEST;
+}
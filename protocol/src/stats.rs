@@ -0,0 +1,53 @@
+//! Client receive statistics
+//!
+//! Clients report what reached them every [`STATS_INTERVAL_MS`]; the server
+//! adapts the stream's bitrate to it.
+
+use crate::{msg, Message};
+use serde::{Deserialize, Serialize};
+
+/// How often clients send [`ClientStats`] while streaming
+pub const STATS_INTERVAL_MS: u32 = 250;
+
+/// What the client received and decoded over one reporting interval
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientStats {
+    /// Length of the interval the counts cover
+    pub interval_ms: u32,
+    pub bytes_received: u32,
+    pub packets_received: u32,
+    /// Packets missing from the sequence numbers seen
+    pub packets_lost: u32,
+    /// RTP interarrival jitter, in microseconds
+    pub jitter_us: u32,
+    /// Average time to decode a frame, in microseconds
+    pub decode_time_us: u32,
+    pub frames_decoded: u32,
+    pub frames_dropped: u32,
+    /// Change in one-way delay of the video packets over the interval, in
+    /// microseconds; it grows while a queue on the path fills up
+    pub delay_gradient_us: i32,
+}
+
+impl ClientStats {
+    /// Share of packets lost, between 0 and 1
+    pub fn loss_ratio(&self) -> f32 {
+        let expected = self.packets_received.saturating_add(self.packets_lost);
+        if expected == 0 {
+            return 0.0;
+        }
+        self.packets_lost as f32 / expected as f32
+    }
+
+    /// Rate the video arrived at, in kbps
+    pub fn received_kbps(&self) -> u32 {
+        if self.interval_ms == 0 {
+            return 0;
+        }
+        (u64::from(self.bytes_received) * 8 / u64::from(self.interval_ms)) as u32
+    }
+}
+
+impl Message for ClientStats {
+    const TYPE: u32 = msg::CLIENT_STATS;
+}
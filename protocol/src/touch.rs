@@ -0,0 +1,35 @@
+//! Touchscreen overlay
+
+use crate::{msg, Message};
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// What a virtual control stands in for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverlayControlKind {
+    Button,
+    Stick,
+}
+
+/// A control drawn on the touchscreen, in touchscreen coordinates
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlayControl {
+    pub label: String,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub kind: OverlayControlKind,
+}
+
+/// Virtual controls of the game being played; the server turns touches
+/// inside them into button presses and stick moves
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TouchOverlay {
+    pub controls: Vec<OverlayControl>,
+}
+
+impl Message for TouchOverlay {
+    const TYPE: u32 = msg::TOUCH_OVERLAY;
+}
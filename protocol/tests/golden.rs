@@ -0,0 +1,472 @@
+//! Pins the byte layout of every message to the files in `tests/golden`
+//!
+//! The server and the client check their own encoders against the same
+//! files, so changing a layout means changing the files, which all three
+//! test suites notice.
+
+use dpstream_protocol::calibration::{
+    CalibrationData, CalibrationPhase, CalibrationRequest, CalibrationResponse,
+};
+use dpstream_protocol::capabilities::{features, Capabilities};
+use dpstream_protocol::emulation::EmulationCommand;
+use dpstream_protocol::hello::Hello;
+use dpstream_protocol::input::{buttons, InputPacket, TouchPoint, WiiExtension};
+use dpstream_protocol::keyboard::KeyboardMouse;
+use dpstream_protocol::latency::{ClockSync, ClockSyncReply, LatencyReport};
+use dpstream_protocol::library::{GameInfo, LibraryRequest, LibraryResponse};
+use dpstream_protocol::rtcp::{
+    decode_compound, encode_compound, Nack, PictureLoss, ReceiverReport, ReportBlock, RtcpPacket,
+    SenderReport,
+};
+use dpstream_protocol::rumble::Rumble;
+use dpstream_protocol::savestate::{SaveSlot, StateEntry, StateOp, StateRequest, StateResponse};
+use dpstream_protocol::slots::{PlayerSlots, SlotRequest};
+use dpstream_protocol::stats::ClientStats;
+use dpstream_protocol::touch::{OverlayControl, OverlayControlKind, TouchOverlay};
+use dpstream_protocol::{msg, FrameReader, Message, ProtocolError, HEADER_LEN, PROTOCOL_VERSION};
+
+/// Bytes of a golden file: hex bytes, with `#` starting a comment
+fn golden(hex: &str) -> Vec<u8> {
+    hex.lines()
+        .flat_map(|line| {
+            line.split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+        })
+        .map(|byte| u8::from_str_radix(byte, 16).expect("golden files hold hex bytes"))
+        .collect()
+}
+
+fn assert_golden<M: Message + PartialEq + std::fmt::Debug>(message: &M, hex: &str) {
+    let bytes = golden(hex);
+    assert_eq!(message.encode(), bytes, "encoding of {message:?} changed");
+    assert_eq!(&M::decode(&bytes).unwrap(), message);
+}
+
+fn input_packet() -> InputPacket {
+    InputPacket {
+        packet_type: 0x0C,
+        controller_index: 1,
+        sequence: Some(300),
+        snapshot: true,
+        button_flags: buttons::A | buttons::UP,
+        left_trigger: 0,
+        right_trigger: 255,
+        left_stick_x: -32768,
+        left_stick_y: 1000,
+        right_stick_x: 0,
+        right_stick_y: -1,
+        timestamp: 1_500_000,
+        gyro_x: Some(0.5),
+        gyro_y: Some(0.0),
+        gyro_z: Some(0.0),
+        accel_x: Some(0.0),
+        accel_y: Some(0.0),
+        accel_z: Some(-1.0),
+        touch_points: Some(vec![TouchPoint {
+            x: 640,
+            y: 360,
+            pressure: 20,
+        }]),
+    }
+}
+
+#[test]
+fn test_input_packet_golden() {
+    assert_golden(&input_packet(), include_str!("golden/input_packet.hex"));
+}
+
+#[test]
+fn test_latency_golden() {
+    assert_golden(
+        &ClockSync {
+            client_time_us: 1_000_000,
+        },
+        include_str!("golden/clock_sync.hex"),
+    );
+    assert_golden(
+        &ClockSyncReply {
+            client_time_us: 1_000_000,
+            server_receive_us: 5_000_250,
+            server_send_us: 5_000_400,
+        },
+        include_str!("golden/clock_sync_reply.hex"),
+    );
+    assert_golden(
+        &LatencyReport {
+            input_to_photon_us: 48_000,
+            uplink_us: 9_000,
+            frame_us: 14_000,
+        },
+        include_str!("golden/latency_report.hex"),
+    );
+}
+
+#[test]
+fn test_stats_and_capabilities_golden() {
+    assert_golden(
+        &ClientStats {
+            interval_ms: 1000,
+            bytes_received: 1_875_000,
+            packets_received: 1500,
+            packets_lost: 12,
+            jitter_us: 2500,
+            decode_time_us: 4200,
+            frames_decoded: 60,
+            frames_dropped: 1,
+            delay_gradient_us: -1800,
+        },
+        include_str!("golden/client_stats.hex"),
+    );
+    assert_golden(
+        &Capabilities {
+            features: features::RUMBLE | features::MULTI_CONTROLLER | features::LATENCY_TAGS,
+            max_width: 1920,
+            max_height: 1080,
+            max_fps: 60,
+        },
+        include_str!("golden/capabilities.hex"),
+    );
+}
+
+fn assert_roundtrip<M: Message + PartialEq + std::fmt::Debug>(messages: &[M]) {
+    for message in messages {
+        assert_eq!(&M::decode(&message.encode()).unwrap(), message);
+    }
+}
+
+#[test]
+fn test_control_golden() {
+    assert_golden(
+        &EmulationCommand::SetSpeed { percent: 150 },
+        include_str!("golden/emulation_control.hex"),
+    );
+    assert_golden(
+        &WiiExtension::Nunchuk,
+        include_str!("golden/wii_extension.hex"),
+    );
+    assert_golden(
+        &SlotRequest::Swap {
+            slot: 3,
+            controller: 1,
+        },
+        include_str!("golden/slot_request.hex"),
+    );
+    assert_golden(
+        &KeyboardMouse::MouseMove { x: -3, y: 12 },
+        include_str!("golden/keyboard_mouse.hex"),
+    );
+    assert_golden(
+        &CalibrationRequest::Identify {
+            client_id: "switch-01".to_string(),
+        },
+        include_str!("golden/calibration.hex"),
+    );
+    assert_golden(
+        &CalibrationResponse::Phase(CalibrationPhase::Range),
+        include_str!("golden/calibration_response.hex"),
+    );
+    assert_golden(
+        &StateRequest {
+            user: "alice".to_string(),
+            game_id: Some("GALE01".to_string()),
+            op: StateOp::Save {
+                slot: SaveSlot::Named("boss".to_string()),
+            },
+        },
+        include_str!("golden/save_state.hex"),
+    );
+    assert_golden(
+        &StateResponse::Saved(StateEntry {
+            user: "alice".to_string(),
+            game_id: "GALE01".to_string(),
+            slot: SaveSlot::Numbered(3),
+            created_secs: 1_700_000_000,
+            size: 4096,
+            has_thumbnail: true,
+        }),
+        include_str!("golden/save_state_response.hex"),
+    );
+    assert_golden(
+        &LibraryRequest::Launch {
+            app_id: 2,
+            user: "alice".to_string(),
+        },
+        include_str!("golden/library.hex"),
+    );
+    assert_golden(
+        &LibraryResponse::Games(vec![GameInfo {
+            app_id: 2,
+            game_id: "GALE01".to_string(),
+            title: "Melee".to_string(),
+            disc_number: 0,
+        }]),
+        include_str!("golden/library_response.hex"),
+    );
+}
+
+#[test]
+fn test_server_push_golden() {
+    assert_golden(
+        &Rumble {
+            controller: 1,
+            low: 0xFFFF,
+            high: 0x8000,
+            latency_us: 1200,
+        },
+        include_str!("golden/rumble.hex"),
+    );
+
+    let slots = PlayerSlots {
+        slots: vec![Some(2), None, Some(3)],
+        occupied: vec![1, 2, 3],
+        reserved: vec![4],
+        spectators: 1,
+    };
+    assert_golden(&slots, include_str!("golden/player_slots.hex"));
+    assert_eq!(
+        (slots.slot(), slots.slot_of(1), slots.slot_of(2)),
+        (Some(2), None, Some(3))
+    );
+    assert_eq!(slots.first_free(), None);
+
+    assert_golden(
+        &TouchOverlay {
+            controls: vec![
+                OverlayControl {
+                    label: "A".to_string(),
+                    x: 1100,
+                    y: 500,
+                    width: 120,
+                    height: 120,
+                    kind: OverlayControlKind::Button,
+                },
+                OverlayControl {
+                    label: "Move".to_string(),
+                    x: 40,
+                    y: 400,
+                    width: 280,
+                    height: 280,
+                    kind: OverlayControlKind::Stick,
+                },
+            ],
+        },
+        include_str!("golden/touch_overlay.hex"),
+    );
+}
+
+#[test]
+fn test_control_variants_roundtrip() {
+    assert_roundtrip(&[
+        EmulationCommand::Pause,
+        EmulationCommand::Resume,
+        EmulationCommand::Reset,
+        EmulationCommand::FrameAdvance,
+        EmulationCommand::SetSpeed { percent: 300 },
+        EmulationCommand::Screenshot,
+        EmulationCommand::ToggleFullscreen,
+        EmulationCommand::SaveSelectedSlot,
+        EmulationCommand::LoadSelectedSlot,
+    ]);
+    assert_roundtrip(&[
+        WiiExtension::None,
+        WiiExtension::Nunchuk,
+        WiiExtension::Classic,
+    ]);
+    assert_roundtrip(&[
+        SlotRequest::Claim {
+            slot: 4,
+            controller: 0,
+        },
+        SlotRequest::Release,
+        SlotRequest::HandOver,
+        SlotRequest::Detach { controller: 2 },
+    ]);
+    assert_roundtrip(&[
+        KeyboardMouse::KeyDown { key: 0x41 },
+        KeyboardMouse::KeyUp { key: 0x41 },
+        KeyboardMouse::MouseDown { button: 1 },
+        KeyboardMouse::MouseUp { button: 3 },
+        KeyboardMouse::MouseWheel { delta: -120 },
+    ]);
+    assert_roundtrip(&[
+        CalibrationRequest::Start,
+        CalibrationRequest::Next,
+        CalibrationRequest::Cancel,
+    ]);
+    assert_roundtrip(&[
+        CalibrationResponse::Identified { calibration: None },
+        CalibrationResponse::Complete(CalibrationData::default()),
+        CalibrationResponse::Cancelled,
+        CalibrationResponse::Failed {
+            reason: "stick did not move".to_string(),
+        },
+    ]);
+    assert_roundtrip(&[LibraryRequest::List]);
+    assert_roundtrip(&[
+        LibraryResponse::Launched {
+            game_id: "GALE01".to_string(),
+        },
+        LibraryResponse::Failed {
+            reason: "no such game".to_string(),
+        },
+    ]);
+    assert_roundtrip(&[StateRequest {
+        user: "bob".to_string(),
+        game_id: None,
+        op: StateOp::List,
+    }]);
+    assert_roundtrip(&[
+        StateResponse::Listed(Vec::new()),
+        StateResponse::Deleted,
+        StateResponse::Exported {
+            path: "/srv/exports/slot-01.sav".to_string(),
+        },
+        StateResponse::Failed {
+            reason: "no game running".to_string(),
+        },
+    ]);
+}
+
+#[test]
+fn test_versions_and_appended_fields() {
+    let mut message = input_packet().encode();
+    assert_eq!(&message[..4], &msg::INPUT.to_le_bytes());
+    assert_eq!(message[HEADER_LEN], PROTOCOL_VERSION);
+
+    // Fields a newer peer appended are skipped
+    message.extend_from_slice(&[7, 7, 7]);
+    let len = (message.len() - HEADER_LEN) as u32;
+    message[4..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+    assert_eq!(InputPacket::decode(&message).unwrap(), input_packet());
+
+    message[HEADER_LEN] = PROTOCOL_VERSION + 1;
+    assert_eq!(
+        InputPacket::decode(&message),
+        Err(ProtocolError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+    );
+    assert_eq!(
+        LatencyReport::decode(&message),
+        Err(ProtocolError::WrongType {
+            expected: msg::LATENCY_REPORT,
+            found: msg::INPUT,
+        })
+    );
+    message[HEADER_LEN] = PROTOCOL_VERSION;
+
+    // A body cut short of its fields, or of the length in the header
+    let mut short = message[..HEADER_LEN + 2].to_vec();
+    short[4..HEADER_LEN].copy_from_slice(&2u32.to_le_bytes());
+    assert_eq!(InputPacket::decode(&short), Err(ProtocolError::Malformed));
+    assert_eq!(
+        InputPacket::decode(&message[..HEADER_LEN + 2]),
+        Err(ProtocolError::Truncated)
+    );
+    assert_eq!(
+        InputPacket::decode(&message[..4]),
+        Err(ProtocolError::Truncated)
+    );
+}
+
+#[test]
+fn test_frames_cut_from_stream() {
+    let first = input_packet().encode();
+    let second = WiiExtension::Classic.encode();
+
+    // Two messages in one read
+    let mut frames = FrameReader::new();
+    frames.extend(&[first.as_slice(), &second].concat());
+    assert_eq!(frames.next_message(), Ok(Some(first.clone())));
+    assert_eq!(frames.next_message(), Ok(Some(second.clone())));
+    assert_eq!(frames.next_message(), Ok(None));
+
+    // One message over several reads
+    for chunk in first.chunks(3) {
+        assert_eq!(frames.next_message(), Ok(None));
+        frames.extend(chunk);
+    }
+    let message = frames.next_message().unwrap().unwrap();
+    assert_eq!(InputPacket::decode(&message).unwrap(), input_packet());
+    assert_eq!(frames.next_message(), Ok(None));
+
+    // A header no peer would send
+    let mut oversized = second;
+    oversized[4..HEADER_LEN].copy_from_slice(&u32::MAX.to_le_bytes());
+    frames.extend(&oversized);
+    assert_eq!(
+        frames.next_message(),
+        Err(ProtocolError::Oversized(u32::MAX))
+    );
+}
+
+#[test]
+fn test_hello_golden() {
+    let server = Hello::new(
+        "dpstream-server-2025.1.1",
+        Capabilities {
+            features: features::RUMBLE | features::TOUCH,
+            max_width: 1920,
+            max_height: 1080,
+            max_fps: 60,
+        },
+    );
+    let client = Hello::new(
+        "dpstream-switch-2025.1.0",
+        Capabilities {
+            features: features::RUMBLE | features::HEVC,
+            max_width: 1280,
+            max_height: 720,
+            max_fps: 60,
+        },
+    );
+    assert_golden(&client, include_str!("golden/hello.hex"));
+    assert_golden(
+        &server.answer(&client, 0),
+        include_str!("golden/hello_reply.hex"),
+    );
+}
+
+#[test]
+fn test_rtcp_golden() {
+    let sender_report = vec![RtcpPacket::SenderReport(SenderReport {
+        ssrc: 0x1234_5678,
+        ntp_timestamp: 16 << 32 | 0x8000_0000,
+        rtp_timestamp: 1_485_000,
+        packet_count: 1500,
+        octet_count: 1_875_000,
+        reports: Vec::new(),
+    })];
+    let feedback = vec![
+        RtcpPacket::ReceiverReport(ReceiverReport {
+            ssrc: 0xCAFE_BABE,
+            reports: vec![ReportBlock {
+                ssrc: 0x1234_5678,
+                fraction_lost: 12,
+                cumulative_lost: 12,
+                highest_sequence: 0x1_05DC,
+                jitter: 225,
+                last_sr: 0x0010_8000,
+                delay_since_last_sr: 0x4000,
+            }],
+        }),
+        RtcpPacket::Nack(Nack {
+            sender_ssrc: 0xCAFE_BABE,
+            media_ssrc: 0x1234_5678,
+            lost: vec![1488, 1489, 1491, 1520],
+        }),
+        RtcpPacket::PictureLoss(PictureLoss {
+            sender_ssrc: 0xCAFE_BABE,
+            media_ssrc: 0x1234_5678,
+        }),
+    ];
+
+    for (packets, hex) in [
+        (sender_report, include_str!("golden/rtcp_sender_report.hex")),
+        (feedback, include_str!("golden/rtcp_feedback.hex")),
+    ] {
+        let bytes = golden(hex);
+        assert_eq!(encode_compound(&packets), bytes);
+        assert_eq!(decode_compound(&bytes).unwrap(), packets);
+    }
+}
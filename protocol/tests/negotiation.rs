@@ -0,0 +1,84 @@
+//! Version and feature negotiation between client and server hellos
+
+use dpstream_protocol::capabilities::{features, Capabilities};
+use dpstream_protocol::hello::{Agreement, Hello, HelloReply, RejectReason};
+use dpstream_protocol::{Message, HEADER_LEN, PROTOCOL_VERSION};
+
+fn hello(protocol_version: u8, min_protocol_version: u8, features: u32) -> Hello {
+    Hello {
+        protocol_version,
+        min_protocol_version,
+        build_version: "dpstream-test".into(),
+        capabilities: Capabilities {
+            features,
+            ..Capabilities::default()
+        },
+    }
+}
+
+#[test]
+fn test_common_features_agreed() {
+    let server = hello(
+        3,
+        2,
+        features::RUMBLE | features::TOUCH | features::ENCRYPTION,
+    );
+    let client = hello(
+        2,
+        1,
+        features::RUMBLE | features::HEVC | features::ENCRYPTION,
+    );
+
+    assert_eq!(
+        server.answer(&client, features::ENCRYPTION),
+        HelloReply::Accepted(Agreement {
+            protocol_version: 2,
+            features: features::RUMBLE | features::ENCRYPTION,
+            server_build: "dpstream-test".into(),
+        })
+    );
+}
+
+#[test]
+fn test_incompatible_versions_rejected() {
+    let server = hello(3, 2, 0);
+    let reject_reason = |client: &Hello| match server.answer(client, 0) {
+        HelloReply::Rejected(rejection) => Some(rejection.reason),
+        HelloReply::Accepted(_) => None,
+    };
+
+    assert_eq!(
+        reject_reason(&hello(1, 1, 0)),
+        Some(RejectReason::ClientTooOld)
+    );
+    assert_eq!(
+        reject_reason(&hello(5, 4, 0)),
+        Some(RejectReason::ServerTooOld)
+    );
+    assert_eq!(reject_reason(&hello(5, 3, 0)), None);
+
+    let HelloReply::Rejected(rejection) = server.answer(&hello(1, 1, 0), 0) else {
+        panic!("old client accepted");
+    };
+    assert_eq!(
+        rejection.to_string(),
+        "dpstream-test needs protocol version 2 or newer; update the Switch client"
+    );
+    let HelloReply::Rejected(rejection) = server.answer(&hello(3, 1, 0), features::ENCRYPTION)
+    else {
+        panic!("client without encryption accepted");
+    };
+    assert_eq!(
+        rejection.reason,
+        RejectReason::MissingFeatures(features::ENCRYPTION)
+    );
+}
+
+#[test]
+fn test_hello_from_any_version_decodes() {
+    // A hello from a newer client still tells the server what it speaks
+    let newer = hello(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 1, 0);
+    let mut message = newer.encode();
+    message[HEADER_LEN] = PROTOCOL_VERSION + 1;
+    assert_eq!(Hello::decode(&message), Ok(newer));
+}
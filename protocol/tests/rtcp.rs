@@ -0,0 +1,62 @@
+//! RTCP framing beyond the golden bytes
+
+use dpstream_protocol::rtcp::{
+    compact_ntp, decode_compound, encode_compound, is_rtcp, Nack, PictureLoss, RtcpPacket,
+};
+use dpstream_protocol::ProtocolError;
+
+fn nack(lost: Vec<u16>) -> RtcpPacket {
+    RtcpPacket::Nack(Nack {
+        sender_ssrc: 1,
+        media_ssrc: 2,
+        lost,
+    })
+}
+
+#[test]
+fn test_nack_bitmask_spans_wraparound() {
+    let lost = vec![65530, 65535, 0, 9, 11, 400];
+    let bytes = encode_compound(&[nack(lost.clone())]);
+
+    // 65530 carries 65535, 0 and 9 in its bitmask; 11 and 400 need their own
+    assert_eq!(bytes.len(), 12 + 3 * 4);
+    assert_eq!(decode_compound(&bytes).unwrap(), vec![nack(lost)]);
+}
+
+#[test]
+fn test_unknown_packets_are_skipped() {
+    let pli = RtcpPacket::PictureLoss(PictureLoss {
+        sender_ssrc: 1,
+        media_ssrc: 2,
+    });
+    // An SDES with one empty chunk, which this side has no use for
+    let mut bytes = vec![0x81, 202, 0, 1, 0, 0, 0, 1];
+    bytes.extend(encode_compound(std::slice::from_ref(&pli)));
+
+    assert!(is_rtcp(&bytes));
+    assert_eq!(decode_compound(&bytes).unwrap(), vec![pli]);
+}
+
+#[test]
+fn test_rtp_and_damaged_rtcp_are_told_apart() {
+    // Video RTP with the marker bit: payload type 96 | 0x80
+    let rtp = [0x80, 0xE0, 0x05, 0xDC, 0, 0, 0, 0, 0, 0, 0, 1];
+    assert!(!is_rtcp(&rtp));
+
+    let bytes = encode_compound(&[nack(vec![7])]);
+    assert_eq!(
+        decode_compound(&bytes[..bytes.len() - 2]),
+        Err(ProtocolError::Truncated)
+    );
+    let mut wrong_version = bytes.clone();
+    wrong_version[0] &= 0x3F;
+    assert_eq!(
+        decode_compound(&wrong_version),
+        Err(ProtocolError::Malformed)
+    );
+}
+
+#[test]
+fn test_compact_ntp_keeps_middle_bits() {
+    assert_eq!(compact_ntp(0x0001_0002_0003_0004), 0x0002_0003);
+}
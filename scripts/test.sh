@@ -53,6 +53,20 @@ run_unit_tests() {
 
     cd ..
 
+    # Wire protocol golden tests, shared by server and client
+    echo -e "${BLUE}Testing wire protocol...${NC}"
+    cd protocol
+
+    if cargo test; then
+        echo -e "${GREEN}✓ Protocol tests passed${NC}"
+    else
+        echo -e "${RED}✗ Protocol tests failed${NC}"
+        cd ..
+        exit 1
+    fi
+
+    cd ..
+
     # Switch client unit tests (if applicable)
     echo -e "${BLUE}Testing switch client components...${NC}"
     cd switch-client
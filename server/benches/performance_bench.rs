@@ -302,6 +302,51 @@ fn bench_enterprise_concurrency(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_udp_batching(c: &mut Criterion) {
+    use dpstream_server::streaming::packet_io::{Datagram, PacketSink, PortableSocket};
+    use tokio::net::UdpSocket;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("udp_batching");
+
+    let bind = || rt.block_on(async { Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()) });
+    // Never read; loopback drops what doesn't fit
+    let receiver = bind();
+    let to = receiver.local_addr().unwrap();
+
+    let mut sinks: Vec<(&str, Box<dyn PacketSink>)> =
+        vec![("per_packet", Box::new(PortableSocket::new(bind())))];
+    #[cfg(target_os = "linux")]
+    {
+        use dpstream_server::streaming::packet_io::MmsgSocket;
+
+        sinks.push(("sendmmsg", Box::new(MmsgSocket::new(bind(), false))));
+        let gso = MmsgSocket::new(bind(), true);
+        if gso.gso_enabled() {
+            sinks.push(("sendmmsg_gso", Box::new(gso)));
+        }
+    }
+
+    // A frame's worth of full-size video packets
+    for packets in [16usize, 64, 256].iter() {
+        let datagrams: Vec<Datagram> = (0..*packets)
+            .map(|_| (to, Arc::from(vec![0u8; 1200])))
+            .collect();
+        group.throughput(criterion::Throughput::Elements(*packets as u64));
+        for (name, sink) in &sinks {
+            group.bench_with_input(
+                BenchmarkId::new(*name, packets),
+                &datagrams,
+                |b, datagrams| {
+                    b.iter(|| rt.block_on(sink.send_batch(black_box(datagrams))).unwrap());
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_memory_allocation,
@@ -312,6 +357,7 @@ criterion_group!(
     bench_optimized_data_structures,
     bench_cache_optimization,
     bench_channel_performance,
-    bench_enterprise_concurrency
+    bench_enterprise_concurrency,
+    bench_udp_batching
 );
 criterion_main!(benches);
@@ -0,0 +1,366 @@
+//! Emulation control for running Dolphin instances
+//!
+//! Dolphin has no remote control API, so pause, reset, frame advance and
+//! speed changes are driven through its hotkeys. A generated `Hotkeys.ini`
+//! binds them to buttons of a pipe input device, and [`EmulationControl`]
+//! presses those buttons by writing to the pipe's FIFO.
+
+use crate::error::{EmulatorError, Result};
+pub use dpstream_protocol::emulation::EmulationCommand;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info};
+
+/// Name of the pipe device the hotkeys are bound to
+pub const HOTKEY_PIPE_NAME: &str = "dpstream-hotkeys";
+
+/// Dolphin's default emulation speed, in percent
+pub const DEFAULT_SPEED_PERCENT: u16 = 100;
+
+/// Speed change applied by one press of the speed hotkeys
+const SPEED_STEP_PERCENT: u16 = 10;
+
+/// Highest speed limit accepted from clients
+const MAX_SPEED_PERCENT: u16 = 300;
+
+/// How long a hotkey button is held so Dolphin's input poll sees it
+const HOTKEY_HOLD: Duration = Duration::from_millis(50);
+
+/// Dolphin hotkeys used for emulation control
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    TogglePause,
+    Reset,
+    FrameAdvance,
+    IncreaseSpeed,
+    DecreaseSpeed,
+    /// Save to the save-state transfer slot
+    SaveTransferSlot,
+    /// Load from the save-state transfer slot
+    LoadTransferSlot,
+    Screenshot,
+    ToggleFullscreen,
+    SaveSelectedSlot,
+    LoadSelectedSlot,
+}
+
+impl Hotkey {
+    const ALL: [Hotkey; 11] = [
+        Hotkey::TogglePause,
+        Hotkey::Reset,
+        Hotkey::FrameAdvance,
+        Hotkey::IncreaseSpeed,
+        Hotkey::DecreaseSpeed,
+        Hotkey::SaveTransferSlot,
+        Hotkey::LoadTransferSlot,
+        Hotkey::Screenshot,
+        Hotkey::ToggleFullscreen,
+        Hotkey::SaveSelectedSlot,
+        Hotkey::LoadSelectedSlot,
+    ];
+
+    /// Hotkey name in Dolphin's `Hotkeys.ini`
+    fn ini_name(self) -> &'static str {
+        match self {
+            Hotkey::TogglePause => "General/Toggle Pause",
+            Hotkey::Reset => "General/Reset",
+            Hotkey::FrameAdvance => "Frame Advance/Frame Advance",
+            Hotkey::IncreaseSpeed => "Emulation Speed/Increase Emulation Speed",
+            Hotkey::DecreaseSpeed => "Emulation Speed/Decrease Emulation Speed",
+            // Must match savestate::TRANSFER_SLOT
+            Hotkey::SaveTransferSlot => "Save State/Save State Slot 10",
+            Hotkey::LoadTransferSlot => "Load State/Load State Slot 10",
+            Hotkey::Screenshot => "General/Take Screenshot",
+            Hotkey::ToggleFullscreen => "General/Toggle Fullscreen",
+            Hotkey::SaveSelectedSlot => "Save State/Save to Selected Slot",
+            Hotkey::LoadSelectedSlot => "Load State/Load from Selected Slot",
+        }
+    }
+
+    /// Pipe device button the hotkey is bound to
+    fn pipe_button(self) -> &'static str {
+        match self {
+            Hotkey::TogglePause => "START",
+            Hotkey::Reset => "Z",
+            Hotkey::FrameAdvance => "X",
+            Hotkey::IncreaseSpeed => "D_UP",
+            Hotkey::DecreaseSpeed => "D_DOWN",
+            Hotkey::SaveTransferSlot => "Y",
+            Hotkey::LoadTransferSlot => "B",
+            Hotkey::Screenshot => "A",
+            Hotkey::ToggleFullscreen => "L",
+            Hotkey::SaveSelectedSlot => "R",
+            Hotkey::LoadSelectedSlot => "D_LEFT",
+        }
+    }
+}
+
+/// Emulation state as tracked by the control surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmulationStatus {
+    pub paused: bool,
+    pub speed_percent: u16,
+}
+
+impl Default for EmulationStatus {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            speed_percent: DEFAULT_SPEED_PERCENT,
+        }
+    }
+}
+
+impl EmulationStatus {
+    /// Hotkeys needed to apply a command, updating the tracked state
+    ///
+    /// Pause and resume are idempotent: Dolphin only exposes a toggle, so a
+    /// press is only emitted when the state actually changes.
+    pub fn apply(&mut self, command: EmulationCommand) -> Vec<Hotkey> {
+        match command {
+            EmulationCommand::Pause if !self.paused => {
+                self.paused = true;
+                vec![Hotkey::TogglePause]
+            }
+            EmulationCommand::Resume if self.paused => {
+                self.paused = false;
+                vec![Hotkey::TogglePause]
+            }
+            EmulationCommand::Pause | EmulationCommand::Resume => Vec::new(),
+            EmulationCommand::Reset => vec![Hotkey::Reset],
+            EmulationCommand::FrameAdvance => {
+                // Frame advance only steps a paused core
+                let mut hotkeys = self.apply(EmulationCommand::Pause);
+                hotkeys.push(Hotkey::FrameAdvance);
+                hotkeys
+            }
+            EmulationCommand::SetSpeed { percent } => {
+                let target = round_speed(percent);
+                let (hotkey, delta) = if target >= self.speed_percent {
+                    (Hotkey::IncreaseSpeed, target - self.speed_percent)
+                } else {
+                    (Hotkey::DecreaseSpeed, self.speed_percent - target)
+                };
+                self.speed_percent = target;
+                vec![hotkey; usize::from(delta / SPEED_STEP_PERCENT)]
+            }
+            EmulationCommand::Screenshot => vec![Hotkey::Screenshot],
+            EmulationCommand::ToggleFullscreen => vec![Hotkey::ToggleFullscreen],
+            EmulationCommand::SaveSelectedSlot => vec![Hotkey::SaveSelectedSlot],
+            EmulationCommand::LoadSelectedSlot => vec![Hotkey::LoadSelectedSlot],
+        }
+    }
+}
+
+/// Clamp a requested speed to what the hotkeys can reach
+fn round_speed(percent: u16) -> u16 {
+    let clamped = percent.clamp(SPEED_STEP_PERCENT, MAX_SPEED_PERCENT);
+    (clamped + SPEED_STEP_PERCENT / 2) / SPEED_STEP_PERCENT * SPEED_STEP_PERCENT
+}
+
+/// Contents of the `Hotkeys.ini` binding control hotkeys to the pipe device
+pub fn hotkeys_ini() -> String {
+    let mut ini = format!("[Hotkeys1]\nDevice = Pipe/0/{HOTKEY_PIPE_NAME}\n");
+    for hotkey in Hotkey::ALL {
+        ini.push_str(&format!(
+            "{} = `Button {}`\n",
+            hotkey.ini_name(),
+            hotkey.pipe_button()
+        ));
+    }
+    ini
+}
+
+/// Emulation command forwarded from a client session, answered with the
+/// emulation state once Dolphin has been sent the command
+#[derive(Debug)]
+pub struct EmulationJob {
+    pub command: EmulationCommand,
+    pub reply: flume::Sender<Result<EmulationStatus>>,
+}
+
+/// Control surface for one Dolphin user directory
+#[derive(Debug)]
+pub struct EmulationControl {
+    pipe_path: PathBuf,
+    status: EmulationStatus,
+}
+
+impl EmulationControl {
+    /// Create the hotkey FIFO and `Hotkeys.ini` inside a Dolphin user directory
+    pub fn install(user_directory: &Path) -> Result<Self> {
+        let pipes_dir = user_directory.join("Pipes");
+        let config_dir = user_directory.join("Config");
+        std::fs::create_dir_all(&pipes_dir)?;
+        std::fs::create_dir_all(&config_dir)?;
+
+        let pipe_path = pipes_dir.join(HOTKEY_PIPE_NAME);
+        if !pipe_path.exists() {
+            create_fifo(&pipe_path)?;
+        }
+
+        std::fs::write(config_dir.join("Hotkeys.ini"), hotkeys_ini())?;
+        info!("Emulation control pipe ready at {}", pipe_path.display());
+
+        Ok(Self {
+            pipe_path,
+            status: EmulationStatus::default(),
+        })
+    }
+
+    /// Tracked emulation state
+    pub fn status(&self) -> EmulationStatus {
+        self.status
+    }
+
+    /// Apply a command by pressing the matching hotkeys
+    pub async fn send(&mut self, command: EmulationCommand) -> Result<EmulationStatus> {
+        let mut next = self.status;
+        let hotkeys = next.apply(command);
+        debug!("Emulation command {:?} -> {:?}", command, hotkeys);
+
+        if !hotkeys.is_empty() {
+            self.press(&hotkeys).await?;
+        }
+
+        self.status = next;
+        Ok(next)
+    }
+
+    /// Press hotkeys that do not change the tracked state
+    pub async fn press(&self, hotkeys: &[Hotkey]) -> Result<()> {
+        let mut pipe = tokio::net::unix::pipe::OpenOptions::new()
+            .open_sender(&self.pipe_path)
+            .map_err(|e| control_error("open hotkey pipe", e))?;
+
+        for hotkey in hotkeys {
+            let button = hotkey.pipe_button();
+            pipe.write_all(format!("PRESS {button}\n").as_bytes())
+                .await
+                .map_err(|e| control_error("press hotkey", e))?;
+            tokio::time::sleep(HOTKEY_HOLD).await;
+            pipe.write_all(format!("RELEASE {button}\n").as_bytes())
+                .await
+                .map_err(|e| control_error("release hotkey", e))?;
+            tokio::time::sleep(HOTKEY_HOLD).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Create a FIFO that Dolphin's pipe input device can read from
+pub(crate) fn create_fifo(path: &Path) -> Result<()> {
+    let c_path =
+        CString::new(path.as_os_str().as_bytes()).map_err(|e| control_error("create pipe", e))?;
+
+    // SAFETY: c_path is a valid NUL-terminated path for the duration of the call
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(control_error(
+            "create pipe",
+            std::io::Error::last_os_error(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn control_error(operation: &str, error: impl std::fmt::Display) -> crate::error::DpstreamError {
+    EmulatorError::ProcessControlFailed {
+        operation: operation.to_string(),
+        reason: error.to_string(),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn test_decode_commands() {
+        use dpstream_protocol::{Message, ProtocolError, PROTOCOL_VERSION};
+
+        assert_eq!(
+            EmulationCommand::decode_payload(&[PROTOCOL_VERSION, 0]),
+            Ok(EmulationCommand::Pause)
+        );
+        assert_eq!(
+            EmulationCommand::decode_payload(&[PROTOCOL_VERSION, 4, 0x96, 0x01]),
+            Ok(EmulationCommand::SetSpeed { percent: 150 })
+        );
+        assert_eq!(
+            EmulationCommand::decode_payload(&[PROTOCOL_VERSION, 4]),
+            Err(ProtocolError::Malformed)
+        );
+        assert_eq!(
+            EmulationCommand::decode_payload(&[PROTOCOL_VERSION, 9]),
+            Err(ProtocolError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_pause_resume_are_idempotent() {
+        let mut status = EmulationStatus::default();
+
+        assert_eq!(
+            status.apply(EmulationCommand::Pause),
+            vec![Hotkey::TogglePause]
+        );
+        assert!(status.apply(EmulationCommand::Pause).is_empty());
+        assert_eq!(
+            status.apply(EmulationCommand::FrameAdvance),
+            vec![Hotkey::FrameAdvance]
+        );
+        assert_eq!(
+            status.apply(EmulationCommand::Resume),
+            vec![Hotkey::TogglePause]
+        );
+        assert!(!status.paused);
+
+        // Frame advance from a running core pauses it first
+        assert_eq!(
+            status.apply(EmulationCommand::FrameAdvance),
+            vec![Hotkey::TogglePause, Hotkey::FrameAdvance]
+        );
+    }
+
+    #[test]
+    fn test_speed_steps() {
+        let mut status = EmulationStatus::default();
+
+        let hotkeys = status.apply(EmulationCommand::SetSpeed { percent: 148 });
+        assert_eq!(hotkeys, vec![Hotkey::IncreaseSpeed; 5]);
+        assert_eq!(status.speed_percent, 150);
+
+        let hotkeys = status.apply(EmulationCommand::SetSpeed { percent: 0 });
+        assert_eq!(hotkeys, vec![Hotkey::DecreaseSpeed; 14]);
+        assert_eq!(status.speed_percent, 10);
+    }
+
+    #[tokio::test]
+    async fn test_send_writes_hotkeys_to_pipe() {
+        let user_dir = std::env::temp_dir().join("dpstream-control-pipe");
+        let _ = std::fs::remove_dir_all(&user_dir);
+
+        let mut control = EmulationControl::install(&user_dir).unwrap();
+        let ini = std::fs::read_to_string(user_dir.join("Config/Hotkeys.ini")).unwrap();
+        assert!(ini.contains("General/Toggle Pause = `Button START`"));
+
+        let mut reader = tokio::net::unix::pipe::OpenOptions::new()
+            .open_receiver(&control.pipe_path)
+            .unwrap();
+
+        let status = control.send(EmulationCommand::Pause).await.unwrap();
+        assert!(status.paused);
+
+        let mut received = vec![0u8; 64];
+        let n = reader.read(&mut received).await.unwrap();
+        let received = String::from_utf8_lossy(&received[..n]);
+        assert!(received.starts_with("PRESS START\n"));
+    }
+}
@@ -0,0 +1,674 @@
+//! Game library scanner
+//!
+//! Walks the ROM roots, reads GameCube/Wii disc headers from ISO, GCM, CISO,
+//! GCZ, WBFS and WIA/RVZ images, and caches the results in an index file so
+//! later scans only re-read files whose size or modification time changed.
+
+use crate::emulator::rom::RomResolver;
+use crate::error::{EmulatorError, Result};
+use crate::input::mapping::{ConsoleType, GameProfile};
+use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+use flate2::read::ZlibDecoder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tracing::{debug, info, warn};
+
+pub use dpstream_protocol::library::{GameInfo, LibraryRequest, LibraryResponse};
+
+/// Index file format version; bump when `GameEntry` changes incompatibly
+const INDEX_VERSION: u32 = 1;
+
+/// Bytes of the disc header needed for ID, title and console detection
+const DISC_HEADER_SIZE: usize = 0x400;
+
+const WII_MAGIC: u32 = 0x5D1C_9EA3;
+const GAMECUBE_MAGIC: u32 = 0xC233_9F3D;
+const GCZ_MAGIC: u32 = 0xB10B_C001;
+
+/// Offset of the first data block in a CISO image
+const CISO_DATA_OFFSET: u64 = 0x8000;
+/// Offset of the copied disc header inside WIA/RVZ header 2
+const WIA_DISC_HEADER_OFFSET: u64 = 0x58;
+/// Size of the disc header copy kept by WIA/RVZ
+const WIA_DISC_HEADER_SIZE: usize = 0x80;
+/// Size of the disc header copy kept by WBFS
+const WBFS_DISC_HEADER_SIZE: usize = 0x100;
+/// Largest compressed GCZ block read; the header block is far smaller
+const GCZ_MAX_BLOCK_SIZE: u64 = 2 << 20;
+
+/// Container format of a disc image
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DiscFormat {
+    Iso,
+    Ciso,
+    Gcz,
+    Wbfs,
+    Wia,
+    Rvz,
+}
+
+/// Disc region derived from the last character of the game code
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Region {
+    NtscU,
+    NtscJ,
+    NtscK,
+    Pal,
+    Unknown,
+}
+
+impl Region {
+    /// Determine the region from a game ID such as `GALE01`
+    pub fn from_game_id(game_id: &str) -> Self {
+        match game_id.as_bytes().get(3) {
+            Some(b'E' | b'N') => Self::NtscU,
+            Some(b'J') => Self::NtscJ,
+            Some(b'K' | b'Q' | b'T') => Self::NtscK,
+            Some(
+                b'D' | b'F' | b'H' | b'I' | b'L' | b'M' | b'P' | b'S' | b'U' | b'X' | b'Y' | b'Z',
+            ) => Self::Pal,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Fields parsed from a GameCube/Wii disc header
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscHeader {
+    pub game_id: String,
+    pub title: String,
+    pub console_type: ConsoleType,
+    pub region: Region,
+    pub disc_number: u8,
+    pub revision: u8,
+}
+
+impl DiscHeader {
+    /// Parse a raw disc header (at least the first 0x20 bytes)
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 0x20 {
+            return None;
+        }
+
+        let game_id = std::str::from_utf8(&data[..6]).ok()?;
+        if !crate::emulator::rom::is_game_id(game_id) {
+            return None;
+        }
+
+        let wii_magic = u32::from_be_bytes([data[0x18], data[0x19], data[0x1A], data[0x1B]]);
+        let gc_magic = u32::from_be_bytes([data[0x1C], data[0x1D], data[0x1E], data[0x1F]]);
+        let console_type = if wii_magic == WII_MAGIC {
+            ConsoleType::Wii
+        } else if gc_magic == GAMECUBE_MAGIC {
+            ConsoleType::GameCube
+        } else {
+            return None;
+        };
+
+        let region = Region::from_game_id(game_id);
+        let title_bytes = &data[0x20.min(data.len())..DISC_HEADER_SIZE.min(data.len())];
+
+        Some(Self {
+            game_id: game_id.to_string(),
+            title: decode_title(title_bytes, region),
+            console_type,
+            region,
+            disc_number: data[6],
+            revision: data[7],
+        })
+    }
+}
+
+/// Decode a NUL-terminated header title; Japanese discs use Shift-JIS
+fn decode_title(bytes: &[u8], region: Region) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let encoding = if region == Region::NtscJ {
+        SHIFT_JIS
+    } else {
+        WINDOWS_1252
+    };
+    let (title, _) = encoding.decode_without_bom_handling(&bytes[..end]);
+    title.trim().to_string()
+}
+
+/// Detect the container format from the first bytes of a file
+pub fn detect_format(magic: &[u8; 4]) -> DiscFormat {
+    match magic {
+        b"CISO" => DiscFormat::Ciso,
+        b"WBFS" => DiscFormat::Wbfs,
+        b"WIA\x01" => DiscFormat::Wia,
+        b"RVZ\x01" => DiscFormat::Rvz,
+        _ if u32::from_le_bytes(*magic) == GCZ_MAGIC => DiscFormat::Gcz,
+        _ => DiscFormat::Iso,
+    }
+}
+
+/// Read the disc header of an image in any supported format
+pub fn read_disc_header(path: &Path) -> Result<Option<(DiscFormat, DiscHeader)>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic).is_err() {
+        return Ok(None);
+    }
+
+    let format = detect_format(&magic);
+    let data = match format {
+        DiscFormat::Iso => read_at(&mut file, 0, DISC_HEADER_SIZE)?,
+        DiscFormat::Ciso => read_at(&mut file, CISO_DATA_OFFSET, DISC_HEADER_SIZE)?,
+        DiscFormat::Wbfs => {
+            let sector_shift = read_at(&mut file, 8, 1)?.first().copied().unwrap_or(9);
+            read_at(&mut file, 1u64 << sector_shift, WBFS_DISC_HEADER_SIZE)?
+        }
+        DiscFormat::Wia | DiscFormat::Rvz => {
+            read_at(&mut file, WIA_DISC_HEADER_OFFSET, WIA_DISC_HEADER_SIZE)?
+        }
+        DiscFormat::Gcz => read_gcz_header(&mut file)?,
+    };
+
+    Ok(DiscHeader::parse(&data).map(|header| (format, header)))
+}
+
+/// Read up to `len` bytes at `offset`; short reads return what is available
+fn read_at(file: &mut File, offset: u64, len: usize) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    file.take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Decompress the first block of a GCZ image, which holds the disc header
+fn read_gcz_header(file: &mut File) -> Result<Vec<u8>> {
+    let header = read_at(file, 0, 32)?;
+    if header.len() < 32 {
+        return Ok(Vec::new());
+    }
+
+    let compressed_size = u64::from_le_bytes(header[8..16].try_into().unwrap_or_default());
+    let num_blocks = u32::from_le_bytes(header[28..32].try_into().unwrap_or_default()) as u64;
+    if num_blocks == 0 {
+        return Ok(Vec::new());
+    }
+
+    let pointers = read_at(file, 32, if num_blocks > 1 { 16 } else { 8 })?;
+    let pointer = |index: usize| {
+        pointers
+            .get(index * 8..index * 8 + 8)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u64::from_le_bytes)
+    };
+
+    const UNCOMPRESSED_FLAG: u64 = 1 << 63;
+    let first = pointer(0).unwrap_or(0);
+    let block_start = first & !UNCOMPRESSED_FLAG;
+    let block_end = match pointer(1) {
+        Some(next) if num_blocks > 1 => next & !UNCOMPRESSED_FLAG,
+        _ => compressed_size,
+    };
+
+    let data_offset = 32 + num_blocks * 12;
+    let block_size = block_end
+        .saturating_sub(block_start)
+        .min(GCZ_MAX_BLOCK_SIZE);
+    let block = read_at(
+        file,
+        data_offset.saturating_add(block_start),
+        block_size as usize,
+    )?;
+
+    if first & UNCOMPRESSED_FLAG != 0 {
+        return Ok(block);
+    }
+
+    let mut data = Vec::with_capacity(DISC_HEADER_SIZE);
+    ZlibDecoder::new(block.as_slice())
+        .take(DISC_HEADER_SIZE as u64)
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// A playable game in the library
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GameEntry {
+    /// Path relative to its ROM root, usable as a launch request
+    pub entry: String,
+    pub path: PathBuf,
+    pub game_id: String,
+    pub title: String,
+    pub console_type: ConsoleType,
+    pub region: Region,
+    pub disc_number: u8,
+    pub revision: u8,
+    pub format: DiscFormat,
+    pub file_size: u64,
+    pub modified_secs: u64,
+}
+
+impl GameEntry {
+    /// How clients see this game when it is `app_id` in their list
+    pub fn info(&self, app_id: u32) -> GameInfo {
+        GameInfo {
+            app_id,
+            game_id: self.game_id.clone(),
+            title: self.title.clone(),
+            disc_number: self.disc_number,
+        }
+    }
+}
+
+/// Launch request forwarded from a client session, answered with the game
+/// profile of the started game so the session can apply it to input
+#[derive(Debug)]
+pub struct LaunchJob {
+    pub game: GameEntry,
+    pub user: String,
+    pub reply: flume::Sender<Result<Option<GameProfile>>>,
+}
+
+/// On-disk cache of scanned games
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LibraryIndex {
+    version: u32,
+    entries: Vec<GameEntry>,
+}
+
+/// Outcome of a library scan
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScanStats {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    pub skipped: usize,
+}
+
+/// Game library backed by an index file
+pub struct GameLibrary {
+    resolver: RomResolver,
+    index_path: PathBuf,
+    entries: Vec<GameEntry>,
+}
+
+impl GameLibrary {
+    /// Open a library over the ROM roots, loading the cached index if present
+    pub fn open<I, P>(roots: I, index_path: impl Into<PathBuf>) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let index_path = index_path.into();
+        let entries = match Self::load_index(&index_path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Ignoring library index {}: {}", index_path.display(), e);
+                Vec::new()
+            }
+        };
+
+        Self {
+            resolver: RomResolver::new(roots),
+            index_path,
+            entries,
+        }
+    }
+
+    /// Rescan the ROM roots, re-reading only new or modified files
+    pub fn rescan(&mut self) -> Result<ScanStats> {
+        let mut stats = ScanStats::default();
+        let mut previous: HashMap<PathBuf, GameEntry> = self
+            .entries
+            .drain(..)
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+
+        for path in self.resolver.list_roms() {
+            // Symlinks out of the roots would be listed but refused at launch
+            if !self.resolver.contains(&path) {
+                stats.skipped += 1;
+                continue;
+            }
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                stats.skipped += 1;
+                continue;
+            };
+            let file_size = metadata.len();
+            let modified_secs = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0);
+
+            let cached = previous.remove(&path);
+            if let Some(entry) = cached.as_ref() {
+                if entry.file_size == file_size && entry.modified_secs == modified_secs {
+                    self.entries.push(entry.clone());
+                    stats.unchanged += 1;
+                    continue;
+                }
+            }
+
+            let header = match read_disc_header(&path) {
+                Ok(Some(header)) => header,
+                Ok(None) => {
+                    debug!("No disc header found in {}", path.display());
+                    stats.skipped += 1;
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to read disc header from {}: {}", path.display(), e);
+                    stats.skipped += 1;
+                    continue;
+                }
+            };
+
+            if cached.is_some() {
+                stats.updated += 1;
+            } else {
+                stats.added += 1;
+            }
+
+            let (format, header) = header;
+            self.entries.push(GameEntry {
+                entry: self.entry_name(&path),
+                path,
+                game_id: header.game_id,
+                title: header.title,
+                console_type: header.console_type,
+                region: header.region,
+                disc_number: header.disc_number,
+                revision: header.revision,
+                format,
+                file_size,
+                modified_secs,
+            });
+        }
+
+        stats.removed = previous.len();
+        self.entries.sort_by(|a, b| {
+            a.title
+                .cmp(&b.title)
+                .then(a.disc_number.cmp(&b.disc_number))
+        });
+        self.save_index()?;
+
+        info!(
+            "Game library scanned: {} games ({} added, {} updated, {} removed)",
+            self.entries.len(),
+            stats.added,
+            stats.updated,
+            stats.removed
+        );
+        Ok(stats)
+    }
+
+    /// All games in the library, sorted by title
+    pub fn entries(&self) -> &[GameEntry] {
+        &self.entries
+    }
+
+    /// Find the first disc of a game by ID
+    pub fn find_by_game_id(&self, game_id: &str) -> Option<&GameEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.game_id == game_id)
+            .min_by_key(|entry| entry.disc_number)
+    }
+
+    /// Load the game profile for a library game, naming it from the disc header
+    pub fn game_profile(&self, game_id: &str) -> Result<GameProfile> {
+        let entry = self
+            .find_by_game_id(game_id)
+            .ok_or_else(|| EmulatorError::RomNotFound {
+                path: game_id.to_string(),
+                suggestions: Vec::new(),
+            })?;
+
+        let mut profile = GameProfile::load_for_game(game_id)?;
+        if profile.game_name == GameProfile::UNKNOWN_GAME_NAME {
+            profile.game_name = entry.title.clone();
+            profile.console_type = entry.console_type;
+        }
+        Ok(profile)
+    }
+
+    /// Path of the index file backing this library
+    pub fn index_path(&self) -> &Path {
+        &self.index_path
+    }
+
+    fn entry_name(&self, path: &Path) -> String {
+        self.resolver
+            .roots()
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .map(|relative| relative.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string_lossy().into_owned())
+    }
+
+    fn load_index(path: &Path) -> Result<Vec<GameEntry>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let index: LibraryIndex = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if index.version != INDEX_VERSION {
+            info!("Library index version changed, rescanning from scratch");
+            return Ok(Vec::new());
+        }
+        Ok(index.entries)
+    }
+
+    fn save_index(&self) -> Result<()> {
+        if let Some(parent) = self.index_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let index = LibraryIndex {
+            version: INDEX_VERSION,
+            entries: self.entries.clone(),
+        };
+        std::fs::write(&self.index_path, serde_json::to_string_pretty(&index)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn disc_header(game_id: &str, title: &[u8], wii: bool) -> Vec<u8> {
+        let mut data = vec![0u8; DISC_HEADER_SIZE];
+        data[..6].copy_from_slice(game_id.as_bytes());
+        data[6] = 1; // Disc 2
+        data[7] = 2; // Revision 2
+        if wii {
+            data[0x18..0x1C].copy_from_slice(&WII_MAGIC.to_be_bytes());
+        } else {
+            data[0x1C..0x20].copy_from_slice(&GAMECUBE_MAGIC.to_be_bytes());
+        }
+        data[0x20..0x20 + title.len()].copy_from_slice(title);
+        data
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dpstream-library-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_gamecube_header() {
+        let header =
+            DiscHeader::parse(&disc_header("GALE01", b"Super Smash Bros Melee", false)).unwrap();
+        assert_eq!(header.game_id, "GALE01");
+        assert_eq!(header.title, "Super Smash Bros Melee");
+        assert_eq!(header.console_type, ConsoleType::GameCube);
+        assert_eq!(header.region, Region::NtscU);
+        assert_eq!(header.disc_number, 1);
+        assert_eq!(header.revision, 2);
+    }
+
+    #[test]
+    fn test_parse_shift_jis_title() {
+        // "ゼルダ" in Shift-JIS
+        let title = [0x83, 0x5B, 0x83, 0x8B, 0x83, 0x5F];
+        let header = DiscHeader::parse(&disc_header("GZLJ01", &title, false)).unwrap();
+        assert_eq!(header.region, Region::NtscJ);
+        assert_eq!(header.title, "ゼルダ");
+    }
+
+    #[test]
+    fn test_rejects_non_disc_data() {
+        assert!(DiscHeader::parse(&[0u8; 0x40]).is_none());
+        assert!(DiscHeader::parse(b"GALE01").is_none());
+    }
+
+    #[test]
+    fn test_read_container_formats() {
+        let dir = test_dir("formats");
+        let header = disc_header("RSBE01", b"Super Smash Bros. Brawl", true);
+
+        let mut ciso = vec![0u8; CISO_DATA_OFFSET as usize];
+        ciso[..4].copy_from_slice(b"CISO");
+        ciso.extend_from_slice(&header);
+        std::fs::write(dir.join("brawl.ciso"), ciso).unwrap();
+
+        let mut wbfs = vec![0u8; 0x200];
+        wbfs[..4].copy_from_slice(b"WBFS");
+        wbfs[8] = 9; // 512-byte HD sectors
+        wbfs.extend_from_slice(&header[..WBFS_DISC_HEADER_SIZE]);
+        std::fs::write(dir.join("brawl.wbfs"), wbfs).unwrap();
+
+        let mut rvz = vec![0u8; WIA_DISC_HEADER_OFFSET as usize];
+        rvz[..4].copy_from_slice(b"RVZ\x01");
+        rvz.extend_from_slice(&header[..WIA_DISC_HEADER_SIZE]);
+        std::fs::write(dir.join("brawl.rvz"), rvz).unwrap();
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&header).unwrap();
+        let block = encoder.finish().unwrap();
+        let mut gcz = Vec::new();
+        gcz.extend_from_slice(&GCZ_MAGIC.to_le_bytes());
+        gcz.extend_from_slice(&1u32.to_le_bytes());
+        gcz.extend_from_slice(&(block.len() as u64).to_le_bytes());
+        gcz.extend_from_slice(&(header.len() as u64).to_le_bytes());
+        gcz.extend_from_slice(&0x4000u32.to_le_bytes());
+        gcz.extend_from_slice(&1u32.to_le_bytes());
+        gcz.extend_from_slice(&0u64.to_le_bytes());
+        gcz.extend_from_slice(&0u32.to_le_bytes());
+        gcz.extend_from_slice(&block);
+        std::fs::write(dir.join("brawl.gcz"), gcz).unwrap();
+
+        for (file, format) in [
+            ("brawl.ciso", DiscFormat::Ciso),
+            ("brawl.wbfs", DiscFormat::Wbfs),
+            ("brawl.rvz", DiscFormat::Rvz),
+            ("brawl.gcz", DiscFormat::Gcz),
+        ] {
+            let (detected, parsed) = read_disc_header(&dir.join(file)).unwrap().unwrap();
+            assert_eq!(detected, format, "{file}");
+            assert_eq!(parsed.game_id, "RSBE01", "{file}");
+            assert_eq!(parsed.console_type, ConsoleType::Wii, "{file}");
+        }
+    }
+
+    #[test]
+    fn test_gcz_block_size_is_capped() {
+        let dir = test_dir("gcz-size");
+        let mut gcz = Vec::new();
+        gcz.extend_from_slice(&GCZ_MAGIC.to_le_bytes());
+        gcz.extend_from_slice(&1u32.to_le_bytes());
+        gcz.extend_from_slice(&u64::MAX.to_le_bytes()); // compressed size
+        gcz.extend_from_slice(&(DISC_HEADER_SIZE as u64).to_le_bytes());
+        gcz.extend_from_slice(&0x4000u32.to_le_bytes());
+        gcz.extend_from_slice(&1u32.to_le_bytes());
+        gcz.extend_from_slice(&(1u64 << 63).to_le_bytes()); // stored uncompressed
+        gcz.extend_from_slice(&0u32.to_le_bytes());
+        gcz.extend_from_slice(&disc_header("GALE01", b"Melee", false));
+        std::fs::write(dir.join("melee.gcz"), gcz).unwrap();
+
+        let (format, header) = read_disc_header(&dir.join("melee.gcz")).unwrap().unwrap();
+        assert_eq!(format, DiscFormat::Gcz);
+        assert_eq!(header.game_id, "GALE01");
+    }
+
+    #[test]
+    fn test_incremental_rescan() {
+        let dir = test_dir("rescan");
+        let roms = dir.join("roms");
+        std::fs::create_dir_all(&roms).unwrap();
+        let index = dir.join("library.json");
+
+        std::fs::write(
+            roms.join("melee.iso"),
+            disc_header("GALE01", b"Melee", false),
+        )
+        .unwrap();
+        std::fs::write(
+            roms.join("prime.gcm"),
+            disc_header("GM4E01", b"Prime", false),
+        )
+        .unwrap();
+
+        let mut library = GameLibrary::open([&roms], &index);
+        let stats = library.rescan().unwrap();
+        assert_eq!(stats.added, 2);
+        assert!(index.exists());
+
+        std::fs::remove_file(roms.join("prime.gcm")).unwrap();
+        let mut reopened = GameLibrary::open([&roms], &index);
+        assert_eq!(
+            reopened.entries().len(),
+            2,
+            "Index should be loaded from disk"
+        );
+
+        let stats = reopened.rescan().unwrap();
+        assert_eq!(stats.unchanged, 1);
+        assert_eq!(stats.removed, 1);
+        assert_eq!(
+            reopened.find_by_game_id("GALE01").unwrap().entry,
+            "melee.iso"
+        );
+    }
+
+    #[test]
+    fn test_rescan_skips_links_out_of_roots() {
+        let dir = test_dir("links");
+        let roms = dir.join("roms");
+        std::fs::create_dir_all(&roms).unwrap();
+        let outside = dir.join("outside.iso");
+        std::fs::write(&outside, disc_header("GALE01", b"Melee", false)).unwrap();
+        std::os::unix::fs::symlink(&outside, roms.join("melee.iso")).unwrap();
+
+        let mut library = GameLibrary::open([&roms], dir.join("index.json"));
+        let stats = library.rescan().unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert!(library.entries().is_empty());
+    }
+
+    #[test]
+    fn test_game_profile_uses_disc_title() {
+        let dir = test_dir("profile");
+        std::fs::write(
+            dir.join("zelda.iso"),
+            disc_header("GZLE01", b"Wind Waker", false),
+        )
+        .unwrap();
+
+        let mut library = GameLibrary::open([&dir], dir.join("index.json"));
+        library.rescan().unwrap();
+
+        let profile = library.game_profile("GZLE01").unwrap();
+        assert_eq!(profile.game_name, "Wind Waker");
+        assert!(library.game_profile("GXXE01").is_err());
+    }
+}
@@ -1,4 +1,10 @@
 pub mod config;
+pub mod control;
+pub mod library;
 pub mod process;
+pub mod rom;
+pub mod saves;
+pub mod savestate;
 
+pub use library::GameLibrary;
 pub use process::{DolphinConfig, DolphinManager};
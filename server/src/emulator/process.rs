@@ -1,8 +1,17 @@
 #![allow(dead_code)]
 
+use crate::emulator::control::{EmulationCommand, EmulationControl, EmulationStatus, Hotkey};
+use crate::emulator::library::GameLibrary;
+use crate::emulator::rom::{ResolvedRom, RomResolver};
+use crate::emulator::saves::{SaveConfig, SaveManager};
+use crate::emulator::savestate::{
+    dolphin_state_path, slot_key, SaveSlot, SaveStateCatalog, StateEntry, StateOp, StateRequest,
+    StateResponse, Thumbnail, TRANSFER_SLOT,
+};
 use crate::error::{EmulatorError, Result};
+use crate::input::GameProfile;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::process::{Child, Command};
 use tokio::time::timeout;
@@ -22,10 +31,25 @@ pub struct DolphinConfig {
     pub video_backend: String,
 }
 
+impl DolphinConfig {
+    /// ROM roots configured in `rom_directory` (colon-separated, like `PATH`)
+    pub fn rom_roots(&self) -> Vec<PathBuf> {
+        env::split_paths(&self.rom_directory)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect()
+    }
+}
+
 pub struct DolphinManager {
     config: DolphinConfig,
     process: Option<Child>,
     window_id: Option<u64>,
+    current_rom: Option<ResolvedRom>,
+    current_profile: Option<GameProfile>,
+    library: Option<GameLibrary>,
+    control: Option<EmulationControl>,
+    saves: SaveManager,
+    save_user: String,
     startup_timeout: Duration,
     process_monitor: Option<tokio::task::JoinHandle<()>>,
 }
@@ -50,9 +74,11 @@ impl DolphinManager {
         }
 
         // Create necessary directories
-        for dir in &[&config.rom_directory, &config.save_directory] {
+        let mut directories = config.rom_roots();
+        directories.push(PathBuf::from(&config.save_directory));
+        for dir in &directories {
             if let Err(e) = std::fs::create_dir_all(dir) {
-                warn!("Failed to create directory {}: {}", dir, e);
+                warn!("Failed to create directory {}: {}", dir.display(), e);
             }
         }
 
@@ -64,28 +90,81 @@ impl DolphinManager {
         debug!("  Video backend: {}", config.video_backend);
         debug!("  Startup timeout: {:?}", startup_timeout);
 
+        let saves = SaveManager::new(Path::new(&config.save_directory), SaveConfig::from_env());
+
         Ok(Self {
             config,
             process: None,
             window_id: None,
+            current_rom: None,
+            current_profile: None,
+            library: None,
+            control: None,
+            saves,
+            save_user: DEFAULT_SAVE_USER.to_string(),
             startup_timeout,
             process_monitor: None,
         })
     }
 
-    pub async fn start_game(&mut self, rom_name: &str) -> Result<()> {
-        let rom_path = format!("{}/{}", self.config.rom_directory, rom_name);
+    /// Resolver over the configured ROM roots
+    pub fn rom_resolver(&self) -> RomResolver {
+        RomResolver::new(self.config.rom_roots())
+    }
+
+    /// Use a scanned library for game profiles, named from the disc headers
+    pub fn set_library(&mut self, library: GameLibrary) {
+        self.library = Some(library);
+    }
 
-        if !std::path::Path::new(&rom_path).exists() {
-            return Err(EmulatorError::RomNotFound { path: rom_path }.into());
+    /// Launch a game by game ID (e.g. `GALE01`) or library entry
+    ///
+    /// The game profile for the resolved ID is loaded automatically and can be
+    /// retrieved with [`DolphinManager::current_profile`].
+    pub async fn start_game(&mut self, game: &str) -> Result<()> {
+        let rom = self.rom_resolver().resolve(game)?;
+        let rom_path = rom.path.clone();
+
+        self.current_profile = match rom.game_id.as_deref() {
+            Some(game_id) => match self.game_profile(game_id) {
+                Ok(profile) => {
+                    info!("Applied game profile: {} ({})", profile.game_name, game_id);
+                    Some(profile)
+                }
+                Err(e) => {
+                    warn!("Failed to load game profile for {}: {}", game_id, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Snapshot the saves this launch may overwrite
+        if let Some(game_id) = rom.game_id.as_deref() {
+            if let Err(e) = self.saves.backup(&self.save_user, game_id) {
+                warn!("Failed to back up saves for {}: {}", game_id, e);
+            }
         }
+        let save_args = self.saves.launch_args(&self.save_user)?;
+        self.current_rom = Some(rom);
+
+        self.control = match EmulationControl::install(Path::new(&self.config.save_directory)) {
+            Ok(control) => Some(control),
+            Err(e) => {
+                warn!("Emulation control unavailable: {}", e);
+                None
+            }
+        };
 
-        info!("Starting Dolphin with ROM: {}", rom_path);
+        info!("Starting Dolphin with ROM: {}", rom_path.display());
 
         let mut cmd = Command::new(&self.config.executable_path);
         cmd.arg("--exec")
             .arg(&rom_path)
             .arg("--nogui")
+            .arg("--user")
+            .arg(&self.config.save_directory)
+            .args(&save_args)
             .arg("--save")
             .arg(&self.config.save_directory)
             .arg("--audio-backend")
@@ -119,7 +198,7 @@ impl DolphinManager {
 
         match startup_result {
             Ok(Ok(())) => {
-                info!("Dolphin started successfully for ROM: {}", rom_name);
+                info!("Dolphin started successfully for: {}", game);
                 Ok(())
             }
             Ok(Err(e)) => {
@@ -135,6 +214,16 @@ impl DolphinManager {
         }
     }
 
+    /// Game profile for a game ID, from the library when it has the game
+    fn game_profile(&self, game_id: &str) -> Result<GameProfile> {
+        match &self.library {
+            Some(library) if library.find_by_game_id(game_id).is_some() => {
+                library.game_profile(game_id)
+            }
+            _ => GameProfile::load_for_game(game_id),
+        }
+    }
+
     pub async fn stop_game(&mut self) -> Result<()> {
         // Stop process monitor first
         if let Some(monitor_handle) = self.process_monitor.take() {
@@ -149,6 +238,9 @@ impl DolphinManager {
                 Ok(_) => {
                     let _ = process.wait().await;
                     self.window_id = None;
+                    self.current_rom = None;
+                    self.current_profile = None;
+                    self.control = None;
                     info!("Dolphin process stopped successfully");
                     Ok(())
                 }
@@ -191,6 +283,172 @@ impl DolphinManager {
         self.window_id
     }
 
+    /// ROM of the running game, if any
+    pub fn current_rom(&self) -> Option<&ResolvedRom> {
+        self.current_rom.as_ref()
+    }
+
+    /// Game profile applied for the running game, if its ID is known
+    pub fn current_profile(&self) -> Option<&GameProfile> {
+        self.current_profile.as_ref()
+    }
+
+    /// Apply an emulation control command to the running game
+    pub async fn control(&mut self, command: EmulationCommand) -> Result<EmulationStatus> {
+        let control = self.running_control(&format!("{command:?}"))?;
+        let status = control.send(command).await?;
+        info!("Emulation {:?} applied: {:?}", command, status);
+        Ok(status)
+    }
+
+    pub async fn pause(&mut self) -> Result<EmulationStatus> {
+        self.control(EmulationCommand::Pause).await
+    }
+
+    pub async fn resume(&mut self) -> Result<EmulationStatus> {
+        self.control(EmulationCommand::Resume).await
+    }
+
+    /// Soft reset the running game
+    pub async fn reset(&mut self) -> Result<EmulationStatus> {
+        self.control(EmulationCommand::Reset).await
+    }
+
+    /// Step one frame, pausing first if the game is running
+    pub async fn frame_advance(&mut self) -> Result<EmulationStatus> {
+        self.control(EmulationCommand::FrameAdvance).await
+    }
+
+    /// Change the emulation speed limit (percent of full speed, 10% steps)
+    pub async fn set_speed(&mut self, percent: u16) -> Result<EmulationStatus> {
+        self.control(EmulationCommand::SetSpeed { percent }).await
+    }
+
+    /// Tracked emulation state of the running game, if any
+    pub fn emulation_status(&self) -> Option<EmulationStatus> {
+        self.control.as_ref().map(EmulationControl::status)
+    }
+
+    /// Select whose memory cards and NAND saves the next launch uses
+    pub fn set_save_user(&mut self, user: &str) {
+        self.save_user = user.to_string();
+    }
+
+    /// Memory card and NAND save manager
+    pub fn save_manager(&self) -> &SaveManager {
+        &self.saves
+    }
+
+    /// Save-state catalog under the save directory
+    pub fn state_catalog(&self) -> SaveStateCatalog {
+        SaveStateCatalog::new(Path::new(&self.config.save_directory))
+    }
+
+    /// Save the running game into one of a user's slots
+    pub async fn save_state(
+        &mut self,
+        user: &str,
+        slot: &SaveSlot,
+        thumbnail: Option<&Thumbnail>,
+    ) -> Result<StateEntry> {
+        slot_key(slot)?;
+        let game_id = self.running_game_id()?;
+        let state_path = self.transfer_state_path(&game_id);
+        let previous = modified_time(&state_path);
+
+        self.running_control("save state")?
+            .press(&[Hotkey::SaveTransferSlot])
+            .await?;
+        wait_for_state_file(&state_path, previous).await?;
+
+        self.state_catalog()
+            .store(user, &game_id, slot, &state_path, thumbnail)
+    }
+
+    /// Load one of a user's slots into the running game
+    pub async fn load_state(&mut self, user: &str, slot: &SaveSlot) -> Result<StateEntry> {
+        let game_id = self.running_game_id()?;
+        let (entry, stored) = self.state_catalog().get(user, &game_id, slot)?;
+        let state_path = self.transfer_state_path(&game_id);
+
+        if let Some(parent) = state_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&stored, &state_path)?;
+
+        self.running_control("load state")?
+            .press(&[Hotkey::LoadTransferSlot])
+            .await?;
+        info!("Loaded save state {} for {} ({})", slot, user, game_id);
+        Ok(entry)
+    }
+
+    /// Handle a save-state request from a client
+    pub async fn handle_state_request(
+        &mut self,
+        request: StateRequest,
+        thumbnail: Option<Thumbnail>,
+    ) -> Result<StateResponse> {
+        let user = request.user.as_str();
+        let catalog = self.state_catalog();
+        let game_id = || match &request.game_id {
+            Some(game_id) => Ok(game_id.clone()),
+            None => self.running_game_id(),
+        };
+
+        Ok(match &request.op {
+            StateOp::Save { slot } => {
+                StateResponse::Saved(self.save_state(user, slot, thumbnail.as_ref()).await?)
+            }
+            StateOp::Load { slot } => StateResponse::Loaded(self.load_state(user, slot).await?),
+            StateOp::List => StateResponse::Listed(catalog.list(user, request.game_id.as_deref())?),
+            StateOp::Delete { slot } => {
+                catalog.delete(user, &game_id()?, slot)?;
+                StateResponse::Deleted
+            }
+            StateOp::Export { slot } => {
+                let path = catalog.export(user, &game_id()?, slot)?;
+                StateResponse::Exported {
+                    path: path.display().to_string(),
+                }
+            }
+        })
+    }
+
+    fn running_control(&mut self, operation: &str) -> Result<&mut EmulationControl> {
+        match (&self.process, self.control.as_mut()) {
+            (Some(_), Some(control)) => Ok(control),
+            (None, _) => Err(EmulatorError::ProcessControlFailed {
+                operation: operation.to_string(),
+                reason: "Dolphin is not running".to_string(),
+            }
+            .into()),
+            (Some(_), None) => Err(EmulatorError::ProcessControlFailed {
+                operation: operation.to_string(),
+                reason: "Emulation control pipe is not installed".to_string(),
+            }
+            .into()),
+        }
+    }
+
+    fn running_game_id(&self) -> Result<String> {
+        self.current_rom
+            .as_ref()
+            .and_then(|rom| rom.game_id.clone())
+            .ok_or_else(|| {
+                EmulatorError::InvalidSaveState("no game with a known ID is running".to_string())
+                    .into()
+            })
+    }
+
+    fn transfer_state_path(&self, game_id: &str) -> PathBuf {
+        dolphin_state_path(
+            Path::new(&self.config.save_directory),
+            game_id,
+            TRANSFER_SLOT,
+        )
+    }
+
     async fn find_dolphin_window(&mut self) -> Result<()> {
         // TODO: Implement X11 window finding using x11 crate
         // This would use X11 APIs to find the Dolphin window by process ID or title
@@ -264,6 +522,9 @@ impl DolphinManager {
         }
         self.process = None;
         self.window_id = None;
+        self.current_rom = None;
+        self.current_profile = None;
+        self.control = None;
         debug!("Process cleanup completed");
     }
 
@@ -286,6 +547,47 @@ impl DolphinManager {
     }
 }
 
+/// Save user for launches before a client picks one
+const DEFAULT_SAVE_USER: &str = "default";
+
+/// Time Dolphin is given to write a save state
+const STATE_SAVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn modified_time(path: &Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Wait until Dolphin has rewritten a state file and its size has settled
+async fn wait_for_state_file(path: &Path, previous: Option<std::time::SystemTime>) -> Result<()> {
+    let poll = Duration::from_millis(100);
+    let mut last_size = None;
+
+    let written = timeout(STATE_SAVE_TIMEOUT, async {
+        loop {
+            tokio::time::sleep(poll).await;
+            let Ok(metadata) = std::fs::metadata(path) else {
+                continue;
+            };
+            if metadata.modified().ok() == previous {
+                continue;
+            }
+            if last_size == Some(metadata.len()) {
+                return;
+            }
+            last_size = Some(metadata.len());
+        }
+    })
+    .await;
+
+    written.map_err(|_| {
+        EmulatorError::ProcessControlFailed {
+            operation: "save state".to_string(),
+            reason: format!("Dolphin did not write {}", path.display()),
+        }
+        .into()
+    })
+}
+
 impl Drop for DolphinManager {
     fn drop(&mut self) {
         if self.process.is_some() {
@@ -396,6 +698,87 @@ mod tests {
         assert!(result.is_err(), "Should fail with nonexistent ROM");
     }
 
+    #[tokio::test]
+    async fn test_rom_path_traversal_rejected() {
+        setup_test_env();
+
+        let config = create_test_config();
+        let mut manager = DolphinManager::new(config).unwrap();
+
+        let result = manager.start_game("../../etc/passwd").await;
+        assert!(result.is_err(), "Should refuse paths outside the ROM roots");
+        assert!(manager.current_rom().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_start_game_by_id_applies_profile() {
+        setup_test_env();
+
+        let test_script = "/tmp/test-dolphin-id.sh";
+        std::fs::write(test_script, "#!/bin/sh\nsleep 60\n").unwrap();
+        std::fs::set_permissions(test_script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let rom_dir = "/tmp/test-roms-by-id";
+        std::fs::create_dir_all(rom_dir).unwrap();
+        std::fs::write(format!("{rom_dir}/Melee [GALE01].iso"), "dummy content").unwrap();
+
+        let mut config = create_test_config();
+        config.executable_path = test_script.to_string();
+        config.rom_directory = rom_dir.to_string();
+        let mut manager = DolphinManager::new(config).unwrap();
+
+        manager.start_game("GALE01").await.unwrap();
+        assert_eq!(
+            manager.current_rom().and_then(|rom| rom.game_id.as_deref()),
+            Some("GALE01")
+        );
+        assert_eq!(
+            manager.current_profile().map(|p| p.game_name.as_str()),
+            Some("Super Smash Bros. Melee")
+        );
+
+        manager.stop_game().await.unwrap();
+        assert!(manager.current_profile().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_control_requires_running_game() {
+        setup_test_env();
+
+        let config = create_test_config();
+        let mut manager = DolphinManager::new(config).unwrap();
+
+        assert!(manager.pause().await.is_err());
+        assert!(manager.emulation_status().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_state_request_without_running_game() {
+        setup_test_env();
+
+        let config = create_test_config();
+        let mut manager = DolphinManager::new(config).unwrap();
+
+        let request = StateRequest {
+            user: "player1".to_string(),
+            game_id: None,
+            op: StateOp::Save {
+                slot: SaveSlot::Numbered(1),
+            },
+        };
+        assert!(manager.handle_state_request(request, None).await.is_err());
+
+        let request = StateRequest {
+            user: "player1".to_string(),
+            game_id: Some("GALE01".to_string()),
+            op: StateOp::List,
+        };
+        assert!(matches!(
+            manager.handle_state_request(request, None).await.unwrap(),
+            StateResponse::Listed(_)
+        ));
+    }
+
     #[tokio::test]
     async fn test_multiple_stop_calls() {
         setup_test_env();
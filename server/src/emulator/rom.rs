@@ -0,0 +1,380 @@
+//! ROM resolution for Dolphin launches
+//!
+//! Maps game IDs (e.g. `GALE01`) and library entries to files under the
+//! configured ROM roots. Every resolved path is canonicalized and must stay
+//! inside one of the roots, so clients cannot launch arbitrary files.
+
+use crate::emulator::library::read_disc_header;
+use crate::error::{EmulatorError, Result};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// File extensions Dolphin can boot directly
+pub const ROM_EXTENSIONS: &[&str] = &[
+    "iso", "gcm", "ciso", "gcz", "wbfs", "rvz", "wia", "dol", "elf", "wad",
+];
+
+/// Maximum number of "did you mean" suggestions returned on a miss
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Directory depth limit when walking ROM roots
+const MAX_SCAN_DEPTH: usize = 4;
+
+/// A ROM request resolved to a file inside the library
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedRom {
+    /// Canonical path of the ROM file
+    pub path: PathBuf,
+    /// Game ID, if it could be determined from the request, file name or header
+    pub game_id: Option<String>,
+}
+
+/// Resolves game IDs and library entries to files under the ROM roots
+#[derive(Debug, Clone)]
+pub struct RomResolver {
+    roots: Vec<PathBuf>,
+}
+
+impl RomResolver {
+    /// Create a resolver over the given roots; roots that do not exist are skipped
+    pub fn new<I, P>(roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let roots = roots
+            .into_iter()
+            .filter_map(|root| match root.as_ref().canonicalize() {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    warn!("Skipping ROM root {}: {}", root.as_ref().display(), e);
+                    None
+                }
+            })
+            .collect();
+
+        Self { roots }
+    }
+
+    /// Canonical ROM roots searched by this resolver
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Resolve a game ID or library entry to a ROM file
+    pub fn resolve(&self, request: &str) -> Result<ResolvedRom> {
+        let request = request.trim();
+
+        if is_game_id(request) {
+            if let Some(path) = self.find_by_game_id(request) {
+                debug!("Resolved game ID {} to {}", request, path.display());
+                return Ok(ResolvedRom {
+                    path,
+                    game_id: Some(request.to_string()),
+                });
+            }
+        }
+
+        if let Some(path) = self.resolve_entry(request) {
+            debug!("Resolved library entry {} to {}", request, path.display());
+            let game_id = game_id_from_file_name(&path).or_else(|| read_game_id(&path));
+            return Ok(ResolvedRom { path, game_id });
+        }
+
+        Err(EmulatorError::RomNotFound {
+            path: request.to_string(),
+            suggestions: self.suggestions(request),
+        }
+        .into())
+    }
+
+    /// List every bootable file under the ROM roots
+    pub fn list_roms(&self) -> Vec<PathBuf> {
+        let mut roms = Vec::new();
+        for root in &self.roots {
+            collect_roms(root, 0, &mut roms);
+        }
+        roms.sort();
+        roms
+    }
+
+    /// Check that a path is inside one of the ROM roots after canonicalization
+    pub fn contains(&self, path: &Path) -> bool {
+        match path.canonicalize() {
+            Ok(canonical) => self.roots.iter().any(|root| canonical.starts_with(root)),
+            Err(_) => false,
+        }
+    }
+
+    fn resolve_entry(&self, entry: &str) -> Option<PathBuf> {
+        if entry.is_empty() {
+            return None;
+        }
+
+        for root in &self.roots {
+            let Ok(candidate) = root.join(entry).canonicalize() else {
+                continue;
+            };
+
+            // Another root may still hold the entry
+            if !candidate.starts_with(root) {
+                warn!("Refusing ROM request outside library: {}", entry);
+                continue;
+            }
+
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+
+        None
+    }
+
+    fn find_by_game_id(&self, game_id: &str) -> Option<PathBuf> {
+        // Symlinks in the library may lead anywhere; skip those leaving it
+        let roms: Vec<PathBuf> = self
+            .list_roms()
+            .into_iter()
+            .filter(|path| self.contains(path))
+            .collect();
+
+        // Prefer an explicit tag in the file name, then fall back to disc headers
+        roms.iter()
+            .find(|path| game_id_from_file_name(path).as_deref() == Some(game_id))
+            .or_else(|| {
+                roms.iter()
+                    .find(|path| read_game_id(path).as_deref() == Some(game_id))
+            })
+            .and_then(|path| path.canonicalize().ok())
+    }
+
+    fn suggestions(&self, request: &str) -> Vec<String> {
+        let needle = request.to_lowercase();
+        let mut scored: Vec<(usize, String)> = self
+            .list_roms()
+            .into_iter()
+            .filter_map(|path| {
+                let entry = self.entry_name(&path)?;
+                let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+                let score = if stem.contains(&needle) || needle.contains(&stem) {
+                    0
+                } else {
+                    edit_distance(&needle, &stem)
+                };
+                (score <= needle.len().max(3) / 2).then_some((score, entry))
+            })
+            .collect();
+
+        scored.sort();
+        scored
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .take(MAX_SUGGESTIONS)
+            .collect()
+    }
+
+    /// Library entry name (path relative to its root) for a ROM file
+    fn entry_name(&self, path: &Path) -> Option<String> {
+        self.roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .map(|relative| relative.to_string_lossy().into_owned())
+    }
+}
+
+/// Check whether a string looks like a GameCube/Wii game ID (e.g. `GALE01`)
+pub fn is_game_id(value: &str) -> bool {
+    value.len() == 6
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+/// Extract a `[GALE01]`-style game ID tag from a file name
+pub fn game_id_from_file_name(path: &Path) -> Option<String> {
+    let name = path.file_stem()?.to_str()?;
+    name.split(['[', ']', '(', ')'])
+        .find(|part| is_game_id(part))
+        .map(str::to_string)
+}
+
+/// Read the game ID from the header of a disc image in any supported format
+pub fn read_game_id(path: &Path) -> Option<String> {
+    match read_disc_header(path) {
+        Ok(header) => header.map(|(_, header)| header.game_id),
+        Err(e) => {
+            debug!("Failed to read disc header from {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn collect_roms(dir: &Path, depth: usize, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth < MAX_SCAN_DEPTH {
+                collect_roms(&path, depth + 1, roms);
+            }
+        } else if has_rom_extension(&path) {
+            roms.push(path);
+        }
+    }
+}
+
+fn has_rom_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Levenshtein distance used to rank ROM suggestions
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DpstreamError;
+
+    fn create_library(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("dpstream-rom-{name}"));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("gamecube")).unwrap();
+
+        let mut melee = b"GALE01".to_vec();
+        melee.resize(0x440, 0);
+        melee[0x1C..0x20].copy_from_slice(&[0xC2, 0x33, 0x9F, 0x3D]);
+        std::fs::write(root.join("gamecube/melee.iso"), melee).unwrap();
+        std::fs::write(root.join("Metroid Prime [GM4E01].rvz"), b"RVZ\x01").unwrap();
+        std::fs::write(root.join("notes.txt"), b"not a rom").unwrap();
+
+        root
+    }
+
+    #[test]
+    fn test_is_game_id() {
+        assert!(is_game_id("GALE01"));
+        assert!(is_game_id("RSBE01"));
+        assert!(!is_game_id("gale01"));
+        assert!(!is_game_id("GALE0"));
+        assert!(!is_game_id("../etc"));
+    }
+
+    #[test]
+    fn test_resolve_by_header_game_id() {
+        let root = create_library("header");
+        let resolver = RomResolver::new([&root]);
+
+        let rom = resolver.resolve("GALE01").unwrap();
+        assert!(rom.path.ends_with("gamecube/melee.iso"));
+        assert_eq!(rom.game_id.as_deref(), Some("GALE01"));
+    }
+
+    #[test]
+    fn test_resolve_by_file_name_tag() {
+        let root = create_library("tag");
+        let resolver = RomResolver::new([&root]);
+
+        let rom = resolver.resolve("GM4E01").unwrap();
+        assert!(rom.path.ends_with("Metroid Prime [GM4E01].rvz"));
+    }
+
+    #[test]
+    fn test_resolve_library_entry_reads_game_id() {
+        let root = create_library("entry");
+        let resolver = RomResolver::new([&root]);
+
+        let rom = resolver.resolve("gamecube/melee.iso").unwrap();
+        assert_eq!(rom.game_id.as_deref(), Some("GALE01"));
+    }
+
+    #[test]
+    fn test_rejects_path_traversal() {
+        let root = create_library("traversal");
+        let outside = root.parent().unwrap().join("dpstream-rom-outside.iso");
+        std::fs::write(&outside, b"GALE01").unwrap();
+        let resolver = RomResolver::new([root.join("gamecube")]);
+
+        let result = resolver.resolve("../../dpstream-rom-outside.iso");
+        assert!(result.is_err(), "Traversal outside the ROM root must fail");
+        assert!(resolver.resolve(outside.to_str().unwrap()).is_err());
+        assert!(!resolver.contains(&outside));
+    }
+
+    #[test]
+    fn test_game_id_skips_symlinks_leaving_library() {
+        let root = create_library("symlink");
+        let outside = root.with_file_name("dpstream-rom-symlink-outside");
+        let _ = std::fs::remove_dir_all(&outside);
+        std::fs::create_dir_all(&outside).unwrap();
+        let mut zelda = b"GZLE01".to_vec();
+        zelda.resize(0x440, 0);
+        std::fs::write(outside.join("zelda.iso"), &zelda).unwrap();
+        std::os::unix::fs::symlink(outside.join("zelda.iso"), root.join("Melee [GALE01].iso"))
+            .unwrap();
+        std::os::unix::fs::symlink(outside.join("zelda.iso"), root.join("zelda.iso")).unwrap();
+        let resolver = RomResolver::new([&root]);
+
+        // Neither the tag nor the header of the escaping links count
+        let rom = resolver.resolve("GALE01").unwrap();
+        assert!(rom.path.ends_with("gamecube/melee.iso"));
+        assert!(resolver.resolve("GZLE01").is_err());
+    }
+
+    #[test]
+    fn test_entry_escaping_one_root_found_in_next() {
+        let first = create_library("escape-first");
+        let second = create_library("escape-second");
+        std::os::unix::fs::symlink(second.join("notes.txt"), first.join("shared.iso")).unwrap();
+        std::fs::write(second.join("shared.iso"), b"GALE01").unwrap();
+        let resolver = RomResolver::new([&first, &second]);
+
+        let rom = resolver.resolve("shared.iso").unwrap();
+        assert_eq!(rom.path, second.join("shared.iso").canonicalize().unwrap());
+    }
+
+    #[test]
+    fn test_not_found_suggests_similar_entries() {
+        let root = create_library("suggest");
+        let resolver = RomResolver::new([&root]);
+
+        match resolver.resolve("melee") {
+            Err(DpstreamError::Emulator(EmulatorError::RomNotFound { suggestions, .. })) => {
+                assert_eq!(suggestions, vec!["gamecube/melee.iso".to_string()]);
+            }
+            other => panic!("Expected RomNotFound, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_list_roms_filters_extensions() {
+        let root = create_library("list");
+        let resolver = RomResolver::new([&root]);
+
+        let roms = resolver.list_roms();
+        assert_eq!(roms.len(), 2);
+        assert!(roms.iter().all(|path| has_rom_extension(path)));
+    }
+}
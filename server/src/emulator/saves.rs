@@ -0,0 +1,916 @@
+//! GameCube memory card and Wii NAND save management
+//!
+//! Each user gets their own tree under `{save_directory}/users/<user>/`:
+//!
+//! - `GC/Card A/` — GCI folder, one `.gci` file per save (Dolphin's naming)
+//! - `GC/MemoryCardA.raw` — raw memory card image, when raw mode is used
+//! - `Wii/` — NAND root; saves live in `title/00010000/<id-hex>/data`
+//! - `Wii/sd/private/wii/title/<id4>/data.bin` — SD-format Wii saves
+//! - `backups/<game_id>/<backup_id>/` — versioned backups
+//!
+//! Dolphin is pointed at these paths with `-C` config overrides at launch.
+
+use crate::emulator::rom::is_game_id;
+use crate::error::{DpstreamError, EmulatorError, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// GCI header size preceding the save blocks
+const GCI_HEADER_SIZE: usize = 0x40;
+
+/// Memory card block size
+const CARD_BLOCK_SIZE: usize = 0x2000;
+
+/// Raw memory card sizes Dolphin accepts (59 to 2043 blocks)
+const RAW_CARD_SIZES: &[u64] = &[0x80000, 0x100000, 0x200000, 0x400000, 0x800000, 0x1000000];
+
+/// Default number of backups kept per user and game
+pub const DEFAULT_BACKUP_RETENTION: usize = 5;
+
+/// How GameCube saves are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CardMode {
+    /// One `.gci` file per save in a folder
+    GciFolder,
+    /// A single raw memory card image
+    Raw,
+}
+
+impl CardMode {
+    /// Dolphin's `EXIDeviceType` value for slot A
+    fn exi_device(self) -> u8 {
+        match self {
+            CardMode::Raw => 1,
+            CardMode::GciFolder => 8,
+        }
+    }
+}
+
+/// Save management settings
+#[derive(Debug, Clone)]
+pub struct SaveConfig {
+    pub card_mode: CardMode,
+    /// Backups kept per user and game; 0 disables automatic backups
+    pub backup_retention: usize,
+}
+
+impl SaveConfig {
+    /// Read `MEMCARD_MODE` (`gci` or `raw`) and `SAVE_BACKUP_RETENTION`
+    pub fn from_env() -> Self {
+        let card_mode = match env::var("MEMCARD_MODE").as_deref() {
+            Ok("raw") => CardMode::Raw,
+            _ => CardMode::GciFolder,
+        };
+        let backup_retention = env::var("SAVE_BACKUP_RETENTION")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_BACKUP_RETENTION);
+
+        Self {
+            card_mode,
+            backup_retention,
+        }
+    }
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        Self {
+            card_mode: CardMode::GciFolder,
+            backup_retention: DEFAULT_BACKUP_RETENTION,
+        }
+    }
+}
+
+/// Header fields of a `.gci` save file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GciInfo {
+    /// Game code and maker code, e.g. `GALE01`
+    pub game_id: String,
+    /// Internal save file name
+    pub file_name: String,
+    pub block_count: u16,
+    pub modified_secs: u32,
+}
+
+impl GciInfo {
+    /// Parse and validate a GCI file
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < GCI_HEADER_SIZE {
+            return Err(EmulatorError::InvalidSave("GCI file is truncated".to_string()).into());
+        }
+
+        let game_id = String::from_utf8_lossy(&data[0..6]).into_owned();
+        if !is_game_id(&game_id) {
+            return Err(EmulatorError::InvalidSave(format!(
+                "GCI has an invalid game code '{game_id}'"
+            ))
+            .into());
+        }
+
+        let name_field = &data[0x08..0x28];
+        let name_len = name_field.iter().position(|&b| b == 0).unwrap_or(0x20);
+        let file_name = String::from_utf8_lossy(&name_field[..name_len]).into_owned();
+        let modified_secs = u32::from_be_bytes([data[0x28], data[0x29], data[0x2A], data[0x2B]]);
+        let block_count = u16::from_be_bytes([data[0x38], data[0x39]]);
+
+        if data.len() != GCI_HEADER_SIZE + usize::from(block_count) * CARD_BLOCK_SIZE {
+            return Err(EmulatorError::InvalidSave(format!(
+                "GCI size does not match its {block_count} blocks"
+            ))
+            .into());
+        }
+
+        Ok(Self {
+            game_id,
+            file_name,
+            block_count,
+            modified_secs,
+        })
+    }
+
+    /// File name Dolphin uses in GCI folders: `<maker>-<gamecode>-<filename>.gci`
+    pub fn folder_file_name(&self) -> String {
+        let safe_name: String = self
+            .file_name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!(
+            "{}-{}-{}.gci",
+            &self.game_id[4..6],
+            &self.game_id[0..4],
+            safe_name
+        )
+    }
+}
+
+/// Metadata of one versioned backup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub id: String,
+    pub game_id: String,
+    pub created_secs: u64,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Saves a user has for one game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveListing {
+    pub game_id: String,
+    pub gci_files: Vec<GciInfo>,
+    /// Size of the raw memory card, which holds saves for every game
+    pub raw_card_size: Option<u64>,
+    pub wii_nand_save: bool,
+    pub wii_data_bin: bool,
+    /// Backups, newest first
+    pub backups: Vec<BackupEntry>,
+}
+
+/// Per-user memory card and NAND save manager
+#[derive(Debug, Clone)]
+pub struct SaveManager {
+    root: PathBuf,
+    config: SaveConfig,
+}
+
+impl SaveManager {
+    /// Manager over `{save_directory}/users`
+    pub fn new(save_directory: &Path, config: SaveConfig) -> Self {
+        Self {
+            root: save_directory.join("users"),
+            config,
+        }
+    }
+
+    /// Dolphin `-C` overrides pointing slot A and the NAND at the user's saves
+    pub fn launch_args(&self, user: &str) -> Result<Vec<String>> {
+        let gci_folder = self.gci_folder(user)?;
+        let raw_card = self.raw_card_path(user)?;
+        let nand_root = self.nand_root(user)?;
+        for dir in [&gci_folder, &nand_root] {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let overrides = [
+            format!("Dolphin.Core.SlotA={}", self.config.card_mode.exi_device()),
+            format!("Dolphin.Core.GCIFolderAPath={}", gci_folder.display()),
+            format!("Dolphin.Core.MemcardAPath={}", raw_card.display()),
+            format!("Dolphin.General.NANDRootPath={}", nand_root.display()),
+        ];
+        Ok(overrides
+            .into_iter()
+            .flat_map(|value| ["-C".to_string(), value])
+            .collect())
+    }
+
+    /// List a user's saves and backups for a game
+    pub fn list_saves(&self, user: &str, game_id: &str) -> Result<SaveListing> {
+        check_game_id(game_id)?;
+
+        Ok(SaveListing {
+            game_id: game_id.to_string(),
+            gci_files: self
+                .gci_files(user, game_id)?
+                .into_iter()
+                .map(|(_, info)| info)
+                .collect(),
+            raw_card_size: std::fs::metadata(self.raw_card_path(user)?)
+                .ok()
+                .map(|m| m.len()),
+            wii_nand_save: self.wii_save_dir(user, game_id)?.is_dir(),
+            wii_data_bin: self.data_bin_path(user, game_id)?.is_file(),
+            backups: self.list_backups(user, game_id)?,
+        })
+    }
+
+    /// Import a `.gci` save into the user's GCI folder
+    pub fn import_gci(&self, user: &str, source: &Path) -> Result<GciInfo> {
+        let info = GciInfo::parse(&std::fs::read(source)?)?;
+        self.backup(user, &info.game_id)?;
+        let folder = self.gci_folder(user)?;
+        std::fs::create_dir_all(&folder)?;
+
+        std::fs::copy(source, folder.join(info.folder_file_name()))?;
+        info!("Imported GCI save {} for {}", info.game_id, user);
+        Ok(info)
+    }
+
+    /// Replace the user's raw memory card, backing up the old one under `game_id`
+    pub fn import_raw_card(&self, user: &str, game_id: &str, source: &Path) -> Result<()> {
+        let size = std::fs::metadata(source)?.len();
+        if !RAW_CARD_SIZES.contains(&size) {
+            return Err(EmulatorError::InvalidSave(format!(
+                "{size} bytes is not a valid memory card size"
+            ))
+            .into());
+        }
+
+        self.backup(user, game_id)?;
+        let target = self.raw_card_path(user)?;
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(source, target)?;
+        info!("Imported raw memory card for {}", user);
+        Ok(())
+    }
+
+    /// Import an SD-format Wii save (`data.bin`) for a game
+    pub fn import_data_bin(&self, user: &str, game_id: &str, source: &Path) -> Result<()> {
+        self.backup(user, game_id)?;
+        let target = self.data_bin_path(user, game_id)?;
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(source, target)?;
+        info!("Imported Wii data.bin for {} ({})", user, game_id);
+        Ok(())
+    }
+
+    /// Export a game's GCI saves, raw card or `data.bin` into a directory
+    pub fn export(&self, user: &str, game_id: &str, destination: &Path) -> Result<Vec<PathBuf>> {
+        check_game_id(game_id)?;
+        std::fs::create_dir_all(destination)?;
+
+        let mut sources: Vec<PathBuf> = self
+            .gci_files(user, game_id)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        match self.config.card_mode {
+            CardMode::Raw => sources.push(self.raw_card_path(user)?),
+            CardMode::GciFolder => {}
+        }
+        sources.push(self.data_bin_path(user, game_id)?);
+
+        let mut exported = Vec::new();
+        for source in sources.into_iter().filter(|path| path.is_file()) {
+            let Some(name) = source.file_name() else {
+                continue;
+            };
+            let target = destination.join(name);
+            std::fs::copy(&source, &target)?;
+            exported.push(target);
+        }
+
+        if exported.is_empty() {
+            return Err(EmulatorError::InvalidSave(format!("no saves found for {game_id}")).into());
+        }
+        Ok(exported)
+    }
+
+    /// Snapshot a user's saves for a game and prune old backups
+    ///
+    /// Returns `None` when there is nothing to back up or backups are disabled.
+    pub fn backup(&self, user: &str, game_id: &str) -> Result<Option<BackupEntry>> {
+        check_game_id(game_id)?;
+        if self.config.backup_retention == 0 {
+            return Ok(None);
+        }
+
+        let gci_files = self.gci_files(user, game_id)?;
+        let raw_card = self.raw_card_path(user)?;
+        let wii_save = self.wii_save_dir(user, game_id)?;
+        let data_bin = self.data_bin_path(user, game_id)?;
+        let has_raw = self.config.card_mode == CardMode::Raw && raw_card.is_file();
+
+        if gci_files.is_empty() && !has_raw && !wii_save.is_dir() && !data_bin.is_file() {
+            debug!("No saves to back up for {} ({})", user, game_id);
+            return Ok(None);
+        }
+
+        let created_secs = now_secs();
+        let backups_dir = self.backups_dir(user, game_id)?;
+        let mut id = created_secs.to_string();
+        let mut suffix = 1;
+        while backups_dir.join(&id).exists() {
+            id = format!("{created_secs}-{suffix}");
+            suffix += 1;
+        }
+        let dir = backups_dir.join(&id);
+        std::fs::create_dir_all(dir.join("gci"))?;
+
+        let mut stats = CopyStats::default();
+        for (path, _) in &gci_files {
+            if let Some(name) = path.file_name() {
+                stats.add(std::fs::copy(path, dir.join("gci").join(name))?);
+            }
+        }
+        if has_raw {
+            stats.add(std::fs::copy(&raw_card, dir.join("MemoryCardA.raw"))?);
+        }
+        if wii_save.is_dir() {
+            copy_dir(&wii_save, &dir.join("wii"), &mut stats)?;
+        }
+        if data_bin.is_file() {
+            stats.add(std::fs::copy(&data_bin, dir.join("data.bin"))?);
+        }
+
+        let entry = BackupEntry {
+            id,
+            game_id: game_id.to_string(),
+            created_secs,
+            files: stats.files,
+            bytes: stats.bytes,
+        };
+        std::fs::write(dir.join("backup.json"), serde_json::to_vec_pretty(&entry)?)?;
+        info!(
+            "Backed up {} save files for {} ({})",
+            entry.files, user, game_id
+        );
+
+        self.prune_backups(user, game_id)?;
+        Ok(Some(entry))
+    }
+
+    /// Backups for a game, newest first
+    pub fn list_backups(&self, user: &str, game_id: &str) -> Result<Vec<BackupEntry>> {
+        let Ok(entries) = std::fs::read_dir(self.backups_dir(user, game_id)?) else {
+            return Ok(Vec::new());
+        };
+
+        let mut backups: Vec<BackupEntry> = entries
+            .flatten()
+            .filter_map(|entry| std::fs::read(entry.path().join("backup.json")).ok())
+            .filter_map(|json| serde_json::from_slice(&json).ok())
+            .collect();
+        backups.sort_by(|a, b| (b.created_secs, &b.id).cmp(&(a.created_secs, &a.id)));
+        Ok(backups)
+    }
+
+    /// Roll a game's saves back to a backup
+    ///
+    /// The current saves are backed up first, so a restore can itself be undone.
+    pub fn restore_backup(&self, user: &str, game_id: &str, backup_id: &str) -> Result<()> {
+        let backup_dir = self.backups_dir(user, game_id)?.join(backup_id);
+        let valid_id = backup_id.chars().all(|c| c.is_ascii_digit() || c == '-');
+        if !valid_id || !backup_dir.join("backup.json").is_file() {
+            return Err(EmulatorError::BackupNotFound {
+                game_id: game_id.to_string(),
+                backup_id: backup_id.to_string(),
+            }
+            .into());
+        }
+
+        self.backup(user, game_id)?;
+
+        // GCI saves: replace this game's files only
+        for (path, _) in self.gci_files(user, game_id)? {
+            std::fs::remove_file(path)?;
+        }
+        let gci_folder = self.gci_folder(user)?;
+        std::fs::create_dir_all(&gci_folder)?;
+        for entry in std::fs::read_dir(backup_dir.join("gci"))?.flatten() {
+            std::fs::copy(entry.path(), gci_folder.join(entry.file_name()))?;
+        }
+
+        let raw_backup = backup_dir.join("MemoryCardA.raw");
+        if raw_backup.is_file() {
+            std::fs::copy(raw_backup, self.raw_card_path(user)?)?;
+        }
+
+        let wii_save = self.wii_save_dir(user, game_id)?;
+        let wii_backup = backup_dir.join("wii");
+        if wii_backup.is_dir() {
+            if wii_save.exists() {
+                std::fs::remove_dir_all(&wii_save)?;
+            }
+            copy_dir(&wii_backup, &wii_save, &mut CopyStats::default())?;
+        }
+
+        let data_bin_backup = backup_dir.join("data.bin");
+        if data_bin_backup.is_file() {
+            let target = self.data_bin_path(user, game_id)?;
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(data_bin_backup, target)?;
+        }
+
+        info!(
+            "Restored {} saves for {} from backup {}",
+            game_id, user, backup_id
+        );
+        Ok(())
+    }
+
+    fn prune_backups(&self, user: &str, game_id: &str) -> Result<()> {
+        let backups_dir = self.backups_dir(user, game_id)?;
+        for old in self
+            .list_backups(user, game_id)?
+            .into_iter()
+            .skip(self.config.backup_retention)
+        {
+            if let Err(e) = std::fs::remove_dir_all(backups_dir.join(&old.id)) {
+                warn!("Failed to prune backup {}: {}", old.id, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn gci_files(&self, user: &str, game_id: &str) -> Result<Vec<(PathBuf, GciInfo)>> {
+        let Ok(entries) = std::fs::read_dir(self.gci_folder(user)?) else {
+            return Ok(Vec::new());
+        };
+
+        let mut files: Vec<(PathBuf, GciInfo)> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("gci"))
+            })
+            .filter_map(|path| {
+                let info = GciInfo::parse(&std::fs::read(&path).ok()?).ok()?;
+                (info.game_id == game_id).then_some((path, info))
+            })
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(files)
+    }
+
+    fn user_dir(&self, user: &str) -> Result<PathBuf> {
+        let valid = !user.is_empty()
+            && user.len() <= 32
+            && user
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+        if !valid {
+            return Err(EmulatorError::InvalidSave(format!("invalid user name '{user}'")).into());
+        }
+        Ok(self.root.join(user))
+    }
+
+    fn gci_folder(&self, user: &str) -> Result<PathBuf> {
+        Ok(self.user_dir(user)?.join("GC").join("Card A"))
+    }
+
+    fn raw_card_path(&self, user: &str) -> Result<PathBuf> {
+        Ok(self.user_dir(user)?.join("GC").join("MemoryCardA.raw"))
+    }
+
+    fn nand_root(&self, user: &str) -> Result<PathBuf> {
+        Ok(self.user_dir(user)?.join("Wii"))
+    }
+
+    fn wii_save_dir(&self, user: &str, game_id: &str) -> Result<PathBuf> {
+        Ok(self
+            .nand_root(user)?
+            .join("title")
+            .join("00010000")
+            .join(title_hex(game_id))
+            .join("data"))
+    }
+
+    fn data_bin_path(&self, user: &str, game_id: &str) -> Result<PathBuf> {
+        Ok(self
+            .nand_root(user)?
+            .join("sd/private/wii/title")
+            .join(&game_id[..4])
+            .join("data.bin"))
+    }
+
+    fn backups_dir(&self, user: &str, game_id: &str) -> Result<PathBuf> {
+        check_game_id(game_id)?;
+        Ok(self.user_dir(user)?.join("backups").join(game_id))
+    }
+}
+
+/// Usage of the `saves` subcommand
+pub const SAVES_USAGE: &str = "usage: dpstream-server saves \
+list <user> <game_id> | \
+import <user> <file.gci> | \
+import <user> <game_id> <file.raw|data.bin> | \
+export <user> <game_id> <dir> | \
+restore <user> <game_id> <backup_id>";
+
+/// Save management from the command line, `dpstream-server saves ...`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveCommand {
+    List {
+        user: String,
+        game_id: String,
+    },
+    Import {
+        user: String,
+        game_id: Option<String>,
+        source: PathBuf,
+    },
+    Export {
+        user: String,
+        game_id: String,
+        destination: PathBuf,
+    },
+    Restore {
+        user: String,
+        game_id: String,
+        backup_id: String,
+    },
+}
+
+impl SaveCommand {
+    /// Parse the arguments following `saves`
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let command = match args.as_slice() {
+            ["list", user, game_id] => SaveCommand::List {
+                user: user.to_string(),
+                game_id: game_id.to_string(),
+            },
+            ["import", user, source] => SaveCommand::Import {
+                user: user.to_string(),
+                game_id: None,
+                source: PathBuf::from(source),
+            },
+            ["import", user, game_id, source] => SaveCommand::Import {
+                user: user.to_string(),
+                game_id: Some(game_id.to_string()),
+                source: PathBuf::from(source),
+            },
+            ["export", user, game_id, destination] => SaveCommand::Export {
+                user: user.to_string(),
+                game_id: game_id.to_string(),
+                destination: PathBuf::from(destination),
+            },
+            ["restore", user, game_id, backup_id] => SaveCommand::Restore {
+                user: user.to_string(),
+                game_id: game_id.to_string(),
+                backup_id: backup_id.to_string(),
+            },
+            _ => return Err(DpstreamError::Config(SAVES_USAGE.to_string())),
+        };
+        Ok(command)
+    }
+}
+
+impl SaveManager {
+    /// Run a command line save command, returning what to print
+    ///
+    /// Imports pick the kind of save from the file: `.gci` files carry their
+    /// game, raw cards and `data.bin` saves are imported for `game_id`.
+    pub fn run(&self, command: &SaveCommand) -> Result<String> {
+        match command {
+            SaveCommand::List { user, game_id } => Ok(serde_json::to_string_pretty(
+                &self.list_saves(user, game_id)?,
+            )?),
+            SaveCommand::Import {
+                user,
+                game_id: None,
+                source,
+            } => {
+                let info = self.import_gci(user, source)?;
+                Ok(format!("Imported {}", info.folder_file_name()))
+            }
+            SaveCommand::Import {
+                user,
+                game_id: Some(game_id),
+                source,
+            } => {
+                let raw = source
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("raw"));
+                if raw {
+                    self.import_raw_card(user, game_id, source)?;
+                } else {
+                    self.import_data_bin(user, game_id, source)?;
+                }
+                Ok(format!("Imported {} for {}", source.display(), game_id))
+            }
+            SaveCommand::Export {
+                user,
+                game_id,
+                destination,
+            } => {
+                let exported = self.export(user, game_id, destination)?;
+                Ok(exported
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            SaveCommand::Restore {
+                user,
+                game_id,
+                backup_id,
+            } => {
+                self.restore_backup(user, game_id, backup_id)?;
+                Ok(format!("Restored {game_id} from backup {backup_id}"))
+            }
+        }
+    }
+}
+
+/// Lowercase hex of the 4-character title code, as used in NAND paths
+fn title_hex(game_id: &str) -> String {
+    game_id
+        .bytes()
+        .take(4)
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn check_game_id(game_id: &str) -> Result<()> {
+    if is_game_id(game_id) {
+        Ok(())
+    } else {
+        Err(EmulatorError::InvalidSave(format!("invalid game ID '{game_id}'")).into())
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Default)]
+struct CopyStats {
+    files: usize,
+    bytes: u64,
+}
+
+impl CopyStats {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+fn copy_dir(source: &Path, target: &Path, stats: &mut CopyStats) -> Result<()> {
+    std::fs::create_dir_all(target)?;
+    for entry in std::fs::read_dir(source)?.flatten() {
+        let path = entry.path();
+        let destination = target.join(entry.file_name());
+        if path.is_dir() {
+            copy_dir(&path, &destination, stats)?;
+        } else {
+            stats.add(std::fs::copy(&path, &destination)?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_manager(name: &str, retention: usize) -> (SaveManager, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("dpstream-saves-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let config = SaveConfig {
+            card_mode: CardMode::GciFolder,
+            backup_retention: retention,
+        };
+        (SaveManager::new(&dir, config), dir)
+    }
+
+    fn gci_file(dir: &Path, game_id: &str, name: &str, fill: u8) -> PathBuf {
+        let mut data = vec![0xFF; GCI_HEADER_SIZE];
+        data[0..6].copy_from_slice(game_id.as_bytes());
+        data[0x08..0x28].fill(0);
+        data[0x08..0x08 + name.len()].copy_from_slice(name.as_bytes());
+        data[0x38..0x3A].copy_from_slice(&1u16.to_be_bytes());
+        data.resize(GCI_HEADER_SIZE + CARD_BLOCK_SIZE, fill);
+
+        let path = dir.join(format!("{name}.gci"));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_gci_parse_and_folder_name() {
+        let (_, dir) = create_manager("gci", 1);
+        let path = gci_file(&dir, "GALE01", "SuperSmashBros", 0);
+
+        let info = GciInfo::parse(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(info.game_id, "GALE01");
+        assert_eq!(info.block_count, 1);
+        assert_eq!(info.folder_file_name(), "01-GALE-SuperSmashBros.gci");
+
+        assert!(GciInfo::parse(&std::fs::read(&path).unwrap()[..0x100]).is_err());
+    }
+
+    #[test]
+    fn test_import_list_and_export() {
+        let (manager, dir) = create_manager("import", 3);
+        manager
+            .import_gci("player1", &gci_file(&dir, "GALE01", "melee", 1))
+            .unwrap();
+        manager
+            .import_gci("player1", &gci_file(&dir, "GM4E01", "prime", 1))
+            .unwrap();
+
+        let data_bin = dir.join("data.bin");
+        std::fs::write(&data_bin, b"wii save").unwrap();
+        manager
+            .import_data_bin("player1", "RSBE01", &data_bin)
+            .unwrap();
+
+        let listing = manager.list_saves("player1", "GALE01").unwrap();
+        assert_eq!(listing.gci_files.len(), 1);
+        assert!(!listing.wii_data_bin);
+        assert!(
+            manager
+                .list_saves("player1", "RSBE01")
+                .unwrap()
+                .wii_data_bin
+        );
+        assert!(manager
+            .list_saves("player2", "GALE01")
+            .unwrap()
+            .gci_files
+            .is_empty());
+
+        let exported = manager
+            .export("player1", "GALE01", &dir.join("out"))
+            .unwrap();
+        assert_eq!(exported.len(), 1);
+        assert!(exported[0].ends_with("01-GALE-melee.gci"));
+    }
+
+    #[test]
+    fn test_imports_back_up_overwritten_saves() {
+        let (manager, dir) = create_manager("import-backup", 3);
+        let melee = gci_file(&dir, "GALE01", "melee", 1);
+        manager.import_gci("player1", &melee).unwrap();
+        assert!(manager
+            .list_backups("player1", "GALE01")
+            .unwrap()
+            .is_empty());
+        manager.import_gci("player1", &melee).unwrap();
+        assert_eq!(manager.list_backups("player1", "GALE01").unwrap().len(), 1);
+
+        let data_bin = dir.join("data.bin");
+        std::fs::write(&data_bin, b"wii save").unwrap();
+        manager
+            .import_data_bin("player1", "RSBE01", &data_bin)
+            .unwrap();
+        manager
+            .import_data_bin("player1", "RSBE01", &data_bin)
+            .unwrap();
+        let backups = manager.list_backups("player1", "RSBE01").unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].files, 1);
+    }
+
+    #[test]
+    fn test_save_commands() {
+        let (manager, dir) = create_manager("commands", 3);
+        let args =
+            |line: &str| -> Vec<String> { line.split_whitespace().map(str::to_string).collect() };
+        assert!(SaveCommand::parse(&args("list player1")).is_err());
+
+        let melee = gci_file(&dir, "GALE01", "melee", 1);
+        let import =
+            SaveCommand::parse(&args(&format!("import player1 {}", melee.display()))).unwrap();
+        assert_eq!(manager.run(&import).unwrap(), "Imported 01-GALE-melee.gci");
+        manager.run(&import).unwrap();
+
+        let listing: SaveListing = serde_json::from_str(
+            &manager
+                .run(&SaveCommand::parse(&args("list player1 GALE01")).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(listing.gci_files.len(), 1);
+        assert_eq!(listing.backups.len(), 1);
+
+        let restore = SaveCommand::parse(&args(&format!(
+            "restore player1 GALE01 {}",
+            listing.backups[0].id
+        )))
+        .unwrap();
+        manager.run(&restore).unwrap();
+
+        let data_bin = dir.join("data.bin");
+        std::fs::write(&data_bin, b"wii save").unwrap();
+        let import = SaveCommand::parse(&args(&format!(
+            "import player1 RSBE01 {}",
+            data_bin.display()
+        )))
+        .unwrap();
+        manager.run(&import).unwrap();
+        let export = SaveCommand::parse(&args(&format!(
+            "export player1 RSBE01 {}",
+            dir.join("out").display()
+        )))
+        .unwrap();
+        assert!(manager.run(&export).unwrap().ends_with("data.bin"));
+    }
+
+    #[test]
+    fn test_raw_card_size_validation() {
+        let (manager, dir) = create_manager("raw", 1);
+        let bad = dir.join("bad.raw");
+        std::fs::write(&bad, vec![0; 1234]).unwrap();
+        assert!(manager.import_raw_card("player1", "GALE01", &bad).is_err());
+
+        let good = dir.join("good.raw");
+        std::fs::write(&good, vec![0; 0x80000]).unwrap();
+        manager.import_raw_card("player1", "GALE01", &good).unwrap();
+        assert_eq!(
+            manager
+                .list_saves("player1", "GALE01")
+                .unwrap()
+                .raw_card_size,
+            Some(0x80000)
+        );
+    }
+
+    #[test]
+    fn test_backup_retention_and_restore() {
+        let (manager, dir) = create_manager("backup", 2);
+        assert!(manager.backup("player1", "GALE01").unwrap().is_none());
+
+        let original = manager
+            .import_gci("player1", &gci_file(&dir, "GALE01", "melee", 1))
+            .unwrap();
+        let first = manager.backup("player1", "GALE01").unwrap().unwrap();
+        manager.backup("player1", "GALE01").unwrap();
+        manager.backup("player1", "GALE01").unwrap();
+        assert_eq!(manager.list_backups("player1", "GALE01").unwrap().len(), 2);
+        assert!(manager
+            .restore_backup("player1", "GALE01", &first.id)
+            .is_err());
+
+        // Overwrite the save, then roll back to the newest backup
+        gci_file(&dir, "GALE01", "melee", 2);
+        manager
+            .import_gci("player1", &dir.join("melee.gci"))
+            .unwrap();
+        let latest = manager.list_backups("player1", "GALE01").unwrap()[0].clone();
+        manager
+            .restore_backup("player1", "GALE01", &latest.id)
+            .unwrap();
+
+        let restored = manager
+            .gci_folder("player1")
+            .unwrap()
+            .join(original.folder_file_name());
+        assert_eq!(std::fs::read(restored).unwrap()[GCI_HEADER_SIZE], 1);
+    }
+
+    #[test]
+    fn test_launch_args_point_at_user_saves() {
+        let (manager, dir) = create_manager("launch", 1);
+        let args = manager.launch_args("player1").unwrap();
+
+        assert_eq!(args[0], "-C");
+        assert!(args.contains(&"Dolphin.Core.SlotA=8".to_string()));
+        let nand = format!(
+            "Dolphin.General.NANDRootPath={}",
+            dir.join("users/player1/Wii").display()
+        );
+        assert!(args.contains(&nand));
+        assert!(manager.launch_args("../escape").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+//! Save-state slots per user and per game
+//!
+//! Dolphin only knows ten anonymous slots per game, shared by everyone. The
+//! catalog keeps each player's states under
+//! `{save_directory}/states/<user>/<game_id>/` and moves them through
+//! Dolphin's [`TRANSFER_SLOT`] when saving or loading.
+
+use crate::emulator::rom::is_game_id;
+use crate::error::{EmulatorError, Result};
+pub use dpstream_protocol::savestate::{
+    SaveSlot, StateEntry, StateOp, StateRequest, StateResponse,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+/// Dolphin slot used to move states in and out of the catalog
+pub const TRANSFER_SLOT: u8 = 10;
+
+/// Highest numbered slot offered to players
+pub const MAX_NUMBERED_SLOT: u8 = 10;
+
+/// Maximum length of a named slot or user name
+const MAX_NAME_LEN: usize = 48;
+
+/// Thumbnail width; height follows the frame's aspect ratio
+const THUMBNAIL_WIDTH: u32 = 160;
+
+/// File name stem used for a slot in the catalog
+pub fn slot_key(slot: &SaveSlot) -> Result<String> {
+    match slot {
+        SaveSlot::Numbered(n) if (1..=MAX_NUMBERED_SLOT).contains(n) => Ok(format!("slot-{n:02}")),
+        SaveSlot::Numbered(n) => Err(EmulatorError::InvalidSaveState(format!(
+            "slot {n} is outside 1-{MAX_NUMBERED_SLOT}"
+        ))
+        .into()),
+        SaveSlot::Named(name) => Ok(format!("named-{}", sanitize_name(name)?)),
+    }
+}
+
+/// Save-state request forwarded from a client session, with a reply channel
+#[derive(Debug)]
+pub struct StateJob {
+    pub request: StateRequest,
+    pub thumbnail: Option<Thumbnail>,
+    pub reply: flume::Sender<StateResponse>,
+}
+
+/// Downscaled RGB image of the latest captured frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// Downscale an I420 frame (full Y plane, then quarter-size U and V planes)
+    pub fn from_i420(data: &[u8], width: u32, height: u32) -> Option<Self> {
+        let (w, h) = (width as usize, height as usize);
+        let luma = w * h;
+        let chroma = (w / 2) * (h / 2);
+        if w < 2 || h < 2 || data.len() < luma + 2 * chroma {
+            return None;
+        }
+
+        let thumb_w = THUMBNAIL_WIDTH.min(width);
+        let thumb_h = (height * thumb_w / width).max(1);
+        let mut rgb = Vec::with_capacity((thumb_w * thumb_h * 3) as usize);
+
+        for ty in 0..thumb_h as usize {
+            let y = ty * h / thumb_h as usize;
+            for tx in 0..thumb_w as usize {
+                let x = tx * w / thumb_w as usize;
+                let c = (y / 2) * (w / 2) + x / 2;
+                let luma_value = f32::from(data[y * w + x]) - 16.0;
+                let u = f32::from(data[luma + c]) - 128.0;
+                let v = f32::from(data[luma + chroma + c]) - 128.0;
+
+                // BT.601 limited range
+                let scaled = 1.164 * luma_value;
+                rgb.push((scaled + 1.596 * v).clamp(0.0, 255.0) as u8);
+                rgb.push((scaled - 0.392 * u - 0.813 * v).clamp(0.0, 255.0) as u8);
+                rgb.push((scaled + 2.017 * u).clamp(0.0, 255.0) as u8);
+            }
+        }
+
+        Some(Self {
+            width: thumb_w,
+            height: thumb_h,
+            rgb,
+        })
+    }
+
+    /// Encode as an 8-bit RGB PNG
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.rgb.len() + self.height as usize);
+        for row in self.rgb.chunks(self.width as usize * 3) {
+            raw.push(0); // Filter: none
+            raw.extend_from_slice(row);
+        }
+
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        let _ = encoder.write_all(&raw);
+        let idat = encoder.finish().unwrap_or_default();
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit truecolor
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_png_chunk(&mut png, b"IHDR", &ihdr);
+        write_png_chunk(&mut png, b"IDAT", &idat);
+        write_png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = flate2::Crc::new();
+    crc.update(kind);
+    crc.update(data);
+
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Per-user, per-game catalog of save states
+#[derive(Debug, Clone)]
+pub struct SaveStateCatalog {
+    root: PathBuf,
+}
+
+impl SaveStateCatalog {
+    /// Catalog stored under `{save_directory}/states`
+    pub fn new(save_directory: &Path) -> Self {
+        Self {
+            root: save_directory.join("states"),
+        }
+    }
+
+    /// Copy a state written by Dolphin into the catalog
+    pub fn store(
+        &self,
+        user: &str,
+        game_id: &str,
+        slot: &SaveSlot,
+        state_file: &Path,
+        thumbnail: Option<&Thumbnail>,
+    ) -> Result<StateEntry> {
+        let dir = self.game_dir(user, game_id)?;
+        let key = slot_key(slot)?;
+        std::fs::create_dir_all(&dir)?;
+
+        let size = std::fs::copy(state_file, dir.join(format!("{key}.sav")))?;
+        let thumbnail_path = dir.join(format!("{key}.png"));
+        match thumbnail {
+            Some(thumbnail) => std::fs::write(&thumbnail_path, thumbnail.to_png())?,
+            None => {
+                let _ = std::fs::remove_file(&thumbnail_path);
+            }
+        }
+
+        let entry = StateEntry {
+            user: user.to_string(),
+            game_id: game_id.to_string(),
+            slot: slot.clone(),
+            created_secs: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            size,
+            has_thumbnail: thumbnail.is_some(),
+        };
+        std::fs::write(
+            dir.join(format!("{key}.json")),
+            serde_json::to_vec_pretty(&entry)?,
+        )?;
+
+        info!("Stored save state {} for {} ({})", slot, user, game_id);
+        Ok(entry)
+    }
+
+    /// Catalog entry and state file for a slot
+    pub fn get(&self, user: &str, game_id: &str, slot: &SaveSlot) -> Result<(StateEntry, PathBuf)> {
+        let dir = self.game_dir(user, game_id)?;
+        let key = slot_key(slot)?;
+        let state_path = dir.join(format!("{key}.sav"));
+
+        let entry = std::fs::read(dir.join(format!("{key}.json")))
+            .ok()
+            .and_then(|json| serde_json::from_slice::<StateEntry>(&json).ok())
+            .filter(|_| state_path.is_file())
+            .ok_or_else(|| EmulatorError::SaveStateNotFound {
+                game_id: game_id.to_string(),
+                slot: slot.to_string(),
+            })?;
+
+        Ok((entry, state_path))
+    }
+
+    /// States for a user, for one game or all games, newest first
+    pub fn list(&self, user: &str, game_id: Option<&str>) -> Result<Vec<StateEntry>> {
+        let user_dir = self.root.join(sanitize_name(user)?);
+        let game_dirs: Vec<PathBuf> = match game_id {
+            Some(game_id) => vec![self.game_dir(user, game_id)?],
+            None => std::fs::read_dir(&user_dir)
+                .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+                .unwrap_or_default(),
+        };
+
+        let mut states = Vec::new();
+        for dir in game_dirs {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                    continue;
+                }
+                match std::fs::read(&path)
+                    .ok()
+                    .and_then(|json| serde_json::from_slice::<StateEntry>(&json).ok())
+                {
+                    Some(entry) => states.push(entry),
+                    None => debug!("Skipping unreadable state metadata {}", path.display()),
+                }
+            }
+        }
+
+        states.sort_by_key(|entry| std::cmp::Reverse(entry.created_secs));
+        Ok(states)
+    }
+
+    /// Remove a stored state and its thumbnail
+    pub fn delete(&self, user: &str, game_id: &str, slot: &SaveSlot) -> Result<()> {
+        self.get(user, game_id, slot)?;
+        let dir = self.game_dir(user, game_id)?;
+        let key = slot_key(slot)?;
+
+        for ext in ["sav", "png", "json"] {
+            let _ = std::fs::remove_file(dir.join(format!("{key}.{ext}")));
+        }
+
+        info!("Deleted save state {} for {} ({})", slot, user, game_id);
+        Ok(())
+    }
+
+    /// Copy a stored state (and thumbnail) to `{save_directory}/exports/<user>`
+    pub fn export(&self, user: &str, game_id: &str, slot: &SaveSlot) -> Result<PathBuf> {
+        let (entry, state_path) = self.get(user, game_id, slot)?;
+        let key = slot_key(slot)?;
+        let export_dir = self
+            .root
+            .parent()
+            .unwrap_or(&self.root)
+            .join("exports")
+            .join(sanitize_name(user)?);
+        std::fs::create_dir_all(&export_dir)?;
+
+        let target = export_dir.join(format!("{game_id}-{key}.sav"));
+        std::fs::copy(&state_path, &target)?;
+        if entry.has_thumbnail {
+            let thumbnail = state_path.with_extension("png");
+            let _ = std::fs::copy(thumbnail, target.with_extension("png"));
+        }
+
+        info!("Exported save state {} to {}", slot, target.display());
+        Ok(target)
+    }
+
+    fn game_dir(&self, user: &str, game_id: &str) -> Result<PathBuf> {
+        if !is_game_id(game_id) {
+            return Err(
+                EmulatorError::InvalidSaveState(format!("invalid game ID '{game_id}'")).into(),
+            );
+        }
+        Ok(self.root.join(sanitize_name(user)?).join(game_id))
+    }
+}
+
+/// Dolphin's own state file for a slot: `{user_dir}/StateSaves/<game_id>.sNN`
+pub fn dolphin_state_path(user_directory: &Path, game_id: &str, slot: u8) -> PathBuf {
+    user_directory
+        .join("StateSaves")
+        .join(format!("{game_id}.s{slot:02}"))
+}
+
+/// Restrict user and slot names to characters safe in file names
+fn sanitize_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty()
+        || name.len() > MAX_NAME_LEN
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ' '))
+    {
+        return Err(EmulatorError::InvalidSaveState(format!(
+            "'{name}' must be 1-{MAX_NAME_LEN} letters, digits, spaces, '-' or '_'"
+        ))
+        .into());
+    }
+    Ok(name.replace(' ', "_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_catalog(name: &str) -> (SaveStateCatalog, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("dpstream-states-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let state_file = dir.join("GALE01.s10");
+        std::fs::write(&state_file, b"dolphin state").unwrap();
+        (SaveStateCatalog::new(&dir), state_file)
+    }
+
+    #[test]
+    fn test_slot_keys() {
+        assert_eq!(slot_key(&SaveSlot::Numbered(3)).unwrap(), "slot-03");
+        assert_eq!(
+            slot_key(&SaveSlot::Named("Before Boss".into())).unwrap(),
+            "named-Before_Boss"
+        );
+        assert!(slot_key(&SaveSlot::Numbered(0)).is_err());
+        assert!(slot_key(&SaveSlot::Named("../escape".into())).is_err());
+    }
+
+    #[test]
+    fn test_store_list_delete() {
+        let (catalog, state_file) = create_catalog("lifecycle");
+        let slot = SaveSlot::Named("final destination".into());
+        let thumbnail = Thumbnail::from_i420(&[128u8; 64 * 36 * 3 / 2], 64, 36).unwrap();
+
+        let entry = catalog
+            .store("player1", "GALE01", &slot, &state_file, Some(&thumbnail))
+            .unwrap();
+        assert_eq!(entry.size, 13);
+        assert!(entry.has_thumbnail);
+
+        catalog
+            .store(
+                "player1",
+                "GALE01",
+                &SaveSlot::Numbered(1),
+                &state_file,
+                None,
+            )
+            .unwrap();
+        catalog
+            .store(
+                "player2",
+                "GALE01",
+                &SaveSlot::Numbered(1),
+                &state_file,
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(catalog.list("player1", None).unwrap().len(), 2);
+        assert_eq!(catalog.list("player2", Some("GALE01")).unwrap().len(), 1);
+
+        catalog.delete("player1", "GALE01", &slot).unwrap();
+        assert_eq!(catalog.list("player1", None).unwrap().len(), 1);
+        assert!(catalog.get("player1", "GALE01", &slot).is_err());
+    }
+
+    #[test]
+    fn test_export_copies_state_and_thumbnail() {
+        let (catalog, state_file) = create_catalog("export");
+        let slot = SaveSlot::Numbered(2);
+        let thumbnail = Thumbnail::from_i420(&[16u8; 32 * 18 * 3 / 2], 32, 18).unwrap();
+        catalog
+            .store("player1", "GALE01", &slot, &state_file, Some(&thumbnail))
+            .unwrap();
+
+        let exported = catalog.export("player1", "GALE01", &slot).unwrap();
+        assert!(exported.ends_with("exports/player1/GALE01-slot-02.sav"));
+        assert_eq!(std::fs::read(&exported).unwrap(), b"dolphin state");
+
+        let png = std::fs::read(exported.with_extension("png")).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn test_request_roundtrip() {
+        use dpstream_protocol::Message;
+
+        let request = StateRequest {
+            user: "player1".to_string(),
+            game_id: None,
+            op: StateOp::Save {
+                slot: SaveSlot::Numbered(3),
+            },
+        };
+        assert_eq!(StateRequest::decode(&request.encode()).unwrap(), request);
+    }
+}
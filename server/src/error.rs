@@ -257,8 +257,11 @@ pub enum EmulatorError {
     #[error("Dolphin window not found after timeout: {timeout:?}")]
     WindowNotFound { timeout: Duration },
 
-    #[error("ROM file not found: {path}")]
-    RomNotFound { path: String },
+    #[error("ROM not found: {path}{}", format_suggestions(.suggestions))]
+    RomNotFound {
+        path: String,
+        suggestions: Vec<String>,
+    },
 
     #[error("Dolphin startup timed out")]
     StartupTimeout,
@@ -268,6 +271,27 @@ pub enum EmulatorError {
 
     #[error("Configuration error: {0}")]
     ConfigError(String),
+
+    #[error("Save state not found: {game_id} slot {slot}")]
+    SaveStateNotFound { game_id: String, slot: String },
+
+    #[error("Invalid save state request: {0}")]
+    InvalidSaveState(String),
+
+    #[error("Invalid save data: {0}")]
+    InvalidSave(String),
+
+    #[error("Save backup {backup_id} not found for {game_id}")]
+    BackupNotFound { game_id: String, backup_id: String },
+}
+
+/// Render ROM suggestions as a "did you mean" hint
+fn format_suggestions(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!(" (did you mean: {}?)", suggestions.join(", "))
+    }
 }
 
 /// Input-related errors
@@ -300,6 +324,21 @@ pub enum InputError {
 
     #[error("Input mapping error: {reason}")]
     MappingError { reason: String },
+
+    #[error("No input session {session_id}")]
+    SessionNotFound { session_id: String },
+
+    #[error("Player slot {slot} unavailable: {reason}")]
+    SlotUnavailable { slot: u8, reason: String },
+
+    #[error("Session {session_id} is spectating without a player slot")]
+    Spectating { session_id: String },
+
+    #[error("Controller {controller} of session {session_id} has no player slot")]
+    ControllerNotSeated { session_id: String, controller: u8 },
+
+    #[error("Invalid input recording: {reason}")]
+    InvalidRecording { reason: String },
 }
 
 /// Streaming-related errors
@@ -347,6 +386,12 @@ pub enum StreamingError {
     #[error("Invalid packet data")]
     InvalidPacket,
 
+    #[error("Malformed control message: {0}")]
+    Protocol(#[from] dpstream_protocol::ProtocolError),
+
+    #[error("Client rejected: {reason}")]
+    ClientRejected { reason: String },
+
     #[error("Configuration error in {field}: {reason}")]
     ConfigurationError { field: String, reason: String },
 
@@ -355,6 +400,9 @@ pub enum StreamingError {
 
     #[error("Capture stop failed: {reason}")]
     CaptureStopFailed { reason: String },
+
+    #[error("Emulation control unavailable: {reason}")]
+    ControlUnavailable { reason: String },
 }
 
 impl StreamingError {
@@ -374,9 +422,12 @@ impl StreamingError {
             Self::PipelineError { .. } => false,  // Pipeline configuration issue
             Self::EncoderNotAvailable { .. } => false, // Hardware/driver issue
             Self::InvalidPacket => true,          // Data corruption, can retry
+            Self::Protocol(_) => true,            // Bad message, the next may be fine
+            Self::ClientRejected { .. } => false, // Client needs another version
             Self::ConfigurationError { .. } => false, // Configuration issue
             Self::CaptureStartFailed { .. } => false, // Setup issue
             Self::CaptureStopFailed { .. } => true, // Can force stop
+            Self::ControlUnavailable { .. } => true, // Emulator may not be running yet
         }
     }
 }
@@ -446,6 +497,14 @@ impl DpstreamError {
                     "Check DOLPHIN_PATH configuration".to_string(),
                 ]
             }
+            DpstreamError::Emulator(EmulatorError::RomNotFound { suggestions, .. }) => {
+                let mut actions: Vec<String> = suggestions
+                    .iter()
+                    .map(|entry| format!("Did you mean '{entry}'?"))
+                    .collect();
+                actions.push("Check ROM_PATH configuration".to_string());
+                actions
+            }
             DpstreamError::Vpn(VpnError::AuthFailed(_)) => {
                 vec![
                     "Check Tailscale authentication".to_string(),
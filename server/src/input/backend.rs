@@ -0,0 +1,236 @@
+//! Pluggable input backends
+//!
+//! [`ServerInputManager`](super::ServerInputManager) hands processed commands
+//! to an [`InputBackend`]: Dolphin's pipe devices, a uinput virtual gamepad
+//! for software that reads real devices, or an in-memory recorder for tests.
+//! Backends that can see Dolphin's rumble output also expose it as a stream
+//! of [`RumbleEvent`]s.
+
+use crate::error::{InputError, Result};
+use crate::input::dolphin::DolphinInputAdapter;
+use crate::input::processor::DolphinCommand;
+use crate::input::rumble::RumbleEvent;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Destination for processed controller input
+#[async_trait]
+pub trait InputBackend: Send + Sync {
+    /// Short backend name for logs and status
+    fn name(&self) -> &'static str;
+
+    /// Attach a controller to a player slot
+    fn connect_controller(&mut self, player_slot: u8) -> Result<()>;
+
+    /// Detach a player slot, releasing any held input
+    fn disconnect_controller(&mut self, player_slot: u8) -> Result<()>;
+
+    /// Deliver a batch of commands
+    async fn send_commands(&mut self, commands: Vec<DolphinCommand>) -> Result<()>;
+
+    /// Release all controllers and stop delivering input
+    async fn shutdown(&mut self) -> Result<()>;
+
+    /// Rumble captured from the emulator, if this backend can see it
+    fn rumble_events(&self) -> Option<flume::Receiver<RumbleEvent>> {
+        None
+    }
+}
+
+/// Input backends selectable by configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputBackendKind {
+    /// Dolphin pipe input devices
+    #[default]
+    DolphinPipe,
+    /// Linux uinput virtual gamepads (`system` feature)
+    Uinput,
+    /// In-memory recorder
+    Recording,
+}
+
+impl InputBackendKind {
+    /// Parse a backend name as used in `INPUT_BACKEND`
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "dolphin" | "pipe" => Ok(Self::DolphinPipe),
+            "uinput" => Ok(Self::Uinput),
+            "recording" => Ok(Self::Recording),
+            other => Err(InputError::ConfigurationError {
+                field: "INPUT_BACKEND".to_string(),
+                value: other.to_string(),
+                reason: "expected dolphin, uinput or recording".to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// Read the backend from `INPUT_BACKEND`, defaulting to Dolphin pipes
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("INPUT_BACKEND") {
+            Ok(name) => Self::parse(&name),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Create the backend
+    ///
+    /// `dolphin_user_dir` is where the Dolphin pipe backend puts its FIFOs and
+    /// controller config; other backends ignore it.
+    pub async fn create(self, dolphin_user_dir: &Path) -> Result<Box<dyn InputBackend>> {
+        match self {
+            Self::DolphinPipe => {
+                let mut adapter = DolphinInputAdapter::new()?;
+                adapter.initialize(dolphin_user_dir).await?;
+                Ok(Box::new(adapter))
+            }
+            #[cfg(feature = "system")]
+            Self::Uinput => Ok(Box::new(super::uinput::UinputBackend::new())),
+            #[cfg(not(feature = "system"))]
+            Self::Uinput => Err(InputError::ConfigurationError {
+                field: "INPUT_BACKEND".to_string(),
+                value: "uinput".to_string(),
+                reason: "built without the system feature".to_string(),
+            }
+            .into()),
+            Self::Recording => Ok(Box::new(RecordingBackend::new())),
+        }
+    }
+}
+
+/// Something observed by the [`RecordingBackend`]
+#[derive(Debug, Clone)]
+pub enum RecordedInput {
+    Connected(u8),
+    Disconnected(u8),
+    Command(DolphinCommand),
+}
+
+/// Backend that keeps everything it is sent in memory
+///
+/// Clones share one log, so a test can keep a clone while the manager owns
+/// the boxed backend.
+#[derive(Debug, Clone)]
+pub struct RecordingBackend {
+    log: Arc<Mutex<Vec<RecordedInput>>>,
+    rumble: (flume::Sender<RumbleEvent>, flume::Receiver<RumbleEvent>),
+}
+
+impl Default for RecordingBackend {
+    fn default() -> Self {
+        Self {
+            log: Arc::default(),
+            rumble: flume::unbounded(),
+        }
+    }
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pretend the emulator rumbled
+    pub fn rumble(&self, event: RumbleEvent) {
+        let _ = self.rumble.0.send(event);
+    }
+
+    /// Everything recorded so far, in order
+    pub fn events(&self) -> Vec<RecordedInput> {
+        self.log.lock().clone()
+    }
+
+    /// Recorded commands, without connection events
+    pub fn commands(&self) -> Vec<DolphinCommand> {
+        self.log
+            .lock()
+            .iter()
+            .filter_map(|event| match event {
+                RecordedInput::Command(command) => Some(command.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Drop everything recorded so far
+    pub fn clear(&self) {
+        self.log.lock().clear();
+    }
+}
+
+#[async_trait]
+impl InputBackend for RecordingBackend {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn connect_controller(&mut self, player_slot: u8) -> Result<()> {
+        self.log.lock().push(RecordedInput::Connected(player_slot));
+        Ok(())
+    }
+
+    fn disconnect_controller(&mut self, player_slot: u8) -> Result<()> {
+        self.log
+            .lock()
+            .push(RecordedInput::Disconnected(player_slot));
+        Ok(())
+    }
+
+    async fn send_commands(&mut self, commands: Vec<DolphinCommand>) -> Result<()> {
+        self.log
+            .lock()
+            .extend(commands.into_iter().map(RecordedInput::Command));
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn rumble_events(&self) -> Option<flume::Receiver<RumbleEvent>> {
+        Some(self.rumble.1.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::processor::DolphinButton;
+
+    #[test]
+    fn test_parse_backend_kind() {
+        assert_eq!(
+            InputBackendKind::parse("Dolphin").unwrap(),
+            InputBackendKind::DolphinPipe
+        );
+        assert_eq!(
+            InputBackendKind::parse("recording").unwrap(),
+            InputBackendKind::Recording
+        );
+        assert!(InputBackendKind::parse("joystick").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recording_backend_shares_log() {
+        let recorder = RecordingBackend::new();
+        let mut backend: Box<dyn InputBackend> = Box::new(recorder.clone());
+
+        backend.connect_controller(1).unwrap();
+        backend
+            .send_commands(vec![DolphinCommand::ButtonPress {
+                player: 1,
+                button: DolphinButton::A,
+                pressed: true,
+            }])
+            .await
+            .unwrap();
+
+        assert_eq!(recorder.events().len(), 2);
+        assert!(matches!(
+            recorder.commands().as_slice(),
+            [DolphinCommand::ButtonPress { player: 1, .. }]
+        ));
+    }
+}
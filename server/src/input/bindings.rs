@@ -0,0 +1,369 @@
+//! Data-driven button bindings
+//!
+//! A [`ControllerMapping`](super::ControllerMapping) carries a table of
+//! [`ButtonBinding`]s from Switch buttons to Dolphin buttons. A binding fires
+//! on one button or on a chord of several, and can hold, toggle, repeat
+//! (turbo) or play a short macro. [`BindingState`] evaluates a table against
+//! each input packet, so timed behaviour advances once per packet; the Switch
+//! client sends one every frame.
+
+use crate::input::processor::DolphinButton;
+use crate::input::MoonlightInputPacket;
+use dpstream_protocol::input::buttons;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Trigger level treated as a ZL/ZR press
+const TRIGGER_PRESS_LEVEL: u8 = 128;
+
+/// Switch controller buttons that reach the server
+///
+/// HOME and Capture stay on the console and are never sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SwitchButton {
+    A,
+    B,
+    X,
+    Y,
+    L,
+    R,
+    ZL,
+    ZR,
+    Plus,
+    Minus,
+    LeftStick,
+    RightStick,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl SwitchButton {
+    /// Moonlight button flag, or `None` for the analog triggers
+    pub fn flag(self) -> Option<u16> {
+        match self {
+            Self::Up => Some(buttons::UP),
+            Self::Down => Some(buttons::DOWN),
+            Self::Left => Some(buttons::LEFT),
+            Self::Right => Some(buttons::RIGHT),
+            Self::Plus => Some(buttons::PLUS),
+            Self::Minus => Some(buttons::MINUS),
+            Self::LeftStick => Some(buttons::L_STICK),
+            Self::RightStick => Some(buttons::R_STICK),
+            Self::L => Some(buttons::L),
+            Self::R => Some(buttons::R),
+            Self::A => Some(buttons::A),
+            Self::B => Some(buttons::B),
+            Self::X => Some(buttons::X),
+            Self::Y => Some(buttons::Y),
+            Self::ZL | Self::ZR => None,
+        }
+    }
+
+    /// Whether the button is down in an input packet
+    pub fn is_held(self, input: &MoonlightInputPacket) -> bool {
+        match self {
+            Self::ZL => input.left_trigger >= TRIGGER_PRESS_LEVEL,
+            Self::ZR => input.right_trigger >= TRIGGER_PRESS_LEVEL,
+            button => button
+                .flag()
+                .is_some_and(|flag| input.button_flags & flag != 0),
+        }
+    }
+}
+
+/// How a binding responds while its inputs are held
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingMode {
+    /// Pressed exactly while held
+    #[default]
+    Hold,
+    /// Each press flips the output on or off
+    Toggle,
+    /// Pressed and released `rate_hz` times a second while held
+    Turbo { rate_hz: f32 },
+}
+
+/// One step of a macro
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroStep {
+    /// Buttons held during the step; empty for a pause
+    pub buttons: Vec<DolphinButton>,
+    pub duration_ms: u32,
+}
+
+/// What a binding does when it fires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingAction {
+    /// Press one or more Dolphin buttons
+    Press(Vec<DolphinButton>),
+    /// Play a sequence once per press, to the end even if released early;
+    /// the binding's mode is ignored
+    Macro(Vec<MacroStep>),
+}
+
+/// A Switch button, or chord of buttons, bound to an action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ButtonBinding {
+    /// Buttons that must all be held; a chord takes its buttons away from
+    /// bindings with fewer inputs
+    pub inputs: Vec<SwitchButton>,
+    pub action: BindingAction,
+    #[serde(default)]
+    pub mode: BindingMode,
+}
+
+impl ButtonBinding {
+    /// Hold one Switch button to hold one Dolphin button
+    pub fn press(input: SwitchButton, button: DolphinButton) -> Self {
+        Self {
+            inputs: vec![input],
+            action: BindingAction::Press(vec![button]),
+            mode: BindingMode::Hold,
+        }
+    }
+
+    /// Hold several Switch buttons together to hold the given Dolphin buttons
+    pub fn chord(inputs: Vec<SwitchButton>, buttons: Vec<DolphinButton>) -> Self {
+        Self {
+            inputs,
+            action: BindingAction::Press(buttons),
+            mode: BindingMode::Hold,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: BindingMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+/// Per-binding memory between packets
+#[derive(Debug, Clone, Copy, Default)]
+struct BindingProgress {
+    was_active: bool,
+    toggled: bool,
+    active_since: Option<Instant>,
+    macro_started: Option<Instant>,
+}
+
+/// Evaluation state of a binding table for one player
+#[derive(Debug, Clone, Default)]
+pub struct BindingState {
+    progress: Vec<BindingProgress>,
+}
+
+impl BindingState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Dolphin buttons held after an input packet
+    pub fn resolve(
+        &mut self,
+        table: &[ButtonBinding],
+        input: &MoonlightInputPacket,
+        now: Instant,
+    ) -> HashSet<DolphinButton> {
+        // A different table starts over
+        if self.progress.len() != table.len() {
+            self.progress = vec![BindingProgress::default(); table.len()];
+        }
+
+        // Larger chords claim their buttons first
+        let mut order: Vec<usize> = (0..table.len()).collect();
+        order.sort_by_key(|&index| std::cmp::Reverse(table[index].inputs.len()));
+
+        let mut claimed = HashSet::new();
+        let mut pressed = HashSet::new();
+        for index in order {
+            let binding = &table[index];
+            let active = !binding.inputs.is_empty()
+                && binding
+                    .inputs
+                    .iter()
+                    .all(|button| button.is_held(input) && !claimed.contains(button));
+            if active && binding.inputs.len() > 1 {
+                claimed.extend(binding.inputs.iter().copied());
+            }
+
+            let progress = &mut self.progress[index];
+            let newly_active = active && !progress.was_active;
+            progress.was_active = active;
+
+            match &binding.action {
+                BindingAction::Press(buttons) => {
+                    if fires(progress, binding.mode, active, newly_active, now) {
+                        pressed.extend(buttons.iter().copied());
+                    }
+                }
+                BindingAction::Macro(steps) => {
+                    if newly_active && progress.macro_started.is_none() {
+                        progress.macro_started = Some(now);
+                    }
+                    if let Some(started) = progress.macro_started {
+                        match macro_step(steps, now.saturating_duration_since(started)) {
+                            Some(step) => pressed.extend(step.buttons.iter().copied()),
+                            None => progress.macro_started = None,
+                        }
+                    }
+                }
+            }
+        }
+        pressed
+    }
+}
+
+/// Whether a press binding outputs its buttons this packet
+fn fires(
+    progress: &mut BindingProgress,
+    mode: BindingMode,
+    active: bool,
+    newly_active: bool,
+    now: Instant,
+) -> bool {
+    match mode {
+        BindingMode::Hold => active,
+        BindingMode::Toggle => {
+            if newly_active {
+                progress.toggled = !progress.toggled;
+            }
+            progress.toggled
+        }
+        BindingMode::Turbo { rate_hz } => {
+            if !active {
+                progress.active_since = None;
+                return false;
+            }
+            let since = *progress.active_since.get_or_insert(now);
+            let cycles = now.saturating_duration_since(since).as_secs_f32() * rate_hz.max(0.0);
+            // Pressed for the first half of every cycle
+            cycles.fract() < 0.5
+        }
+    }
+}
+
+/// The macro step playing `elapsed` after the start, if any is left
+fn macro_step(steps: &[MacroStep], elapsed: Duration) -> Option<&MacroStep> {
+    let mut end = Duration::ZERO;
+    steps.iter().find(|step| {
+        end += Duration::from_millis(step.duration_ms as u64);
+        elapsed < end
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(button_flags: u16, right_trigger: u8) -> MoonlightInputPacket {
+        MoonlightInputPacket {
+            button_flags,
+            right_trigger,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_chord_claims_its_buttons() {
+        let table = vec![
+            ButtonBinding::press(SwitchButton::L, DolphinButton::L),
+            ButtonBinding::press(SwitchButton::R, DolphinButton::R),
+            ButtonBinding::press(SwitchButton::Up, DolphinButton::Up),
+            ButtonBinding::chord(
+                vec![SwitchButton::L, SwitchButton::R],
+                vec![DolphinButton::Start],
+            ),
+            // One Switch button can press two GameCube buttons
+            ButtonBinding::chord(
+                vec![SwitchButton::ZR],
+                vec![DolphinButton::R, DolphinButton::A],
+            ),
+        ];
+        let mut state = BindingState::new();
+        let now = Instant::now();
+
+        let pressed = state.resolve(&table, &packet(0x0100 | 0x0200, 0), now);
+        assert_eq!(pressed, HashSet::from([DolphinButton::Start]));
+
+        let pressed = state.resolve(&table, &packet(0x0001, 255), now);
+        assert_eq!(
+            pressed,
+            HashSet::from([DolphinButton::R, DolphinButton::A, DolphinButton::Up])
+        );
+    }
+
+    #[test]
+    fn test_turbo_and_toggle() {
+        let table = vec![
+            ButtonBinding::press(SwitchButton::Y, DolphinButton::B)
+                .with_mode(BindingMode::Turbo { rate_hz: 10.0 }),
+            ButtonBinding::press(SwitchButton::LeftStick, DolphinButton::X)
+                .with_mode(BindingMode::Toggle),
+        ];
+        let mut state = BindingState::new();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // 10 Hz turbo: pressed for 50 ms of every 100 ms
+        let held = packet(0x8000, 0);
+        assert!(state
+            .resolve(&table, &held, at(0))
+            .contains(&DolphinButton::B));
+        assert!(!state
+            .resolve(&table, &held, at(60))
+            .contains(&DolphinButton::B));
+        assert!(state
+            .resolve(&table, &held, at(110))
+            .contains(&DolphinButton::B));
+
+        let click = packet(0x0040, 0);
+        let released = packet(0, 0);
+        assert!(state
+            .resolve(&table, &click, at(200))
+            .contains(&DolphinButton::X));
+        assert!(state
+            .resolve(&table, &released, at(216))
+            .contains(&DolphinButton::X));
+        assert!(!state
+            .resolve(&table, &click, at(233))
+            .contains(&DolphinButton::X));
+    }
+
+    #[test]
+    fn test_macro_plays_to_the_end() {
+        let table = vec![ButtonBinding {
+            inputs: vec![SwitchButton::Minus],
+            action: BindingAction::Macro(vec![
+                MacroStep {
+                    buttons: vec![DolphinButton::Down],
+                    duration_ms: 50,
+                },
+                MacroStep {
+                    buttons: vec![DolphinButton::Down, DolphinButton::B],
+                    duration_ms: 50,
+                },
+            ]),
+            mode: BindingMode::Hold,
+        }];
+        let mut state = BindingState::new();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let tap = packet(0x0020, 0);
+        let released = packet(0, 0);
+        assert_eq!(
+            state.resolve(&table, &tap, at(0)),
+            HashSet::from([DolphinButton::Down])
+        );
+        assert_eq!(
+            state.resolve(&table, &released, at(70)),
+            HashSet::from([DolphinButton::Down, DolphinButton::B])
+        );
+        assert!(state.resolve(&table, &released, at(120)).is_empty());
+    }
+}
@@ -0,0 +1,522 @@
+//! Analog stick and trigger calibration and response
+//!
+//! Raw stick readings are first normalized with the controller's
+//! [`CalibrationData`], then shaped by the mapping's [`AnalogSettings`]:
+//! inner and outer deadzones, an anti-deadzone and a response curve, with
+//! optional emulation of the GameCube's octagonal stick gate.
+//!
+//! Clients measure their controller with a [`Calibrator`], stepping it
+//! through [`CalibrationRequest`]s on the control channel, and the result is
+//! kept in a [`CalibrationStore`] under the client's id so it applies again
+//! on the next connection.
+
+use crate::error::{DpstreamError, InputError, Result};
+use crate::input::mapping::{CalibrationData, StickCalibration};
+use crate::input::MoonlightInputPacket;
+pub use dpstream_protocol::calibration::{
+    CalibrationPhase, CalibrationRequest, CalibrationResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_4, FRAC_PI_8};
+use std::fs;
+use std::path::PathBuf;
+
+/// Packets needed to measure the stick centers
+const MIN_CENTER_SAMPLES: u32 = 10;
+
+/// Furthest a resting stick may sit from zero
+const MAX_CENTER_OFFSET: i32 = 8192;
+
+/// Least travel from the center accepted for each direction of an axis
+const MIN_AXIS_TRAVEL: i32 = 8192;
+
+/// Trigger peak below which a trigger is taken as unmeasured
+const MIN_TRIGGER_PEAK: u8 = 64;
+
+/// Longest client id accepted
+const MAX_CLIENT_ID_LEN: usize = 64;
+
+/// Shape of an analog response between the deadzones
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    /// Input raised to `exponent`; above 1.0 gives finer control near rest
+    Exponential { exponent: f32 },
+    /// Straight lines through `points`, from (0, 0) to (1, 1); points are
+    /// `[input, output]` pairs in increasing input order
+    Custom { points: Vec<[f32; 2]> },
+}
+
+impl ResponseCurve {
+    /// Map a level in 0.0 to 1.0
+    pub fn apply(&self, value: f32) -> f32 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => value,
+            Self::Exponential { exponent } => value.powf(exponent.max(0.0)),
+            Self::Custom { points } => {
+                let mut previous = [0.0, 0.0];
+                for &point in points.iter().chain(std::iter::once(&[1.0, 1.0])) {
+                    if value <= point[0] {
+                        let span = point[0] - previous[0];
+                        if span <= f32::EPSILON {
+                            return point[1].clamp(0.0, 1.0);
+                        }
+                        let t = (value - previous[0]) / span;
+                        return (previous[1] + (point[1] - previous[1]) * t).clamp(0.0, 1.0);
+                    }
+                    previous = point;
+                }
+                1.0
+            }
+        }
+    }
+}
+
+/// Response of one analog input, applied to its magnitude
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalogResponse {
+    /// Levels below this read as rest
+    pub deadzone: f32,
+    /// Levels within this of full read as full
+    pub outer_deadzone: f32,
+    /// Lowest level output once past the deadzone, for games with their own
+    /// deadzone
+    pub anti_deadzone: f32,
+    pub curve: ResponseCurve,
+}
+
+impl Default for AnalogResponse {
+    fn default() -> Self {
+        Self {
+            deadzone: 0.0,
+            outer_deadzone: 0.0,
+            anti_deadzone: 0.0,
+            curve: ResponseCurve::Linear,
+        }
+    }
+}
+
+impl AnalogResponse {
+    /// Map a level in 0.0 to 1.0
+    pub fn apply(&self, level: f32) -> f32 {
+        let level = level.clamp(0.0, 1.0);
+        if level <= self.deadzone {
+            return 0.0;
+        }
+        let live = 1.0 - self.outer_deadzone - self.deadzone;
+        let t = if live <= f32::EPSILON {
+            1.0
+        } else {
+            ((level - self.deadzone) / live).min(1.0)
+        };
+        let anti = self.anti_deadzone.clamp(0.0, 1.0);
+        anti + (1.0 - anti) * self.curve.apply(t)
+    }
+}
+
+/// Response of a stick
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct StickResponse {
+    #[serde(flatten)]
+    pub response: AnalogResponse,
+    /// Confine the stick to the GameCube's octagonal gate, so full diagonals
+    /// land where games expect them
+    pub octagonal_gate: bool,
+}
+
+impl StickResponse {
+    /// Shape a normalized stick position, keeping its direction
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        let magnitude = x.hypot(y);
+        if magnitude <= f32::EPSILON {
+            return (0.0, 0.0);
+        }
+
+        let mut shaped = self.response.apply(magnitude.min(1.0));
+        if self.octagonal_gate {
+            shaped = shaped.min(octagon_radius(y.atan2(x)));
+        }
+        (x / magnitude * shaped, y / magnitude * shaped)
+    }
+}
+
+/// Distance to the edge of a gate with corners at the cardinal and diagonal
+/// directions, one unit out
+fn octagon_radius(angle: f32) -> f32 {
+    let offset = angle.rem_euclid(FRAC_PI_4) - FRAC_PI_8;
+    FRAC_PI_8.cos() / offset.cos()
+}
+
+/// Analog response settings of a mapping
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalogSettings {
+    pub main_stick: StickResponse,
+    pub c_stick: StickResponse,
+    pub triggers: AnalogResponse,
+}
+
+impl Default for AnalogSettings {
+    fn default() -> Self {
+        Self::with_deadzone(0.1)
+    }
+}
+
+impl AnalogSettings {
+    /// Linear sticks with the given inner deadzone
+    pub fn with_deadzone(deadzone: f32) -> Self {
+        let stick = StickResponse {
+            response: AnalogResponse {
+                deadzone,
+                ..AnalogResponse::default()
+            },
+            octagonal_gate: false,
+        };
+        Self {
+            main_stick: stick.clone(),
+            c_stick: stick,
+            triggers: AnalogResponse::default(),
+        }
+    }
+}
+
+/// Measures a controller from the input packets it sends
+#[derive(Debug, Clone)]
+pub struct Calibrator {
+    phase: CalibrationPhase,
+    center_sums: [i64; 4],
+    center_samples: u32,
+    measured: CalibrationData,
+}
+
+impl Default for Calibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Calibrator {
+    pub fn new() -> Self {
+        Self {
+            phase: CalibrationPhase::Center,
+            center_sums: [0; 4],
+            center_samples: 0,
+            measured: CalibrationData::default(),
+        }
+    }
+
+    pub fn phase(&self) -> CalibrationPhase {
+        self.phase
+    }
+
+    /// Take in one input packet
+    pub fn observe(&mut self, input: &MoonlightInputPacket) {
+        match self.phase {
+            CalibrationPhase::Center => {
+                let axes = [
+                    input.left_stick_x,
+                    input.left_stick_y,
+                    input.right_stick_x,
+                    input.right_stick_y,
+                ];
+                for (sum, axis) in self.center_sums.iter_mut().zip(axes) {
+                    *sum += axis as i64;
+                }
+                self.center_samples += 1;
+            }
+            CalibrationPhase::Range => {
+                let measured = &mut self.measured;
+                widen(
+                    &mut measured.left_stick,
+                    input.left_stick_x,
+                    input.left_stick_y,
+                );
+                widen(
+                    &mut measured.right_stick,
+                    input.right_stick_x,
+                    input.right_stick_y,
+                );
+                measured.left_trigger_max = measured.left_trigger_max.max(input.left_trigger);
+                measured.right_trigger_max = measured.right_trigger_max.max(input.right_trigger);
+            }
+        }
+    }
+
+    /// Finish the current phase, returning the calibration after the last
+    ///
+    /// On failure the phase is kept, so the client can retry it.
+    pub fn advance(&mut self) -> Result<Option<CalibrationData>> {
+        match self.phase {
+            CalibrationPhase::Center => {
+                if self.center_samples < MIN_CENTER_SAMPLES {
+                    return Err(calibration_failed("no stick input while centered"));
+                }
+                let samples = self.center_samples as i64;
+                let [left_x, left_y, right_x, right_y] =
+                    self.center_sums.map(|sum| (sum / samples) as i16);
+                if [left_x, left_y, right_x, right_y]
+                    .iter()
+                    .any(|center| (*center as i32).abs() > MAX_CENTER_OFFSET)
+                {
+                    return Err(calibration_failed("sticks were not at rest"));
+                }
+
+                self.measured = CalibrationData {
+                    left_stick: StickCalibration::collapsed(left_x, left_y),
+                    right_stick: StickCalibration::collapsed(right_x, right_y),
+                    left_trigger_max: 0,
+                    right_trigger_max: 0,
+                };
+                self.phase = CalibrationPhase::Range;
+                Ok(None)
+            }
+            CalibrationPhase::Range => {
+                let mut calibration = self.measured.clone();
+                for (name, stick) in [
+                    ("left", &calibration.left_stick),
+                    ("right", &calibration.right_stick),
+                ] {
+                    if !stick.has_travel(MIN_AXIS_TRAVEL) {
+                        return Err(calibration_failed(&format!(
+                            "{name} stick did not reach its edges"
+                        )));
+                    }
+                }
+
+                // Triggers that were never pressed keep the full range
+                for peak in [
+                    &mut calibration.left_trigger_max,
+                    &mut calibration.right_trigger_max,
+                ] {
+                    if *peak < MIN_TRIGGER_PEAK {
+                        *peak = u8::MAX;
+                    }
+                }
+                Ok(Some(calibration))
+            }
+        }
+    }
+}
+
+fn widen(stick: &mut StickCalibration, x: i16, y: i16) {
+    stick.min_x = stick.min_x.min(x);
+    stick.max_x = stick.max_x.max(x);
+    stick.min_y = stick.min_y.min(y);
+    stick.max_y = stick.max_y.max(y);
+}
+
+fn calibration_failed(reason: &str) -> DpstreamError {
+    InputError::CalibrationFailed {
+        reason: reason.to_string(),
+    }
+    .into()
+}
+
+/// Calibration data kept per client id, optionally saved as
+/// `<directory>/<client_id>.json`
+#[derive(Debug, Clone, Default)]
+pub struct CalibrationStore {
+    directory: Option<PathBuf>,
+    entries: HashMap<String, CalibrationData>,
+}
+
+impl CalibrationStore {
+    /// Store that persists to `directory`
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: Some(directory.into()),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Store that forgets everything on restart
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Calibration saved for a client, if any
+    pub fn get(&mut self, client_id: &str) -> Result<Option<CalibrationData>> {
+        validate_client_id(client_id)?;
+        if let Some(calibration) = self.entries.get(client_id) {
+            return Ok(Some(calibration.clone()));
+        }
+
+        let Some(path) = self.path(client_id) else {
+            return Ok(None);
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path).map_err(|e| InputError::ConfigurationError {
+            field: "calibration".to_string(),
+            value: path.display().to_string(),
+            reason: e.to_string(),
+        })?;
+        let calibration: CalibrationData =
+            serde_json::from_str(&content).map_err(|e| InputError::ConfigurationError {
+                field: "calibration".to_string(),
+                value: path.display().to_string(),
+                reason: e.to_string(),
+            })?;
+
+        self.entries
+            .insert(client_id.to_string(), calibration.clone());
+        Ok(Some(calibration))
+    }
+
+    /// Save a client's calibration
+    pub fn insert(&mut self, client_id: &str, calibration: CalibrationData) -> Result<()> {
+        validate_client_id(client_id)?;
+        if let Some(path) = self.path(client_id) {
+            let json = serde_json::to_string_pretty(&calibration).map_err(|e| {
+                InputError::ConfigurationError {
+                    field: "calibration".to_string(),
+                    value: "json".to_string(),
+                    reason: e.to_string(),
+                }
+            })?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| InputError::ConfigurationError {
+                    field: "directory".to_string(),
+                    value: parent.display().to_string(),
+                    reason: e.to_string(),
+                })?;
+            }
+            fs::write(&path, json).map_err(|e| InputError::ConfigurationError {
+                field: "calibration".to_string(),
+                value: path.display().to_string(),
+                reason: e.to_string(),
+            })?;
+        }
+
+        self.entries.insert(client_id.to_string(), calibration);
+        Ok(())
+    }
+
+    fn path(&self, client_id: &str) -> Option<PathBuf> {
+        Some(self.directory.as_ref()?.join(format!("{client_id}.json")))
+    }
+}
+
+/// Client ids name files, so only allow plain identifiers
+fn validate_client_id(client_id: &str) -> Result<()> {
+    let valid = !client_id.is_empty()
+        && client_id.len() <= MAX_CLIENT_ID_LEN
+        && client_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(InputError::ConfigurationError {
+            field: "client_id".to_string(),
+            value: client_id.to_string(),
+            reason: "expected 1-64 letters, digits, '-' or '_'".to_string(),
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(left: (i16, i16), right: (i16, i16), triggers: (u8, u8)) -> MoonlightInputPacket {
+        MoonlightInputPacket {
+            left_trigger: triggers.0,
+            right_trigger: triggers.1,
+            left_stick_x: left.0,
+            left_stick_y: left.1,
+            right_stick_x: right.0,
+            right_stick_y: right.1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_response_deadzones_and_curves() {
+        let response = AnalogResponse {
+            deadzone: 0.1,
+            outer_deadzone: 0.1,
+            anti_deadzone: 0.2,
+            curve: ResponseCurve::Linear,
+        };
+        assert_eq!(response.apply(0.05), 0.0);
+        assert!((response.apply(0.5) - 0.6).abs() < 1e-5);
+        assert_eq!(response.apply(0.95), 1.0);
+
+        let exponential = ResponseCurve::Exponential { exponent: 2.0 };
+        assert!((exponential.apply(0.5) - 0.25).abs() < 1e-6);
+
+        let custom = ResponseCurve::Custom {
+            points: vec![[0.5, 0.2], [0.8, 0.9]],
+        };
+        assert!((custom.apply(0.25) - 0.1).abs() < 1e-6);
+        assert!((custom.apply(0.65) - 0.55).abs() < 1e-5);
+        assert!((custom.apply(0.9) - 0.95).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_octagonal_gate() {
+        let gated = StickResponse {
+            response: AnalogResponse::default(),
+            octagonal_gate: true,
+        };
+
+        // Cardinals and diagonals reach the corners of the gate
+        let (x, y) = gated.apply(1.0, 0.0);
+        assert!((x - 1.0).abs() < 1e-5 && y.abs() < 1e-5);
+        let (x, y) = gated.apply(1.0, 1.0);
+        let corner = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((x - corner).abs() < 1e-5 && (y - corner).abs() < 1e-5);
+
+        // Between corners the stick stops at the flat edge
+        let angle = FRAC_PI_8;
+        let (x, y) = gated.apply(angle.cos(), angle.sin());
+        assert!((x.hypot(y) - FRAC_PI_8.cos()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_calibration_flow() {
+        let mut calibrator = Calibrator::new();
+        assert!(calibrator.advance().is_err());
+
+        for _ in 0..20 {
+            calibrator.observe(&packet((1000, -500), (-200, 300), (0, 0)));
+        }
+        assert_eq!(calibrator.advance().unwrap(), None);
+        assert_eq!(calibrator.phase(), CalibrationPhase::Range);
+
+        // Only the left stick has moved so far
+        for (x, y) in [(30000, 0), (-28000, 0), (0, 29000), (0, -31000)] {
+            calibrator.observe(&packet((x, y), (-200, 300), (200, 0)));
+        }
+        assert!(calibrator.advance().is_err());
+
+        for (x, y) in [(32000, 0), (-32000, 0), (0, 32000), (0, -32000)] {
+            calibrator.observe(&packet((1000, -500), (x, y), (0, 0)));
+        }
+        let calibration = calibrator.advance().unwrap().unwrap();
+        assert_eq!(calibration.left_stick.center_x, 1000);
+        assert_eq!(calibration.left_stick.min_x, -28000);
+        assert_eq!(calibration.left_trigger_max, 200);
+        assert_eq!(calibration.right_trigger_max, u8::MAX);
+
+        // The measured extremes now read as full deflection
+        let (x, _) = calibration.left_stick.normalize(-28000, -500);
+        assert_eq!(x, -1.0);
+        let (x, y) = calibration.left_stick.normalize(1000, -500);
+        assert_eq!((x, y), (0.0, 0.0));
+
+        let mut store = CalibrationStore::in_memory();
+        assert!(store.insert("../escape", calibration.clone()).is_err());
+        store.insert("switch-01", calibration.clone()).unwrap();
+        assert_eq!(store.get("switch-01").unwrap(), Some(calibration));
+    }
+}
@@ -2,21 +2,212 @@
 
 //! Dolphin emulator input adapter
 //!
-//! Handles communication with Dolphin emulator for input injection
+//! Injects controller input through Dolphin's pipe input devices. Each player
+//! slot gets a FIFO under `<user>/Pipes/` and a `GCPadNew.ini` section binding
+//! that slot to `Pipe/0/<name>`; Dolphin then reads line commands such as
+//! `PRESS A` or `SET MAIN 0.5 0.5` from the FIFO.
+//!
+//! Wii Remotes reuse the player's pad pipe for the remote itself. A pipe
+//! device only has twelve buttons, so extensions get a second pipe that the
+//! `WiimoteNew.ini` bindings reach with device-qualified inputs.
+//!
+//! Pipe devices have no outputs, so rumble is bound to a force-feedback evdev
+//! device per player, created through uinput when built with the `system`
+//! feature.
 
-use crate::error::{EmulatorError, InputError, Result};
+use crate::emulator::control::create_fifo;
+use crate::error::{InputError, Result};
+use crate::input::backend::InputBackend;
 use crate::input::processor::{AnalogStick, DolphinButton, DolphinCommand};
+use crate::input::rumble::{rumble_device_name, RumbleEvent};
+use crate::input::wiimote::{WiiButton, WiiExtension};
+use async_trait::async_trait;
 use std::collections::HashMap;
-use std::process::Stdio;
+use std::path::{Path, PathBuf};
 use tokio::io::AsyncWriteExt;
+use tokio::net::unix::pipe;
 use tokio::sync::mpsc;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, info, warn};
+
+/// Number of GameCube controller ports
+pub const MAX_PLAYERS: u8 = 4;
+
+/// Prefix of the per-player pipe names, followed by the 1-based player slot
+pub const PAD_PIPE_PREFIX: &str = "dpstream-pad";
+
+/// Prefix of the per-player Wii Remote extension pipe names
+pub const EXT_PIPE_PREFIX: &str = "dpstream-ext";
+
+/// Pipe device button names, in `DolphinButton` order
+const PIPE_BUTTONS: [&str; 12] = [
+    "A", "B", "X", "Y", "Z", "L", "R", "START", "D_UP", "D_DOWN", "D_LEFT", "D_RIGHT",
+];
+
+/// GameCube pad controls and the pipe device inputs they are bound to
+///
+/// Pipe axes are split at 0.5 into `-` and `+` halves, so the analog
+/// triggers use the full-range `-+` input to follow `SET L`/`SET R` 0.0-1.0.
+const GCPAD_BINDINGS: [(&str, &str); 22] = [
+    ("Buttons/A", "Button A"),
+    ("Buttons/B", "Button B"),
+    ("Buttons/X", "Button X"),
+    ("Buttons/Y", "Button Y"),
+    ("Buttons/Z", "Button Z"),
+    ("Buttons/Start", "Button START"),
+    ("Main Stick/Up", "Axis MAIN Y +"),
+    ("Main Stick/Down", "Axis MAIN Y -"),
+    ("Main Stick/Left", "Axis MAIN X -"),
+    ("Main Stick/Right", "Axis MAIN X +"),
+    ("C-Stick/Up", "Axis C Y +"),
+    ("C-Stick/Down", "Axis C Y -"),
+    ("C-Stick/Left", "Axis C X -"),
+    ("C-Stick/Right", "Axis C X +"),
+    ("Triggers/L", "Button L"),
+    ("Triggers/R", "Button R"),
+    ("Triggers/L-Analog", "Axis L -+"),
+    ("Triggers/R-Analog", "Axis R -+"),
+    ("D-Pad/Up", "Button D_UP"),
+    ("D-Pad/Down", "Button D_DOWN"),
+    ("D-Pad/Left", "Button D_LEFT"),
+    ("D-Pad/Right", "Button D_RIGHT"),
+];
+
+/// Wii Remote controls bound to the player's pad pipe
+const WIIMOTE_BINDINGS: [(&str, &str); 23] = [
+    ("Buttons/A", "Button A"),
+    ("Buttons/B", "Button B"),
+    ("Buttons/1", "Button X"),
+    ("Buttons/2", "Button Y"),
+    ("Buttons/-", "Button L"),
+    ("Buttons/+", "Button START"),
+    ("Buttons/Home", "Button R"),
+    ("D-Pad/Up", "Button D_UP"),
+    ("D-Pad/Down", "Button D_DOWN"),
+    ("D-Pad/Left", "Button D_LEFT"),
+    ("D-Pad/Right", "Button D_RIGHT"),
+    ("IR/Up", "Axis MAIN Y +"),
+    ("IR/Down", "Axis MAIN Y -"),
+    ("IR/Left", "Axis MAIN X -"),
+    ("IR/Right", "Axis MAIN X +"),
+    ("Tilt/Forward", "Axis C Y +"),
+    ("Tilt/Backward", "Axis C Y -"),
+    ("Tilt/Left", "Axis C X -"),
+    ("Tilt/Right", "Axis C X +"),
+    ("Shake/X", "Button Z"),
+    ("Shake/Y", "Button Z"),
+    ("Shake/Z", "Button Z"),
+    ("Classic/Buttons/Home", "Button R"),
+];
+
+/// Nunchuk and Classic Controller controls bound to the extension pipe
+const EXTENSION_BINDINGS: [(&str, &str); 30] = [
+    ("Nunchuk/Buttons/C", "Button X"),
+    ("Nunchuk/Buttons/Z", "Button Z"),
+    ("Nunchuk/Stick/Up", "Axis MAIN Y +"),
+    ("Nunchuk/Stick/Down", "Axis MAIN Y -"),
+    ("Nunchuk/Stick/Left", "Axis MAIN X -"),
+    ("Nunchuk/Stick/Right", "Axis MAIN X +"),
+    ("Classic/Buttons/A", "Button A"),
+    ("Classic/Buttons/B", "Button B"),
+    ("Classic/Buttons/X", "Button X"),
+    ("Classic/Buttons/Y", "Button Y"),
+    ("Classic/Buttons/ZL", "Button L"),
+    ("Classic/Buttons/ZR", "Button R"),
+    ("Classic/Buttons/-", "Button Z"),
+    ("Classic/Buttons/+", "Button START"),
+    ("Classic/D-Pad/Up", "Button D_UP"),
+    ("Classic/D-Pad/Down", "Button D_DOWN"),
+    ("Classic/D-Pad/Left", "Button D_LEFT"),
+    ("Classic/D-Pad/Right", "Button D_RIGHT"),
+    ("Classic/Left Stick/Up", "Axis MAIN Y +"),
+    ("Classic/Left Stick/Down", "Axis MAIN Y -"),
+    ("Classic/Left Stick/Left", "Axis MAIN X -"),
+    ("Classic/Left Stick/Right", "Axis MAIN X +"),
+    ("Classic/Right Stick/Up", "Axis C Y +"),
+    ("Classic/Right Stick/Down", "Axis C Y -"),
+    ("Classic/Right Stick/Left", "Axis C X -"),
+    ("Classic/Right Stick/Right", "Axis C X +"),
+    ("Classic/Triggers/L", "Axis L -+"),
+    ("Classic/Triggers/R", "Axis R -+"),
+    ("Classic/Triggers/L-Analog", "Axis L -+"),
+    ("Classic/Triggers/R-Analog", "Axis R -+"),
+];
+
+/// Pipe name for a 1-based player slot
+pub fn pad_pipe_name(player_slot: u8) -> String {
+    format!("{PAD_PIPE_PREFIX}{player_slot}")
+}
+
+/// Extension pipe name for a 1-based player slot
+pub fn ext_pipe_name(player_slot: u8) -> String {
+    format!("{EXT_PIPE_PREFIX}{player_slot}")
+}
+
+/// Rumble binding for a player's force-feedback device
+fn rumble_binding(player_slot: u8) -> String {
+    format!(
+        "Rumble/Motor = `evdev/0/{}:Strong`\n",
+        rumble_device_name(player_slot)
+    )
+}
+
+/// `WiimoteNew.ini` binding every emulated Wii Remote to its pipe devices
+///
+/// The extension is an input expression on the pad pipe's full-range L axis,
+/// so it can be switched while a game runs: 0.0 detaches it, 0.5 picks the
+/// Nunchuk and 1.0 the Classic Controller (Dolphin's attachment indices 0, 1
+/// and 2).
+pub fn wiimote_ini() -> String {
+    let mut ini = String::new();
+    for player in 1..=MAX_PLAYERS {
+        let ext_device = format!("Pipe/0/{}", ext_pipe_name(player));
+        ini.push_str(&format!(
+            "[Wiimote{player}]\nDevice = Pipe/0/{}\nSource = 1\nExtension = `Axis L -+` * 2\n",
+            pad_pipe_name(player)
+        ));
+        for (control, input) in WIIMOTE_BINDINGS {
+            ini.push_str(&format!("{control} = `{input}`\n"));
+        }
+        for (control, input) in EXTENSION_BINDINGS {
+            ini.push_str(&format!("{control} = `{ext_device}:{input}`\n"));
+        }
+        ini.push_str(&rumble_binding(player));
+    }
+    ini
+}
+
+/// `GCPadNew.ini` binding every player port to its pipe device
+pub fn gcpad_ini() -> String {
+    let mut ini = String::new();
+    for player in 1..=MAX_PLAYERS {
+        ini.push_str(&format!(
+            "[GCPad{player}]\nDevice = Pipe/0/{}\n",
+            pad_pipe_name(player)
+        ));
+        for (control, input) in GCPAD_BINDINGS {
+            ini.push_str(&format!("{control} = `{input}`\n"));
+        }
+        ini.push_str(&rumble_binding(player));
+    }
+    ini
+}
+
+/// A command line destined for one pipe
+#[derive(Debug)]
+struct PipeCommand {
+    pipe: String,
+    line: String,
+}
 
 /// Adapter for sending input commands to Dolphin emulator
 pub struct DolphinInputAdapter {
     connected_controllers: HashMap<u8, ControllerConnection>,
-    command_sender: Option<mpsc::UnboundedSender<String>>,
-    dolphin_process: Option<tokio::process::Child>,
+    command_sender: Option<mpsc::UnboundedSender<PipeCommand>>,
+    pipes_directory: Option<PathBuf>,
+    wii_extensions: HashMap<u8, WiiExtension>,
+    rumble_events: Option<flume::Receiver<RumbleEvent>>,
+    #[cfg(feature = "system")]
+    rumble_outputs: Vec<super::uinput::RumbleOutput>,
     is_active: bool,
     connection_health: ConnectionHealth,
     last_command_time: std::time::Instant,
@@ -40,7 +231,11 @@ impl DolphinInputAdapter {
         Ok(Self {
             connected_controllers: HashMap::new(),
             command_sender: None,
-            dolphin_process: None,
+            pipes_directory: None,
+            wii_extensions: HashMap::new(),
+            rumble_events: None,
+            #[cfg(feature = "system")]
+            rumble_outputs: Vec::new(),
             is_active: false,
             connection_health: ConnectionHealth {
                 commands_sent: 0,
@@ -52,62 +247,69 @@ impl DolphinInputAdapter {
         })
     }
 
-    /// Initialize connection to Dolphin
-    pub async fn initialize(&mut self, dolphin_path: &str) -> Result<()> {
-        info!("Connecting to Dolphin emulator at: {}", dolphin_path);
-
-        // Start Dolphin with pipe input enabled
-        let mut dolphin = tokio::process::Command::new(dolphin_path)
-            .arg("--interface=Pipe")
-            .arg("--input-socket")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| EmulatorError::StartupFailed {
-                reason: format!("Failed to start Dolphin with pipe input: {e}"),
-            })?;
-
-        // Set up command channel
-        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
-
-        // Get stdin handle for sending commands
-        let mut stdin = dolphin
-            .stdin
-            .take()
-            .ok_or_else(|| EmulatorError::StartupFailed {
-                reason: "Failed to get Dolphin stdin".to_string(),
-            })?;
-
-        // Spawn task to handle command sending
-        tokio::spawn(async move {
-            while let Some(command) = receiver.recv().await {
-                if let Err(e) = stdin.write_all(command.as_bytes()).await {
-                    error!("Failed to send command to Dolphin: {}", e);
-                    break;
-                }
-                if let Err(e) = stdin.write_all(b"\n").await {
-                    error!("Failed to send newline to Dolphin: {}", e);
-                    break;
-                }
-                if let Err(e) = stdin.flush().await {
-                    error!("Failed to flush Dolphin stdin: {}", e);
-                    break;
+    /// Create the player FIFOs and controller configs inside a Dolphin user directory
+    ///
+    /// Dolphin only opens pipes it finds at startup, so this must run before the
+    /// instance using `user_directory` is launched.
+    pub async fn initialize(&mut self, user_directory: &Path) -> Result<()> {
+        let pipes_dir = user_directory.join("Pipes");
+        let config_dir = user_directory.join("Config");
+        std::fs::create_dir_all(&pipes_dir)?;
+        std::fs::create_dir_all(&config_dir)?;
+
+        for player in 1..=MAX_PLAYERS {
+            for name in [pad_pipe_name(player), ext_pipe_name(player)] {
+                let path = pipes_dir.join(name);
+                if !path.exists() {
+                    create_fifo(&path)?;
                 }
             }
-        });
+        }
+        std::fs::write(config_dir.join("GCPadNew.ini"), gcpad_ini())?;
+        std::fs::write(config_dir.join("WiimoteNew.ini"), wiimote_ini())?;
+
+        #[cfg(feature = "system")]
+        self.create_rumble_outputs();
+
+        let (sender, receiver) = mpsc::unbounded_channel::<PipeCommand>();
+        tokio::spawn(write_pipe_commands(pipes_dir.clone(), receiver));
+
+        info!("Controller pipes ready in {}", pipes_dir.display());
 
         self.command_sender = Some(sender);
-        self.dolphin_process = Some(dolphin);
+        self.pipes_directory = Some(pipes_dir);
         self.is_active = true;
-
-        info!("Successfully connected to Dolphin emulator");
         Ok(())
     }
 
+    /// Create the force-feedback devices Dolphin rumbles
+    #[cfg(feature = "system")]
+    fn create_rumble_outputs(&mut self) {
+        let (sender, receiver) = flume::unbounded();
+        let mut outputs = Vec::new();
+        for player in 1..=MAX_PLAYERS {
+            match super::uinput::RumbleOutput::create(player, sender.clone()) {
+                Ok(output) => outputs.push(output),
+                Err(e) => {
+                    warn!("Rumble unavailable: {}", e);
+                    return;
+                }
+            }
+        }
+        self.rumble_outputs = outputs;
+        self.rumble_events = Some(receiver);
+    }
+
+    /// Path of a player's FIFO, once initialized
+    pub fn pipe_path(&self, player_slot: u8) -> Option<PathBuf> {
+        self.pipes_directory
+            .as_ref()
+            .map(|dir| dir.join(pad_pipe_name(player_slot)))
+    }
+
     /// Connect a controller to a specific player slot
     pub fn connect_controller(&mut self, player_slot: u8) -> Result<()> {
-        if player_slot == 0 || player_slot > 4 {
+        if player_slot == 0 || player_slot > MAX_PLAYERS {
             return Err(InputError::InvalidPlayer {
                 player: player_slot,
             }
@@ -122,9 +324,8 @@ impl DolphinInputAdapter {
 
         self.connected_controllers.insert(player_slot, connection);
 
-        // Send connection command to Dolphin
-        let command = format!("SET CONTROLLER {player_slot} STANDARD");
-        self.send_command(command)?;
+        // Start from a neutral pad in case a previous session left input held
+        self.send_neutral_commands(player_slot)?;
 
         info!("Connected controller for player {}", player_slot);
         Ok(())
@@ -133,13 +334,23 @@ impl DolphinInputAdapter {
     /// Disconnect controller from player slot
     pub fn disconnect_controller(&mut self, player_slot: u8) -> Result<()> {
         if self.connected_controllers.remove(&player_slot).is_some() {
-            let command = format!("SET CONTROLLER {player_slot} NONE");
-            self.send_command(command)?;
+            self.send_neutral_commands(player_slot)?;
             info!("Disconnected controller for player {}", player_slot);
         }
         Ok(())
     }
 
+    /// Neutralize both of a player's pipes, keeping the Wii Remote extension
+    fn send_neutral_commands(&mut self, player_slot: u8) -> Result<()> {
+        let mut pad = self.format_neutral_command();
+        if let Some(extension) = self.wii_extensions.get(&player_slot) {
+            pad.push('\n');
+            pad.push_str(&self.format_extension_command(*extension));
+        }
+        self.send_command(pad_pipe_name(player_slot), pad)?;
+        self.send_command(ext_pipe_name(player_slot), self.format_neutral_command())
+    }
+
     /// Send multiple commands to Dolphin
     pub async fn send_commands(&mut self, commands: Vec<DolphinCommand>) -> Result<()> {
         if !self.is_active {
@@ -155,45 +366,83 @@ impl DolphinInputAdapter {
 
     /// Send a single command to Dolphin
     async fn send_dolphin_command(&mut self, command: DolphinCommand) -> Result<()> {
-        let dolphin_cmd = match command {
+        let pad = |player: u8, line: String| (player, pad_pipe_name(player), line);
+        let ext = |player: u8, line: String| (player, ext_pipe_name(player), line);
+
+        let (player, pipe, dolphin_cmd) = match command {
             DolphinCommand::ButtonPress {
                 player,
                 button,
                 pressed,
-            } => {
-                if !self.connected_controllers.contains_key(&player) {
-                    self.connect_controller(player)?;
-                }
-                self.format_button_command(player, button, pressed)
-            }
+            } => pad(player, self.format_button_command(button, pressed)),
             DolphinCommand::AnalogInput {
                 player,
                 stick,
                 x,
                 y,
-            } => self.format_analog_command(player, stick, x, y),
+            } => pad(player, self.format_analog_command(stick, x, y)),
             DolphinCommand::TriggerInput {
                 player,
                 left_trigger,
                 right_trigger,
-            } => self.format_trigger_command(player, left_trigger, right_trigger),
+            } => pad(
+                player,
+                self.format_trigger_command(left_trigger, right_trigger),
+            ),
             DolphinCommand::DPadInput {
                 player,
                 up,
                 down,
                 left,
                 right,
-            } => self.format_dpad_command(player, up, down, left, right),
-            DolphinCommand::WiiPointerInput { player, x, y, z } => {
-                self.format_pointer_command(player, x, y, z)
+            } => pad(player, self.format_dpad_command(up, down, left, right)),
+            DolphinCommand::WiiPointerInput { player, x, y, .. } => {
+                pad(player, self.format_pointer_command(x, y))
+            }
+            DolphinCommand::WiiButtonPress {
+                player,
+                button,
+                pressed,
+            } => {
+                let (on_extension, line) = self.format_wii_button_command(button, pressed);
+                if on_extension {
+                    ext(player, line)
+                } else {
+                    pad(player, line)
+                }
+            }
+            DolphinCommand::WiiMotionInput {
+                player,
+                pitch,
+                roll,
+                shake,
+            } => pad(player, self.format_motion_command(pitch, roll, shake)),
+            DolphinCommand::WiiExtensionStick {
+                player,
+                stick,
+                x,
+                y,
+            } => ext(player, self.format_analog_command(stick, x, y)),
+            DolphinCommand::WiiExtensionTrigger {
+                player,
+                left,
+                right,
+            } => ext(player, self.format_trigger_command(left, right)),
+            DolphinCommand::WiiExtensionChange { player, extension } => {
+                self.wii_extensions.insert(player, extension);
+                pad(player, self.format_extension_command(extension))
             }
         };
 
-        self.send_command(dolphin_cmd)?;
+        if !self.connected_controllers.contains_key(&player) {
+            self.connect_controller(player)?;
+        }
+
+        self.send_command(pipe, dolphin_cmd)?;
         Ok(())
     }
 
-    fn format_button_command(&self, player: u8, button: DolphinButton, pressed: bool) -> String {
+    fn format_button_command(&self, button: DolphinButton, pressed: bool) -> String {
         let button_name = match button {
             DolphinButton::A => "A",
             DolphinButton::B => "B",
@@ -203,81 +452,123 @@ impl DolphinInputAdapter {
             DolphinButton::L => "L",
             DolphinButton::R => "R",
             DolphinButton::Start => "START",
-            DolphinButton::Up => "UP",
-            DolphinButton::Down => "DOWN",
-            DolphinButton::Left => "LEFT",
-            DolphinButton::Right => "RIGHT",
+            DolphinButton::Up => "D_UP",
+            DolphinButton::Down => "D_DOWN",
+            DolphinButton::Left => "D_LEFT",
+            DolphinButton::Right => "D_RIGHT",
         };
 
         let state = if pressed { "PRESS" } else { "RELEASE" };
-        format!("BUTTON {player} {button_name} {state}")
+        format!("{state} {button_name}")
     }
 
-    fn format_analog_command(&self, player: u8, stick: AnalogStick, x: f32, y: f32) -> String {
+    fn format_analog_command(&self, stick: AnalogStick, x: f32, y: f32) -> String {
         let stick_name = match stick {
             AnalogStick::Main => "MAIN",
             AnalogStick::CStick => "C",
         };
 
-        // Convert -1.0 to 1.0 range to 0-255 range for Dolphin
-        let x_val = ((x + 1.0) * 127.5) as u8;
-        let y_val = ((y + 1.0) * 127.5) as u8;
+        // Dolphin's pipe axes run 0.0-1.0 with 0.5 as center
+        let x_val = ((x + 1.0) / 2.0).clamp(0.0, 1.0);
+        let y_val = ((y + 1.0) / 2.0).clamp(0.0, 1.0);
 
-        format!("ANALOG {player} {stick_name} {x_val} {y_val}")
+        format!("SET {stick_name} {x_val:.3} {y_val:.3}")
     }
 
-    fn format_trigger_command(&self, player: u8, left: f32, right: f32) -> String {
-        // Convert 0.0-1.0 to 0-255 for Dolphin
-        let left_val = (left * 255.0) as u8;
-        let right_val = (right * 255.0) as u8;
+    fn format_trigger_command(&self, left: f32, right: f32) -> String {
+        let left_val = left.clamp(0.0, 1.0);
+        let right_val = right.clamp(0.0, 1.0);
 
-        format!("TRIGGER {player} {left_val} {right_val}")
+        format!("SET L {left_val:.3}\nSET R {right_val:.3}")
     }
 
-    fn format_dpad_command(
-        &self,
-        player: u8,
-        up: bool,
-        down: bool,
-        left: bool,
-        right: bool,
-    ) -> String {
-        let mut dpad_val = 0u8;
-        if up {
-            dpad_val |= 1;
-        }
-        if down {
-            dpad_val |= 2;
-        }
-        if left {
-            dpad_val |= 4;
-        }
-        if right {
-            dpad_val |= 8;
-        }
+    fn format_dpad_command(&self, up: bool, down: bool, left: bool, right: bool) -> String {
+        [
+            (DolphinButton::Up, up),
+            (DolphinButton::Down, down),
+            (DolphinButton::Left, left),
+            (DolphinButton::Right, right),
+        ]
+        .into_iter()
+        .map(|(button, pressed)| self.format_button_command(button, pressed))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
 
-        format!("DPAD {player} {dpad_val}")
+    /// Point the Wii Remote at a screen position, -1.0 to 1.0 with y down
+    fn format_pointer_command(&self, x: f32, y: f32) -> String {
+        // IR/Up is bound to the positive Y axis, so flip screen coordinates
+        self.format_analog_command(AnalogStick::Main, x, -y)
     }
 
-    fn format_pointer_command(&self, player: u8, x: f32, y: f32, z: f32) -> String {
-        // Convert -1.0 to 1.0 coordinates to screen coordinates
-        let screen_x = ((x + 1.0) * 512.0) as u16; // Assuming 1024x768 screen
-        let screen_y = ((y + 1.0) * 384.0) as u16;
+    /// Wii button line, and whether it belongs on the extension pipe
+    fn format_wii_button_command(&self, button: WiiButton, pressed: bool) -> (bool, String) {
+        let (on_extension, button) = match button {
+            WiiButton::A => (false, DolphinButton::A),
+            WiiButton::B => (false, DolphinButton::B),
+            WiiButton::One => (false, DolphinButton::X),
+            WiiButton::Two => (false, DolphinButton::Y),
+            WiiButton::Minus => (false, DolphinButton::L),
+            WiiButton::Plus => (false, DolphinButton::Start),
+            WiiButton::Home => (false, DolphinButton::R),
+            WiiButton::Up => (false, DolphinButton::Up),
+            WiiButton::Down => (false, DolphinButton::Down),
+            WiiButton::Left => (false, DolphinButton::Left),
+            WiiButton::Right => (false, DolphinButton::Right),
+            WiiButton::NunchukC => (true, DolphinButton::X),
+            WiiButton::NunchukZ => (true, DolphinButton::Z),
+            WiiButton::ClassicA => (true, DolphinButton::A),
+            WiiButton::ClassicB => (true, DolphinButton::B),
+            WiiButton::ClassicX => (true, DolphinButton::X),
+            WiiButton::ClassicY => (true, DolphinButton::Y),
+            WiiButton::ClassicZL => (true, DolphinButton::L),
+            WiiButton::ClassicZR => (true, DolphinButton::R),
+            WiiButton::ClassicMinus => (true, DolphinButton::Z),
+            WiiButton::ClassicPlus => (true, DolphinButton::Start),
+            WiiButton::ClassicUp => (true, DolphinButton::Up),
+            WiiButton::ClassicDown => (true, DolphinButton::Down),
+            WiiButton::ClassicLeft => (true, DolphinButton::Left),
+            WiiButton::ClassicRight => (true, DolphinButton::Right),
+        };
+
+        (on_extension, self.format_button_command(button, pressed))
+    }
 
+    /// Tilt on the C axes and shake on the Z button
+    fn format_motion_command(&self, pitch: f32, roll: f32, shake: bool) -> String {
         format!(
-            "POINTER {} {} {} {}",
-            player,
-            screen_x,
-            screen_y,
-            (z * 100.0) as u8
+            "{}\n{}",
+            self.format_analog_command(AnalogStick::CStick, roll, pitch),
+            self.format_button_command(DolphinButton::Z, shake)
         )
     }
 
-    fn send_command(&mut self, command: String) -> Result<()> {
+    /// Select an extension through the expression bound in `WiimoteNew.ini`
+    fn format_extension_command(&self, extension: WiiExtension) -> String {
+        let level = extension.attachment_index() as f32 / 2.0;
+        format!("SET L {level:.3}")
+    }
+
+    /// Release every button and center all axes
+    fn format_neutral_command(&self) -> String {
+        let mut lines: Vec<String> = PIPE_BUTTONS
+            .iter()
+            .map(|button| format!("RELEASE {button}"))
+            .collect();
+        lines.push(self.format_analog_command(AnalogStick::Main, 0.0, 0.0));
+        lines.push(self.format_analog_command(AnalogStick::CStick, 0.0, 0.0));
+        lines.push(self.format_trigger_command(0.0, 0.0));
+        lines.join("\n")
+    }
+
+    fn send_command(&mut self, pipe: String, command: String) -> Result<()> {
         if let Some(sender) = &self.command_sender {
-            debug!("Sending command to Dolphin: {}", command);
+            debug!("Sending command to Dolphin pipe {}: {}", pipe, command);
 
-            match sender.send(command) {
+            match sender.send(PipeCommand {
+                pipe,
+                line: command,
+            }) {
                 Ok(_) => {
                     self.connection_health.commands_sent += 1;
                     self.last_command_time = std::time::Instant::now();
@@ -304,15 +595,16 @@ impl DolphinInputAdapter {
         Ok(())
     }
 
-    /// Check connection health and attempt recovery if needed
+    /// Check connection health
+    ///
+    /// The pipe grammar has no no-op command, so this checks that the pipe
+    /// writer task is still running instead of sending a heartbeat.
     pub fn check_health(&mut self) -> bool {
-        let now = std::time::Instant::now();
+        self.connection_health.last_health_check = std::time::Instant::now();
 
-        // Check if too much time has passed since last command
-        if now.duration_since(self.last_command_time).as_secs() > 5 {
-            // Send a heartbeat command to verify connection
-            if self.send_command("HEARTBEAT".to_string()).is_err() {
-                warn!("Dolphin connection health check failed");
+        if let Some(sender) = &self.command_sender {
+            if sender.is_closed() {
+                warn!("Dolphin pipe writer stopped");
                 self.connection_health.is_healthy = false;
             }
         }
@@ -325,7 +617,7 @@ impl DolphinInputAdapter {
         AdapterStatus {
             is_active: self.is_active,
             connected_controllers: self.connected_controllers.len(),
-            has_dolphin_process: self.dolphin_process.is_some(),
+            pipes_ready: self.pipes_directory.is_some(),
         }
     }
 
@@ -333,29 +625,84 @@ impl DolphinInputAdapter {
     pub async fn shutdown(&mut self) -> Result<()> {
         info!("Shutting down Dolphin input adapter");
 
-        self.is_active = false;
-
         // Disconnect all controllers
         let player_slots: Vec<u8> = self.connected_controllers.keys().cloned().collect();
         for slot in player_slots {
             self.disconnect_controller(slot)?;
         }
 
-        // Close command sender
+        // Close command sender; the writer drains queued commands and exits
+        self.is_active = false;
         self.command_sender = None;
-
-        // Terminate Dolphin process if we started it
-        if let Some(mut process) = self.dolphin_process.take() {
-            if let Err(e) = process.kill().await {
-                warn!("Failed to kill Dolphin process: {}", e);
-            }
-        }
+        #[cfg(feature = "system")]
+        self.rumble_outputs.clear();
 
         info!("Dolphin input adapter shutdown complete");
         Ok(())
     }
 }
 
+#[async_trait]
+impl InputBackend for DolphinInputAdapter {
+    fn name(&self) -> &'static str {
+        "dolphin-pipe"
+    }
+
+    fn connect_controller(&mut self, player_slot: u8) -> Result<()> {
+        DolphinInputAdapter::connect_controller(self, player_slot)
+    }
+
+    fn disconnect_controller(&mut self, player_slot: u8) -> Result<()> {
+        DolphinInputAdapter::disconnect_controller(self, player_slot)
+    }
+
+    async fn send_commands(&mut self, commands: Vec<DolphinCommand>) -> Result<()> {
+        DolphinInputAdapter::send_commands(self, commands).await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        DolphinInputAdapter::shutdown(self).await
+    }
+
+    fn rumble_events(&self) -> Option<flume::Receiver<RumbleEvent>> {
+        self.rumble_events.clone()
+    }
+}
+
+/// Write queued commands to the player FIFOs
+///
+/// Opening a FIFO for writing fails until Dolphin has opened it for reading,
+/// so pipes are opened lazily and reopened after write errors.
+async fn write_pipe_commands(
+    pipes_dir: PathBuf,
+    mut receiver: mpsc::UnboundedReceiver<PipeCommand>,
+) {
+    let mut pipes: HashMap<String, pipe::Sender> = HashMap::new();
+
+    while let Some(command) = receiver.recv().await {
+        let pipe = match pipes.entry(command.pipe.clone()) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let path = pipes_dir.join(&command.pipe);
+                match pipe::OpenOptions::new().open_sender(&path) {
+                    Ok(sender) => entry.insert(sender),
+                    Err(e) => {
+                        debug!("Dolphin is not reading {}: {}", path.display(), e);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let mut line = command.line;
+        line.push('\n');
+        if let Err(e) = pipe.write_all(line.as_bytes()).await {
+            warn!("Failed to write to pipe {}: {}", command.pipe, e);
+            pipes.remove(&command.pipe);
+        }
+    }
+}
+
 /// Controller connection information
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -383,14 +730,13 @@ enum DolphinControllerType {
 pub struct AdapterStatus {
     pub is_active: bool,
     pub connected_controllers: usize,
-    pub has_dolphin_process: bool,
+    pub pipes_ready: bool,
 }
 
 impl Drop for DolphinInputAdapter {
     fn drop(&mut self) {
         if self.is_active {
             debug!("Dolphin input adapter dropped while active");
-            // Note: Cannot use async in drop, but Dolphin process will be terminated
         }
     }
 }
@@ -398,6 +744,7 @@ impl Drop for DolphinInputAdapter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::io::AsyncReadExt;
 
     #[test]
     fn test_adapter_creation() {
@@ -413,29 +760,225 @@ mod tests {
     fn test_button_command_formatting() {
         let adapter = DolphinInputAdapter::new().unwrap();
 
-        let cmd = adapter.format_button_command(1, DolphinButton::A, true);
-        assert_eq!(cmd, "BUTTON 1 A PRESS");
+        let cmd = adapter.format_button_command(DolphinButton::A, true);
+        assert_eq!(cmd, "PRESS A");
 
-        let cmd = adapter.format_button_command(2, DolphinButton::Start, false);
-        assert_eq!(cmd, "BUTTON 2 START RELEASE");
+        let cmd = adapter.format_button_command(DolphinButton::Start, false);
+        assert_eq!(cmd, "RELEASE START");
+
+        let cmd = adapter.format_dpad_command(true, false, false, true);
+        assert_eq!(
+            cmd,
+            "PRESS D_UP\nRELEASE D_DOWN\nRELEASE D_LEFT\nPRESS D_RIGHT"
+        );
     }
 
     #[test]
     fn test_analog_command_formatting() {
         let adapter = DolphinInputAdapter::new().unwrap();
 
-        let cmd = adapter.format_analog_command(1, AnalogStick::Main, 0.0, 0.0);
-        assert_eq!(cmd, "ANALOG 1 MAIN 127 127"); // Center position
+        let cmd = adapter.format_analog_command(AnalogStick::Main, 0.0, 0.0);
+        assert_eq!(cmd, "SET MAIN 0.500 0.500"); // Center position
 
-        let cmd = adapter.format_analog_command(1, AnalogStick::CStick, 1.0, -1.0);
-        assert_eq!(cmd, "ANALOG 1 C 255 0"); // Full right, full down
+        let cmd = adapter.format_analog_command(AnalogStick::CStick, 1.0, -1.0);
+        assert_eq!(cmd, "SET C 1.000 0.000"); // Full right, full down
     }
 
     #[test]
     fn test_trigger_command_formatting() {
         let adapter = DolphinInputAdapter::new().unwrap();
 
-        let cmd = adapter.format_trigger_command(1, 0.5, 1.0);
-        assert_eq!(cmd, "TRIGGER 1 127 255"); // Half left, full right
+        let cmd = adapter.format_trigger_command(0.5, 1.0);
+        assert_eq!(cmd, "SET L 0.500\nSET R 1.000"); // Half left, full right
+    }
+
+    /// State Dolphin reads from a pipe input after `SET <axis> <value>`
+    fn pipe_input_state(input: &str, value: f32) -> f32 {
+        let high = ((value - 0.5) * 2.0).max(0.0);
+        let low = ((0.5 - value) * 2.0).max(0.0);
+        match input.rsplit(' ').next() {
+            Some("+") => high,
+            Some("-") => low,
+            Some("-+") => (1.0 + high - low) / 2.0,
+            Some("+-") => (1.0 + low - high) / 2.0,
+            _ => panic!("not an axis input: {input}"),
+        }
+    }
+
+    /// Input bound to `control` in an ini section's backquoted expression
+    fn bound_input<'a>(ini: &'a str, control: &str) -> &'a str {
+        let prefix = format!("{control} = `");
+        let line = ini
+            .lines()
+            .find(|line| line.starts_with(&prefix))
+            .unwrap_or_else(|| panic!("{control} is not bound"));
+        line[prefix.len()..].split('`').next().unwrap()
+    }
+
+    /// Value an axis is set to by a formatted command
+    fn set_value(command: &str, axis: &str) -> f32 {
+        let prefix = format!("SET {axis} ");
+        command
+            .lines()
+            .find_map(|line| line.strip_prefix(&prefix))
+            .unwrap_or_else(|| panic!("{command} does not set {axis}"))
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_gcpad_triggers_follow_formatted_level() {
+        let adapter = DolphinInputAdapter::new().unwrap();
+        let ini = gcpad_ini();
+
+        for level in [0.0, 0.25, 0.5, 0.75, 1.0] {
+            let cmd = adapter.format_trigger_command(level, 1.0 - level);
+            let left =
+                pipe_input_state(bound_input(&ini, "Triggers/L-Analog"), set_value(&cmd, "L"));
+            let right =
+                pipe_input_state(bound_input(&ini, "Triggers/R-Analog"), set_value(&cmd, "R"));
+            assert!((left - level).abs() < 1e-3, "L {level} read as {left}");
+            assert!((right - (1.0 - level)).abs() < 1e-3, "R read as {right}");
+        }
+    }
+
+    #[test]
+    fn test_wii_extension_follows_formatted_level() {
+        let adapter = DolphinInputAdapter::new().unwrap();
+        let ini = wiimote_ini();
+        let expression = ini
+            .lines()
+            .find_map(|line| line.strip_prefix("Extension = `"))
+            .unwrap();
+        let (input, scale) = expression.split_once('`').unwrap();
+        let scale: f32 = scale.trim_start_matches(" * ").parse().unwrap();
+
+        for extension in [
+            WiiExtension::None,
+            WiiExtension::Nunchuk,
+            WiiExtension::Classic,
+        ] {
+            let cmd = adapter.format_extension_command(extension);
+            let index = (pipe_input_state(input, set_value(&cmd, "L")) * scale).round();
+            assert_eq!(
+                index,
+                f32::from(extension.attachment_index()),
+                "{extension:?}"
+            );
+        }
+
+        for level in [0.0, 0.25, 0.5, 1.0] {
+            let cmd = adapter.format_trigger_command(level, level);
+            for control in ["Classic/Triggers/L-Analog", "Classic/Triggers/R-Analog"] {
+                let axis = if control.contains("/L") { "L" } else { "R" };
+                let state = pipe_input_state(bound_input(&ini, control), set_value(&cmd, axis));
+                assert!(
+                    (state - level).abs() < 1e-3,
+                    "{control} {level} read as {state}"
+                );
+            }
+        }
+        let cmd = adapter.format_trigger_command(0.0, 1.0);
+        let left = pipe_input_state(
+            bound_input(&ini, "Classic/Triggers/L"),
+            set_value(&cmd, "L"),
+        );
+        let right = pipe_input_state(
+            bound_input(&ini, "Classic/Triggers/R"),
+            set_value(&cmd, "R"),
+        );
+        assert_eq!((left, right), (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_wii_command_formatting() {
+        let adapter = DolphinInputAdapter::new().unwrap();
+
+        assert_eq!(
+            adapter.format_wii_button_command(WiiButton::Two, true),
+            (false, "PRESS Y".to_string())
+        );
+        assert_eq!(
+            adapter.format_wii_button_command(WiiButton::NunchukC, false),
+            (true, "RELEASE X".to_string())
+        );
+        assert_eq!(
+            adapter.format_pointer_command(-1.0, -1.0),
+            "SET MAIN 0.000 1.000" // Top left
+        );
+        assert_eq!(
+            adapter.format_motion_command(0.0, 1.0, true),
+            "SET C 1.000 0.500\nPRESS Z"
+        );
+        assert_eq!(
+            adapter.format_extension_command(WiiExtension::Classic),
+            "SET L 1.000"
+        );
+
+        let ini = wiimote_ini();
+        assert!(ini.contains("[Wiimote2]\nDevice = Pipe/0/dpstream-pad2\n"));
+        assert!(ini.contains("Extension = `Axis L -+` * 2"));
+        assert!(ini.contains("Nunchuk/Buttons/C = `Pipe/0/dpstream-ext2:Button X`"));
+    }
+
+    #[test]
+    fn test_gcpad_ini_binds_pipes() {
+        let ini = gcpad_ini();
+        assert!(ini.contains("[GCPad1]\nDevice = Pipe/0/dpstream-pad1\n"));
+        assert!(ini.contains("[GCPad4]\nDevice = Pipe/0/dpstream-pad4\n"));
+        assert!(ini.contains("Buttons/A = `Button A`"));
+        assert!(ini.contains("Main Stick/Up = `Axis MAIN Y +`"));
+        assert!(ini.contains("D-Pad/Left = `Button D_LEFT`"));
+        assert!(ini.contains("Rumble/Motor = `evdev/0/dpstream Rumble 3:Strong`"));
+    }
+
+    #[tokio::test]
+    async fn test_commands_reach_player_pipe() {
+        let user_dir =
+            std::env::temp_dir().join(format!("dpstream-input-pipes-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&user_dir);
+        let mut adapter = DolphinInputAdapter::new().unwrap();
+        adapter.initialize(&user_dir).await.unwrap();
+
+        assert!(user_dir.join("Config/GCPadNew.ini").exists());
+        let mut reader = pipe::OpenOptions::new()
+            .open_receiver(adapter.pipe_path(2).unwrap())
+            .unwrap();
+
+        adapter
+            .send_commands(vec![
+                DolphinCommand::ButtonPress {
+                    player: 2,
+                    button: DolphinButton::B,
+                    pressed: true,
+                },
+                DolphinCommand::AnalogInput {
+                    player: 2,
+                    stick: AnalogStick::Main,
+                    x: 1.0,
+                    y: 0.0,
+                },
+            ])
+            .await
+            .unwrap();
+        adapter.shutdown().await.unwrap();
+
+        let mut output = String::new();
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            reader.read_to_string(&mut output),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.first(), Some(&"RELEASE A"));
+        assert!(lines.contains(&"PRESS B"));
+        assert!(lines.contains(&"SET MAIN 1.000 0.500"));
+        assert_eq!(lines.last(), Some(&"SET R 0.000"));
+        assert!(adapter.pipe_path(1).unwrap().exists());
+
+        std::fs::remove_dir_all(&user_dir).unwrap();
     }
 }
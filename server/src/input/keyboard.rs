@@ -0,0 +1,583 @@
+//! Keyboard and mouse input
+//!
+//! Desktop Moonlight clients send key and mouse events instead of (or next
+//! to) controller packets. A [`KeyboardMapping`] from the game profile turns
+//! held keys and mouse buttons into pad buttons and stick directions, mouse
+//! motion into the Wii pointer, and hotkeys into [`EmulationCommand`]s.
+//! Keys are Windows virtual-key codes as Moonlight sends them; mouse
+//! buttons are numbered 1 (left), 2 (middle), 3 (right), 4 and 5.
+
+use crate::emulator::control::EmulationCommand;
+use crate::input::mapping::ConsoleType;
+use crate::input::processor::{AnalogStick, DolphinButton, DolphinCommand};
+use crate::input::wiimote::WiiButton;
+use crate::streaming::moonlight::InputEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// Virtual-key codes used by the default mappings
+pub mod keys {
+    pub const BACKSPACE: u32 = 0x08;
+    pub const ENTER: u32 = 0x0D;
+    pub const SHIFT: u32 = 0x10;
+    pub const SPACE: u32 = 0x20;
+    pub const HOME: u32 = 0x24;
+    pub const LEFT: u32 = 0x25;
+    pub const UP: u32 = 0x26;
+    pub const RIGHT: u32 = 0x27;
+    pub const DOWN: u32 = 0x28;
+    pub const ONE: u32 = 0x31;
+    pub const TWO: u32 = 0x32;
+    pub const A: u32 = 0x41;
+    pub const C: u32 = 0x43;
+    pub const D: u32 = 0x44;
+    pub const E: u32 = 0x45;
+    pub const F: u32 = 0x46;
+    pub const I: u32 = 0x49;
+    pub const J: u32 = 0x4A;
+    pub const K: u32 = 0x4B;
+    pub const L: u32 = 0x4C;
+    pub const Q: u32 = 0x51;
+    pub const R: u32 = 0x52;
+    pub const S: u32 = 0x53;
+    pub const W: u32 = 0x57;
+    pub const Z: u32 = 0x5A;
+    pub const F5: u32 = 0x74;
+    pub const F9: u32 = 0x78;
+    pub const F11: u32 = 0x7A;
+    pub const F12: u32 = 0x7B;
+}
+
+/// Mouse button numbers
+pub const MOUSE_LEFT: u8 = 1;
+pub const MOUSE_RIGHT: u8 = 3;
+
+/// What a key or mouse button does while held
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum KeyAction {
+    /// Hold a GameCube pad button
+    Button { button: DolphinButton },
+    /// Hold a Wii Remote or extension button
+    WiiButton { button: WiiButton },
+    /// Push a stick in a direction; held directions add up
+    Stick { stick: AnalogStick, x: f32, y: f32 },
+    /// Run an emulation command once per press
+    Emulation { command: EmulationCommand },
+}
+
+/// Keyboard and mouse bindings of a game profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyboardMapping {
+    pub keys: HashMap<u32, KeyAction>,
+    pub mouse_buttons: HashMap<u8, KeyAction>,
+    /// Move the Wii pointer with the mouse
+    pub mouse_pointer: bool,
+    /// Pointer travel per count of mouse motion, the screen being 2.0 wide
+    pub pointer_speed: f32,
+    /// Tapped once per wheel notch
+    #[serde(default)]
+    pub wheel_up: Option<KeyAction>,
+    #[serde(default)]
+    pub wheel_down: Option<KeyAction>,
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        Self::gamecube()
+    }
+}
+
+impl KeyboardMapping {
+    /// WASD for the main stick, IJKL for the C-stick, arrows for the D-pad
+    pub fn gamecube() -> Self {
+        let button = |button| KeyAction::Button { button };
+        let mut keys = Self::stick_keys(AnalogStick::Main, [keys::W, keys::A, keys::S, keys::D]);
+        keys.extend(Self::stick_keys(
+            AnalogStick::CStick,
+            [keys::I, keys::J, keys::K, keys::L],
+        ));
+        keys.extend([
+            (keys::SPACE, button(DolphinButton::A)),
+            (keys::SHIFT, button(DolphinButton::B)),
+            (keys::R, button(DolphinButton::X)),
+            (keys::F, button(DolphinButton::Y)),
+            (keys::Z, button(DolphinButton::Z)),
+            (keys::Q, button(DolphinButton::L)),
+            (keys::E, button(DolphinButton::R)),
+            (keys::ENTER, button(DolphinButton::Start)),
+            (keys::UP, button(DolphinButton::Up)),
+            (keys::DOWN, button(DolphinButton::Down)),
+            (keys::LEFT, button(DolphinButton::Left)),
+            (keys::RIGHT, button(DolphinButton::Right)),
+        ]);
+        keys.extend(Self::hotkeys());
+
+        Self {
+            keys,
+            mouse_buttons: HashMap::from([
+                (MOUSE_LEFT, button(DolphinButton::A)),
+                (MOUSE_RIGHT, button(DolphinButton::B)),
+            ]),
+            mouse_pointer: false,
+            pointer_speed: 1.0 / 640.0,
+            wheel_up: None,
+            wheel_down: None,
+        }
+    }
+
+    /// Mouse aims the pointer and clicks A and B; WASD drives the Nunchuk
+    pub fn wii() -> Self {
+        let button = |button| KeyAction::WiiButton { button };
+        let mut keys = Self::stick_keys(AnalogStick::Main, [keys::W, keys::A, keys::S, keys::D]);
+        keys.extend([
+            (keys::SPACE, button(WiiButton::A)),
+            (keys::SHIFT, button(WiiButton::B)),
+            (keys::ONE, button(WiiButton::One)),
+            (keys::TWO, button(WiiButton::Two)),
+            (keys::BACKSPACE, button(WiiButton::Minus)),
+            (keys::ENTER, button(WiiButton::Plus)),
+            (keys::HOME, button(WiiButton::Home)),
+            (keys::C, button(WiiButton::NunchukC)),
+            (keys::Z, button(WiiButton::NunchukZ)),
+            (keys::UP, button(WiiButton::Up)),
+            (keys::DOWN, button(WiiButton::Down)),
+            (keys::LEFT, button(WiiButton::Left)),
+            (keys::RIGHT, button(WiiButton::Right)),
+        ]);
+        keys.extend(Self::hotkeys());
+
+        Self {
+            keys,
+            mouse_buttons: HashMap::from([
+                (MOUSE_LEFT, button(WiiButton::A)),
+                (MOUSE_RIGHT, button(WiiButton::B)),
+            ]),
+            mouse_pointer: true,
+            pointer_speed: 1.0 / 640.0,
+            wheel_up: Some(button(WiiButton::Plus)),
+            wheel_down: Some(button(WiiButton::Minus)),
+        }
+    }
+
+    /// Default mapping for a console
+    pub fn for_console(console_type: ConsoleType) -> Self {
+        match console_type {
+            ConsoleType::GameCube => Self::gamecube(),
+            ConsoleType::Wii | ConsoleType::WiiU => Self::wii(),
+        }
+    }
+
+    /// Up, left, down, right keys for a stick
+    fn stick_keys(
+        stick: AnalogStick,
+        [up, left, down, right]: [u32; 4],
+    ) -> HashMap<u32, KeyAction> {
+        let direction = |x, y| KeyAction::Stick { stick, x, y };
+        HashMap::from([
+            (up, direction(0.0, 1.0)),
+            (left, direction(-1.0, 0.0)),
+            (down, direction(0.0, -1.0)),
+            (right, direction(1.0, 0.0)),
+        ])
+    }
+
+    fn hotkeys() -> [(u32, KeyAction); 4] {
+        let emulation = |command| KeyAction::Emulation { command };
+        [
+            (keys::F5, emulation(EmulationCommand::SaveSelectedSlot)),
+            (keys::F9, emulation(EmulationCommand::LoadSelectedSlot)),
+            (keys::F11, emulation(EmulationCommand::ToggleFullscreen)),
+            (keys::F12, emulation(EmulationCommand::Screenshot)),
+        ]
+    }
+}
+
+/// What one keyboard or mouse event produced
+#[derive(Debug, Default, PartialEq)]
+pub struct KeyboardOutput {
+    pub commands: Vec<DolphinCommand>,
+    pub emulation: Vec<EmulationCommand>,
+}
+
+/// Held keys and pointer position of one player's keyboard and mouse
+#[derive(Debug, Clone, Default)]
+pub struct KeyboardState {
+    keys: BTreeSet<u32>,
+    mouse_buttons: BTreeSet<u8>,
+    pointer: (f32, f32),
+    /// What the held keys added up to after the last event
+    held: Held,
+}
+
+/// Pad state the held keys add up to
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Held {
+    buttons: Vec<DolphinButton>,
+    wii_buttons: Vec<WiiButton>,
+    main_stick: (f32, f32),
+    c_stick: (f32, f32),
+}
+
+impl KeyboardState {
+    /// Apply one event, returning the commands for what changed
+    ///
+    /// Only changed buttons and sticks are sent, so a controller on the same
+    /// player keeps working alongside. Key repeats are ignored.
+    pub fn handle(
+        &mut self,
+        player: u8,
+        console_type: ConsoleType,
+        mapping: &KeyboardMapping,
+        event: &InputEvent,
+    ) -> KeyboardOutput {
+        let mut output = KeyboardOutput::default();
+        let before = self.held(mapping);
+
+        match *event {
+            InputEvent::KeyDown { key } => {
+                if self.keys.insert(key) {
+                    output
+                        .emulation
+                        .extend(emulation_command(mapping.keys.get(&key)));
+                }
+            }
+            InputEvent::KeyUp { key } => {
+                self.keys.remove(&key);
+            }
+            InputEvent::MouseDown { button } => {
+                if self.mouse_buttons.insert(button) {
+                    output
+                        .emulation
+                        .extend(emulation_command(mapping.mouse_buttons.get(&button)));
+                }
+            }
+            InputEvent::MouseUp { button } => {
+                self.mouse_buttons.remove(&button);
+            }
+            InputEvent::MouseMove { x, y } => {
+                if mapping.mouse_pointer {
+                    let (pointer_x, pointer_y) = &mut self.pointer;
+                    *pointer_x = (*pointer_x + x as f32 * mapping.pointer_speed).clamp(-1.0, 1.0);
+                    // Screen y grows downwards, the pointer's upwards
+                    *pointer_y = (*pointer_y - y as f32 * mapping.pointer_speed).clamp(-1.0, 1.0);
+                    output.commands.push(DolphinCommand::WiiPointerInput {
+                        player,
+                        x: *pointer_x,
+                        y: *pointer_y,
+                        z: 0.0,
+                    });
+                }
+            }
+            InputEvent::MouseWheel { delta } => {
+                let action = if delta > 0 {
+                    &mapping.wheel_up
+                } else {
+                    &mapping.wheel_down
+                };
+                if let Some(action) = action.as_ref().filter(|_| delta != 0) {
+                    output.emulation.extend(emulation_command(Some(action)));
+                    output.commands.extend(tap(player, action));
+                }
+            }
+            InputEvent::ControllerInput { .. } => {}
+        }
+
+        let after = self.held(mapping);
+        output
+            .commands
+            .extend(changes(player, console_type, &before, &after));
+        self.held = after;
+        output
+    }
+
+    /// Pad buttons the held keys and mouse buttons press
+    pub fn held_buttons(&self) -> &[DolphinButton] {
+        &self.held.buttons
+    }
+
+    /// Wii buttons the held keys and mouse buttons press
+    pub fn held_wii_buttons(&self) -> &[WiiButton] {
+        &self.held.wii_buttons
+    }
+
+    fn held(&self, mapping: &KeyboardMapping) -> Held {
+        let actions = self
+            .keys
+            .iter()
+            .filter_map(|key| mapping.keys.get(key))
+            .chain(
+                self.mouse_buttons
+                    .iter()
+                    .filter_map(|button| mapping.mouse_buttons.get(button)),
+            );
+
+        let mut held = Held::default();
+        for action in actions {
+            match action {
+                KeyAction::Button { button } if !held.buttons.contains(button) => {
+                    held.buttons.push(*button)
+                }
+                KeyAction::WiiButton { button } if !held.wii_buttons.contains(button) => {
+                    held.wii_buttons.push(*button)
+                }
+                KeyAction::Stick { stick, x, y } => {
+                    let direction = match stick {
+                        AnalogStick::Main => &mut held.main_stick,
+                        AnalogStick::CStick => &mut held.c_stick,
+                    };
+                    direction.0 += x;
+                    direction.1 += y;
+                }
+                _ => {}
+            }
+        }
+        held.main_stick = clamp_to_circle(held.main_stick);
+        held.c_stick = clamp_to_circle(held.c_stick);
+        held
+    }
+}
+
+fn emulation_command(action: Option<&KeyAction>) -> Option<EmulationCommand> {
+    match action {
+        Some(KeyAction::Emulation { command }) => Some(*command),
+        _ => None,
+    }
+}
+
+/// Press and release of a wheel-bound button
+fn tap(player: u8, action: &KeyAction) -> Vec<DolphinCommand> {
+    match *action {
+        KeyAction::Button { button } => [true, false]
+            .map(|pressed| DolphinCommand::ButtonPress {
+                player,
+                button,
+                pressed,
+            })
+            .to_vec(),
+        KeyAction::WiiButton { button } => [true, false]
+            .map(|pressed| DolphinCommand::WiiButtonPress {
+                player,
+                button,
+                pressed,
+            })
+            .to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+/// Commands taking the pad from `before` to `after`
+fn changes(
+    player: u8,
+    console_type: ConsoleType,
+    before: &Held,
+    after: &Held,
+) -> Vec<DolphinCommand> {
+    let mut commands = Vec::new();
+
+    let dpad = [
+        DolphinButton::Up,
+        DolphinButton::Down,
+        DolphinButton::Left,
+        DolphinButton::Right,
+    ];
+    let toggled =
+        |button: &DolphinButton| before.buttons.contains(button) != after.buttons.contains(button);
+    for button in before.buttons.iter().chain(&after.buttons) {
+        if toggled(button) && !dpad.contains(button) {
+            commands.push(DolphinCommand::ButtonPress {
+                player,
+                button: *button,
+                pressed: after.buttons.contains(button),
+            });
+        }
+    }
+    if dpad.iter().any(toggled) {
+        let held = |button| after.buttons.contains(&button);
+        commands.push(DolphinCommand::DPadInput {
+            player,
+            up: held(DolphinButton::Up),
+            down: held(DolphinButton::Down),
+            left: held(DolphinButton::Left),
+            right: held(DolphinButton::Right),
+        });
+    }
+
+    for button in before.wii_buttons.iter().chain(&after.wii_buttons) {
+        let pressed = after.wii_buttons.contains(button);
+        if before.wii_buttons.contains(button) != pressed {
+            commands.push(DolphinCommand::WiiButtonPress {
+                player,
+                button: *button,
+                pressed,
+            });
+        }
+    }
+
+    for (stick, was, is) in [
+        (AnalogStick::Main, before.main_stick, after.main_stick),
+        (AnalogStick::CStick, before.c_stick, after.c_stick),
+    ] {
+        if was == is {
+            continue;
+        }
+        let (x, y) = is;
+        commands.push(match console_type {
+            // Wii games read sticks off the attached extension
+            ConsoleType::Wii | ConsoleType::WiiU => DolphinCommand::WiiExtensionStick {
+                player,
+                stick,
+                x,
+                y,
+            },
+            ConsoleType::GameCube => DolphinCommand::AnalogInput {
+                player,
+                stick,
+                x,
+                y,
+            },
+        });
+    }
+
+    commands
+}
+
+/// Keep diagonals from reaching past the stick's gate
+fn clamp_to_circle((x, y): (f32, f32)) -> (f32, f32) {
+    let length = (x * x + y * y).sqrt();
+    if length > 1.0 {
+        (x / length, y / length)
+    } else {
+        (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::mapping::ControllerMapping;
+    use crate::input::processor::InputProcessor;
+    use crate::input::MoonlightInputPacket;
+
+    #[test]
+    fn test_keys_drive_buttons_sticks_and_hotkeys() {
+        let mapping = KeyboardMapping::gamecube();
+        let mut state = KeyboardState::default();
+        let mut handle = |event| state.handle(0, ConsoleType::GameCube, &mapping, &event);
+
+        let output = handle(InputEvent::KeyDown { key: keys::SPACE });
+        assert_eq!(
+            output.commands,
+            [DolphinCommand::ButtonPress {
+                player: 0,
+                button: DolphinButton::A,
+                pressed: true,
+            }]
+        );
+        // A key repeat changes nothing
+        assert_eq!(
+            handle(InputEvent::KeyDown { key: keys::SPACE }),
+            KeyboardOutput::default()
+        );
+
+        handle(InputEvent::KeyDown { key: keys::W });
+        let output = handle(InputEvent::KeyDown { key: keys::D });
+        let DolphinCommand::AnalogInput { x, y, .. } = output.commands[0] else {
+            panic!("expected a stick update, got {:?}", output.commands);
+        };
+        assert!((x - y).abs() < 1e-6 && (x * x + y * y - 1.0).abs() < 1e-5);
+
+        let output = handle(InputEvent::KeyDown { key: keys::F12 });
+        assert_eq!(output.emulation, [EmulationCommand::Screenshot]);
+        assert!(output.commands.is_empty());
+
+        let output = handle(InputEvent::KeyUp { key: keys::SPACE });
+        assert_eq!(
+            output.commands,
+            [DolphinCommand::ButtonPress {
+                player: 0,
+                button: DolphinButton::A,
+                pressed: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_mouse_aims_wii_pointer() {
+        let mapping = KeyboardMapping::wii();
+        let mut state = KeyboardState::default();
+        let mut handle = |event| state.handle(1, ConsoleType::Wii, &mapping, &event);
+
+        let output = handle(InputEvent::MouseMove { x: 320, y: -320 });
+        assert_eq!(
+            output.commands,
+            [DolphinCommand::WiiPointerInput {
+                player: 1,
+                x: 0.5,
+                y: 0.5,
+                z: 0.0,
+            }]
+        );
+        // The pointer stops at the screen edge
+        let output = handle(InputEvent::MouseMove { x: 10_000, y: 0 });
+        assert!(matches!(
+            output.commands[0],
+            DolphinCommand::WiiPointerInput { x, .. } if x == 1.0
+        ));
+
+        let output = handle(InputEvent::MouseDown { button: MOUSE_LEFT });
+        assert_eq!(
+            output.commands,
+            [DolphinCommand::WiiButtonPress {
+                player: 1,
+                button: WiiButton::A,
+                pressed: true,
+            }]
+        );
+
+        let output = handle(InputEvent::MouseWheel { delta: 120 });
+        assert_eq!(output.commands.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_controller_packets_keep_keys_held() {
+        let mut processor = InputProcessor::new().unwrap();
+        let mapping = KeyboardMapping::gamecube();
+        for key in [keys::SPACE, keys::UP] {
+            processor.process_event(
+                1,
+                ConsoleType::GameCube,
+                &mapping,
+                &InputEvent::KeyDown { key },
+            );
+        }
+        processor.get_dolphin_commands().await.unwrap();
+
+        processor
+            .process_input(
+                1,
+                ControllerMapping::default_gamecube(),
+                MoonlightInputPacket::default(),
+            )
+            .await
+            .unwrap();
+        let commands = processor.get_dolphin_commands().await.unwrap().unwrap();
+        assert!(commands.contains(&DolphinCommand::ButtonPress {
+            player: 1,
+            button: DolphinButton::A,
+            pressed: true,
+        }));
+        assert!(commands.contains(&DolphinCommand::ButtonPress {
+            player: 1,
+            button: DolphinButton::B,
+            pressed: false,
+        }));
+        assert!(commands.contains(&DolphinCommand::DPadInput {
+            player: 1,
+            up: true,
+            down: false,
+            left: false,
+            right: false,
+        }));
+    }
+}
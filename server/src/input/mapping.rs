@@ -2,10 +2,19 @@
 
 //! Controller mapping and configuration system
 //!
-//! Provides flexible mapping between Switch controllers and GameCube/Wii controllers
+//! Provides flexible mapping between Switch controllers and GameCube/Wii controllers.
+//! Buttons are bound through a [`ButtonBinding`] table; profiles saved before
+//! the table existed still load, their fixed per-button fields becoming
+//! equivalent bindings.
 
 use crate::error::{InputError, Result};
+use crate::input::bindings::{ButtonBinding, SwitchButton};
+use crate::input::calibration::AnalogSettings;
+use crate::input::keyboard::KeyboardMapping;
+use crate::input::motion::{MotionSettings, SensitivityCurve};
 use crate::input::processor::DolphinButton;
+use crate::input::touch::TouchSettings;
+pub use dpstream_protocol::calibration::{CalibrationData, StickCalibration};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
@@ -13,20 +22,14 @@ use std::path::Path;
 
 /// Controller mapping configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredMapping")]
 pub struct ControllerMapping {
     pub name: String,
     pub description: String,
     pub console_type: ConsoleType,
 
     // Button mappings
-    pub a_button: DolphinButton,
-    pub b_button: DolphinButton,
-    pub x_button: DolphinButton,
-    pub y_button: DolphinButton,
-    pub z_button: DolphinButton,
-    pub l_button: DolphinButton,
-    pub r_button: DolphinButton,
-    pub start_button: DolphinButton,
+    pub bindings: Vec<ButtonBinding>,
 
     // Analog stick settings
     pub main_stick_sensitivity: f32,
@@ -35,11 +38,13 @@ pub struct ControllerMapping {
 
     // Switch-specific features
     pub enable_gyro_pointer: bool,
-    pub enable_touch_pointer: bool,
     pub gyro_sensitivity: f32,
+    #[serde(default)]
+    pub motion: MotionSettings,
+    pub touch: TouchSettings,
 
     // Advanced settings
-    pub deadzone: f32,
+    pub analog: AnalogSettings,
     pub vibration_strength: f32,
     pub invert_y_axis: bool,
 }
@@ -53,27 +58,22 @@ impl ControllerMapping {
             console_type: ConsoleType::GameCube,
 
             // Standard button layout
-            a_button: DolphinButton::A,
-            b_button: DolphinButton::B,
-            x_button: DolphinButton::X,
-            y_button: DolphinButton::Y,
-            z_button: DolphinButton::Z,
-            l_button: DolphinButton::L,
-            r_button: DolphinButton::R,
-            start_button: DolphinButton::Start,
+            bindings: LegacyButtons::default().into_bindings(),
 
             // Default sensitivities
             main_stick_sensitivity: 1.0,
             c_stick_sensitivity: 1.0,
             trigger_sensitivity: 1.0,
 
-            // Switch features disabled for GameCube
+            // Switch features disabled for GameCube, but a touch C-stick
+            // for handheld play
             enable_gyro_pointer: false,
-            enable_touch_pointer: false,
             gyro_sensitivity: 1.0,
+            motion: MotionSettings::default(),
+            touch: TouchSettings::c_stick_overlay(),
 
             // Standard settings
-            deadzone: 0.1,
+            analog: AnalogSettings::with_deadzone(0.1),
             vibration_strength: 1.0,
             invert_y_axis: false,
         }
@@ -87,14 +87,12 @@ impl ControllerMapping {
             console_type: ConsoleType::Wii,
 
             // Wii Remote button layout (horizontal orientation)
-            a_button: DolphinButton::A,
-            b_button: DolphinButton::B,
-            x_button: DolphinButton::Y, // 1 button
-            y_button: DolphinButton::X, // 2 button
-            z_button: DolphinButton::Z,
-            l_button: DolphinButton::L,
-            r_button: DolphinButton::R,
-            start_button: DolphinButton::Start,
+            bindings: LegacyButtons {
+                x_button: DolphinButton::Y, // 1 button
+                y_button: DolphinButton::X, // 2 button
+                ..LegacyButtons::default()
+            }
+            .into_bindings(),
 
             // Lower sensitivity for pointer control
             main_stick_sensitivity: 0.8,
@@ -103,11 +101,12 @@ impl ControllerMapping {
 
             // Enable motion controls
             enable_gyro_pointer: true,
-            enable_touch_pointer: true,
             gyro_sensitivity: 2.0,
+            motion: MotionSettings::default(),
+            touch: TouchSettings::pointer(),
 
             // More forgiving deadzone for motion
-            deadzone: 0.05,
+            analog: AnalogSettings::with_deadzone(0.05),
             vibration_strength: 0.8,
             invert_y_axis: false,
         }
@@ -132,8 +131,11 @@ impl ControllerMapping {
 
         // Higher C-stick sensitivity for quick smash attacks
         mapping.c_stick_sensitivity = 1.2;
-        // Lower deadzone for precise movement
-        mapping.deadzone = 0.05;
+        // Lower deadzone for precise movement, inside the stick gates the
+        // game was tuned for
+        mapping.analog = AnalogSettings::with_deadzone(0.05);
+        mapping.analog.main_stick.octagonal_gate = true;
+        mapping.analog.c_stick.octagonal_gate = true;
         // Higher trigger sensitivity for L-canceling
         mapping.trigger_sensitivity = 1.1;
 
@@ -149,6 +151,11 @@ impl ControllerMapping {
         // Enable gyro for aiming
         mapping.enable_gyro_pointer = true;
         mapping.gyro_sensitivity = 1.5;
+        // Steadier, finer aim near the reticle
+        mapping.motion.smoothing = 0.5;
+        mapping.motion.curve = SensitivityCurve::Power { exponent: 1.5 };
+        // R is taken by the GameCube pad, so ratchet on the right stick click
+        mapping.motion.ratchet_flag = 0x0080;
         // Invert Y for FPS-style aiming
         mapping.invert_y_axis = true;
 
@@ -172,7 +179,7 @@ impl ControllerMapping {
 
         // Enable both gyro and touch for flexibility
         mapping.enable_gyro_pointer = true;
-        mapping.enable_touch_pointer = true;
+        mapping.touch = TouchSettings::pointer();
         mapping.gyro_sensitivity = 1.8;
 
         mapping
@@ -215,6 +222,116 @@ impl ControllerMapping {
     }
 }
 
+/// Per-button fields of profiles saved before the binding table
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+struct LegacyButtons {
+    a_button: DolphinButton,
+    b_button: DolphinButton,
+    x_button: DolphinButton,
+    y_button: DolphinButton,
+    z_button: DolphinButton,
+    l_button: DolphinButton,
+    r_button: DolphinButton,
+    start_button: DolphinButton,
+}
+
+impl Default for LegacyButtons {
+    fn default() -> Self {
+        Self {
+            a_button: DolphinButton::A,
+            b_button: DolphinButton::B,
+            x_button: DolphinButton::X,
+            y_button: DolphinButton::Y,
+            z_button: DolphinButton::Z,
+            l_button: DolphinButton::L,
+            r_button: DolphinButton::R,
+            start_button: DolphinButton::Start,
+        }
+    }
+}
+
+impl LegacyButtons {
+    /// The bindings the fixed layout stood for: Minus was Z, Plus was Start
+    /// and the D-pad always drove the D-pad
+    fn into_bindings(self) -> Vec<ButtonBinding> {
+        vec![
+            ButtonBinding::press(SwitchButton::A, self.a_button),
+            ButtonBinding::press(SwitchButton::B, self.b_button),
+            ButtonBinding::press(SwitchButton::X, self.x_button),
+            ButtonBinding::press(SwitchButton::Y, self.y_button),
+            ButtonBinding::press(SwitchButton::L, self.l_button),
+            ButtonBinding::press(SwitchButton::R, self.r_button),
+            ButtonBinding::press(SwitchButton::Minus, self.z_button),
+            ButtonBinding::press(SwitchButton::Plus, self.start_button),
+            ButtonBinding::press(SwitchButton::Up, DolphinButton::Up),
+            ButtonBinding::press(SwitchButton::Down, DolphinButton::Down),
+            ButtonBinding::press(SwitchButton::Left, DolphinButton::Left),
+            ButtonBinding::press(SwitchButton::Right, DolphinButton::Right),
+        ]
+    }
+}
+
+/// A mapping as stored in profile JSON, with or without a binding table
+#[derive(Deserialize)]
+struct StoredMapping {
+    name: String,
+    description: String,
+    console_type: ConsoleType,
+    bindings: Option<Vec<ButtonBinding>>,
+    #[serde(flatten)]
+    legacy_buttons: LegacyButtons,
+    main_stick_sensitivity: f32,
+    c_stick_sensitivity: f32,
+    trigger_sensitivity: f32,
+    enable_gyro_pointer: bool,
+    /// Touch switch of profiles saved before [`TouchSettings`]
+    #[serde(default)]
+    enable_touch_pointer: bool,
+    touch: Option<TouchSettings>,
+    gyro_sensitivity: f32,
+    #[serde(default)]
+    motion: MotionSettings,
+    analog: Option<AnalogSettings>,
+    /// Stick deadzone of profiles saved before [`AnalogSettings`]
+    deadzone: Option<f32>,
+    vibration_strength: f32,
+    invert_y_axis: bool,
+}
+
+impl From<StoredMapping> for ControllerMapping {
+    fn from(stored: StoredMapping) -> Self {
+        Self {
+            name: stored.name,
+            description: stored.description,
+            console_type: stored.console_type,
+            bindings: stored
+                .bindings
+                .unwrap_or_else(|| stored.legacy_buttons.into_bindings()),
+            main_stick_sensitivity: stored.main_stick_sensitivity,
+            c_stick_sensitivity: stored.c_stick_sensitivity,
+            trigger_sensitivity: stored.trigger_sensitivity,
+            enable_gyro_pointer: stored.enable_gyro_pointer,
+            gyro_sensitivity: stored.gyro_sensitivity,
+            motion: stored.motion,
+            touch: stored.touch.unwrap_or_else(|| {
+                if stored.enable_touch_pointer {
+                    TouchSettings::pointer()
+                } else {
+                    TouchSettings::off()
+                }
+            }),
+            analog: stored.analog.unwrap_or_else(|| {
+                stored
+                    .deadzone
+                    .map_or_else(AnalogSettings::default, AnalogSettings::with_deadzone)
+            }),
+            vibration_strength: stored.vibration_strength,
+            invert_y_axis: stored.invert_y_axis,
+        }
+    }
+}
+
 /// Game-specific controller profiles
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameProfile {
@@ -222,10 +339,16 @@ pub struct GameProfile {
     pub game_name: String,
     pub console_type: ConsoleType,
     pub controller_mapping: ControllerMapping,
+    /// Keyboard and mouse bindings, the console's defaults when unset
+    #[serde(default)]
+    pub keyboard: Option<KeyboardMapping>,
     pub recommended_settings: HashMap<String, String>,
 }
 
 impl GameProfile {
+    /// Name given to default profiles for games without a built-in preset
+    pub const UNKNOWN_GAME_NAME: &'static str = "Unknown Game";
+
     /// Load profile for a specific game
     pub fn load_for_game(game_id: &str) -> Result<Self> {
         let profile_path = format!("profiles/{game_id}.json");
@@ -257,7 +380,7 @@ impl GameProfile {
                 ControllerMapping::smash_brawl_mapping(),
             ),
             _ => (
-                "Unknown Game".to_string(),
+                Self::UNKNOWN_GAME_NAME.to_string(),
                 ConsoleType::GameCube,
                 ControllerMapping::default_gamecube(),
             ),
@@ -272,10 +395,18 @@ impl GameProfile {
             game_name,
             console_type,
             controller_mapping: mapping,
+            keyboard: None,
             recommended_settings: settings,
         }
     }
 
+    /// Keyboard and mouse bindings to use for this game
+    pub fn keyboard_mapping(&self) -> KeyboardMapping {
+        self.keyboard
+            .clone()
+            .unwrap_or_else(|| KeyboardMapping::for_console(self.console_type))
+    }
+
     /// Save profile to file
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let json =
@@ -330,28 +461,6 @@ pub enum ConsoleType {
     WiiU, // For future expansion
 }
 
-/// Calibration data for analog inputs
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CalibrationData {
-    pub stick_center_x: i16,
-    pub stick_center_y: i16,
-    pub stick_range_x: i16,
-    pub stick_range_y: i16,
-    pub trigger_range: u8,
-}
-
-impl Default for CalibrationData {
-    fn default() -> Self {
-        Self {
-            stick_center_x: 0,
-            stick_center_y: 0,
-            stick_range_x: 32767,
-            stick_range_y: 32767,
-            trigger_range: 255,
-        }
-    }
-}
-
 /// Mapping presets for quick setup
 pub struct MappingPresets;
 
@@ -382,7 +491,7 @@ mod tests {
         let mapping = ControllerMapping::default_gamecube();
         assert_eq!(mapping.console_type, ConsoleType::GameCube);
         assert!(!mapping.enable_gyro_pointer);
-        assert_eq!(mapping.deadzone, 0.1);
+        assert_eq!(mapping.analog.main_stick.response.deadzone, 0.1);
     }
 
     #[test]
@@ -390,14 +499,15 @@ mod tests {
         let mapping = ControllerMapping::default_wii_remote();
         assert_eq!(mapping.console_type, ConsoleType::Wii);
         assert!(mapping.enable_gyro_pointer);
-        assert!(mapping.enable_touch_pointer);
+        assert!(mapping.touch.moves_pointer());
     }
 
     #[test]
     fn test_game_specific_mapping() {
         let melee = ControllerMapping::for_game("GALE01");
         assert_eq!(melee.name, "Smash Bros. Melee");
-        assert_eq!(melee.deadzone, 0.05); // Lower deadzone for precision
+        assert_eq!(melee.analog.main_stick.response.deadzone, 0.05); // Lower deadzone for precision
+        assert!(melee.analog.main_stick.octagonal_gate);
 
         let prime = ControllerMapping::for_game("GM4E01");
         assert!(prime.enable_gyro_pointer);
@@ -411,6 +521,38 @@ mod tests {
         let deserialized: ControllerMapping = serde_json::from_str(&json).unwrap();
 
         assert_eq!(mapping.name, deserialized.name);
-        assert_eq!(mapping.deadzone, deserialized.deadzone);
+        assert_eq!(mapping.analog, deserialized.analog);
+        assert_eq!(mapping.bindings, deserialized.bindings);
+    }
+
+    #[test]
+    fn test_legacy_button_fields_become_bindings() {
+        let mut json = serde_json::to_value(ControllerMapping::default_gamecube()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("bindings");
+        fields.insert("a_button".to_string(), "B".into());
+        fields.insert("z_button".to_string(), "Start".into());
+
+        let mapping: ControllerMapping = serde_json::from_value(json).unwrap();
+        assert_eq!(mapping.bindings.len(), 12);
+        assert!(mapping
+            .bindings
+            .contains(&ButtonBinding::press(SwitchButton::A, DolphinButton::B)));
+        assert!(mapping.bindings.contains(&ButtonBinding::press(
+            SwitchButton::Minus,
+            DolphinButton::Start
+        )));
+        assert!(mapping
+            .bindings
+            .contains(&ButtonBinding::press(SwitchButton::X, DolphinButton::X)));
+    }
+
+    #[test]
+    fn test_mapping_without_motion_settings() {
+        let mut json = serde_json::to_value(ControllerMapping::for_game("GM4E01")).unwrap();
+        json.as_object_mut().unwrap().remove("motion");
+
+        let mapping: ControllerMapping = serde_json::from_value(json).unwrap();
+        assert_eq!(mapping.motion, MotionSettings::default());
     }
 }
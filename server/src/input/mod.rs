@@ -4,28 +4,75 @@
 //!
 //! Handles input events from Moonlight clients and routes them to Dolphin emulator
 
+pub mod backend;
+pub mod bindings;
+pub mod calibration;
 pub mod dolphin;
+pub mod keyboard;
 pub mod mapping;
+pub mod motion;
 pub mod processor;
-
-use crate::error::Result;
-use serde::{Deserialize, Serialize};
+pub mod recording;
+pub mod rumble;
+pub mod sequencing;
+pub mod slots;
+pub mod touch;
+#[cfg(feature = "system")]
+pub mod uinput;
+pub mod wiimote;
+
+use crate::emulator::control::EmulationCommand;
+use crate::error::{InputError, Result};
+use crate::streaming::latency::AppliedInput;
+use crate::streaming::moonlight::InputEvent;
+use calibration::{CalibrationRequest, CalibrationResponse, CalibrationStore, Calibrator};
+use keyboard::KeyboardMapping;
+use mapping::CalibrationData;
+use parking_lot::Mutex;
+use recording::{InputRecorder, RecordedKind};
+use rumble::{RumbleEvent, RumbleMessage, RumbleStats, RumbleSubscription};
+use sequencing::{InputSequencer, SequenceStats};
+use slots::{ControllerId, PlayerSlots, SlotRequest, SlotTable, RECONNECT_GRACE};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::mpsc;
+use touch::TouchOverlay;
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+pub use backend::{InputBackend, InputBackendKind};
 pub use dolphin::DolphinInputAdapter;
+/// Moonlight input packet from client, shared with the Switch client
+pub use dpstream_protocol::input::InputPacket as MoonlightInputPacket;
+pub use dpstream_protocol::input::TouchPoint;
 pub use mapping::{ControllerMapping, GameProfile};
 pub use processor::InputProcessor;
+pub use wiimote::WiiExtension;
+
+/// Directory holding saved controller calibrations, one file per client
+pub const CALIBRATION_DIR: &str = "calibration";
 
 /// Main input manager for the server
 pub struct ServerInputManager {
     processor: InputProcessor,
     sessions: HashMap<Uuid, ClientInputSession>,
-    dolphin_adapter: DolphinInputAdapter,
+    backend: Box<dyn InputBackend>,
     global_mapping: ControllerMapping,
+    keyboard_mapping: KeyboardMapping,
+    rumble_events: Option<flume::Receiver<RumbleEvent>>,
+    rumble_subscribers: HashMap<Uuid, flume::Sender<RumbleMessage>>,
+    rumble_stats: Arc<Mutex<RumbleStats>>,
+    calibrations: CalibrationStore,
+    slots: SlotTable,
+    slot_subscribers: HashMap<Uuid, flume::Sender<PlayerSlots>>,
+    overlay_subscribers: HashMap<Uuid, flume::Sender<TouchOverlay>>,
+    recorder: Option<InputRecorder>,
+    /// Newest numbered input of each session that reached the backend
+    applied_inputs: HashMap<Uuid, AppliedInput>,
+    /// Sequencing counters of sessions that are gone
+    retired_sequence_stats: SequenceStats,
 }
 
 impl ServerInputManager {
@@ -33,14 +80,518 @@ impl ServerInputManager {
     pub fn new() -> Result<Self> {
         info!("Initializing server input manager");
 
+        let backend: Box<dyn InputBackend> = Box::new(DolphinInputAdapter::new()?);
         Ok(Self {
             processor: InputProcessor::new()?,
             sessions: HashMap::new(),
-            dolphin_adapter: DolphinInputAdapter::new()?,
+            rumble_events: backend.rumble_events(),
+            backend,
             global_mapping: ControllerMapping::default_gamecube(),
+            keyboard_mapping: KeyboardMapping::default(),
+            rumble_subscribers: HashMap::new(),
+            rumble_stats: Arc::default(),
+            calibrations: CalibrationStore::new(CALIBRATION_DIR),
+            slots: SlotTable::new(),
+            slot_subscribers: HashMap::new(),
+            overlay_subscribers: HashMap::new(),
+            recorder: None,
+            applied_inputs: HashMap::new(),
+            retired_sequence_stats: SequenceStats::default(),
         })
     }
 
+    /// Create a manager that delivers input through the given backend
+    pub fn with_backend(backend: Box<dyn InputBackend>) -> Result<Self> {
+        let mut manager = Self::new()?;
+        manager.rumble_events = backend.rumble_events();
+        manager.backend = backend;
+        Ok(manager)
+    }
+
+    /// Replace the input backend
+    pub fn set_backend(&mut self, backend: Box<dyn InputBackend>) {
+        info!("Using {} input backend", backend.name());
+        self.rumble_events = backend.rumble_events();
+        if self.rumble_events.is_none() {
+            info!("{} input backend cannot capture rumble", backend.name());
+        }
+        self.backend = backend;
+    }
+
+    /// Record processed input and its commands to a file until
+    /// [`stop_recording`](Self::stop_recording)
+    ///
+    /// Calibrations in use are recorded up front; other per-player state
+    /// only from here on, so recordings replay best when started before
+    /// clients connect.
+    pub fn start_recording(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.stop_recording()?;
+        let mut recorder = InputRecorder::create(&path)?;
+
+        let now = Instant::now();
+        for player in self.slots.assignments().into_values() {
+            let calibration = self.processor.player_calibration(player).cloned();
+            recorder.record(
+                now,
+                RecordedKind::Calibration {
+                    player,
+                    calibration,
+                },
+            )?;
+        }
+
+        info!("Recording input to {}", path.as_ref().display());
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Finish the current recording, if any
+    pub fn stop_recording(&mut self) -> Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Add an event to the recording; a failing recording is dropped
+    fn record(&mut self, kind: impl FnOnce() -> RecordedKind) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        if let Err(e) = recorder.record(Instant::now(), kind()) {
+            warn!("Input recording stopped: {}", e);
+            self.recorder = None;
+        }
+    }
+
+    /// Set a player's calibration, recording the change
+    fn set_player_calibration(&mut self, player: u8, calibration: Option<CalibrationData>) {
+        self.record(|| RecordedKind::Calibration {
+            player,
+            calibration: calibration.clone(),
+        });
+        self.processor.set_calibration(player, calibration);
+    }
+
+    /// Receive rumble for a client session, scaled by its mapping
+    pub fn subscribe_rumble(&mut self, session_id: Uuid) -> RumbleSubscription {
+        let (sender, receiver) = flume::unbounded();
+        self.rumble_subscribers.insert(session_id, sender);
+        RumbleSubscription::new(receiver, Arc::clone(&self.rumble_stats))
+    }
+
+    /// Stop delivering rumble to a client session
+    pub fn unsubscribe_rumble(&mut self, session_id: &Uuid) {
+        self.rumble_subscribers.remove(session_id);
+    }
+
+    /// Rumble captured by the backend and not yet routed
+    pub fn rumble_source(&self) -> Option<flume::Receiver<RumbleEvent>> {
+        self.rumble_events.clone()
+    }
+
+    /// Hand a rumble event to the session playing its player slot
+    pub fn route_rumble(&mut self, event: RumbleEvent) {
+        let mut stats = self.rumble_stats.lock();
+        stats.events_captured += 1;
+
+        let target = self.slots.occupant(event.player).and_then(|controller| {
+            let session_id = controller.session_id;
+            let session = self.sessions.get(&session_id).filter(|s| s.is_active)?;
+            let subscriber = self.rumble_subscribers.get(&session_id)?;
+            Some((controller, subscriber, session.mapping.vibration_strength))
+        });
+
+        match target {
+            Some((ControllerId { session_id, index }, subscriber, strength)) => {
+                let message = RumbleMessage::from_event(&event, strength).for_controller(index);
+                if subscriber.send(message).is_err() {
+                    stats.events_dropped += 1;
+                    drop(stats);
+                    self.rumble_subscribers.remove(&session_id);
+                }
+            }
+            None => stats.events_dropped += 1,
+        }
+    }
+
+    /// Route everything the backend has captured so far
+    pub fn forward_rumble(&mut self) -> usize {
+        let Some(events) = self.rumble_events.clone() else {
+            return 0;
+        };
+        let pending: Vec<RumbleEvent> = events.try_iter().collect();
+        let count = pending.len();
+        for event in pending {
+            self.route_rumble(event);
+        }
+        count
+    }
+
+    /// Attach an extension to a session's Wii Remote
+    pub fn set_wii_extension(&mut self, session_id: &Uuid, extension: WiiExtension) -> Result<()> {
+        let player_slot = self.seated_slot(&ControllerId::primary(*session_id))?;
+        self.record(|| RecordedKind::WiiExtension {
+            player: player_slot,
+            extension,
+        });
+        self.processor.set_wii_extension(player_slot, extension);
+        Ok(())
+    }
+
+    /// Apply a keyboard or mouse event from a session to its player
+    ///
+    /// Returns the emulation commands bound to the keys pressed, for the
+    /// caller to forward to the emulator.
+    pub fn handle_event(
+        &mut self,
+        session_id: &Uuid,
+        event: &InputEvent,
+    ) -> Result<Vec<EmulationCommand>> {
+        let player_slot = self.seated_slot(&ControllerId::primary(*session_id))?;
+        let session =
+            self.sessions
+                .get_mut(session_id)
+                .ok_or_else(|| InputError::SessionNotFound {
+                    session_id: session_id.to_string(),
+                })?;
+        session.last_input_time = Instant::now();
+        let console_type = session.mapping.console_type;
+
+        if let Some(recorder) = &mut self.recorder {
+            let now = recorder.clock(Instant::now());
+            if let Err(e) = recorder.record_keyboard(
+                now,
+                *session_id,
+                player_slot,
+                console_type,
+                &self.keyboard_mapping,
+                event,
+            ) {
+                warn!("Input recording stopped: {}", e);
+                self.recorder = None;
+            }
+        }
+        Ok(self
+            .processor
+            .process_event(player_slot, console_type, &self.keyboard_mapping, event))
+    }
+
+    /// Keep client calibrations somewhere else, e.g. in memory for tests
+    pub fn set_calibration_store(&mut self, store: CalibrationStore) {
+        self.calibrations = store;
+    }
+
+    /// Step a session's calibration flow
+    ///
+    /// Calibration of a session that never identified itself applies until it
+    /// disconnects but is not saved.
+    pub fn handle_calibration(
+        &mut self,
+        session_id: &Uuid,
+        request: CalibrationRequest,
+    ) -> Result<CalibrationResponse> {
+        if let CalibrationRequest::Identify { client_id } = request {
+            return self.identify_client(session_id, client_id);
+        }
+
+        let player_slot = self.player_slot(session_id);
+        let session =
+            self.sessions
+                .get_mut(session_id)
+                .ok_or_else(|| InputError::SessionNotFound {
+                    session_id: session_id.to_string(),
+                })?;
+
+        match request {
+            CalibrationRequest::Identify { .. } => unreachable!("handled above"),
+            CalibrationRequest::Start => {
+                let calibrator = Calibrator::new();
+                let phase = calibrator.phase();
+                session.calibrator = Some(calibrator);
+                Ok(CalibrationResponse::Phase(phase))
+            }
+            CalibrationRequest::Next => {
+                let calibrator =
+                    session
+                        .calibrator
+                        .as_mut()
+                        .ok_or_else(|| InputError::CalibrationFailed {
+                            reason: "no calibration in progress".to_string(),
+                        })?;
+                let Some(calibration) = calibrator.advance()? else {
+                    return Ok(CalibrationResponse::Phase(calibrator.phase()));
+                };
+
+                session.calibrator = None;
+                if let Some(client_id) = &session.client_id {
+                    self.calibrations.insert(client_id, calibration.clone())?;
+                }
+                if let Some(player_slot) = player_slot {
+                    self.set_player_calibration(player_slot, Some(calibration.clone()));
+                }
+                info!("Calibrated controller for session {}", session_id);
+                Ok(CalibrationResponse::Complete(calibration))
+            }
+            CalibrationRequest::Cancel => {
+                session.calibrator = None;
+                Ok(CalibrationResponse::Cancelled)
+            }
+        }
+    }
+
+    /// Name a session's client, returning it to the slot held for it and
+    /// applying its saved calibration
+    fn identify_client(
+        &mut self,
+        session_id: &Uuid,
+        client_id: String,
+    ) -> Result<CalibrationResponse> {
+        let session =
+            self.sessions
+                .get_mut(session_id)
+                .ok_or_else(|| InputError::SessionNotFound {
+                    session_id: session_id.to_string(),
+                })?;
+        let calibration = self.calibrations.get(&client_id)?;
+        session.client_id = Some(client_id.clone());
+
+        // Further controllers get their held slots back as they send input
+        let now = Instant::now();
+        let primary = ControllerId::primary(*session_id);
+        if let Some(held) = self.slots.held_slot(&client_id, 0, now) {
+            let before = self.slots.assignments();
+            self.slots.claim(primary, held, Some(&client_id), now)?;
+            info!("Client {} reclaimed player {}", client_id, held);
+            self.apply_slot_changes(before)?;
+        }
+
+        if let Some(player_slot) = self.slots.slot_of(&primary) {
+            self.set_player_calibration(player_slot, calibration.clone());
+        }
+        Ok(CalibrationResponse::Identified { calibration })
+    }
+
+    /// Player slot of a session's first controller, or `None` while it
+    /// spectates
+    pub fn player_slot(&self, session_id: &Uuid) -> Option<u8> {
+        self.slots.slot_of(&ControllerId::primary(*session_id))
+    }
+
+    /// Player slot of one of a session's local controllers
+    pub fn controller_slot(&self, session_id: &Uuid, controller: u8) -> Option<u8> {
+        self.slots
+            .slot_of(&ControllerId::new(*session_id, controller))
+    }
+
+    /// Receive the slot layout whenever it changes, starting with the
+    /// current one
+    pub fn subscribe_slots(&mut self, session_id: Uuid) -> flume::Receiver<PlayerSlots> {
+        let (sender, receiver) = flume::unbounded();
+        let _ = sender.send(self.slots.layout_for(&session_id, Instant::now()));
+        self.slot_subscribers.insert(session_id, sender);
+        receiver
+    }
+
+    /// Touch overlay a session's client should draw, now and on every change
+    pub fn subscribe_touch_overlay(&mut self, session_id: Uuid) -> flume::Receiver<TouchOverlay> {
+        let (sender, receiver) = flume::unbounded();
+        let _ = sender.send(self.touch_overlay(&session_id));
+        self.overlay_subscribers.insert(session_id, sender);
+        receiver
+    }
+
+    fn touch_overlay(&self, session_id: &Uuid) -> TouchOverlay {
+        self.sessions
+            .get(session_id)
+            .map_or(&self.global_mapping, |session| &session.mapping)
+            .touch
+            .overlay()
+    }
+
+    fn broadcast_touch_overlays(&mut self) {
+        let overlays: Vec<(Uuid, TouchOverlay)> = self
+            .overlay_subscribers
+            .keys()
+            .map(|session_id| (*session_id, self.touch_overlay(session_id)))
+            .collect();
+        for (session_id, overlay) in overlays {
+            let delivered = self
+                .overlay_subscribers
+                .get(&session_id)
+                .is_some_and(|subscriber| subscriber.send(overlay).is_ok());
+            if !delivered {
+                self.overlay_subscribers.remove(&session_id);
+            }
+        }
+    }
+
+    /// Move a session into a free slot, or one held for its client
+    pub fn request_slot(&mut self, session_id: &Uuid, player_slot: u8) -> Result<()> {
+        self.claim_slot(ControllerId::primary(*session_id), player_slot)
+    }
+
+    /// Move one of a session's controllers into a free or held slot
+    pub fn claim_slot(&mut self, controller: ControllerId, player_slot: u8) -> Result<()> {
+        let session = self.sessions.get(&controller.session_id).ok_or_else(|| {
+            InputError::SessionNotFound {
+                session_id: controller.session_id.to_string(),
+            }
+        })?;
+        let before = self.slots.assignments();
+        self.slots.claim(
+            controller,
+            player_slot,
+            session.client_id.as_deref(),
+            Instant::now(),
+        )?;
+        self.apply_slot_changes(before)
+    }
+
+    /// Exchange the players in two slots
+    pub fn swap_slots(&mut self, first: u8, second: u8) -> Result<()> {
+        let before = self.slots.assignments();
+        self.slots.swap(first, second)?;
+        self.apply_slot_changes(before)
+    }
+
+    /// Hold a free slot for a client's controller that is expected to
+    /// reconnect
+    pub fn reserve_slot(&mut self, player_slot: u8, client_id: &str, controller: u8) -> Result<()> {
+        self.slots.reserve(
+            player_slot,
+            client_id,
+            controller,
+            Instant::now() + RECONNECT_GRACE,
+        )?;
+        self.broadcast_slots();
+        Ok(())
+    }
+
+    /// Give a slot to a spectating session; its player starts spectating
+    pub fn hand_over_slot(&mut self, player_slot: u8, spectator: &Uuid) -> Result<()> {
+        let before = self.slots.assignments();
+        self.slots.hand_over(player_slot, *spectator)?;
+        self.apply_slot_changes(before)
+    }
+
+    /// Carry out a slot change a client asked for
+    pub fn handle_slot_request(&mut self, session_id: &Uuid, request: SlotRequest) -> Result<()> {
+        match request {
+            SlotRequest::Claim { slot, controller } => {
+                self.claim_slot(ControllerId::new(*session_id, controller), slot)
+            }
+            SlotRequest::Swap { slot, controller } => {
+                let own = self.seated_slot(&ControllerId::new(*session_id, controller))?;
+                self.swap_slots(own, slot)
+            }
+            SlotRequest::Release | SlotRequest::Detach { controller: 0 } => {
+                let before = self.slots.assignments();
+                self.slots.spectate(*session_id);
+                self.apply_slot_changes(before)
+            }
+            SlotRequest::Detach { controller } => {
+                let controller = ControllerId::new(*session_id, controller);
+                let before = self.slots.assignments();
+                self.slots.leave_controller(&controller);
+                self.apply_slot_changes(before)
+            }
+            SlotRequest::HandOver => {
+                let own = self.seated_slot(&ControllerId::primary(*session_id))?;
+                let spectator = self.slots.spectators().first().copied().ok_or_else(|| {
+                    InputError::SlotUnavailable {
+                        slot: own,
+                        reason: "no spectators waiting".to_string(),
+                    }
+                })?;
+                self.hand_over_slot(own, &spectator)
+            }
+        }
+    }
+
+    /// Slot a registered session's controller plays in
+    fn seated_slot(&self, controller: &ControllerId) -> Result<u8> {
+        let session_id = controller.session_id.to_string();
+        if !self.sessions.contains_key(&controller.session_id) {
+            return Err(InputError::SessionNotFound { session_id }.into());
+        }
+        self.slots.slot_of(controller).ok_or_else(|| {
+            match controller.index {
+                0 => InputError::Spectating { session_id },
+                index => InputError::ControllerNotSeated {
+                    session_id,
+                    controller: index,
+                },
+            }
+            .into()
+        })
+    }
+
+    /// Follow the slot table after it changed from `before`: controllers
+    /// leave and join the backend, per-player state moves with its
+    /// controller and every client hears the new layout
+    fn apply_slot_changes(&mut self, before: HashMap<ControllerId, u8>) -> Result<()> {
+        let after = self.slots.assignments();
+
+        let mut carried = HashMap::new();
+        for (controller, slot) in &before {
+            if after.get(controller) != Some(slot) {
+                self.record(|| RecordedKind::Park { player: *slot });
+                carried.insert(*controller, self.processor.take_player(*slot));
+                self.backend.disconnect_controller(*slot)?;
+            }
+        }
+
+        for (controller, slot) in &after {
+            if before.get(controller) == Some(slot) {
+                continue;
+            }
+            self.backend.connect_controller(*slot)?;
+            match carried.remove(controller) {
+                Some(state) => {
+                    let from = before[controller];
+                    self.record(|| RecordedKind::Restore {
+                        player: *slot,
+                        from,
+                    });
+                    self.processor.restore_player(*slot, state);
+                }
+                None => {
+                    // Newly seated: the client's saved calibration belongs
+                    // to its first controller
+                    let calibration = self
+                        .sessions
+                        .get(&controller.session_id)
+                        .filter(|_| controller.index == 0)
+                        .and_then(|session| session.client_id.as_deref())
+                        .and_then(|client_id| self.calibrations.get(client_id).ok().flatten());
+                    self.set_player_calibration(*slot, calibration);
+                }
+            }
+            info!(
+                "Player {} is now controller {} of session {}",
+                slot, controller.index, controller.session_id
+            );
+        }
+
+        self.broadcast_slots();
+        Ok(())
+    }
+
+    /// Tell every subscribed session the current slot layout
+    fn broadcast_slots(&mut self) {
+        let now = Instant::now();
+        let slots = &self.slots;
+        self.slot_subscribers.retain(|session_id, subscriber| {
+            subscriber.send(slots.layout_for(session_id, now)).is_ok()
+        });
+    }
+
+    /// Name of the active input backend
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
     /// Register a new client session
     pub fn register_client(
         &mut self,
@@ -50,44 +601,84 @@ impl ServerInputManager {
 
         let (sender, receiver) = mpsc::unbounded_channel();
 
-        let player_slot = self.assign_player_slot();
+        // Registering again keeps the slot and the client's identity
+        let client_id = self
+            .sessions
+            .get(&session_id)
+            .and_then(|session| session.client_id.clone());
         let session = ClientInputSession {
             id: session_id,
             receiver,
             mapping: self.global_mapping.clone(),
-            player_slot,
             last_input_time: Instant::now(),
             is_active: true,
+            client_id,
+            calibrator: None,
+            sequencers: HashMap::new(),
         };
+        if let Some(previous) = self.sessions.insert(session_id, session) {
+            previous.merge_sequence_stats(&mut self.retired_sequence_stats);
+        }
 
-        self.sessions.insert(session_id, session);
-
-        info!(
-            "Input session registered: {} (Player {})",
-            session_id, player_slot
-        );
+        let before = self.slots.assignments();
+        match self
+            .slots
+            .join(ControllerId::primary(session_id), None, Instant::now())
+        {
+            Some(player_slot) => info!(
+                "Input session registered: {} (Player {})",
+                session_id, player_slot
+            ),
+            None => info!("Input session registered: {} (spectating)", session_id),
+        }
+        self.apply_slot_changes(before)?;
         Ok(sender)
     }
 
     /// Remove a client session
     pub fn unregister_client(&mut self, session_id: &Uuid) -> Result<()> {
-        if let Some(session) = self.sessions.remove(session_id) {
+        let Some(session) = self.sessions.remove(session_id) else {
+            return Ok(());
+        };
+        session.merge_sequence_stats(&mut self.retired_sequence_stats);
+        self.slot_subscribers.remove(session_id);
+        self.overlay_subscribers.remove(session_id);
+        self.applied_inputs.remove(session_id);
+
+        let before = self.slots.assignments();
+        for (controller, player_slot) in self.slots.leave(session_id) {
             info!(
                 "Input session unregistered: {} (Player {})",
-                session_id, session.player_slot
+                session_id, player_slot
             );
-            self.dolphin_adapter
-                .disconnect_controller(session.player_slot)?;
+            match &session.client_id {
+                // Hold the slot in case the client is only reconnecting
+                Some(client_id) => self.slots.reserve(
+                    player_slot,
+                    client_id,
+                    controller,
+                    Instant::now() + RECONNECT_GRACE,
+                )?,
+                // Otherwise the longest-waiting spectator takes over
+                None => {
+                    if let Some(spectator) = self.slots.spectators().first().copied() {
+                        self.slots.hand_over(player_slot, spectator)?;
+                    }
+                }
+            }
         }
-        Ok(())
+        self.apply_slot_changes(before)
     }
 
     /// Process input from all sessions with enhanced error resilience
     pub async fn process_inputs(&mut self) -> Result<()> {
+        self.forward_rumble();
+
         // Collect inputs from all active sessions
-        let mut inputs_to_process = Vec::new();
+        let mut received = Vec::new();
         let mut sessions_to_remove = Vec::new();
 
+        let now = Instant::now();
         for (session_id, session) in self.sessions.iter_mut() {
             // Check for timeouts
             if session.last_input_time.elapsed().as_secs() > 30 {
@@ -97,15 +688,21 @@ impl ServerInputManager {
                 continue;
             }
 
-            // Try to receive input without blocking
+            // Try to receive input without blocking, putting each
+            // controller's numbered packets back in order
+            let mut ready: Vec<_> = session
+                .sequencers
+                .values_mut()
+                .flat_map(|sequencer| sequencer.flush_expired(now))
+                .collect();
             let mut input_count = 0;
             while let Ok(input_packet) = session.receiver.try_recv() {
-                session.last_input_time = Instant::now();
-                inputs_to_process.push((
-                    session.player_slot,
-                    session.mapping.clone(),
-                    input_packet,
-                ));
+                session.last_input_time = now;
+                let sequencer = session
+                    .sequencers
+                    .entry(input_packet.controller_index)
+                    .or_default();
+                ready.extend(sequencer.push(input_packet, now));
 
                 // Prevent excessive input processing in a single frame
                 input_count += 1;
@@ -113,6 +710,21 @@ impl ServerInputManager {
                     break;
                 }
             }
+
+            for input_packet in ready {
+                if let Some(calibrator) = &mut session.calibrator {
+                    if input_packet.controller_index == 0 {
+                        calibrator.observe(&input_packet);
+                    }
+                }
+                let controller = ControllerId::new(*session_id, input_packet.controller_index);
+                received.push((
+                    controller,
+                    session.client_id.clone(),
+                    session.mapping.clone(),
+                    input_packet,
+                ));
+            }
         }
 
         // Remove timed out sessions
@@ -120,17 +732,61 @@ impl ServerInputManager {
             self.unregister_client(&session_id)?;
         }
 
+        // A client's further local controllers take slots as they appear
+        let before = self.slots.assignments();
+        for (controller, client_id, _, _) in &received {
+            if controller.index > 0 && !before.contains_key(controller) {
+                self.slots.join(*controller, client_id.as_deref(), now);
+            }
+        }
+        if self.slots.assignments() != before {
+            self.apply_slot_changes(before)?;
+        }
+
+        // Spectators' and unseated controllers' input goes nowhere
+        let inputs_to_process: Vec<_> = received
+            .into_iter()
+            .filter_map(|(controller, _, mapping, input_packet)| {
+                let player_slot = self.slots.slot_of(&controller)?;
+                Some((controller.session_id, player_slot, mapping, input_packet))
+            })
+            .collect();
+
         // Process all collected inputs with error resilience
         let mut successful_inputs = 0;
         let mut failed_inputs = 0;
-
-        for (player_slot, mapping, input_packet) in inputs_to_process {
+        let mut processed_sequences = Vec::new();
+
+        // While recording, the processor runs on the recording's clock
+        let now = self
+            .recorder
+            .as_ref()
+            .map_or(now, |recorder| recorder.clock(now));
+        for (session_id, player_slot, mapping, input_packet) in inputs_to_process {
+            // Frames are tagged with the first controller's input, which
+            // the client stamps for latency measurements
+            let sequence = input_packet
+                .sequence
+                .filter(|_| input_packet.controller_index == 0);
+            if let Some(recorder) = &mut self.recorder {
+                if let Err(e) =
+                    recorder.record_input(now, session_id, player_slot, &mapping, &input_packet)
+                {
+                    warn!("Input recording stopped: {}", e);
+                    self.recorder = None;
+                }
+            }
             match self
                 .processor
-                .process_input(player_slot, mapping, input_packet)
+                .process_input_at(player_slot, mapping, input_packet, now)
                 .await
             {
-                Ok(_) => successful_inputs += 1,
+                Ok(_) => {
+                    successful_inputs += 1;
+                    if let Some(sequence) = sequence {
+                        processed_sequences.push((session_id, sequence));
+                    }
+                }
                 Err(e) => {
                     failed_inputs += 1;
                     warn!("Failed to process input for player {}: {}", player_slot, e);
@@ -146,66 +802,99 @@ impl ServerInputManager {
         }
 
         // Send processed inputs to Dolphin with batch size limit for performance
-        match self.processor.get_dolphin_commands_batched(20).await {
+        let delivered = match self.processor.get_dolphin_commands_batched(20).await {
             Ok(Some(dolphin_commands)) => {
-                if let Err(e) = self.dolphin_adapter.send_commands(dolphin_commands).await {
-                    warn!("Failed to send commands to Dolphin: {}", e);
+                self.record(|| RecordedKind::Commands {
+                    commands: dolphin_commands.clone(),
+                });
+                match self.backend.send_commands(dolphin_commands).await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!(
+                            "Failed to send commands to {} backend: {}",
+                            self.backend.name(),
+                            e
+                        );
+                        false
+                    }
                 }
             }
-            Ok(None) => {} // No commands to send
+            Ok(None) => true, // Nothing changed, the input is still applied
             Err(e) => {
                 warn!("Failed to get Dolphin commands: {}", e);
+                false
+            }
+        };
+
+        // Frames sent from now on show these inputs
+        if delivered {
+            let applied_at = Instant::now();
+            for (session_id, sequence) in processed_sequences {
+                self.applied_inputs.insert(
+                    session_id,
+                    AppliedInput {
+                        sequence,
+                        applied_at,
+                    },
+                );
             }
         }
 
         Ok(())
     }
 
+    /// Newest numbered input of a session that reached the backend, for
+    /// tagging the video frames that show it
+    pub fn last_applied_input(&self, session_id: &Uuid) -> Option<AppliedInput> {
+        self.applied_inputs.get(session_id).copied()
+    }
+
     /// Update controller mapping for a session
     pub fn update_mapping(&mut self, session_id: &Uuid, mapping: ControllerMapping) -> Result<()> {
         if let Some(session) = self.sessions.get_mut(session_id) {
             session.mapping = mapping;
             debug!("Updated controller mapping for session: {}", session_id);
         }
+        self.broadcast_touch_overlays();
         Ok(())
     }
 
     /// Load game-specific controller profile
     pub fn load_game_profile(&mut self, game_id: &str) -> Result<()> {
         if let Ok(profile) = GameProfile::load_for_game(game_id) {
-            self.global_mapping = profile.controller_mapping;
-            info!("Loaded controller profile for game: {}", game_id);
-
-            // Update all active sessions
-            for session in self.sessions.values_mut() {
-                session.mapping = self.global_mapping.clone();
-            }
+            self.apply_game_profile(&profile);
         }
         Ok(())
     }
 
+    /// Apply an already-loaded game profile to all sessions
+    pub fn apply_game_profile(&mut self, profile: &GameProfile) {
+        self.global_mapping = profile.controller_mapping.clone();
+        self.keyboard_mapping = profile.keyboard_mapping();
+        info!("Loaded controller profile for game: {}", profile.game_id);
+
+        // Update all active sessions
+        for session in self.sessions.values_mut() {
+            session.mapping = self.global_mapping.clone();
+        }
+        self.broadcast_touch_overlays();
+    }
+
     /// Get input statistics
     pub fn get_stats(&self) -> InputStats {
+        let mut sequencing = self.retired_sequence_stats.clone();
+        for session in self.sessions.values() {
+            session.merge_sequence_stats(&mut sequencing);
+        }
+
         InputStats {
             active_sessions: self.sessions.values().filter(|s| s.is_active).count(),
             total_sessions: self.sessions.len(),
             processor_stats: self.processor.get_stats(),
+            rumble: self.rumble_stats.lock().clone(),
+            sequencing,
         }
     }
-
-    fn assign_player_slot(&self) -> u8 {
-        // Find the first available player slot (1-4 for GameCube, 1-8 for Wii)
-        for slot in 1..=4 {
-            if !self
-                .sessions
-                .values()
-                .any(|s| s.player_slot == slot && s.is_active)
-            {
-                return slot;
-            }
-        }
-        1 // Default to player 1 if all slots taken
-    }
 }
 
 /// Input session for a connected client
@@ -214,40 +903,21 @@ struct ClientInputSession {
     id: Uuid,
     receiver: mpsc::UnboundedReceiver<MoonlightInputPacket>,
     mapping: ControllerMapping,
-    player_slot: u8,
     last_input_time: Instant,
     is_active: bool,
+    /// Stable id the client identified itself with, keying its calibration
+    client_id: Option<String>,
+    calibrator: Option<Calibrator>,
+    /// Ordering of each local controller's numbered packets
+    sequencers: HashMap<u8, InputSequencer>,
 }
 
-/// Moonlight input packet from client
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MoonlightInputPacket {
-    pub packet_type: u8,
-    pub button_flags: u16,
-    pub left_trigger: u8,
-    pub right_trigger: u8,
-    pub left_stick_x: i16,
-    pub left_stick_y: i16,
-    pub right_stick_x: i16,
-    pub right_stick_y: i16,
-    pub timestamp: u64,
-
-    // Extended data for Switch-specific features
-    pub gyro_x: Option<f32>,
-    pub gyro_y: Option<f32>,
-    pub gyro_z: Option<f32>,
-    pub accel_x: Option<f32>,
-    pub accel_y: Option<f32>,
-    pub accel_z: Option<f32>,
-    pub touch_points: Option<Vec<TouchPoint>>,
-}
-
-/// Touch point data
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TouchPoint {
-    pub x: u16,
-    pub y: u16,
-    pub pressure: u8,
+impl ClientInputSession {
+    fn merge_sequence_stats(&self, stats: &mut SequenceStats) {
+        for sequencer in self.sequencers.values() {
+            stats.merge(sequencer.stats());
+        }
+    }
 }
 
 /// Input processing statistics
@@ -256,6 +926,9 @@ pub struct InputStats {
     pub active_sessions: usize,
     pub total_sessions: usize,
     pub processor_stats: processor::ProcessorStats,
+    pub rumble: RumbleStats,
+    /// Dropped, late and reordered input across all sessions
+    pub sequencing: SequenceStats,
 }
 
 impl Default for ServerInputManager {
@@ -0,0 +1,405 @@
+//! Motion sensor fusion for pointer aiming
+//!
+//! A complementary filter fuses the gyro with the accelerometer's view of
+//! gravity into an orientation, learning the gyro's zero-rate offset while
+//! the controller rests. [`PointerTracker`] turns orientation relative to a
+//! reference into a smoothed screen position, with a ratchet button and
+//! automatic recentering.
+//!
+//! Units follow libnx: angular velocity in rotations per second and
+//! acceleration in g. Positive `gyro_x` raises pitch and positive `gyro_z`
+//! turns left, matching the tilt read from gravity.
+
+use crate::input::mapping::ControllerMapping;
+use crate::input::MoonlightInputPacket;
+use serde::{Deserialize, Serialize};
+
+/// Pointer travel, in pointer units, per rotation at sensitivity 1.0
+///
+/// The pointer spans -1.0 to 1.0, so a third of a turn crosses the screen.
+pub const POINTER_GAIN: f32 = 6.0;
+
+/// Angular speed, in rotations per second, below which the controller counts
+/// as resting
+const REST_RATE: f32 = 0.01;
+
+/// Allowed deviation of the acceleration magnitude from 1 g while resting
+const REST_ACCEL_TOLERANCE: f32 = 0.05;
+
+/// Accelerometer readings further than this from 1 g are not trusted for tilt
+const GRAVITY_TOLERANCE: f32 = 0.3;
+
+/// Time constant, in seconds, of the gyro offset estimate
+const BIAS_TIME_CONSTANT: f32 = 1.0;
+
+/// Time, in seconds, over which auto-recentering eases the pointer home
+const RECENTER_TIME_CONSTANT: f32 = 0.25;
+
+/// Response curve applied to the pointer offset
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum SensitivityCurve {
+    /// Pointer moves proportionally to rotation
+    #[default]
+    Linear,
+    /// Offsets are raised to `exponent`; above 1.0 gives finer control near
+    /// the center of the screen
+    Power { exponent: f32 },
+}
+
+impl SensitivityCurve {
+    /// Map an offset in -1.0 to 1.0, keeping its sign
+    pub fn apply(self, value: f32) -> f32 {
+        match self {
+            Self::Linear => value,
+            Self::Power { exponent } => value.signum() * value.abs().powf(exponent),
+        }
+    }
+
+    /// Inverse of [`apply`](Self::apply)
+    pub fn invert(self, value: f32) -> f32 {
+        match self {
+            Self::Linear => value,
+            Self::Power { exponent } => value.signum() * value.abs().powf(exponent.recip()),
+        }
+    }
+}
+
+/// Motion aiming settings carried by a [`ControllerMapping`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionSettings {
+    /// Share of the previous pointer position kept per 60 Hz frame, 0.0 to
+    /// just under 1.0
+    pub smoothing: f32,
+    /// Rate, per second, at which gravity corrects gyro drift in pitch
+    pub accel_correction: f32,
+    pub curve: SensitivityCurve,
+    /// Moonlight button flag that freezes the pointer while the controller
+    /// is repositioned; 0 disables the ratchet
+    pub ratchet_flag: u16,
+    /// Seconds at rest before the pointer returns to the center; 0.0 disables
+    pub auto_recenter_secs: f32,
+}
+
+impl Default for MotionSettings {
+    fn default() -> Self {
+        Self {
+            smoothing: 0.3,
+            accel_correction: 2.0,
+            curve: SensitivityCurve::Linear,
+            ratchet_flag: 0x0200, // Right shoulder
+            auto_recenter_secs: 2.0,
+        }
+    }
+}
+
+/// One gyro and accelerometer reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuSample {
+    pub gyro: [f32; 3],
+    pub accel: Option<[f32; 3]>,
+}
+
+impl ImuSample {
+    /// Motion data of an input packet, if it carries a gyro reading
+    pub fn from_packet(input: &MoonlightInputPacket) -> Option<Self> {
+        let gyro = [input.gyro_x?, input.gyro_y?, input.gyro_z?];
+        let accel = match (input.accel_x, input.accel_y, input.accel_z) {
+            (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+            _ => None,
+        };
+        Some(Self { gyro, accel })
+    }
+}
+
+/// Complementary filter estimating orientation, in rotations
+#[derive(Debug, Clone, Default)]
+pub struct OrientationFilter {
+    pitch: f32,
+    roll: f32,
+    yaw: f32,
+    bias: [f32; 3],
+    initialized: bool,
+    resting: bool,
+}
+
+impl OrientationFilter {
+    /// Advance the estimate by `dt` seconds
+    pub fn update(&mut self, sample: &ImuSample, dt: f32, accel_correction: f32) {
+        let gravity = sample.accel.and_then(gravity_tilt);
+
+        if !self.initialized {
+            if let Some((pitch, roll)) = gravity {
+                (self.pitch, self.roll) = (pitch, roll);
+            }
+            self.initialized = true;
+        }
+
+        let rate: [f32; 3] = std::array::from_fn(|axis| sample.gyro[axis] - self.bias[axis]);
+        let speed = rate.iter().map(|r| r * r).sum::<f32>().sqrt();
+        let magnitude = sample.accel.map(|[x, y, z]| (x * x + y * y + z * z).sqrt());
+        self.resting =
+            speed < REST_RATE && magnitude.is_some_and(|m| (m - 1.0).abs() < REST_ACCEL_TOLERANCE);
+
+        // While resting the gyro should read zero, so whatever it reads is offset
+        if self.resting {
+            let k = (dt / BIAS_TIME_CONSTANT).min(1.0);
+            for (bias, gyro) in self.bias.iter_mut().zip(sample.gyro) {
+                *bias += (gyro - *bias) * k;
+            }
+        }
+
+        self.pitch += rate[0] * dt;
+        self.roll += rate[1] * dt;
+        self.yaw += rate[2] * dt;
+
+        // Gravity has no say in yaw, which relies on the offset estimate alone
+        if let Some((pitch, roll)) = gravity {
+            let k = (accel_correction * dt).min(1.0);
+            self.pitch += (pitch - self.pitch) * k;
+            self.roll += (roll - self.roll) * k;
+        }
+    }
+
+    /// Pitch, roll and yaw in rotations
+    pub fn orientation(&self) -> (f32, f32, f32) {
+        (self.pitch, self.roll, self.yaw)
+    }
+
+    /// Whether the last sample looked like the controller was set down
+    pub fn is_resting(&self) -> bool {
+        self.resting
+    }
+
+    /// Estimated gyro zero-rate offset
+    pub fn bias(&self) -> [f32; 3] {
+        self.bias
+    }
+}
+
+/// Pitch and roll, in rotations, from an accelerometer reading of gravity
+fn gravity_tilt([x, y, z]: [f32; 3]) -> Option<(f32, f32)> {
+    let magnitude = (x * x + y * y + z * z).sqrt();
+    if (magnitude - 1.0).abs() > GRAVITY_TOLERANCE {
+        return None;
+    }
+    let turn = std::f32::consts::TAU;
+    Some((y.atan2((x * x + z * z).sqrt()) / turn, x.atan2(z) / turn))
+}
+
+/// Screen pointer driven by fused orientation
+#[derive(Debug, Clone, Default)]
+pub struct PointerTracker {
+    filter: OrientationFilter,
+    /// Yaw and pitch the screen center corresponds to
+    reference: (f32, f32),
+    position: (f32, f32),
+    rest_time: f32,
+}
+
+impl PointerTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fuse a sample and return the pointer, -1.0 to 1.0 with y growing
+    /// downwards
+    pub fn update(
+        &mut self,
+        sample: &ImuSample,
+        dt: f32,
+        mapping: &ControllerMapping,
+        ratchet_held: bool,
+    ) -> (f32, f32) {
+        let settings = &mapping.motion;
+        let previous_aim = self.aim();
+        self.filter.update(sample, dt, settings.accel_correction);
+        let aim = self.aim();
+
+        // Holding the ratchet drags the reference along, freezing the pointer
+        if ratchet_held {
+            self.reference.0 += aim.0 - previous_aim.0;
+            self.reference.1 += aim.1 - previous_aim.1;
+        }
+
+        self.rest_time = if self.filter.is_resting() {
+            self.rest_time + dt
+        } else {
+            0.0
+        };
+        if settings.auto_recenter_secs > 0.0 && self.rest_time >= settings.auto_recenter_secs {
+            let k = (dt / RECENTER_TIME_CONSTANT).min(1.0);
+            self.reference.0 += (aim.0 - self.reference.0) * k;
+            self.reference.1 += (aim.1 - self.reference.1) * k;
+        }
+
+        let scale = scale(mapping);
+        let invert = if mapping.invert_y_axis { -1.0 } else { 1.0 };
+        let mut offset = (
+            -(aim.0 - self.reference.0) * scale,
+            -(aim.1 - self.reference.1) * scale * invert,
+        );
+
+        // Past the edge the reference follows, so turning back moves the
+        // pointer straight away
+        if offset.0.abs() > 1.0 {
+            self.reference.0 = aim.0 + offset.0.signum() / scale;
+            offset.0 = offset.0.signum();
+        }
+        if offset.1.abs() > 1.0 {
+            self.reference.1 = aim.1 + offset.1.signum() * invert / scale;
+            offset.1 = offset.1.signum();
+        }
+
+        let target = (
+            settings.curve.apply(offset.0),
+            settings.curve.apply(offset.1),
+        );
+        let keep = settings.smoothing.clamp(0.0, 0.99).powf(dt * 60.0);
+        self.position.0 = target.0 + (self.position.0 - target.0) * keep;
+        self.position.1 = target.1 + (self.position.1 - target.1) * keep;
+        self.position
+    }
+
+    /// Current pointer position
+    pub fn position(&self) -> (f32, f32) {
+        self.position
+    }
+
+    /// Make the current orientation point at the middle of the screen
+    pub fn recenter(&mut self) {
+        self.reference = self.aim();
+        self.position = (0.0, 0.0);
+    }
+
+    /// Make the current orientation point at `position`
+    pub fn place(&mut self, position: (f32, f32), mapping: &ControllerMapping) {
+        let scale = scale(mapping);
+        let invert = if mapping.invert_y_axis { -1.0 } else { 1.0 };
+        let curve = mapping.motion.curve;
+        let aim = self.aim();
+        self.reference = (
+            aim.0 + curve.invert(position.0) / scale,
+            aim.1 + curve.invert(position.1) * invert / scale,
+        );
+        self.position = position;
+    }
+
+    /// The underlying orientation estimate
+    pub fn filter(&self) -> &OrientationFilter {
+        &self.filter
+    }
+
+    /// Yaw and pitch used for aiming
+    fn aim(&self) -> (f32, f32) {
+        let (pitch, _, yaw) = self.filter.orientation();
+        (yaw, pitch)
+    }
+}
+
+/// Pointer units per rotation
+fn scale(mapping: &ControllerMapping) -> f32 {
+    POINTER_GAIN * mapping.gyro_sensitivity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse a trace of `t_ms,gyro_x,gyro_y,gyro_z,accel_x,accel_y,accel_z`
+    /// rows into samples and the time since the previous row
+    fn trace(csv: &str) -> Vec<(ImuSample, f32)> {
+        let mut previous_ms = None;
+        csv.lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with("t_ms"))
+            .map(|line| {
+                let v: Vec<f32> = line.split(',').map(|f| f.parse().unwrap()).collect();
+                let dt = previous_ms.map_or(0.0, |p| (v[0] - p) / 1000.0);
+                previous_ms = Some(v[0]);
+                let sample = ImuSample {
+                    gyro: [v[1], v[2], v[3]],
+                    accel: Some([v[4], v[5], v[6]]),
+                };
+                (sample, dt)
+            })
+            .collect()
+    }
+
+    fn aiming_mapping() -> ControllerMapping {
+        let mut mapping = ControllerMapping::default_wii_remote();
+        mapping.gyro_sensitivity = 1.0;
+        mapping.motion.smoothing = 0.0;
+        mapping.motion.auto_recenter_secs = 0.0;
+        mapping
+    }
+
+    #[test]
+    fn test_resting_controller_does_not_drift() {
+        let mapping = aiming_mapping();
+        let mut tracker = PointerTracker::new();
+
+        for (sample, dt) in trace(include_str!("testdata/imu_resting.csv")) {
+            tracker.update(&sample, dt, &mapping, false);
+        }
+
+        // Integrating the raw offset for 4 s would move the pointer ~0.1
+        let (x, y) = tracker.position();
+        assert!(x.abs() < 0.05 && y.abs() < 0.05, "drifted to ({x}, {y})");
+        assert!((tracker.filter().bias()[2] - 0.004).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_sweep_moves_pointer_and_returns() {
+        let mapping = aiming_mapping();
+        let mut tracker = PointerTracker::new();
+        let samples = trace(include_str!("testdata/imu_sweep.csv"));
+
+        // A twelfth of a turn at 6 pointer units per turn
+        for (sample, dt) in &samples[..150] {
+            tracker.update(sample, *dt, &mapping, false);
+        }
+        assert!((tracker.position().0 - 0.5).abs() < 0.05);
+
+        for (sample, dt) in &samples[150..] {
+            tracker.update(sample, *dt, &mapping, false);
+        }
+        assert!(tracker.position().0.abs() < 0.05);
+        assert!(tracker.position().1.abs() < 0.05);
+    }
+
+    #[test]
+    fn test_ratchet_holds_pointer() {
+        let mapping = aiming_mapping();
+        let mut tracker = PointerTracker::new();
+        let samples = trace(include_str!("testdata/imu_sweep.csv"));
+
+        for (sample, dt) in &samples[..150] {
+            tracker.update(sample, *dt, &mapping, false);
+        }
+        // Turning back with the ratchet held leaves the pointer where it was
+        for (sample, dt) in &samples[150..] {
+            tracker.update(sample, *dt, &mapping, true);
+        }
+        assert!((tracker.position().0 - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_curve_and_auto_recenter() {
+        let curve = SensitivityCurve::Power { exponent: 2.0 };
+        assert_eq!(curve.apply(-0.5), -0.25);
+        assert!((curve.invert(curve.apply(0.3)) - 0.3).abs() < 1e-6);
+
+        let mut mapping = aiming_mapping();
+        mapping.motion.auto_recenter_secs = 1.0;
+        let mut tracker = PointerTracker::new();
+
+        // Turn away, then set the controller down
+        let sweep = trace(include_str!("testdata/imu_sweep.csv"));
+        let resting = trace(include_str!("testdata/imu_resting.csv"));
+        for (sample, dt) in sweep[..120].iter().chain(&resting[1..]) {
+            tracker.update(sample, *dt, &mapping, false);
+        }
+        assert!(tracker.position().0.abs() < 0.05);
+    }
+}
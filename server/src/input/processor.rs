@@ -4,10 +4,17 @@
 //!
 //! Converts Moonlight input packets to Dolphin-compatible commands
 
+use crate::emulator::control::EmulationCommand;
 use crate::error::Result;
-use crate::input::mapping::ControllerMapping;
+use crate::input::bindings::BindingState;
+use crate::input::keyboard::{KeyboardMapping, KeyboardState};
+use crate::input::mapping::{CalibrationData, ConsoleType, ControllerMapping};
+use crate::input::motion::{ImuSample, PointerTracker};
+use crate::input::touch::TouchState;
+use crate::input::wiimote::{WiiButton, WiiExtension, WiiRemoteState};
 use crate::input::{MoonlightInputPacket, TouchPoint};
-use std::collections::VecDeque;
+use crate::streaming::moonlight::InputEvent;
+use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
 use tracing::{debug, warn};
 
@@ -16,8 +23,14 @@ use tracing::{debug, warn};
 pub struct InputProcessor {
     command_buffer: VecDeque<DolphinCommand>,
     stats: ProcessorStats,
+    /// Time of the latest processed input, the processor's clock
     last_process_time: Instant,
-    deadzone_threshold: f32,
+    wii_remotes: HashMap<u8, (WiiRemoteState, Instant)>,
+    gyro_pointers: HashMap<u8, (PointerTracker, Instant)>,
+    bindings: HashMap<u8, BindingState>,
+    calibrations: HashMap<u8, CalibrationData>,
+    keyboards: HashMap<u8, KeyboardState>,
+    touches: HashMap<u8, TouchState>,
 }
 
 impl InputProcessor {
@@ -27,7 +40,12 @@ impl InputProcessor {
             command_buffer: VecDeque::with_capacity(1000),
             stats: ProcessorStats::default(),
             last_process_time: Instant::now(),
-            deadzone_threshold: 0.1, // 10% deadzone
+            wii_remotes: HashMap::new(),
+            gyro_pointers: HashMap::new(),
+            bindings: HashMap::new(),
+            calibrations: HashMap::new(),
+            keyboards: HashMap::new(),
+            touches: HashMap::new(),
         })
     }
 
@@ -37,7 +55,12 @@ impl InputProcessor {
             command_buffer: VecDeque::with_capacity(capacity),
             stats: ProcessorStats::default(),
             last_process_time: Instant::now(),
-            deadzone_threshold: 0.1,
+            wii_remotes: HashMap::new(),
+            gyro_pointers: HashMap::new(),
+            bindings: HashMap::new(),
+            calibrations: HashMap::new(),
+            keyboards: HashMap::new(),
+            touches: HashMap::new(),
         })
     }
 
@@ -47,16 +70,183 @@ impl InputProcessor {
         player_slot: u8,
         mapping: ControllerMapping,
         input_packet: MoonlightInputPacket,
+    ) -> Result<()> {
+        self.process_input_at(player_slot, mapping, input_packet, Instant::now())
+            .await
+    }
+
+    /// Process a single input packet as if it arrived at `now`
+    ///
+    /// Pointer smoothing, turbo and macros only see time through `now`, so
+    /// the same packets at the same times always give the same commands.
+    pub async fn process_input_at(
+        &mut self,
+        player_slot: u8,
+        mapping: ControllerMapping,
+        mut input_packet: MoonlightInputPacket,
+        now: Instant,
     ) -> Result<()> {
         let start_time = Instant::now();
+        self.last_process_time = now;
+
+        // Virtual controls become the inputs they stand in for
+        self.touches
+            .entry(player_slot)
+            .or_default()
+            .apply(&mapping.touch, &mut input_packet);
 
         // Update statistics
         self.stats.packets_processed += 1;
 
         // Convert Moonlight input to Dolphin commands
-        let commands = self.convert_to_dolphin_commands(player_slot, mapping, input_packet)?;
+        let mut commands =
+            self.convert_to_dolphin_commands(player_slot, mapping, input_packet, now)?;
+        self.hold_keyboard_buttons(player_slot, &mut commands);
+
+        self.buffer_commands(commands);
+
+        // Update processing time statistics
+        let processing_time = start_time.elapsed();
+        self.stats.total_processing_time += processing_time;
+        self.stats.average_processing_time =
+            self.stats.total_processing_time / self.stats.packets_processed as u32;
+
+        Ok(())
+    }
+
+    /// Process a keyboard or mouse event for a player
+    ///
+    /// Returns the emulation commands its hotkeys triggered; pad commands
+    /// are buffered like those of controller input.
+    pub fn process_event(
+        &mut self,
+        player_slot: u8,
+        console_type: ConsoleType,
+        mapping: &KeyboardMapping,
+        event: &InputEvent,
+    ) -> Vec<EmulationCommand> {
+        self.stats.events_processed += 1;
+        let output = self.keyboards.entry(player_slot).or_default().handle(
+            player_slot,
+            console_type,
+            mapping,
+            event,
+        );
+        self.buffer_commands(output.commands);
+        output.emulation
+    }
+
+    /// Attach a different extension to a player's Wii Remote
+    pub fn set_wii_extension(&mut self, player_slot: u8, extension: WiiExtension) {
+        let now = self.last_process_time;
+        let (remote, _) = self
+            .wii_remotes
+            .entry(player_slot)
+            .or_insert_with(|| (WiiRemoteState::default(), now));
+        remote.set_extension(extension);
+
+        debug!(
+            "Player {} Wii Remote extension: {:?}",
+            player_slot, extension
+        );
+        self.buffer_commands(vec![DolphinCommand::WiiExtensionChange {
+            player: player_slot,
+            extension,
+        }]);
+    }
+
+    /// Use a controller's measured ranges for a player, or nominal ones
+    pub fn set_calibration(&mut self, player_slot: u8, calibration: Option<CalibrationData>) {
+        match calibration {
+            Some(calibration) => {
+                self.calibrations.insert(player_slot, calibration);
+            }
+            None => {
+                self.calibrations.remove(&player_slot);
+            }
+        }
+    }
+
+    /// Measured ranges in use for a player, if any
+    pub fn player_calibration(&self, player_slot: u8) -> Option<&CalibrationData> {
+        self.calibrations.get(&player_slot)
+    }
+
+    /// Remove everything kept for a player slot, to follow its controller
+    /// to another slot
+    pub fn take_player(&mut self, player_slot: u8) -> PlayerState {
+        PlayerState {
+            wii_remote: self.wii_remotes.remove(&player_slot),
+            gyro_pointer: self.gyro_pointers.remove(&player_slot),
+            bindings: self.bindings.remove(&player_slot),
+            calibration: self.calibrations.remove(&player_slot),
+            keyboard: self.keyboards.remove(&player_slot),
+            touch: self.touches.remove(&player_slot),
+        }
+    }
+
+    /// Install state taken with [`take_player`](Self::take_player) in a slot
+    pub fn restore_player(&mut self, player_slot: u8, state: PlayerState) {
+        if let Some(remote) = state.wii_remote {
+            // The adapter only knows extensions per slot, so re-announce it
+            let extension = remote.0.extension();
+            self.wii_remotes.insert(player_slot, remote);
+            if extension != WiiExtension::None {
+                self.buffer_commands(vec![DolphinCommand::WiiExtensionChange {
+                    player: player_slot,
+                    extension,
+                }]);
+            }
+        }
+        if let Some(pointer) = state.gyro_pointer {
+            self.gyro_pointers.insert(player_slot, pointer);
+        }
+        if let Some(bindings) = state.bindings {
+            self.bindings.insert(player_slot, bindings);
+        }
+        if let Some(keyboard) = state.keyboard {
+            self.keyboards.insert(player_slot, keyboard);
+        }
+        if let Some(touch) = state.touch {
+            self.touches.insert(player_slot, touch);
+        }
+        self.set_calibration(player_slot, state.calibration);
+    }
 
-        // Buffer commands for batch processing
+    /// Keep buttons the player holds on the keyboard pressed, since
+    /// controller packets carry every button's state and would release them
+    fn hold_keyboard_buttons(&self, player_slot: u8, commands: &mut [DolphinCommand]) {
+        let Some(keyboard) = self.keyboards.get(&player_slot) else {
+            return;
+        };
+        let held = |button| keyboard.held_buttons().contains(&button);
+        for command in commands {
+            match command {
+                DolphinCommand::ButtonPress {
+                    button, pressed, ..
+                } => *pressed |= held(*button),
+                DolphinCommand::DPadInput {
+                    up,
+                    down,
+                    left,
+                    right,
+                    ..
+                } => {
+                    *up |= held(DolphinButton::Up);
+                    *down |= held(DolphinButton::Down);
+                    *left |= held(DolphinButton::Left);
+                    *right |= held(DolphinButton::Right);
+                }
+                DolphinCommand::WiiButtonPress {
+                    button, pressed, ..
+                } => *pressed |= keyboard.held_wii_buttons().contains(button),
+                _ => {}
+            }
+        }
+    }
+
+    /// Buffer commands for batch processing
+    fn buffer_commands(&mut self, commands: Vec<DolphinCommand>) {
         for command in commands {
             if self.command_buffer.len() >= self.command_buffer.capacity() {
                 // Drop oldest command if buffer is full
@@ -66,14 +256,6 @@ impl InputProcessor {
             }
             self.command_buffer.push_back(command);
         }
-
-        // Update processing time statistics
-        let processing_time = start_time.elapsed();
-        self.stats.total_processing_time += processing_time;
-        self.stats.average_processing_time =
-            self.stats.total_processing_time / self.stats.packets_processed as u32;
-
-        Ok(())
     }
 
     /// Get buffered commands for Dolphin
@@ -126,15 +308,20 @@ impl InputProcessor {
     }
 
     fn convert_to_dolphin_commands(
-        &self,
+        &mut self,
         player_slot: u8,
         mapping: ControllerMapping,
         input: MoonlightInputPacket,
+        now: Instant,
     ) -> Result<Vec<DolphinCommand>> {
+        if mapping.console_type == ConsoleType::Wii {
+            return Ok(self.convert_wii_input(player_slot, &mapping, &input, now));
+        }
+
         let mut commands = Vec::new();
 
         // Convert button inputs
-        commands.extend(self.convert_buttons(player_slot, &mapping, input.button_flags)?);
+        commands.extend(self.convert_buttons(player_slot, &mapping, &input, now)?);
 
         // Convert analog inputs
         commands.extend(self.convert_analog_inputs(player_slot, &mapping, &input)?);
@@ -143,8 +330,8 @@ impl InputProcessor {
         commands.extend(self.convert_triggers(player_slot, &mapping, &input)?);
 
         // Handle special Switch features
-        if let (Some(gx), Some(gy), Some(gz)) = (input.gyro_x, input.gyro_y, input.gyro_z) {
-            commands.extend(self.convert_gyro_input(player_slot, &mapping, gx, gy, gz)?);
+        if let Some(sample) = ImuSample::from_packet(&input) {
+            commands.extend(self.convert_gyro_input(player_slot, &mapping, &input, sample, now)?);
         }
 
         if let Some(touch_points) = input.touch_points {
@@ -154,47 +341,62 @@ impl InputProcessor {
         Ok(commands)
     }
 
+    fn convert_wii_input(
+        &mut self,
+        player_slot: u8,
+        mapping: &ControllerMapping,
+        input: &MoonlightInputPacket,
+        now: Instant,
+    ) -> Vec<DolphinCommand> {
+        let (remote, last_update) = self
+            .wii_remotes
+            .entry(player_slot)
+            .or_insert_with(|| (WiiRemoteState::default(), now));
+
+        // Cap the step so a stalled connection doesn't fling the pointer
+        let dt = now.duration_since(*last_update).as_secs_f32().min(0.1);
+        *last_update = now;
+
+        remote.update(player_slot, mapping, input, dt)
+    }
+
     fn convert_buttons(
-        &self,
+        &mut self,
         player_slot: u8,
         mapping: &ControllerMapping,
-        button_flags: u16,
+        input: &MoonlightInputPacket,
+        now: Instant,
     ) -> Result<Vec<DolphinCommand>> {
-        let mut commands = Vec::new();
-
-        // Moonlight button flags to GameCube buttons mapping
-        let button_mappings = [
-            (0x1000, &mapping.a_button),     // A
-            (0x2000, &mapping.b_button),     // B
-            (0x4000, &mapping.x_button),     // X
-            (0x8000, &mapping.y_button),     // Y
-            (0x0100, &mapping.l_button),     // L shoulder
-            (0x0200, &mapping.r_button),     // R shoulder
-            (0x0020, &mapping.z_button),     // Back (mapped to Z)
-            (0x0010, &mapping.start_button), // Start
-        ];
-
-        for (flag, dolphin_button) in button_mappings {
-            let is_pressed = (button_flags & flag) != 0;
-            commands.push(DolphinCommand::ButtonPress {
-                player: player_slot,
-                button: *dolphin_button,
-                pressed: is_pressed,
-            });
-        }
-
-        // D-Pad mapping
-        let dpad_up = (button_flags & 0x0001) != 0;
-        let dpad_down = (button_flags & 0x0002) != 0;
-        let dpad_left = (button_flags & 0x0004) != 0;
-        let dpad_right = (button_flags & 0x0008) != 0;
+        let pressed =
+            self.bindings
+                .entry(player_slot)
+                .or_default()
+                .resolve(&mapping.bindings, input, now);
+
+        let mut commands: Vec<DolphinCommand> = [
+            DolphinButton::A,
+            DolphinButton::B,
+            DolphinButton::X,
+            DolphinButton::Y,
+            DolphinButton::Z,
+            DolphinButton::L,
+            DolphinButton::R,
+            DolphinButton::Start,
+        ]
+        .into_iter()
+        .map(|button| DolphinCommand::ButtonPress {
+            player: player_slot,
+            button,
+            pressed: pressed.contains(&button),
+        })
+        .collect();
 
         commands.push(DolphinCommand::DPadInput {
             player: player_slot,
-            up: dpad_up,
-            down: dpad_down,
-            left: dpad_left,
-            right: dpad_right,
+            up: pressed.contains(&DolphinButton::Up),
+            down: pressed.contains(&DolphinButton::Down),
+            left: pressed.contains(&DolphinButton::Left),
+            right: pressed.contains(&DolphinButton::Right),
         });
 
         Ok(commands)
@@ -203,14 +405,17 @@ impl InputProcessor {
     fn convert_analog_inputs(
         &self,
         player_slot: u8,
-        _mapping: &ControllerMapping,
+        mapping: &ControllerMapping,
         input: &MoonlightInputPacket,
     ) -> Result<Vec<DolphinCommand>> {
         let mut commands = Vec::new();
+        let calibration = self.calibration(player_slot);
 
         // Convert left stick (GameCube main analog stick)
-        let left_x = self.apply_deadzone(input.left_stick_x as f32 / 32767.0);
-        let left_y = self.apply_deadzone(input.left_stick_y as f32 / 32767.0);
+        let (left_x, left_y) = calibration
+            .left_stick
+            .normalize(input.left_stick_x, input.left_stick_y);
+        let (left_x, left_y) = mapping.analog.main_stick.apply(left_x, left_y);
 
         commands.push(DolphinCommand::AnalogInput {
             player: player_slot,
@@ -220,8 +425,10 @@ impl InputProcessor {
         });
 
         // Convert right stick (GameCube C-stick)
-        let right_x = self.apply_deadzone(input.right_stick_x as f32 / 32767.0);
-        let right_y = self.apply_deadzone(input.right_stick_y as f32 / 32767.0);
+        let (right_x, right_y) = calibration
+            .right_stick
+            .normalize(input.right_stick_x, input.right_stick_y);
+        let (right_x, right_y) = mapping.analog.c_stick.apply(right_x, right_y);
 
         commands.push(DolphinCommand::AnalogInput {
             player: player_slot,
@@ -236,14 +443,27 @@ impl InputProcessor {
     fn convert_triggers(
         &self,
         player_slot: u8,
-        _mapping: &ControllerMapping,
+        mapping: &ControllerMapping,
         input: &MoonlightInputPacket,
     ) -> Result<Vec<DolphinCommand>> {
         let mut commands = Vec::new();
+        let calibration = self.calibration(player_slot);
 
         // GameCube triggers are analog (0.0 to 1.0)
-        let left_trigger = input.left_trigger as f32 / 255.0;
-        let right_trigger = input.right_trigger as f32 / 255.0;
+        let left_trigger = mapping
+            .analog
+            .triggers
+            .apply(CalibrationData::normalize_trigger(
+                input.left_trigger,
+                calibration.left_trigger_max,
+            ));
+        let right_trigger = mapping
+            .analog
+            .triggers
+            .apply(CalibrationData::normalize_trigger(
+                input.right_trigger,
+                calibration.right_trigger_max,
+            ));
 
         commands.push(DolphinCommand::TriggerInput {
             player: player_slot,
@@ -254,22 +474,37 @@ impl InputProcessor {
         Ok(commands)
     }
 
+    fn calibration(&self, player_slot: u8) -> CalibrationData {
+        self.calibrations
+            .get(&player_slot)
+            .cloned()
+            .unwrap_or_default()
+    }
+
     fn convert_gyro_input(
-        &self,
+        &mut self,
         player_slot: u8,
         mapping: &ControllerMapping,
-        gyro_x: f32,
-        gyro_y: f32,
-        _gyro_z: f32,
+        input: &MoonlightInputPacket,
+        sample: ImuSample,
+        now: Instant,
     ) -> Result<Vec<DolphinCommand>> {
         let mut commands = Vec::new();
 
         // For Wii games, gyro can be mapped to Wii Remote pointer
         if mapping.enable_gyro_pointer {
-            // Convert gyro angular velocity to pointer position
-            // This is a simplified conversion - real implementation would need calibration
-            let pointer_x = (gyro_y * 0.1).clamp(-1.0, 1.0);
-            let pointer_y = (gyro_x * 0.1).clamp(-1.0, 1.0);
+            let (tracker, last_update) = self
+                .gyro_pointers
+                .entry(player_slot)
+                .or_insert_with(|| (PointerTracker::new(), now));
+
+            // Cap the step so a stalled connection doesn't fling the pointer
+            let dt = now.duration_since(*last_update).as_secs_f32().min(0.1);
+            *last_update = now;
+
+            let ratchet = mapping.motion.ratchet_flag;
+            let ratchet_held = ratchet != 0 && input.button_flags & ratchet != 0;
+            let (pointer_x, pointer_y) = tracker.update(&sample, dt, mapping, ratchet_held);
 
             commands.push(DolphinCommand::WiiPointerInput {
                 player: player_slot,
@@ -290,7 +525,7 @@ impl InputProcessor {
     ) -> Result<Vec<DolphinCommand>> {
         let mut commands = Vec::new();
 
-        if mapping.enable_touch_pointer && !touch_points.is_empty() {
+        if mapping.touch.moves_pointer() && !touch_points.is_empty() {
             // Use first touch point for pointer control
             let touch = &touch_points[0];
 
@@ -318,22 +553,21 @@ impl InputProcessor {
 
         Ok(commands)
     }
+}
 
-    fn apply_deadzone(&self, value: f32) -> f32 {
-        let abs_value = value.abs();
-        if abs_value < self.deadzone_threshold {
-            0.0
-        } else {
-            // Scale the remaining range to 0.0-1.0
-            let sign = value.signum();
-            let scaled = (abs_value - self.deadzone_threshold) / (1.0 - self.deadzone_threshold);
-            sign * scaled.min(1.0)
-        }
-    }
+/// Per-player processing state, moved along when a controller changes slot
+#[derive(Debug, Default)]
+pub struct PlayerState {
+    wii_remote: Option<(WiiRemoteState, Instant)>,
+    gyro_pointer: Option<(PointerTracker, Instant)>,
+    bindings: Option<BindingState>,
+    calibration: Option<CalibrationData>,
+    keyboard: Option<KeyboardState>,
+    touch: Option<TouchState>,
 }
 
 /// Commands that can be sent to Dolphin emulator
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum DolphinCommand {
     ButtonPress {
         player: u8,
@@ -364,10 +598,36 @@ pub enum DolphinCommand {
         y: f32,
         z: f32,
     },
+    WiiButtonPress {
+        player: u8,
+        button: WiiButton,
+        pressed: bool,
+    },
+    WiiMotionInput {
+        player: u8,
+        pitch: f32,
+        roll: f32,
+        shake: bool,
+    },
+    WiiExtensionStick {
+        player: u8,
+        stick: AnalogStick,
+        x: f32,
+        y: f32,
+    },
+    WiiExtensionTrigger {
+        player: u8,
+        left: f32,
+        right: f32,
+    },
+    WiiExtensionChange {
+        player: u8,
+        extension: WiiExtension,
+    },
 }
 
 /// GameCube/Wii controller buttons
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum DolphinButton {
     A,
     B,
@@ -384,7 +644,7 @@ pub enum DolphinButton {
 }
 
 /// Analog stick types
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum AnalogStick {
     Main,   // Left stick (GameCube main analog)
     CStick, // Right stick (GameCube C-stick)
@@ -394,6 +654,8 @@ pub enum AnalogStick {
 #[derive(Debug, Clone, Default)]
 pub struct ProcessorStats {
     pub packets_processed: u64,
+    /// Keyboard and mouse events
+    pub events_processed: u64,
     pub commands_sent: u64,
     pub commands_dropped: u64,
     pub total_processing_time: Duration,
@@ -0,0 +1,470 @@
+//! Input recording and deterministic replay
+//!
+//! An [`InputRecorder`] attached to the
+//! [`ServerInputManager`](super::ServerInputManager) writes every input
+//! packet it processes, the per-player state changes around them and the
+//! [`DolphinCommand`]s that came out, as gzip-compressed JSON lines.
+//! [`InputRecording::replay`] feeds a recording back through a fresh
+//! [`InputProcessor`] on the recorded clock, so two replays of one file give
+//! identical commands and [`diff_commands`] pinpoints where a mapping change
+//! made them diverge.
+
+use super::keyboard::KeyboardMapping;
+use super::mapping::{CalibrationData, ConsoleType, ControllerMapping};
+use super::processor::{DolphinCommand, InputProcessor, PlayerState};
+use super::wiimote::WiiExtension;
+use super::MoonlightInputPacket;
+use crate::error::{InputError, Result};
+use crate::streaming::moonlight::InputEvent;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Format version written at the start of every recording
+pub const RECORDING_VERSION: u32 = 1;
+
+/// One line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Microseconds since recording started
+    pub at_us: u64,
+    #[serde(flatten)]
+    pub kind: RecordedKind,
+}
+
+/// What happened at a point in a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RecordedKind {
+    /// First line of every recording
+    Header {
+        version: u32,
+    },
+    /// Mapping used for a player's input from here on
+    Mapping {
+        player: u8,
+        mapping: Box<ControllerMapping>,
+    },
+    /// A packet processed for a player
+    Input {
+        session_id: Uuid,
+        player: u8,
+        packet: MoonlightInputPacket,
+    },
+    /// Keyboard and mouse bindings used from here on
+    KeyboardMapping {
+        mapping: Box<KeyboardMapping>,
+    },
+    /// A keyboard or mouse event processed for a player
+    Keyboard {
+        session_id: Uuid,
+        player: u8,
+        console_type: ConsoleType,
+        input_event: InputEvent,
+    },
+    Calibration {
+        player: u8,
+        calibration: Option<CalibrationData>,
+    },
+    WiiExtension {
+        player: u8,
+        extension: WiiExtension,
+    },
+    /// A player's state was taken out of its slot to follow its controller
+    Park {
+        player: u8,
+    },
+    /// State parked from slot `from` was installed in `player`
+    Restore {
+        player: u8,
+        from: u8,
+    },
+    /// Commands handed to the input backend
+    Commands {
+        commands: Vec<DolphinCommand>,
+    },
+}
+
+/// Writes a recording as it happens
+pub struct InputRecorder {
+    writer: GzEncoder<Box<dyn Write + Send + Sync>>,
+    started: Instant,
+    /// Serialized mapping last recorded per player
+    mappings: HashMap<u8, String>,
+    /// Serialized keyboard mapping last recorded
+    keyboard_mapping: Option<String>,
+}
+
+impl InputRecorder {
+    /// Start a recording file, replacing any existing one
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Self::new(Box::new(file))
+    }
+
+    /// Start a recording on any writer
+    pub fn new(writer: Box<dyn Write + Send + Sync>) -> Result<Self> {
+        let mut recorder = Self {
+            writer: GzEncoder::new(writer, Compression::default()),
+            started: Instant::now(),
+            mappings: HashMap::new(),
+            keyboard_mapping: None,
+        };
+        recorder.record(
+            recorder.started,
+            RecordedKind::Header {
+                version: RECORDING_VERSION,
+            },
+        )?;
+        Ok(recorder)
+    }
+
+    /// `now` rounded to the recording's microsecond clock
+    ///
+    /// Processing at the rounded time keeps live output identical to what a
+    /// replay computes from the stored timestamps.
+    pub fn clock(&self, now: Instant) -> Instant {
+        self.started + Duration::from_micros(self.at_us(now))
+    }
+
+    /// Append one event
+    pub fn record(&mut self, now: Instant, kind: RecordedKind) -> Result<()> {
+        let event = RecordedEvent {
+            at_us: self.at_us(now),
+            kind,
+        };
+        serde_json::to_writer(&mut self.writer, &event)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Append a processed packet, preceded by its mapping if that changed
+    pub fn record_input(
+        &mut self,
+        now: Instant,
+        session_id: Uuid,
+        player: u8,
+        mapping: &ControllerMapping,
+        packet: &MoonlightInputPacket,
+    ) -> Result<()> {
+        let serialized = serde_json::to_string(mapping)?;
+        if self.mappings.get(&player) != Some(&serialized) {
+            self.mappings.insert(player, serialized);
+            self.record(
+                now,
+                RecordedKind::Mapping {
+                    player,
+                    mapping: Box::new(mapping.clone()),
+                },
+            )?;
+        }
+        self.record(
+            now,
+            RecordedKind::Input {
+                session_id,
+                player,
+                packet: packet.clone(),
+            },
+        )
+    }
+
+    /// Append a processed keyboard or mouse event, preceded by the keyboard
+    /// mapping if that changed
+    pub fn record_keyboard(
+        &mut self,
+        now: Instant,
+        session_id: Uuid,
+        player: u8,
+        console_type: ConsoleType,
+        mapping: &KeyboardMapping,
+        event: &InputEvent,
+    ) -> Result<()> {
+        let serialized = serde_json::to_string(mapping)?;
+        if self.keyboard_mapping.as_ref() != Some(&serialized) {
+            self.keyboard_mapping = Some(serialized);
+            self.record(
+                now,
+                RecordedKind::KeyboardMapping {
+                    mapping: Box::new(mapping.clone()),
+                },
+            )?;
+        }
+        self.record(
+            now,
+            RecordedKind::Keyboard {
+                session_id,
+                player,
+                console_type,
+                input_event: event.clone(),
+            },
+        )
+    }
+
+    /// Flush and close the recording
+    pub fn finish(self) -> Result<()> {
+        self.writer.finish()?.flush()?;
+        Ok(())
+    }
+
+    fn at_us(&self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.started).as_micros();
+        u64::try_from(elapsed).unwrap_or(u64::MAX)
+    }
+}
+
+/// How fast a replay feeds its events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    /// Keep the recorded gaps between events
+    Original,
+    /// No waiting; the recorded clock still drives the processor
+    Unpaced,
+}
+
+/// A recording read back from disk
+#[derive(Debug, Clone, Default)]
+pub struct InputRecording {
+    pub events: Vec<RecordedEvent>,
+}
+
+impl InputRecording {
+    /// Read a recording file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Read a gzip-compressed recording
+    pub fn from_reader(reader: impl Read) -> Result<Self> {
+        let mut events = Vec::new();
+        for line in BufReader::new(GzDecoder::new(reader)).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                events.push(serde_json::from_str::<RecordedEvent>(&line)?);
+            }
+        }
+
+        match events.first().map(|event| &event.kind) {
+            Some(RecordedKind::Header { version }) if *version == RECORDING_VERSION => {}
+            Some(RecordedKind::Header { version }) => {
+                return Err(invalid(format!("unsupported version {version}")))
+            }
+            _ => return Err(invalid("missing header".to_string())),
+        }
+        Ok(Self { events })
+    }
+
+    /// Number of recorded input packets
+    pub fn input_count(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|event| matches!(event.kind, RecordedKind::Input { .. }))
+            .count()
+    }
+
+    /// Commands the live run handed to its backend, in order
+    pub fn recorded_commands(&self) -> Vec<DolphinCommand> {
+        self.events
+            .iter()
+            .filter_map(|event| match &event.kind {
+                RecordedKind::Commands { commands } => Some(commands.iter().cloned()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Run the recorded input through a fresh processor and collect the
+    /// commands it produces
+    pub async fn replay(&self, speed: ReplaySpeed) -> Result<Vec<DolphinCommand>> {
+        let mut processor = InputProcessor::new()?;
+        let mut mappings: HashMap<u8, ControllerMapping> = HashMap::new();
+        let mut keyboard_mapping = KeyboardMapping::default();
+        let mut parked: HashMap<u8, PlayerState> = HashMap::new();
+        let mut output = Vec::new();
+        let started = Instant::now();
+
+        for event in &self.events {
+            let at = started + Duration::from_micros(event.at_us);
+            if speed == ReplaySpeed::Original {
+                tokio::time::sleep_until(at.into()).await;
+            }
+
+            match &event.kind {
+                RecordedKind::Header { .. } | RecordedKind::Commands { .. } => {}
+                RecordedKind::Mapping { player, mapping } => {
+                    mappings.insert(*player, (**mapping).clone());
+                }
+                RecordedKind::Input { player, packet, .. } => {
+                    let mapping = mappings
+                        .get(player)
+                        .cloned()
+                        .unwrap_or_else(ControllerMapping::default_gamecube);
+                    processor
+                        .process_input_at(*player, mapping, packet.clone(), at)
+                        .await?;
+                }
+                RecordedKind::KeyboardMapping { mapping } => {
+                    keyboard_mapping = (**mapping).clone();
+                }
+                RecordedKind::Keyboard {
+                    player,
+                    console_type,
+                    input_event,
+                    ..
+                } => {
+                    processor.process_event(*player, *console_type, &keyboard_mapping, input_event);
+                }
+                RecordedKind::Calibration {
+                    player,
+                    calibration,
+                } => processor.set_calibration(*player, calibration.clone()),
+                RecordedKind::WiiExtension { player, extension } => {
+                    processor.set_wii_extension(*player, *extension)
+                }
+                RecordedKind::Park { player } => {
+                    parked.insert(*player, processor.take_player(*player));
+                }
+                RecordedKind::Restore { player, from } => {
+                    if let Some(state) = parked.remove(from) {
+                        processor.restore_player(*player, state);
+                    }
+                }
+            }
+
+            if let Some(commands) = processor.get_dolphin_commands().await? {
+                output.extend(commands);
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// First place two command streams differ
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandDiff {
+    pub index: usize,
+    /// `None` where that stream had already ended
+    pub expected: Option<DolphinCommand>,
+    pub actual: Option<DolphinCommand>,
+}
+
+/// Compare the commands of two runs, `None` if they match
+pub fn diff_commands(
+    expected: &[DolphinCommand],
+    actual: &[DolphinCommand],
+) -> Option<CommandDiff> {
+    let index = expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .or_else(|| (expected.len() != actual.len()).then(|| expected.len().min(actual.len())))?;
+
+    Some(CommandDiff {
+        index,
+        expected: expected.get(index).cloned(),
+        actual: actual.get(index).cloned(),
+    })
+}
+
+fn invalid(reason: String) -> crate::error::DpstreamError {
+    InputError::InvalidRecording { reason }.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::bindings::{BindingMode, ButtonBinding, SwitchButton};
+    use crate::input::processor::DolphinButton;
+    use std::sync::{Arc, Mutex};
+
+    /// Writer whose bytes stay readable after the recorder is done
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(button_flags: u16) -> MoonlightInputPacket {
+        MoonlightInputPacket {
+            button_flags,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_reproduces_recorded_commands() {
+        let buffer = SharedBuffer::default();
+        let mut recorder = InputRecorder::new(Box::new(buffer.clone())).unwrap();
+        let mut processor = InputProcessor::new().unwrap();
+
+        // Turbo depends on time, which replay takes from the recording
+        let mut mapping = ControllerMapping::default_gamecube();
+        mapping.bindings.push(
+            ButtonBinding::press(SwitchButton::X, DolphinButton::A)
+                .with_mode(BindingMode::Turbo { rate_hz: 20.0 }),
+        );
+
+        let session_id = Uuid::new_v4();
+        for step in 0..12u64 {
+            let now = recorder.clock(Instant::now() + Duration::from_millis(step * 7));
+            let packet = packet(if step < 9 { 0x4000 } else { 0 });
+            recorder
+                .record_input(now, session_id, 1, &mapping, &packet)
+                .unwrap();
+            processor
+                .process_input_at(1, mapping.clone(), packet, now)
+                .await
+                .unwrap();
+            let commands = processor.get_dolphin_commands().await.unwrap().unwrap();
+            recorder
+                .record(now, RecordedKind::Commands { commands })
+                .unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let bytes = buffer.0.lock().unwrap().clone();
+        let recording = InputRecording::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(recording.input_count(), 12);
+
+        let expected = recording.recorded_commands();
+        let replayed = recording.replay(ReplaySpeed::Unpaced).await.unwrap();
+        assert_eq!(diff_commands(&expected, &replayed), None);
+    }
+
+    #[test]
+    fn test_diff_commands_finds_first_divergence() {
+        let press = |button, pressed| DolphinCommand::ButtonPress {
+            player: 1,
+            button,
+            pressed,
+        };
+        let expected = [
+            press(DolphinButton::A, true),
+            press(DolphinButton::B, false),
+        ];
+        let changed = [press(DolphinButton::A, true), press(DolphinButton::B, true)];
+
+        assert_eq!(diff_commands(&expected, &expected), None);
+        let diff = diff_commands(&expected, &changed).unwrap();
+        assert_eq!(diff.index, 1);
+        assert_eq!(diff.actual, Some(press(DolphinButton::B, true)));
+
+        let truncated = diff_commands(&expected, &expected[..1]).unwrap();
+        assert_eq!((truncated.index, truncated.actual), (1, None));
+    }
+}
@@ -0,0 +1,161 @@
+//! Rumble back-channel
+//!
+//! Dolphin drives rumble through a force-feedback evdev device per player.
+//! Backends turn what it plays into [`RumbleEvent`]s, which the
+//! [`ServerInputManager`](super::ServerInputManager) scales by the player's
+//! mapping and hands to the owning session as [`RumbleMessage`]s.
+
+pub use dpstream_protocol::rumble::Rumble;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Name of the force-feedback device Dolphin rumbles for a player
+pub fn rumble_device_name(player: u8) -> String {
+    format!("dpstream Rumble {player}")
+}
+
+/// Motor levels requested for one player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RumbleEvent {
+    pub player: u8,
+    /// Low-frequency, heavy motor
+    pub strong: u16,
+    /// High-frequency, light motor
+    pub weak: u16,
+    pub captured_at: Instant,
+}
+
+impl RumbleEvent {
+    pub fn new(player: u8, strong: u16, weak: u16) -> Self {
+        Self {
+            player,
+            strong,
+            weak,
+            captured_at: Instant::now(),
+        }
+    }
+
+    /// Both motors off
+    pub fn stop(player: u8) -> Self {
+        Self::new(player, 0, 0)
+    }
+}
+
+/// Rumble on its way to a client
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RumbleMessage {
+    /// Local controller on the client that should rumble
+    pub controller: u8,
+    pub low: u16,
+    pub high: u16,
+    pub captured_at: Instant,
+}
+
+impl RumbleMessage {
+    /// Scale an event by a mapping's vibration strength
+    pub fn from_event(event: &RumbleEvent, strength: f32) -> Self {
+        let scale = |level: u16| (level as f32 * strength.clamp(0.0, 1.0)).round() as u16;
+        Self {
+            controller: 0,
+            low: scale(event.strong),
+            high: scale(event.weak),
+            captured_at: event.captured_at,
+        }
+    }
+
+    /// Address the message to one of the client's local controllers
+    pub fn for_controller(self, controller: u8) -> Self {
+        Self { controller, ..self }
+    }
+
+    /// What to send the client, with the time since capture as of `now`
+    pub fn to_wire(self, now: Instant) -> Rumble {
+        let age = now.saturating_duration_since(self.captured_at).as_micros();
+        Rumble {
+            controller: self.controller,
+            low: self.low,
+            high: self.high,
+            latency_us: u32::try_from(age).unwrap_or(u32::MAX),
+        }
+    }
+}
+
+/// Rumble path counters
+#[derive(Debug, Clone, Default)]
+pub struct RumbleStats {
+    pub events_captured: u64,
+    pub messages_delivered: u64,
+    /// Events for a player without a listening session
+    pub events_dropped: u64,
+    /// Capture to control-channel write, averaged over deliveries
+    pub average_latency_us: f64,
+    pub max_latency_us: u64,
+}
+
+impl RumbleStats {
+    fn record_delivery(&mut self, latency: Duration) {
+        let latency_us = latency.as_micros() as u64;
+        self.messages_delivered += 1;
+        self.average_latency_us +=
+            (latency_us as f64 - self.average_latency_us) / self.messages_delivered as f64;
+        self.max_latency_us = self.max_latency_us.max(latency_us);
+    }
+}
+
+/// A session's feed of rumble messages
+pub struct RumbleSubscription {
+    pub messages: flume::Receiver<RumbleMessage>,
+    stats: Arc<Mutex<RumbleStats>>,
+}
+
+impl RumbleSubscription {
+    pub(crate) fn new(
+        messages: flume::Receiver<RumbleMessage>,
+        stats: Arc<Mutex<RumbleStats>>,
+    ) -> Self {
+        Self { messages, stats }
+    }
+
+    /// Note that `message` has been written to the client
+    pub fn delivered(&self, message: &RumbleMessage) {
+        self.stats
+            .lock()
+            .record_delivery(message.captured_at.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_scaling_and_encoding() {
+        let event = RumbleEvent::new(1, 0xFFFF, 0x8000);
+        let message = RumbleMessage::from_event(&event, 0.5);
+        assert_eq!((message.low, message.high), (0x8000, 0x4000));
+
+        let rumble = message
+            .for_controller(2)
+            .to_wire(event.captured_at + Duration::from_micros(1500));
+        assert_eq!(
+            rumble,
+            Rumble {
+                controller: 2,
+                low: 0x8000,
+                high: 0x4000,
+                latency_us: 1500,
+            }
+        );
+    }
+
+    #[test]
+    fn test_delivery_latency_stats() {
+        let mut stats = RumbleStats::default();
+        stats.record_delivery(Duration::from_micros(100));
+        stats.record_delivery(Duration::from_micros(300));
+        assert_eq!(stats.messages_delivered, 2);
+        assert_eq!(stats.average_latency_us, 200.0);
+        assert_eq!(stats.max_latency_us, 300);
+    }
+}
@@ -0,0 +1,219 @@
+//! Ordered input delivery
+//!
+//! Clients number the input packets of each local controller. An
+//! [`InputSequencer`] per controller hands packets on in that order: early
+//! ones wait in a small window for the gap before them to fill, and packets
+//! older than what was already delivered are dropped so a stale "pressed"
+//! can't undo a newer release. Gaps that never fill are skipped once the
+//! window is full or the wait times out.
+//! Clients also send a full-state snapshot now and then; a snapshot
+//! supersedes everything its controller sent before it, healing buttons a
+//! lost packet left held.
+
+use super::MoonlightInputPacket;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Packets that may wait for an earlier one
+pub const REORDER_WINDOW: usize = 8;
+
+/// How long a gap may hold up later packets
+pub const REORDER_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Sequencing counters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SequenceStats {
+    pub packets_delivered: u64,
+    /// Packets that arrived early and were held for an earlier one
+    pub packets_reordered: u64,
+    /// Packets that arrived after later ones were delivered
+    pub packets_late: u64,
+    /// Sequence numbers skipped without ever arriving
+    pub packets_lost: u64,
+    pub snapshots: u64,
+}
+
+impl SequenceStats {
+    /// Add another controller's counters
+    pub fn merge(&mut self, other: &SequenceStats) {
+        self.packets_delivered += other.packets_delivered;
+        self.packets_reordered += other.packets_reordered;
+        self.packets_late += other.packets_late;
+        self.packets_lost += other.packets_lost;
+        self.snapshots += other.snapshots;
+    }
+}
+
+/// Puts one controller's input packets back in order
+#[derive(Debug, Default)]
+pub struct InputSequencer {
+    /// Position expected next, once the first packet arrived; positions
+    /// are sequence numbers that keep counting past wraparound
+    next: Option<u64>,
+    pending: BTreeMap<u64, (MoonlightInputPacket, Instant)>,
+    stats: SequenceStats,
+}
+
+impl InputSequencer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in a packet, returning those now ready in order
+    ///
+    /// Packets without a sequence number pass straight through.
+    pub fn push(
+        &mut self,
+        packet: MoonlightInputPacket,
+        now: Instant,
+    ) -> Vec<MoonlightInputPacket> {
+        let Some(sequence) = packet.sequence else {
+            self.stats.packets_delivered += 1;
+            return vec![packet];
+        };
+        let next = *self.next.get_or_insert(u64::from(sequence));
+
+        let ahead = sequence.wrapping_sub(next as u32) as i32;
+        let position = next.wrapping_add_signed(i64::from(ahead));
+        if ahead < 0 || self.pending.contains_key(&position) {
+            self.stats.packets_late += 1;
+            return Vec::new();
+        }
+
+        let mut ready = Vec::new();
+        if packet.snapshot {
+            // Full state: whatever is missing or waiting before it is obsolete
+            self.stats.snapshots += 1;
+            let newer = self.pending.split_off(&position);
+            let superseded = std::mem::replace(&mut self.pending, newer).len();
+            self.stats.packets_lost += ahead as u64 - superseded as u64;
+            self.deliver(position, packet, &mut ready);
+        } else if ahead == 0 {
+            self.deliver(position, packet, &mut ready);
+        } else {
+            self.stats.packets_reordered += 1;
+            self.pending.insert(position, (packet, now));
+            if self.pending.len() >= REORDER_WINDOW {
+                self.skip_gap(&mut ready);
+            }
+        }
+        self.drain(&mut ready);
+        ready
+    }
+
+    /// Give up on gaps that held packets back for too long
+    pub fn flush_expired(&mut self, now: Instant) -> Vec<MoonlightInputPacket> {
+        let mut ready = Vec::new();
+        while let Some((_, (_, since))) = self.pending.first_key_value() {
+            if now.saturating_duration_since(*since) < REORDER_TIMEOUT {
+                break;
+            }
+            self.skip_gap(&mut ready);
+            self.drain(&mut ready);
+        }
+        ready
+    }
+
+    pub fn stats(&self) -> &SequenceStats {
+        &self.stats
+    }
+
+    fn deliver(
+        &mut self,
+        position: u64,
+        packet: MoonlightInputPacket,
+        ready: &mut Vec<MoonlightInputPacket>,
+    ) {
+        self.next = Some(position + 1);
+        self.stats.packets_delivered += 1;
+        ready.push(packet);
+    }
+
+    /// Count the missing packets before the oldest waiting one as lost and
+    /// deliver it
+    fn skip_gap(&mut self, ready: &mut Vec<MoonlightInputPacket>) {
+        let (Some(next), Some((position, (packet, _)))) = (self.next, self.pending.pop_first())
+        else {
+            return;
+        };
+        self.stats.packets_lost += position - next;
+        self.deliver(position, packet, ready);
+    }
+
+    /// Deliver waiting packets that follow on without a gap
+    fn drain(&mut self, ready: &mut Vec<MoonlightInputPacket>) {
+        while let Some(next) = self.next {
+            let Some((packet, _)) = self.pending.remove(&next) else {
+                break;
+            };
+            self.deliver(next, packet, ready);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(sequence: u32, button_flags: u16) -> MoonlightInputPacket {
+        MoonlightInputPacket {
+            sequence: Some(sequence),
+            button_flags,
+            ..Default::default()
+        }
+    }
+
+    fn sequences(packets: &[MoonlightInputPacket]) -> Vec<u32> {
+        packets
+            .iter()
+            .filter_map(|packet| packet.sequence)
+            .collect()
+    }
+
+    #[test]
+    fn test_reorders_and_drops_stale_packets() {
+        let mut sequencer = InputSequencer::new();
+        let now = Instant::now();
+
+        assert_eq!(sequences(&sequencer.push(packet(10, 0x1000), now)), [10]);
+        assert!(sequencer.push(packet(12, 0), now).is_empty());
+        assert_eq!(
+            sequences(&sequencer.push(packet(11, 0x1000), now)),
+            [11, 12]
+        );
+
+        // The stale press arrives after the release and is ignored
+        assert!(sequencer.push(packet(11, 0x1000), now).is_empty());
+
+        let stats = sequencer.stats();
+        assert_eq!(stats.packets_delivered, 3);
+        assert_eq!(stats.packets_reordered, 1);
+        assert_eq!(stats.packets_late, 1);
+        assert_eq!(stats.packets_lost, 0);
+    }
+
+    #[test]
+    fn test_skips_gaps_on_timeout_and_snapshot() {
+        let mut sequencer = InputSequencer::new();
+        let now = Instant::now();
+
+        sequencer.push(packet(u32::MAX, 0x1000), now);
+        assert!(sequencer.push(packet(1, 0x1000), now).is_empty());
+        assert!(sequencer.flush_expired(now).is_empty());
+        let flushed = sequencer.flush_expired(now + REORDER_TIMEOUT);
+        assert_eq!(sequences(&flushed), [1]);
+        assert_eq!(sequencer.stats().packets_lost, 1);
+
+        // A snapshot past a gap is applied at once, releasing the button
+        let mut snapshot = packet(5, 0);
+        snapshot.snapshot = true;
+        sequencer.push(packet(4, 0x1000), now);
+        let delivered = sequencer.push(snapshot, now);
+        assert_eq!(sequences(&delivered), [5]);
+        assert_eq!(delivered[0].button_flags, 0);
+
+        let stats = sequencer.stats();
+        assert_eq!(stats.snapshots, 1);
+        assert_eq!((stats.packets_lost, stats.packets_late), (3, 0));
+    }
+}
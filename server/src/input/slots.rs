@@ -0,0 +1,415 @@
+//! Player slot assignment
+//!
+//! [`SlotTable`] decides which client controller plays which controller
+//! port. A session's first controller is its own seat: sessions without one
+//! watch as spectators until a port frees up or is handed to them. Further
+//! local controllers on the same client, numbered from 1, take free ports of
+//! their own. A port a client drops out of can be held for it by client id
+//! and controller number, so reconnecting lands it back where it was.
+
+use crate::error::{DpstreamError, InputError, Result};
+use crate::input::dolphin::MAX_PLAYERS;
+pub use dpstream_protocol::slots::{PlayerSlots, SlotRequest};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long a dropped client's slot is held for it
+pub const RECONNECT_GRACE: Duration = Duration::from_secs(60);
+
+/// One of a session's local controllers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ControllerId {
+    pub session_id: Uuid,
+    /// Controller number on the client, 0 for its first
+    pub index: u8,
+}
+
+impl ControllerId {
+    pub fn new(session_id: Uuid, index: u8) -> Self {
+        Self { session_id, index }
+    }
+
+    /// The controller a session joins with
+    pub fn primary(session_id: Uuid) -> Self {
+        Self::new(session_id, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SlotState {
+    Occupied(ControllerId),
+    Reserved {
+        client_id: String,
+        index: u8,
+        until: Instant,
+    },
+}
+
+/// Which controller holds each player slot
+#[derive(Debug, Clone, Default)]
+pub struct SlotTable {
+    slots: BTreeMap<u8, SlotState>,
+    /// Sessions without a slot, longest waiting first
+    spectators: Vec<Uuid>,
+}
+
+impl SlotTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Slot a controller plays in, if it has one
+    pub fn slot_of(&self, controller: &ControllerId) -> Option<u8> {
+        self.slots.iter().find_map(|(slot, state)| match state {
+            SlotState::Occupied(occupant) if occupant == controller => Some(*slot),
+            _ => None,
+        })
+    }
+
+    /// Controller numbers and slots of a session's seated controllers
+    pub fn slots_of(&self, session_id: &Uuid) -> Vec<(u8, u8)> {
+        let mut seated: Vec<(u8, u8)> = self
+            .slots
+            .iter()
+            .filter_map(|(slot, state)| match state {
+                SlotState::Occupied(occupant) if occupant.session_id == *session_id => {
+                    Some((occupant.index, *slot))
+                }
+                _ => None,
+            })
+            .collect();
+        seated.sort_unstable();
+        seated
+    }
+
+    /// Controller playing in a slot
+    pub fn occupant(&self, slot: u8) -> Option<ControllerId> {
+        match self.slots.get(&slot) {
+            Some(SlotState::Occupied(controller)) => Some(*controller),
+            _ => None,
+        }
+    }
+
+    /// Client a slot is being held for
+    pub fn reserved_for(&self, slot: u8, now: Instant) -> Option<&str> {
+        match self.slots.get(&slot) {
+            Some(SlotState::Reserved {
+                client_id, until, ..
+            }) if *until > now => Some(client_id),
+            _ => None,
+        }
+    }
+
+    /// Every seated controller and its slot
+    pub fn assignments(&self) -> HashMap<ControllerId, u8> {
+        self.slots
+            .iter()
+            .filter_map(|(slot, state)| match state {
+                SlotState::Occupied(controller) => Some((*controller, *slot)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Sessions waiting for a slot, longest waiting first
+    pub fn spectators(&self) -> &[Uuid] {
+        &self.spectators
+    }
+
+    /// Seat a controller: in the slot held for it, else in the first free
+    /// slot. A session's first controller spectates when none is left; its
+    /// other controllers only get seats while the first one has one.
+    pub fn join(
+        &mut self,
+        controller: ControllerId,
+        client_id: Option<&str>,
+        now: Instant,
+    ) -> Option<u8> {
+        if let Some(slot) = self.slot_of(&controller) {
+            return Some(slot);
+        }
+        if controller.index > 0 && !self.is_seated(&controller.session_id) {
+            return None;
+        }
+
+        let held = client_id.and_then(|client_id| self.held_slot(client_id, controller.index, now));
+        let slot = held.or_else(|| (1..=MAX_PLAYERS).find(|slot| self.is_free(*slot, now)));
+        match slot {
+            Some(slot) => {
+                self.slots.insert(slot, SlotState::Occupied(controller));
+                self.spectators
+                    .retain(|spectator| *spectator != controller.session_id);
+            }
+            None if controller.index == 0 && !self.spectators.contains(&controller.session_id) => {
+                self.spectators.push(controller.session_id)
+            }
+            None => {}
+        }
+        slot
+    }
+
+    /// Remove a session, returning the controller numbers and slots it left
+    pub fn leave(&mut self, session_id: &Uuid) -> Vec<(u8, u8)> {
+        self.spectators.retain(|spectator| spectator != session_id);
+        let seated = self.slots_of(session_id);
+        for (_, slot) in &seated {
+            self.slots.remove(slot);
+        }
+        seated
+    }
+
+    /// Free the slot of one controller, e.g. after it was unpaired
+    pub fn leave_controller(&mut self, controller: &ControllerId) -> Option<u8> {
+        let slot = self.slot_of(controller)?;
+        self.slots.remove(&slot);
+        Some(slot)
+    }
+
+    /// Give up all of a session's slots and spectate
+    pub fn spectate(&mut self, session_id: Uuid) -> Vec<(u8, u8)> {
+        let seated = self.leave(&session_id);
+        self.spectators.push(session_id);
+        seated
+    }
+
+    /// Move a controller into a slot that is free or held for its client
+    pub fn claim(
+        &mut self,
+        controller: ControllerId,
+        slot: u8,
+        client_id: Option<&str>,
+        now: Instant,
+    ) -> Result<()> {
+        check_slot(slot)?;
+        if self.slot_of(&controller) == Some(slot) {
+            return Ok(());
+        }
+        if controller.index > 0 && !self.is_seated(&controller.session_id) {
+            return Err(unavailable(slot, "session is spectating"));
+        }
+        let held_for_client = client_id.is_some() && self.reserved_for(slot, now) == client_id;
+        if !self.is_free(slot, now) && !held_for_client {
+            return Err(unavailable(slot, "taken"));
+        }
+
+        self.leave_controller(&controller);
+        self.spectators
+            .retain(|spectator| *spectator != controller.session_id);
+        self.slots.insert(slot, SlotState::Occupied(controller));
+        Ok(())
+    }
+
+    /// Exchange whoever holds two slots
+    pub fn swap(&mut self, first: u8, second: u8) -> Result<()> {
+        check_slot(first)?;
+        check_slot(second)?;
+        let first_state = self.slots.remove(&first);
+        let second_state = self.slots.remove(&second);
+        if let Some(state) = first_state {
+            self.slots.insert(second, state);
+        }
+        if let Some(state) = second_state {
+            self.slots.insert(first, state);
+        }
+        Ok(())
+    }
+
+    /// Hold an unoccupied slot for a client's controller until `until`
+    pub fn reserve(&mut self, slot: u8, client_id: &str, index: u8, until: Instant) -> Result<()> {
+        check_slot(slot)?;
+        if self.occupant(slot).is_some() {
+            return Err(unavailable(slot, "taken"));
+        }
+        self.slots.insert(
+            slot,
+            SlotState::Reserved {
+                client_id: client_id.to_string(),
+                index,
+                until,
+            },
+        );
+        Ok(())
+    }
+
+    /// Give a slot to a spectating session's first controller
+    ///
+    /// A displaced first controller takes its session into spectating, other
+    /// controllers of that session included; a displaced further controller
+    /// simply loses its slot.
+    pub fn hand_over(&mut self, slot: u8, spectator: Uuid) -> Result<Option<ControllerId>> {
+        check_slot(slot)?;
+        if !self.spectators.contains(&spectator) {
+            return Err(unavailable(slot, "recipient is not spectating"));
+        }
+
+        self.spectators.retain(|waiting| *waiting != spectator);
+        let previous = self.occupant(slot);
+        self.slots
+            .insert(slot, SlotState::Occupied(ControllerId::primary(spectator)));
+        if let Some(previous) = previous.filter(|previous| previous.index == 0) {
+            self.spectate(previous.session_id);
+        }
+        Ok(previous)
+    }
+
+    /// A slot with nobody in it and no live reservation
+    fn is_free(&self, slot: u8, now: Instant) -> bool {
+        match self.slots.get(&slot) {
+            None => true,
+            Some(SlotState::Occupied(_)) => false,
+            Some(SlotState::Reserved { until, .. }) => *until <= now,
+        }
+    }
+
+    /// Whether a session's first controller has a slot
+    fn is_seated(&self, session_id: &Uuid) -> bool {
+        self.slot_of(&ControllerId::primary(*session_id)).is_some()
+    }
+
+    /// Slot held for one of a client's controllers, if any
+    pub fn held_slot(&self, client_id: &str, index: u8, now: Instant) -> Option<u8> {
+        (1..=MAX_PLAYERS).find(|slot| match self.slots.get(slot) {
+            Some(SlotState::Reserved {
+                client_id: holder,
+                index: held_index,
+                until,
+            }) => holder == client_id && *held_index == index && *until > now,
+            _ => false,
+        })
+    }
+
+    /// The table from one session's point of view
+    pub fn layout_for(&self, session_id: &Uuid, now: Instant) -> PlayerSlots {
+        let seated = self.slots_of(session_id);
+        let count = seated.last().map_or(1, |(index, _)| *index as usize + 1);
+        let mut slots = vec![None; count];
+        for (index, slot) in seated {
+            slots[index as usize] = Some(slot);
+        }
+
+        PlayerSlots {
+            slots,
+            occupied: (1..=MAX_PLAYERS)
+                .filter(|slot| self.occupant(*slot).is_some())
+                .collect(),
+            reserved: (1..=MAX_PLAYERS)
+                .filter(|slot| self.reserved_for(*slot, now).is_some())
+                .collect(),
+            spectators: self.spectators().len() as u32,
+        }
+    }
+}
+
+fn check_slot(slot: u8) -> Result<()> {
+    if slot == 0 || slot > MAX_PLAYERS {
+        return Err(InputError::InvalidPlayer { player: slot }.into());
+    }
+    Ok(())
+}
+
+fn unavailable(slot: u8, reason: &str) -> DpstreamError {
+    InputError::SlotUnavailable {
+        slot,
+        reason: reason.to_string(),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_claim_and_swap() {
+        let mut table = SlotTable::new();
+        let now = Instant::now();
+        let sessions: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+
+        let primary = |index: usize| ControllerId::primary(sessions[index]);
+
+        for index in 0..sessions.len() {
+            let expected = (index < 4).then_some(index as u8 + 1);
+            assert_eq!(table.join(primary(index), None, now), expected);
+        }
+        assert_eq!(table.spectators(), &sessions[4..]);
+
+        // Player 1 leaves and the spectator takes over port 1 by choice
+        table.leave(&sessions[0]);
+        assert!(table.claim(primary(4), 2, None, now).is_err());
+        table.claim(primary(4), 1, None, now).unwrap();
+        assert!(table.spectators().is_empty());
+
+        table.swap(1, 3).unwrap();
+        assert_eq!(table.slot_of(&primary(4)), Some(3));
+        assert_eq!(table.slot_of(&primary(2)), Some(1));
+        assert!(table.swap(1, 5).is_err());
+
+        let layout = table.layout_for(&sessions[4], now);
+        assert_eq!(layout.slot(), Some(3));
+        assert_eq!(layout.occupied, [1, 2, 3, 4]);
+        assert!(layout.reserved.is_empty());
+        assert_eq!(layout.spectators, 0);
+    }
+
+    #[test]
+    fn test_reservation_and_hand_over() {
+        let mut table = SlotTable::new();
+        let now = Instant::now();
+        let (player, rejoined, spectator) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        table.join(ControllerId::primary(player), Some("switch-a"), now);
+        let (index, slot) = table.leave(&player)[0];
+        table
+            .reserve(slot, "switch-a", index, now + RECONNECT_GRACE)
+            .unwrap();
+
+        // Someone else skips the held slot, the returning client gets it back
+        let other = ControllerId::primary(Uuid::new_v4());
+        assert_eq!(table.join(other, None, now), Some(2));
+        let rejoined = ControllerId::primary(rejoined);
+        assert_eq!(table.join(rejoined, Some("switch-a"), now), Some(slot));
+
+        // Once the hold lapses the slot is free for anyone
+        table.leave(&rejoined.session_id);
+        table.reserve(slot, "switch-a", 0, now).unwrap();
+        assert!(table.claim(other, slot, None, now).is_ok());
+
+        for _ in 0..3 {
+            table.join(ControllerId::primary(Uuid::new_v4()), None, now);
+        }
+        table.join(ControllerId::primary(spectator), None, now);
+        assert_eq!(table.spectators(), &[spectator]);
+        assert_eq!(table.hand_over(1, spectator).unwrap(), Some(other));
+        assert_eq!(table.slot_of(&ControllerId::primary(spectator)), Some(1));
+        assert_eq!(table.spectators(), &[other.session_id]);
+    }
+
+    #[test]
+    fn test_local_controllers_share_a_session() {
+        let mut table = SlotTable::new();
+        let now = Instant::now();
+        let (couch, other) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // Extra controllers need the session's first one seated
+        assert_eq!(table.join(ControllerId::new(couch, 1), None, now), None);
+        assert_eq!(table.join(ControllerId::primary(couch), None, now), Some(1));
+        assert_eq!(table.join(ControllerId::new(couch, 2), None, now), Some(2));
+        assert_eq!(table.join(ControllerId::primary(other), None, now), Some(3));
+        assert_eq!(table.join(ControllerId::new(couch, 1), None, now), Some(4));
+
+        let layout = table.layout_for(&couch, now);
+        assert_eq!(layout.slots, [Some(1), Some(4), Some(2)]);
+        assert_eq!(layout.slot_of(1), Some(4));
+        assert_eq!(layout.occupied, [1, 2, 3, 4]);
+
+        // Unpairing one controller frees only its port
+        assert_eq!(
+            table.leave_controller(&ControllerId::new(couch, 2)),
+            Some(2)
+        );
+        assert_eq!(table.slots_of(&couch), [(0, 1), (1, 4)]);
+        assert_eq!(table.leave(&couch), [(0, 1), (1, 4)]);
+        assert_eq!(table.assignments().len(), 1);
+    }
+}
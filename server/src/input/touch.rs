@@ -0,0 +1,335 @@
+//! Touchscreen modes and virtual controls
+//!
+//! What touching the Switch's screen does is part of the controller mapping.
+//! Touches inside a control of the [`TouchSettings::overlay`] work that
+//! control as if it were physical: a virtual button sets its button flag and
+//! a virtual stick deflects its stick, so bindings and response curves apply
+//! as usual. Remaining touches follow the [`TouchMode`]: point the Wii
+//! pointer at the touched spot, drag it around like a trackpad, or nothing.
+//! Clients draw the overlay from the [`TouchOverlay`] the server sends them.
+
+use crate::input::bindings::SwitchButton;
+use crate::input::{MoonlightInputPacket, TouchPoint};
+pub use dpstream_protocol::touch::{OverlayControl, OverlayControlKind, TouchOverlay};
+use serde::{Deserialize, Serialize};
+
+/// Touchscreen size in the coordinates clients report
+pub const SCREEN_WIDTH: u16 = 1280;
+pub const SCREEN_HEIGHT: u16 = 720;
+
+/// What touches outside the overlay do
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum TouchMode {
+    Off,
+    /// The pointer jumps to the touched spot
+    Pointer,
+    /// Dragging moves the pointer by `speed` times the finger's travel
+    Trackpad {
+        speed: f32,
+    },
+}
+
+/// Which stick a virtual stick stands in for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StickSide {
+    Left,
+    Right,
+}
+
+/// The physical control a virtual one stands in for
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VirtualInput {
+    Button {
+        button: SwitchButton,
+    },
+    /// Deflected by how far from the centre of the control the touch is
+    Stick {
+        stick: StickSide,
+    },
+}
+
+/// A control drawn on the touchscreen, in screen coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VirtualControl {
+    pub label: String,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    #[serde(flatten)]
+    pub input: VirtualInput,
+}
+
+impl VirtualControl {
+    fn contains(&self, touch: &TouchPoint) -> bool {
+        (self.x..self.x.saturating_add(self.width)).contains(&touch.x)
+            && (self.y..self.y.saturating_add(self.height)).contains(&touch.y)
+    }
+
+    /// Work the control with a touch inside it
+    fn apply(&self, touch: &TouchPoint, packet: &mut MoonlightInputPacket) {
+        match self.input {
+            VirtualInput::Button { button } => match button {
+                SwitchButton::ZL => packet.left_trigger = u8::MAX,
+                SwitchButton::ZR => packet.right_trigger = u8::MAX,
+                _ => packet.button_flags |= button.flag().unwrap_or(0),
+            },
+            VirtualInput::Stick { stick } => {
+                let half_width = f32::from(self.width.max(2)) / 2.0;
+                let half_height = f32::from(self.height.max(2)) / 2.0;
+                let x = (f32::from(touch.x) - f32::from(self.x) - half_width) / half_width;
+                // Screen y grows downwards, stick y upwards
+                let y = (f32::from(self.y) + half_height - f32::from(touch.y)) / half_height;
+                let length = (x * x + y * y).sqrt().max(1.0);
+                let raw = |value: f32| (value / length * f32::from(i16::MAX)) as i16;
+                match stick {
+                    StickSide::Left => {
+                        packet.left_stick_x = raw(x);
+                        packet.left_stick_y = raw(y);
+                    }
+                    StickSide::Right => {
+                        packet.right_stick_x = raw(x);
+                        packet.right_stick_y = raw(y);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Touch handling of a controller mapping
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TouchSettings {
+    pub mode: TouchMode,
+    #[serde(default)]
+    pub overlay: Vec<VirtualControl>,
+}
+
+impl Default for TouchSettings {
+    fn default() -> Self {
+        Self::off()
+    }
+}
+
+impl TouchSettings {
+    /// Touches are ignored
+    pub fn off() -> Self {
+        Self {
+            mode: TouchMode::Off,
+            overlay: Vec::new(),
+        }
+    }
+
+    /// Touch points the Wii pointer
+    pub fn pointer() -> Self {
+        Self {
+            mode: TouchMode::Pointer,
+            overlay: Vec::new(),
+        }
+    }
+
+    /// A virtual C-stick in the bottom right corner, for handheld play where
+    /// the right stick is taken by the camera or missing on a single Joy-Con
+    pub fn c_stick_overlay() -> Self {
+        Self {
+            mode: TouchMode::Off,
+            overlay: vec![VirtualControl {
+                label: "C".to_string(),
+                x: SCREEN_WIDTH - 260,
+                y: SCREEN_HEIGHT - 260,
+                width: 220,
+                height: 220,
+                input: VirtualInput::Stick {
+                    stick: StickSide::Right,
+                },
+            }],
+        }
+    }
+
+    /// Whether touches outside the overlay move the pointer
+    pub fn moves_pointer(&self) -> bool {
+        self.mode != TouchMode::Off
+    }
+
+    /// What clients should draw
+    pub fn overlay(&self) -> TouchOverlay {
+        TouchOverlay {
+            controls: self
+                .overlay
+                .iter()
+                .map(|control| OverlayControl {
+                    label: control.label.clone(),
+                    x: control.x,
+                    y: control.y,
+                    width: control.width,
+                    height: control.height,
+                    kind: match control.input {
+                        VirtualInput::Button { .. } => OverlayControlKind::Button,
+                        VirtualInput::Stick { .. } => OverlayControlKind::Stick,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Trackpad state of one player
+#[derive(Debug, Clone)]
+pub struct TouchState {
+    /// Where the dragging finger was last seen
+    last_touch: Option<(u16, u16)>,
+    /// Trackpad pointer position in screen coordinates
+    pointer: (f32, f32),
+}
+
+impl Default for TouchState {
+    fn default() -> Self {
+        Self {
+            last_touch: None,
+            pointer: (
+                f32::from(SCREEN_WIDTH) / 2.0,
+                f32::from(SCREEN_HEIGHT) / 2.0,
+            ),
+        }
+    }
+}
+
+impl TouchState {
+    /// Apply the overlay and touch mode to a packet's touches
+    ///
+    /// Afterwards the packet holds the overlay's buttons and sticks, and at
+    /// most the touches that should point the pointer, at absolute screen
+    /// positions.
+    pub fn apply(&mut self, settings: &TouchSettings, packet: &mut MoonlightInputPacket) {
+        let touches = packet.touch_points.take().unwrap_or_default();
+        let mut free = Vec::new();
+        for touch in touches {
+            match settings
+                .overlay
+                .iter()
+                .find(|control| control.contains(&touch))
+            {
+                Some(control) => control.apply(&touch, packet),
+                None => free.push(touch),
+            }
+        }
+
+        let pointing = match settings.mode {
+            TouchMode::Off => Vec::new(),
+            TouchMode::Pointer => free,
+            TouchMode::Trackpad { speed } => match free.first() {
+                Some(touch) => {
+                    if let Some((last_x, last_y)) = self.last_touch {
+                        let (x, y) = &mut self.pointer;
+                        *x = (*x + (f32::from(touch.x) - f32::from(last_x)) * speed)
+                            .clamp(0.0, f32::from(SCREEN_WIDTH));
+                        *y = (*y + (f32::from(touch.y) - f32::from(last_y)) * speed)
+                            .clamp(0.0, f32::from(SCREEN_HEIGHT));
+                    }
+                    self.last_touch = Some((touch.x, touch.y));
+                    vec![TouchPoint {
+                        x: self.pointer.0.round() as u16,
+                        y: self.pointer.1.round() as u16,
+                        // Dragging isn't pressing
+                        pressure: 0,
+                    }]
+                }
+                None => Vec::new(),
+            },
+        };
+        if !matches!(settings.mode, TouchMode::Trackpad { .. }) || pointing.is_empty() {
+            self.last_touch = None;
+        }
+
+        packet.touch_points = (!pointing.is_empty()).then_some(pointing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touching(points: &[(u16, u16)]) -> MoonlightInputPacket {
+        MoonlightInputPacket {
+            touch_points: Some(
+                points
+                    .iter()
+                    .map(|&(x, y)| TouchPoint {
+                        x,
+                        y,
+                        pressure: 200,
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_overlay_works_controls_and_passes_other_touches() {
+        let mut settings = TouchSettings::c_stick_overlay();
+        settings.mode = TouchMode::Pointer;
+        settings.overlay.push(VirtualControl {
+            label: "Z".to_string(),
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+            input: VirtualInput::Button {
+                button: SwitchButton::ZR,
+            },
+        });
+        let mut state = TouchState::default();
+
+        // Right edge of the C-stick, the Z button and a free touch
+        let stick = &settings.overlay[0];
+        let right_edge = (stick.x + stick.width - 1, stick.y + stick.height / 2);
+        let mut packet = touching(&[right_edge, (50, 50), (640, 300)]);
+        state.apply(&settings, &mut packet);
+
+        assert!(packet.right_stick_x > 32000);
+        assert!(packet.right_stick_y.abs() < 200);
+        assert_eq!(packet.right_trigger, u8::MAX);
+        let free = packet.touch_points.unwrap();
+        assert_eq!((free.len(), free[0].x, free[0].y), (1, 640, 300));
+
+        // With the mode off, free touches are dropped
+        settings.mode = TouchMode::Off;
+        let mut packet = touching(&[(640, 300)]);
+        state.apply(&settings, &mut packet);
+        assert!(packet.touch_points.is_none());
+    }
+
+    #[test]
+    fn test_trackpad_moves_pointer_relatively() {
+        let settings = TouchSettings {
+            mode: TouchMode::Trackpad { speed: 2.0 },
+            overlay: Vec::new(),
+        };
+        let mut state = TouchState::default();
+        let pointer = |state: &mut TouchState, touch: &[(u16, u16)]| {
+            let mut packet = touching(touch);
+            state.apply(&settings, &mut packet);
+            packet
+                .touch_points
+                .map(|points| (points[0].x, points[0].y, points[0].pressure))
+        };
+
+        // Touching down doesn't jump; dragging moves twice as far
+        assert_eq!(pointer(&mut state, &[(100, 100)]), Some((640, 360, 0)));
+        assert_eq!(pointer(&mut state, &[(110, 95)]), Some((660, 350, 0)));
+
+        // Lifting and touching elsewhere carries on from the same spot
+        assert_eq!(pointer(&mut state, &[]), None);
+        assert_eq!(pointer(&mut state, &[(900, 600)]), Some((660, 350, 0)));
+        assert_eq!(pointer(&mut state, &[(2000, 600)]), Some((1280, 350, 0)));
+
+        let overlay = TouchSettings::c_stick_overlay().overlay();
+        assert_eq!(overlay.controls.len(), 1);
+        assert_eq!(overlay.controls[0].label, "C");
+        assert_eq!(overlay.controls[0].kind, OverlayControlKind::Stick);
+    }
+}
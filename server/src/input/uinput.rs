@@ -0,0 +1,764 @@
+//! Linux uinput virtual gamepad backend
+//!
+//! Creates one evdev gamepad per player slot through `/dev/uinput`, for
+//! emulators or games that only read real input devices.
+//!
+//! Devices advertise `FF_RUMBLE`, and a thread per device serves the effect
+//! uploads and playback the reader sends back, reporting motor levels as
+//! [`RumbleEvent`]s. [`RumbleOutput`] is the same without the gamepad, for
+//! backends whose input devices cannot rumble.
+
+use crate::error::{InputError, Result};
+use crate::input::backend::InputBackend;
+use crate::input::processor::{AnalogStick, DolphinButton, DolphinCommand};
+use crate::input::rumble::{rumble_device_name, RumbleEvent};
+use async_trait::async_trait;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+const UINPUT_PATH: &str = "/dev/uinput";
+
+// ioctl requests from linux/uinput.h
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+const UI_SET_EVBIT: libc::c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x4004_5565;
+const UI_SET_ABSBIT: libc::c_ulong = 0x4004_5567;
+const UI_SET_FFBIT: libc::c_ulong = 0x4004_556b;
+const UI_BEGIN_FF_UPLOAD: libc::c_ulong = uinput_ioc(
+    IOC_READ | IOC_WRITE,
+    200,
+    std::mem::size_of::<libc::uinput_ff_upload>(),
+);
+const UI_END_FF_UPLOAD: libc::c_ulong = uinput_ioc(
+    IOC_WRITE,
+    201,
+    std::mem::size_of::<libc::uinput_ff_upload>(),
+);
+const UI_BEGIN_FF_ERASE: libc::c_ulong = uinput_ioc(
+    IOC_READ | IOC_WRITE,
+    202,
+    std::mem::size_of::<libc::uinput_ff_erase>(),
+);
+const UI_END_FF_ERASE: libc::c_ulong =
+    uinput_ioc(IOC_WRITE, 203, std::mem::size_of::<libc::uinput_ff_erase>());
+
+const IOC_WRITE: libc::c_ulong = 1;
+const IOC_READ: libc::c_ulong = 2;
+
+/// `_IOC` for the uinput ioctl type `'U'`
+const fn uinput_ioc(direction: libc::c_ulong, number: libc::c_ulong, size: usize) -> libc::c_ulong {
+    (direction << 30) | ((size as libc::c_ulong) << 16) | (0x55 << 8) | number
+}
+
+// Event types and codes from linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const EV_FF: u16 = 0x15;
+const EV_UINPUT: u16 = 0x0101;
+const SYN_REPORT: u16 = 0x00;
+
+const UI_FF_UPLOAD: u16 = 1;
+const UI_FF_ERASE: u16 = 2;
+const FF_RUMBLE: u16 = 0x50;
+const FF_GAIN: u16 = 0x60;
+
+/// Effects a device accepts at once
+const FF_EFFECTS_MAX: u32 = 16;
+
+/// How often force-feedback threads check whether to stop
+const FF_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const BTN_SOUTH: u16 = 0x130;
+const BTN_EAST: u16 = 0x131;
+const BTN_NORTH: u16 = 0x133;
+const BTN_WEST: u16 = 0x134;
+const BTN_TR: u16 = 0x137;
+const BTN_TL2: u16 = 0x138;
+const BTN_TR2: u16 = 0x139;
+const BTN_START: u16 = 0x13b;
+const BTN_DPAD_UP: u16 = 0x220;
+const BTN_DPAD_DOWN: u16 = 0x221;
+const BTN_DPAD_LEFT: u16 = 0x222;
+const BTN_DPAD_RIGHT: u16 = 0x223;
+
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+
+const KEYS: [u16; 12] = [
+    BTN_SOUTH,
+    BTN_EAST,
+    BTN_NORTH,
+    BTN_WEST,
+    BTN_TR,
+    BTN_TL2,
+    BTN_TR2,
+    BTN_START,
+    BTN_DPAD_UP,
+    BTN_DPAD_DOWN,
+    BTN_DPAD_LEFT,
+    BTN_DPAD_RIGHT,
+];
+
+const STICK_AXES: [u16; 4] = [ABS_X, ABS_Y, ABS_RX, ABS_RY];
+const TRIGGER_AXES: [u16; 2] = [ABS_Z, ABS_RZ];
+const STICK_MAX: i32 = 32767;
+const TRIGGER_MAX: i32 = 255;
+
+/// One evdev event: type, code, value
+type Event = (u16, u16, i32);
+
+fn button_code(button: DolphinButton) -> u16 {
+    match button {
+        DolphinButton::A => BTN_SOUTH,
+        DolphinButton::B => BTN_EAST,
+        DolphinButton::X => BTN_NORTH,
+        DolphinButton::Y => BTN_WEST,
+        DolphinButton::Z => BTN_TR,
+        DolphinButton::L => BTN_TL2,
+        DolphinButton::R => BTN_TR2,
+        DolphinButton::Start => BTN_START,
+        DolphinButton::Up => BTN_DPAD_UP,
+        DolphinButton::Down => BTN_DPAD_DOWN,
+        DolphinButton::Left => BTN_DPAD_LEFT,
+        DolphinButton::Right => BTN_DPAD_RIGHT,
+    }
+}
+
+/// Translate a command into evdev events, without the trailing sync
+fn command_events(command: &DolphinCommand) -> Vec<Event> {
+    match *command {
+        DolphinCommand::ButtonPress {
+            button, pressed, ..
+        } => vec![(EV_KEY, button_code(button), pressed as i32)],
+        DolphinCommand::AnalogInput { stick, x, y, .. } => {
+            let (x_axis, y_axis) = match stick {
+                AnalogStick::Main => (ABS_X, ABS_Y),
+                AnalogStick::CStick => (ABS_RX, ABS_RY),
+            };
+            // evdev Y grows downwards
+            vec![
+                (
+                    EV_ABS,
+                    x_axis,
+                    (x.clamp(-1.0, 1.0) * STICK_MAX as f32) as i32,
+                ),
+                (
+                    EV_ABS,
+                    y_axis,
+                    (-y.clamp(-1.0, 1.0) * STICK_MAX as f32) as i32,
+                ),
+            ]
+        }
+        DolphinCommand::TriggerInput {
+            left_trigger,
+            right_trigger,
+            ..
+        } => vec![
+            (
+                EV_ABS,
+                ABS_Z,
+                (left_trigger.clamp(0.0, 1.0) * TRIGGER_MAX as f32) as i32,
+            ),
+            (
+                EV_ABS,
+                ABS_RZ,
+                (right_trigger.clamp(0.0, 1.0) * TRIGGER_MAX as f32) as i32,
+            ),
+        ],
+        DolphinCommand::DPadInput {
+            up,
+            down,
+            left,
+            right,
+            ..
+        } => vec![
+            (EV_KEY, BTN_DPAD_UP, up as i32),
+            (EV_KEY, BTN_DPAD_DOWN, down as i32),
+            (EV_KEY, BTN_DPAD_LEFT, left as i32),
+            (EV_KEY, BTN_DPAD_RIGHT, right as i32),
+        ],
+        // A plain gamepad has no pointer, motion or extensions
+        DolphinCommand::WiiPointerInput { .. }
+        | DolphinCommand::WiiButtonPress { .. }
+        | DolphinCommand::WiiMotionInput { .. }
+        | DolphinCommand::WiiExtensionStick { .. }
+        | DolphinCommand::WiiExtensionTrigger { .. }
+        | DolphinCommand::WiiExtensionChange { .. } => Vec::new(),
+    }
+}
+
+fn command_player(command: &DolphinCommand) -> u8 {
+    match *command {
+        DolphinCommand::ButtonPress { player, .. }
+        | DolphinCommand::AnalogInput { player, .. }
+        | DolphinCommand::TriggerInput { player, .. }
+        | DolphinCommand::DPadInput { player, .. }
+        | DolphinCommand::WiiPointerInput { player, .. }
+        | DolphinCommand::WiiButtonPress { player, .. }
+        | DolphinCommand::WiiMotionInput { player, .. }
+        | DolphinCommand::WiiExtensionStick { player, .. }
+        | DolphinCommand::WiiExtensionTrigger { player, .. }
+        | DolphinCommand::WiiExtensionChange { player, .. } => player,
+    }
+}
+
+fn uinput_error(operation: &str, error: std::io::Error) -> crate::error::DpstreamError {
+    InputError::InitializationFailed {
+        reason: format!("uinput {operation}: {error}"),
+    }
+    .into()
+}
+
+fn ioctl(file: &File, request: libc::c_ulong, arg: libc::c_int) -> std::io::Result<()> {
+    // SAFETY: the uinput requests used here take an int argument or none
+    if unsafe { libc::ioctl(file.as_raw_fd(), request, arg) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn ioctl_ptr<T>(file: &File, request: libc::c_ulong, arg: &mut T) -> std::io::Result<()> {
+    // SAFETY: callers pass the struct type the request is defined with
+    if unsafe { libc::ioctl(file.as_raw_fd(), request, arg as *mut T) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Where a device's force-feedback requests end up
+#[derive(Clone)]
+struct RumbleFeed {
+    player: u8,
+    sender: flume::Sender<RumbleEvent>,
+}
+
+/// A virtual gamepad for one player slot
+struct VirtualPad {
+    file: File,
+    force_feedback: Option<ForceFeedbackThread>,
+}
+
+impl VirtualPad {
+    fn create(player_slot: u8, rumble: Option<RumbleFeed>) -> std::io::Result<Self> {
+        let name = format!("dpstream Pad {player_slot}");
+        Self::open(&name, rumble, |file, device| {
+            ioctl(file, UI_SET_EVBIT, EV_KEY as libc::c_int)?;
+            ioctl(file, UI_SET_EVBIT, EV_ABS as libc::c_int)?;
+            for key in KEYS {
+                ioctl(file, UI_SET_KEYBIT, key as libc::c_int)?;
+            }
+            for axis in STICK_AXES {
+                ioctl(file, UI_SET_ABSBIT, axis as libc::c_int)?;
+                device.absmin[axis as usize] = -STICK_MAX;
+                device.absmax[axis as usize] = STICK_MAX;
+                device.absflat[axis as usize] = 128;
+            }
+            for axis in TRIGGER_AXES {
+                ioctl(file, UI_SET_ABSBIT, axis as libc::c_int)?;
+                device.absmax[axis as usize] = TRIGGER_MAX;
+            }
+            Ok(())
+        })
+    }
+
+    /// Open and create a uinput device, adding force feedback when `rumble`
+    /// is given
+    fn open(
+        name: &str,
+        rumble: Option<RumbleFeed>,
+        configure: impl FnOnce(&File, &mut libc::uinput_user_dev) -> std::io::Result<()>,
+    ) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)?;
+
+        // SAFETY: uinput_user_dev is plain old data; all-zero is a valid value
+        let mut device: libc::uinput_user_dev = unsafe { std::mem::zeroed() };
+        for (dst, src) in device.name.iter_mut().zip(name.bytes()) {
+            *dst = src as libc::c_char;
+        }
+        device.id.bustype = 0x03; // BUS_USB
+        device.id.vendor = 0x1209;
+        device.id.product = 0x0d50;
+        device.id.version = 1;
+
+        configure(&file, &mut device)?;
+        if rumble.is_some() {
+            ioctl(&file, UI_SET_EVBIT, EV_FF as libc::c_int)?;
+            ioctl(&file, UI_SET_FFBIT, FF_RUMBLE as libc::c_int)?;
+            ioctl(&file, UI_SET_FFBIT, FF_GAIN as libc::c_int)?;
+            device.ff_effects_max = FF_EFFECTS_MAX;
+        }
+
+        // SAFETY: the slice covers exactly the bytes of `device`
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &device as *const libc::uinput_user_dev as *const u8,
+                std::mem::size_of::<libc::uinput_user_dev>(),
+            )
+        };
+        (&file).write_all(bytes)?;
+        ioctl(&file, UI_DEV_CREATE, 0)?;
+
+        let force_feedback = rumble
+            .map(|feed| ForceFeedbackThread::spawn(&file, feed))
+            .transpose()?;
+        Ok(Self {
+            file,
+            force_feedback,
+        })
+    }
+
+    fn emit(&mut self, events: &[Event]) -> std::io::Result<()> {
+        let mut buffer =
+            Vec::with_capacity((events.len() + 1) * std::mem::size_of::<libc::input_event>());
+        for &(type_, code, value) in events.iter().chain([(EV_SYN, SYN_REPORT, 0)].iter()) {
+            let event = libc::input_event {
+                time: libc::timeval {
+                    tv_sec: 0,
+                    tv_usec: 0,
+                },
+                type_,
+                code,
+                value,
+            };
+            // SAFETY: the slice covers exactly the bytes of `event`
+            buffer.extend_from_slice(unsafe {
+                std::slice::from_raw_parts(
+                    &event as *const libc::input_event as *const u8,
+                    std::mem::size_of::<libc::input_event>(),
+                )
+            });
+        }
+        self.file.write_all(&buffer)
+    }
+}
+
+impl Drop for VirtualPad {
+    fn drop(&mut self) {
+        // Stop serving effects before the device goes away
+        self.force_feedback.take();
+        if let Err(e) = ioctl(&self.file, UI_DEV_DESTROY, 0) {
+            debug!("Failed to destroy uinput device: {}", e);
+        }
+    }
+}
+
+/// An uploaded rumble effect
+#[derive(Debug, Clone, Copy)]
+struct RumbleEffect {
+    strong: u16,
+    weak: u16,
+    /// Playback length; zero plays until stopped
+    length: Duration,
+}
+
+/// Effects a device holds and which of them are playing
+#[derive(Debug)]
+struct ForceFeedbackState {
+    effects: HashMap<i16, RumbleEffect>,
+    /// Playing effects and when they run out
+    playing: HashMap<i16, Option<Instant>>,
+    gain: u16,
+}
+
+impl Default for ForceFeedbackState {
+    fn default() -> Self {
+        Self {
+            effects: HashMap::new(),
+            playing: HashMap::new(),
+            gain: u16::MAX,
+        }
+    }
+}
+
+impl ForceFeedbackState {
+    fn upload(&mut self, id: i16, effect: RumbleEffect) {
+        self.effects.insert(id, effect);
+    }
+
+    fn erase(&mut self, id: i16) {
+        self.effects.remove(&id);
+        self.playing.remove(&id);
+    }
+
+    /// Start (`count` > 0) or stop an effect
+    fn play(&mut self, id: i16, count: i32, now: Instant) {
+        match self.effects.get(&id) {
+            Some(effect) if count > 0 => {
+                let until = (!effect.length.is_zero()).then(|| now + effect.length * count as u32);
+                self.playing.insert(id, until);
+            }
+            _ => {
+                self.playing.remove(&id);
+            }
+        }
+    }
+
+    /// Drop effects whose playback ran out
+    fn expire(&mut self, now: Instant) {
+        self.playing
+            .retain(|_, until| until.is_none_or(|until| until > now));
+    }
+
+    /// Earliest time a playing effect runs out
+    fn next_expiry(&self) -> Option<Instant> {
+        self.playing.values().flatten().min().copied()
+    }
+
+    /// Combined strong and weak motor levels after the gain
+    fn levels(&self) -> (u16, u16) {
+        let (strong, weak) = self
+            .playing
+            .keys()
+            .filter_map(|id| self.effects.get(id))
+            .fold((0u32, 0u32), |(strong, weak), effect| {
+                (strong + effect.strong as u32, weak + effect.weak as u32)
+            });
+        let gain =
+            |level: u32| (level.min(u16::MAX as u32) * self.gain as u32 / u16::MAX as u32) as u16;
+        (gain(strong), gain(weak))
+    }
+}
+
+/// Thread answering a device's force-feedback requests
+struct ForceFeedbackThread {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ForceFeedbackThread {
+    fn spawn(file: &File, feed: RumbleFeed) -> std::io::Result<Self> {
+        let file = file.try_clone()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let handle = std::thread::Builder::new()
+            .name(format!("uinput-ff-{}", feed.player))
+            .spawn(move || serve_force_feedback(file, feed, thread_stop))?;
+        Ok(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for ForceFeedbackThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Read force-feedback requests until told to stop, reporting motor levels
+/// whenever they change
+fn serve_force_feedback(mut file: File, feed: RumbleFeed, stop: Arc<AtomicBool>) {
+    let event_size = std::mem::size_of::<libc::input_event>();
+    let mut buffer = vec![0u8; event_size * 16];
+    let mut state = ForceFeedbackState::default();
+    let mut reported = (0, 0);
+
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        let timeout = state.next_expiry().map_or(FF_POLL_INTERVAL, |until| {
+            until.saturating_duration_since(now).min(FF_POLL_INTERVAL)
+        });
+        let mut poll = libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: one valid pollfd
+        let ready = unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as libc::c_int) };
+        if ready < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() != std::io::ErrorKind::Interrupted {
+                warn!(
+                    "Force-feedback poll for player {} failed: {}",
+                    feed.player, error
+                );
+                return;
+            }
+        }
+
+        loop {
+            let read = match file.read(&mut buffer) {
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!(
+                        "Force-feedback read for player {} failed: {}",
+                        feed.player, e
+                    );
+                    return;
+                }
+            };
+            for chunk in buffer[..read].chunks_exact(event_size) {
+                // SAFETY: the chunk holds one input_event written by the kernel
+                let event: libc::input_event =
+                    unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const _) };
+                if let Err(e) = handle_force_feedback_event(&file, &mut state, &event) {
+                    debug!(
+                        "Force-feedback request for player {} failed: {}",
+                        feed.player, e
+                    );
+                }
+            }
+        }
+
+        state.expire(Instant::now());
+        let levels = state.levels();
+        if levels != reported {
+            reported = levels;
+            if feed
+                .sender
+                .send(RumbleEvent::new(feed.player, levels.0, levels.1))
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+fn handle_force_feedback_event(
+    file: &File,
+    state: &mut ForceFeedbackState,
+    event: &libc::input_event,
+) -> std::io::Result<()> {
+    match (event.type_, event.code) {
+        (EV_UINPUT, UI_FF_UPLOAD) => {
+            // SAFETY: uinput_ff_upload is plain old data; all-zero is valid
+            let mut upload: libc::uinput_ff_upload = unsafe { std::mem::zeroed() };
+            upload.request_id = event.value as u32;
+            ioctl_ptr(file, UI_BEGIN_FF_UPLOAD, &mut upload)?;
+            if upload.effect.type_ == FF_RUMBLE {
+                // SAFETY: rumble effects keep ff_rumble_effect at the start of the union
+                let rumble: libc::ff_rumble_effect =
+                    unsafe { std::ptr::read_unaligned(upload.effect.u.as_ptr() as *const _) };
+                state.upload(
+                    upload.effect.id,
+                    RumbleEffect {
+                        strong: rumble.strong_magnitude,
+                        weak: rumble.weak_magnitude,
+                        length: Duration::from_millis(upload.effect.replay.length as u64),
+                    },
+                );
+                upload.retval = 0;
+            } else {
+                upload.retval = -libc::EINVAL;
+            }
+            ioctl_ptr(file, UI_END_FF_UPLOAD, &mut upload)
+        }
+        (EV_UINPUT, UI_FF_ERASE) => {
+            // SAFETY: uinput_ff_erase is plain old data; all-zero is valid
+            let mut erase: libc::uinput_ff_erase = unsafe { std::mem::zeroed() };
+            erase.request_id = event.value as u32;
+            ioctl_ptr(file, UI_BEGIN_FF_ERASE, &mut erase)?;
+            state.erase(erase.effect_id as i16);
+            erase.retval = 0;
+            ioctl_ptr(file, UI_END_FF_ERASE, &mut erase)
+        }
+        (EV_FF, FF_GAIN) => {
+            state.gain = event.value.clamp(0, u16::MAX as i32) as u16;
+            Ok(())
+        }
+        (EV_FF, id) => {
+            state.play(id as i16, event.value, Instant::now());
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Force-feedback-only device that an emulator can rumble
+pub struct RumbleOutput {
+    _device: VirtualPad,
+}
+
+impl RumbleOutput {
+    /// Create the device named by [`rumble_device_name`]
+    pub fn create(player_slot: u8, sender: flume::Sender<RumbleEvent>) -> Result<Self> {
+        let feed = RumbleFeed {
+            player: player_slot,
+            sender,
+        };
+        let device = VirtualPad::open(&rumble_device_name(player_slot), Some(feed), |_, _| Ok(()))
+            .map_err(|e| uinput_error("create rumble device", e))?;
+        Ok(Self { _device: device })
+    }
+}
+
+/// Backend exposing each player as a uinput gamepad
+pub struct UinputBackend {
+    pads: HashMap<u8, VirtualPad>,
+    rumble: (flume::Sender<RumbleEvent>, flume::Receiver<RumbleEvent>),
+}
+
+impl Default for UinputBackend {
+    fn default() -> Self {
+        Self {
+            pads: HashMap::new(),
+            rumble: flume::unbounded(),
+        }
+    }
+}
+
+impl UinputBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl InputBackend for UinputBackend {
+    fn name(&self) -> &'static str {
+        "uinput"
+    }
+
+    fn connect_controller(&mut self, player_slot: u8) -> Result<()> {
+        if player_slot == 0 || player_slot > 4 {
+            return Err(InputError::InvalidPlayer {
+                player: player_slot,
+            }
+            .into());
+        }
+
+        if let Entry::Vacant(entry) = self.pads.entry(player_slot) {
+            let feed = RumbleFeed {
+                player: player_slot,
+                sender: self.rumble.0.clone(),
+            };
+            let pad = VirtualPad::create(player_slot, Some(feed))
+                .map_err(|e| uinput_error("create device", e))?;
+            entry.insert(pad);
+            info!("Created uinput gamepad for player {}", player_slot);
+        }
+        Ok(())
+    }
+
+    fn disconnect_controller(&mut self, player_slot: u8) -> Result<()> {
+        if self.pads.remove(&player_slot).is_some() {
+            info!("Removed uinput gamepad for player {}", player_slot);
+        }
+        Ok(())
+    }
+
+    async fn send_commands(&mut self, commands: Vec<DolphinCommand>) -> Result<()> {
+        let mut events: HashMap<u8, Vec<Event>> = HashMap::new();
+        for command in &commands {
+            events
+                .entry(command_player(command))
+                .or_default()
+                .extend(command_events(command));
+        }
+
+        for (player, events) in events {
+            if events.is_empty() {
+                continue;
+            }
+            self.connect_controller(player)?;
+            if let Some(pad) = self.pads.get_mut(&player) {
+                if let Err(e) = pad.emit(&events) {
+                    warn!("Failed to write uinput events for player {}: {}", player, e);
+                    return Err(InputError::CommandSendFailed {
+                        reason: e.to_string(),
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.pads.clear();
+        Ok(())
+    }
+
+    fn rumble_events(&self) -> Option<flume::Receiver<RumbleEvent>> {
+        Some(self.rumble.1.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_events() {
+        let events = command_events(&DolphinCommand::AnalogInput {
+            player: 1,
+            stick: AnalogStick::Main,
+            x: 1.0,
+            y: 1.0,
+        });
+        assert_eq!(
+            events,
+            vec![(EV_ABS, ABS_X, 32767), (EV_ABS, ABS_Y, -32767)]
+        );
+
+        let events = command_events(&DolphinCommand::ButtonPress {
+            player: 2,
+            button: DolphinButton::Start,
+            pressed: true,
+        });
+        assert_eq!(events, vec![(EV_KEY, BTN_START, 1)]);
+    }
+
+    #[test]
+    fn test_force_feedback_playback() {
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(UI_BEGIN_FF_UPLOAD, 0xc068_55c8);
+
+        let now = Instant::now();
+        let mut state = ForceFeedbackState::default();
+        state.upload(
+            0,
+            RumbleEffect {
+                strong: 0x8000,
+                weak: 0,
+                length: Duration::ZERO,
+            },
+        );
+        state.upload(
+            1,
+            RumbleEffect {
+                strong: 0x8000,
+                weak: 0x1000,
+                length: Duration::from_millis(50),
+            },
+        );
+
+        state.play(0, 1, now);
+        state.play(1, 1, now);
+        assert_eq!(state.levels(), (0xFFFF, 0x1000));
+
+        // The timed effect runs out, the other plays until stopped
+        state.expire(now + Duration::from_millis(60));
+        assert_eq!(state.levels(), (0x8000, 0));
+
+        state.gain = 0x8000;
+        assert_eq!(state.levels().0, 0x4000);
+        state.play(0, 0, now);
+        assert_eq!(state.levels(), (0, 0));
+    }
+}
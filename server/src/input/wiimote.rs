@@ -0,0 +1,414 @@
+//! Wii Remote emulation
+//!
+//! Builds a Wii Remote, optionally with a Nunchuk or Classic Controller, from
+//! Switch controller input. Fused motion moves the IR pointer, the
+//! accelerometer drives tilt and shake, and the left Joy-Con stands in for
+//! the Nunchuk.
+//!
+//! Motion follows libnx units: angular velocity in rotations per second and
+//! acceleration in g, with the controller held pointing at the screen.
+
+use crate::input::mapping::ControllerMapping;
+use crate::input::motion::{ImuSample, PointerTracker};
+use crate::input::processor::{AnalogStick, DolphinCommand};
+use crate::input::MoonlightInputPacket;
+use dpstream_protocol::input::buttons;
+pub use dpstream_protocol::input::WiiExtension;
+use serde::{Deserialize, Serialize};
+
+// Moonlight button flags
+const FLAG_DPAD_UP: u16 = buttons::UP;
+const FLAG_DPAD_DOWN: u16 = buttons::DOWN;
+const FLAG_DPAD_LEFT: u16 = buttons::LEFT;
+const FLAG_DPAD_RIGHT: u16 = buttons::RIGHT;
+const FLAG_START: u16 = buttons::PLUS;
+const FLAG_BACK: u16 = buttons::MINUS;
+const FLAG_LEFT_STICK: u16 = buttons::L_STICK;
+const FLAG_RIGHT_STICK: u16 = buttons::R_STICK;
+const FLAG_LEFT_SHOULDER: u16 = buttons::L;
+const FLAG_RIGHT_SHOULDER: u16 = buttons::R;
+const FLAG_A: u16 = buttons::A;
+const FLAG_B: u16 = buttons::B;
+const FLAG_X: u16 = buttons::X;
+const FLAG_Y: u16 = buttons::Y;
+
+/// Acceleration magnitude, in g, that starts a shake
+const SHAKE_START_G: f32 = 2.0;
+
+/// Acceleration magnitude, in g, below which a shake ends
+const SHAKE_END_G: f32 = 1.3;
+
+/// Tilt angle reported as full deflection
+const TILT_RANGE: f32 = std::f32::consts::FRAC_PI_2;
+
+/// Analog trigger level treated as a digital press
+const TRIGGER_PRESS_LEVEL: u8 = 128;
+
+/// Switch touch screen resolution
+const TOUCH_WIDTH: f32 = 1280.0;
+const TOUCH_HEIGHT: f32 = 720.0;
+
+/// Buttons of the Wii Remote and its extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WiiButton {
+    A,
+    B,
+    One,
+    Two,
+    Minus,
+    Plus,
+    Home,
+    Up,
+    Down,
+    Left,
+    Right,
+    NunchukC,
+    NunchukZ,
+    ClassicA,
+    ClassicB,
+    ClassicX,
+    ClassicY,
+    ClassicZL,
+    ClassicZR,
+    ClassicMinus,
+    ClassicPlus,
+    ClassicUp,
+    ClassicDown,
+    ClassicLeft,
+    ClassicRight,
+}
+
+/// Emulated Wii Remote state for one player
+#[derive(Debug, Clone, Default)]
+pub struct WiiRemoteState {
+    extension: WiiExtension,
+    pointer: (f32, f32),
+    tracker: PointerTracker,
+    shaking: bool,
+    previous_buttons: u16,
+}
+
+impl WiiRemoteState {
+    pub fn new(extension: WiiExtension) -> Self {
+        Self {
+            extension,
+            ..Self::default()
+        }
+    }
+
+    /// Currently attached extension
+    pub fn extension(&self) -> WiiExtension {
+        self.extension
+    }
+
+    /// Swap the attached extension
+    pub fn set_extension(&mut self, extension: WiiExtension) {
+        self.extension = extension;
+    }
+
+    /// Pointer position, -1.0 to 1.0 with y growing downwards
+    pub fn pointer(&self) -> (f32, f32) {
+        self.pointer
+    }
+
+    /// Move the pointer back to the middle of the screen
+    pub fn recenter(&mut self) {
+        self.pointer = (0.0, 0.0);
+        self.tracker.recenter();
+    }
+
+    /// Commands describing the remote after an input packet
+    ///
+    /// `dt` is the time since the previous packet, in seconds, used to
+    /// integrate motion into the pointer position.
+    pub fn update(
+        &mut self,
+        player: u8,
+        mapping: &ControllerMapping,
+        input: &MoonlightInputPacket,
+        dt: f32,
+    ) -> Vec<DolphinCommand> {
+        let flags = input.button_flags;
+        let newly_pressed = flags & !self.previous_buttons;
+        self.previous_buttons = flags;
+
+        if newly_pressed & FLAG_RIGHT_STICK != 0 {
+            self.recenter();
+        }
+
+        let mut commands = self.button_commands(player, input);
+        commands.extend(self.pointer_commands(player, mapping, input, dt));
+        commands.extend(self.motion_commands(player, input));
+        commands.extend(self.extension_commands(player, mapping, input));
+        commands
+    }
+
+    fn button_commands(&self, player: u8, input: &MoonlightInputPacket) -> Vec<DolphinCommand> {
+        let flags = input.button_flags;
+        let held = |flag: u16| flags & flag != 0;
+
+        let buttons: Vec<(WiiButton, bool)> = if self.extension == WiiExtension::Classic {
+            vec![
+                (WiiButton::ClassicA, held(FLAG_A)),
+                (WiiButton::ClassicB, held(FLAG_B)),
+                (WiiButton::ClassicX, held(FLAG_X)),
+                (WiiButton::ClassicY, held(FLAG_Y)),
+                (WiiButton::ClassicZL, held(FLAG_LEFT_SHOULDER)),
+                (WiiButton::ClassicZR, held(FLAG_RIGHT_SHOULDER)),
+                (WiiButton::ClassicMinus, held(FLAG_BACK)),
+                (WiiButton::ClassicPlus, held(FLAG_START)),
+                (WiiButton::Home, held(FLAG_LEFT_STICK)),
+                (WiiButton::ClassicUp, held(FLAG_DPAD_UP)),
+                (WiiButton::ClassicDown, held(FLAG_DPAD_DOWN)),
+                (WiiButton::ClassicLeft, held(FLAG_DPAD_LEFT)),
+                (WiiButton::ClassicRight, held(FLAG_DPAD_RIGHT)),
+            ]
+        } else {
+            // B is the Wii Remote's trigger, so ZR presses it too
+            let b = held(FLAG_B) || input.right_trigger >= TRIGGER_PRESS_LEVEL;
+            vec![
+                (WiiButton::A, held(FLAG_A)),
+                (WiiButton::B, b),
+                (WiiButton::One, held(FLAG_X)),
+                (WiiButton::Two, held(FLAG_Y)),
+                (WiiButton::Minus, held(FLAG_BACK)),
+                (WiiButton::Plus, held(FLAG_START)),
+                (WiiButton::Home, held(FLAG_LEFT_STICK)),
+                (WiiButton::Up, held(FLAG_DPAD_UP)),
+                (WiiButton::Down, held(FLAG_DPAD_DOWN)),
+                (WiiButton::Left, held(FLAG_DPAD_LEFT)),
+                (WiiButton::Right, held(FLAG_DPAD_RIGHT)),
+            ]
+        };
+
+        buttons
+            .into_iter()
+            .map(|(button, pressed)| DolphinCommand::WiiButtonPress {
+                player,
+                button,
+                pressed,
+            })
+            .collect()
+    }
+
+    fn pointer_commands(
+        &mut self,
+        player: u8,
+        mapping: &ControllerMapping,
+        input: &MoonlightInputPacket,
+        dt: f32,
+    ) -> Option<DolphinCommand> {
+        let touch = input
+            .touch_points
+            .as_ref()
+            .and_then(|points| points.first())
+            .filter(|_| mapping.touch.moves_pointer());
+
+        if let Some(touch) = touch {
+            // Touching the screen points straight at that spot, and motion
+            // carries on from there
+            self.pointer = (
+                (touch.x as f32 / TOUCH_WIDTH) * 2.0 - 1.0,
+                (touch.y as f32 / TOUCH_HEIGHT) * 2.0 - 1.0,
+            );
+            self.tracker.place(self.pointer, mapping);
+        } else if let Some(sample) =
+            ImuSample::from_packet(input).filter(|_| mapping.enable_gyro_pointer)
+        {
+            // ZR owns the right shoulder when the Classic Controller is in
+            let ratchet = mapping.motion.ratchet_flag;
+            let ratchet_held = ratchet != 0
+                && input.button_flags & ratchet != 0
+                && !(self.extension == WiiExtension::Classic && ratchet == FLAG_RIGHT_SHOULDER);
+            self.pointer = self.tracker.update(&sample, dt, mapping, ratchet_held);
+        } else {
+            return None;
+        }
+
+        Some(DolphinCommand::WiiPointerInput {
+            player,
+            x: self.pointer.0,
+            y: self.pointer.1,
+            z: 0.0,
+        })
+    }
+
+    fn motion_commands(
+        &mut self,
+        player: u8,
+        input: &MoonlightInputPacket,
+    ) -> Option<DolphinCommand> {
+        let (x, y, z) = (input.accel_x?, input.accel_y?, input.accel_z?);
+
+        let magnitude = (x * x + y * y + z * z).sqrt();
+        if magnitude >= SHAKE_START_G {
+            self.shaking = true;
+        } else if magnitude <= SHAKE_END_G {
+            self.shaking = false;
+        }
+
+        // Gravity gives the orientation; ignore it while the remote is shaken
+        let (pitch, roll) = if self.shaking {
+            (0.0, 0.0)
+        } else {
+            (
+                (y.atan2((x * x + z * z).sqrt()) / TILT_RANGE).clamp(-1.0, 1.0),
+                (x.atan2(z) / TILT_RANGE).clamp(-1.0, 1.0),
+            )
+        };
+
+        Some(DolphinCommand::WiiMotionInput {
+            player,
+            pitch,
+            roll,
+            shake: self.shaking,
+        })
+    }
+
+    fn extension_commands(
+        &self,
+        player: u8,
+        mapping: &ControllerMapping,
+        input: &MoonlightInputPacket,
+    ) -> Vec<DolphinCommand> {
+        let stick = |x: i16, y: i16, stick: AnalogStick| {
+            let response = match stick {
+                AnalogStick::Main => &mapping.analog.main_stick,
+                AnalogStick::CStick => &mapping.analog.c_stick,
+            };
+            let (x, y) = response.apply(x as f32 / 32767.0, y as f32 / 32767.0);
+            DolphinCommand::WiiExtensionStick {
+                player,
+                stick,
+                x,
+                y,
+            }
+        };
+
+        match self.extension {
+            WiiExtension::None => Vec::new(),
+            WiiExtension::Nunchuk => vec![
+                stick(input.left_stick_x, input.left_stick_y, AnalogStick::Main),
+                DolphinCommand::WiiButtonPress {
+                    player,
+                    button: WiiButton::NunchukC,
+                    pressed: input.button_flags & FLAG_LEFT_SHOULDER != 0,
+                },
+                DolphinCommand::WiiButtonPress {
+                    player,
+                    button: WiiButton::NunchukZ,
+                    pressed: input.left_trigger >= TRIGGER_PRESS_LEVEL,
+                },
+            ],
+            WiiExtension::Classic => vec![
+                stick(input.left_stick_x, input.left_stick_y, AnalogStick::Main),
+                stick(
+                    input.right_stick_x,
+                    input.right_stick_y,
+                    AnalogStick::CStick,
+                ),
+                DolphinCommand::WiiExtensionTrigger {
+                    player,
+                    left: input.left_trigger as f32 / 255.0,
+                    right: input.right_trigger as f32 / 255.0,
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::TouchPoint;
+
+    fn packet(button_flags: u16) -> MoonlightInputPacket {
+        MoonlightInputPacket {
+            button_flags,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_gyro_pointer_and_recenter() {
+        let mapping = ControllerMapping::default_wii_remote();
+        let mut remote = WiiRemoteState::new(WiiExtension::None);
+
+        let mut input = packet(0);
+        (input.gyro_x, input.gyro_y, input.gyro_z) = (Some(0.0), Some(0.0), Some(-0.1));
+        remote.update(1, &mapping, &input, 0.5);
+        assert!(remote.pointer().0 > 0.5);
+
+        remote.update(1, &mapping, &packet(FLAG_RIGHT_STICK), 0.0);
+        assert_eq!(remote.pointer(), (0.0, 0.0));
+
+        let mut input = packet(0);
+        input.touch_points = Some(vec![TouchPoint {
+            x: 1280,
+            y: 0,
+            pressure: 0,
+        }]);
+        remote.update(1, &mapping, &input, 0.0);
+        assert_eq!(remote.pointer(), (1.0, -1.0));
+    }
+
+    #[test]
+    fn test_shake_and_tilt() {
+        let mapping = ControllerMapping::default_wii_remote();
+        let mut remote = WiiRemoteState::new(WiiExtension::None);
+
+        let mut input = packet(0);
+        (input.accel_x, input.accel_y, input.accel_z) = (Some(1.0), Some(0.0), Some(0.0));
+        let commands = remote.update(1, &mapping, &input, 0.0);
+        assert!(commands.iter().any(|c| matches!(
+            c,
+            DolphinCommand::WiiMotionInput { roll, shake: false, .. } if *roll == 1.0
+        )));
+
+        input.accel_x = Some(3.0);
+        let commands = remote.update(1, &mapping, &input, 0.0);
+        assert!(commands
+            .iter()
+            .any(|c| matches!(c, DolphinCommand::WiiMotionInput { shake: true, .. })));
+    }
+
+    #[test]
+    fn test_extension_layouts() {
+        let mapping = ControllerMapping::default_wii_remote();
+        let mut remote = WiiRemoteState::new(WiiExtension::Nunchuk);
+
+        let mut input = packet(FLAG_LEFT_SHOULDER);
+        input.left_stick_x = 32767;
+        let commands = remote.update(2, &mapping, &input, 0.0);
+        assert!(commands.iter().any(|c| matches!(
+            c,
+            DolphinCommand::WiiButtonPress {
+                button: WiiButton::NunchukC,
+                pressed: true,
+                ..
+            }
+        )));
+        assert!(commands.iter().any(|c| matches!(
+            c,
+            DolphinCommand::WiiExtensionStick { stick: AnalogStick::Main, x, .. } if *x == 1.0
+        )));
+
+        remote.set_extension(WiiExtension::Classic);
+        let commands = remote.update(2, &mapping, &packet(FLAG_A), 0.0);
+        assert!(commands.iter().any(|c| matches!(
+            c,
+            DolphinCommand::WiiButtonPress {
+                button: WiiButton::ClassicA,
+                pressed: true,
+                ..
+            }
+        )));
+        assert!(!commands.iter().any(|c| matches!(
+            c,
+            DolphinCommand::WiiButtonPress {
+                button: WiiButton::A,
+                ..
+            }
+        )));
+    }
+}
@@ -9,12 +9,18 @@ mod input;
 mod network;
 mod streaming;
 
-use emulator::{DolphinConfig, DolphinManager};
+use emulator::saves::{SaveCommand, SaveConfig, SaveManager};
+use emulator::savestate::StateResponse;
+use emulator::{DolphinConfig, DolphinManager, GameLibrary};
 use error::{DpstreamError, ErrorReport, Result};
 use health::{run_health_monitoring, HealthMonitor};
-use input::ServerInputManager;
+use input::{InputBackendKind, ServerInputManager};
 use network::VpnManager;
 use std::sync::Arc;
+use streaming::capture::{QualityPreset, VideoCaptureConfig};
+use streaming::congestion::RateLimits;
+use streaming::encoder::EncoderConfig;
+use streaming::pipeline::VideoPipeline;
 use streaming::{HealthServer, MoonlightServer, ServerConfig};
 
 #[tokio::main]
@@ -25,6 +31,12 @@ async fn main() -> Result<()> {
     // Load environment variables
     dotenv::dotenv().ok();
 
+    // `dpstream-server saves ...` manages saves instead of serving
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("saves") {
+        return run_saves_command(&args[1..]);
+    }
+
     info!("Starting Dolphin Remote Gaming Server v1.0.0");
     info!("Platform: {}", env::consts::OS);
     info!("Architecture: {}", env::consts::ARCH);
@@ -72,8 +84,24 @@ async fn main() -> Result<()> {
         enable_encryption: true,
         enable_authentication: true,
         stream_timeout_ms: 30000,
+        rate_limits: RateLimits {
+            min_bitrate_kbps: env::var("MIN_BITRATE")
+                .unwrap_or_else(|_| "1500".to_string())
+                .parse()
+                .map_err(|e| DpstreamError::Config(format!("Invalid MIN_BITRATE: {e}")))?,
+            max_bitrate_kbps: env::var("BITRATE")
+                .unwrap_or_else(|_| "15000".to_string())
+                .parse()
+                .map_err(|e| DpstreamError::Config(format!("Invalid BITRATE: {e}")))?,
+            ..RateLimits::default()
+        },
+        udp_gso: env::var("UDP_GSO")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .map_err(|e| DpstreamError::Config(format!("Invalid UDP_GSO: {e}")))?,
     };
 
+    let max_bitrate = streaming_config.rate_limits.max_bitrate_kbps;
     let mut streaming_server = MoonlightServer::new(streaming_config).await.map_err(|e| {
         let report = ErrorReport::new(e)
             .with_context("Failed to initialize streaming server".to_string())
@@ -102,6 +130,22 @@ async fn main() -> Result<()> {
         video_backend: "OpenGL".to_string(),
     };
 
+    // Scan the game library so clients can browse what's playable
+    debug!("Scanning game library...");
+    let library_index = env::var("LIBRARY_INDEX_PATH")
+        .unwrap_or_else(|_| format!("{}/library-index.json", dolphin_config.save_directory));
+    let mut game_library = GameLibrary::open(dolphin_config.rom_roots(), library_index);
+    if let Err(e) = game_library.rescan() {
+        warn!("Game library scan failed: {}", e);
+    }
+
+    info!(
+        "Game library ready: {} games, indexed in {}",
+        game_library.entries().len(),
+        game_library.index_path().display()
+    );
+
+    let dolphin_user_dir = std::path::PathBuf::from(&dolphin_config.save_directory);
     let mut dolphin_manager = DolphinManager::new(dolphin_config).map_err(|e| {
         let report = ErrorReport::new(e)
             .with_context("Failed to initialize Dolphin manager".to_string())
@@ -110,11 +154,15 @@ async fn main() -> Result<()> {
         report.error
     })?;
 
+    // Clients pick from the library; the manager names profiles from it
+    streaming_server.set_library(game_library.entries());
+    dolphin_manager.set_library(game_library);
+
     info!("Dolphin emulator manager initialized");
 
     // Initialize input manager
     debug!("Initializing input manager...");
-    let input_manager = ServerInputManager::new().map_err(|e| {
+    let mut input_manager = ServerInputManager::new().map_err(|e| {
         let report = ErrorReport::new(e)
             .with_context("Failed to initialize input manager".to_string())
             .with_correlation_id(session_id.clone());
@@ -122,6 +170,22 @@ async fn main() -> Result<()> {
         report.error
     })?;
 
+    // Dolphin's controller pipes must exist before it starts so it opens them
+    match InputBackendKind::from_env() {
+        Ok(kind) => match kind.create(&dolphin_user_dir).await {
+            Ok(backend) => input_manager.set_backend(backend),
+            Err(e) => warn!("Failed to set up {:?} input backend: {}", kind, e),
+        },
+        Err(e) => warn!("Invalid input backend configuration: {}", e),
+    }
+
+    // Record input for later replay when asked to
+    if let Ok(path) = env::var("INPUT_RECORDING") {
+        if let Err(e) = input_manager.start_recording(&path) {
+            warn!("Failed to start input recording at {}: {}", path, e);
+        }
+    }
+
     info!("Input manager initialized");
 
     // Initialize health monitoring
@@ -138,7 +202,9 @@ async fn main() -> Result<()> {
 
     // Start health server
     debug!("Starting health check server...");
-    let health_server = HealthServer::new(health_monitor.clone(), 8080);
+    let health_server = HealthServer::new(health_monitor.clone(), 8080)
+        .with_latency_metrics(streaming_server.latency_metrics())
+        .with_pacing_metrics(streaming_server.pacing_metrics());
     tokio::spawn(async move {
         if let Err(e) = health_server.run().await {
             error!("Health server error: {}", e);
@@ -149,30 +215,121 @@ async fn main() -> Result<()> {
 
     // Connect input manager to streaming server
     streaming_server.set_input_manager(input_manager);
+
+    // Client input reaches the backend on the input thread's ticks
+    let input_driver = streaming_server.input_driver();
+    if let Err(e) = std::thread::Builder::new()
+        .name("input".to_string())
+        .spawn(move || input_driver.run())
+    {
+        error!("Failed to start input thread: {}", e);
+    }
     streaming_server.set_health_monitor(health_monitor);
 
+    // Route client emulation control requests to the Dolphin manager
+    let (emulation_tx, emulation_rx) = flume::unbounded();
+    streaming_server.set_emulation_control(emulation_tx);
+    let (state_tx, state_rx) = flume::unbounded();
+    streaming_server.set_state_control(state_tx);
+    let (launch_tx, launch_rx) = flume::unbounded();
+    streaming_server.set_launch_control(launch_tx);
+
+    // Capture and encode the emulator's display, following the sessions'
+    // congestion control
+    debug!("Starting video pipeline...");
+    let (rate_tx, rate_rx) = flume::unbounded();
+    streaming_server.set_rate_control(rate_tx);
+    let (refresh_tx, refresh_rx) = flume::unbounded();
+    streaming_server.set_refresh_control(refresh_tx);
+    let capture_config = VideoCaptureConfig {
+        window_id: 0, // Root window; Dolphin's own is only known once a game runs
+        width: 1280,
+        height: 720,
+        fps: 60,
+        bitrate: max_bitrate,
+        encoder: streaming::capture::VideoEncoder::Software,
+        quality_preset: QualityPreset::UltraFast,
+    };
+    let encoder_config = EncoderConfig {
+        bitrate: max_bitrate,
+        width: capture_config.width,
+        height: capture_config.height,
+        fps: capture_config.fps,
+        ..EncoderConfig::default()
+    };
+    match VideoPipeline::start(
+        capture_config,
+        encoder_config,
+        streaming_server.video_output(),
+    )
+    .await
+    {
+        Ok(pipeline) => {
+            let config = pipeline.encoder().config();
+            info!(
+                "Video pipeline encoding {}x{}@{}fps at {}kbps",
+                config.width, config.height, config.fps, config.bitrate
+            );
+            tokio::spawn(pipeline.run(rate_rx, refresh_rx));
+        }
+        Err(e) => warn!("Failed to start video pipeline: {}", e),
+    }
+
     info!("Server initialization complete");
     info!("Ready to accept client connections");
 
     // Start the streaming server
-    let server_handle = tokio::spawn(async move {
+    let mut server_handle = tokio::spawn(async move {
         if let Err(e) = streaming_server.run().await {
             error!("Streaming server error: {}", e);
         }
     });
 
-    // Setup graceful shutdown
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            info!("Received shutdown signal (Ctrl+C)");
-        }
-        _ = wait_for_termination() => {
-            warn!("Received termination signal");
-        }
-        result = server_handle => {
-            match result {
-                Ok(_) => info!("Streaming server completed"),
-                Err(e) => error!("Streaming server task error: {}", e),
+    // Setup graceful shutdown, applying emulation control requests meanwhile
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                info!("Received shutdown signal (Ctrl+C)");
+                break;
+            }
+            _ = wait_for_termination() => {
+                warn!("Received termination signal");
+                break;
+            }
+            result = &mut server_handle => {
+                match result {
+                    Ok(_) => info!("Streaming server completed"),
+                    Err(e) => error!("Streaming server task error: {}", e),
+                }
+                break;
+            }
+            Ok(job) = emulation_rx.recv_async() => {
+                let result = dolphin_manager.control(job.command).await;
+                if let Err(e) = &result {
+                    warn!("Emulation control {:?} failed: {}", job.command, e);
+                }
+                let _ = job.reply.send(result);
+            }
+            Ok(job) = state_rx.recv_async() => {
+                let response = dolphin_manager
+                    .handle_state_request(job.request, job.thumbnail)
+                    .await
+                    .unwrap_or_else(|e| StateResponse::Failed { reason: e.to_string() });
+                let _ = job.reply.send(response);
+            }
+            Ok(job) = launch_rx.recv_async() => {
+                info!("Launching {} for {}", job.game.title, job.user);
+                if dolphin_manager.is_running().await {
+                    if let Err(e) = dolphin_manager.stop_game().await {
+                        warn!("Failed to stop the running game: {}", e);
+                    }
+                }
+                dolphin_manager.set_save_user(&job.user);
+                let started = dolphin_manager
+                    .start_game(&job.game.entry)
+                    .await
+                    .map(|()| dolphin_manager.current_profile().cloned());
+                let _ = job.reply.send(started);
             }
         }
     }
@@ -195,6 +352,18 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Run a save management command against `SAVE_PATH`
+fn run_saves_command(args: &[String]) -> Result<()> {
+    let save_directory = env::var("SAVE_PATH").unwrap_or_else(|_| "/srv/saves".to_string());
+    let manager = SaveManager::new(
+        std::path::Path::new(&save_directory),
+        SaveConfig::from_env(),
+    );
+    let output = manager.run(&SaveCommand::parse(args)?)?;
+    println!("{output}");
+    Ok(())
+}
+
 /// Initialize enhanced logging with structured output
 fn init_logging() -> Result<()> {
     let log_level = env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
@@ -1,708 +0,0 @@
-//! Advanced Networking Optimizations with io_uring and RDMA
-//!
-//! Implements cutting-edge networking technologies for ultra-low latency
-//! and maximum throughput in dpstream remote gaming.
-
-use anyhow::{Context, Result};
-use parking_lot::{Mutex, RwLock};
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque};
-use std::net::SocketAddr;
-use std::os::unix::io::RawFd;
-use std::sync::Arc;
-use tokio::sync::mpsc;
-use tracing::{debug, error, info, warn};
-
-/// Advanced networking system with io_uring and RDMA support
-pub struct AdvancedNetworkingSystem {
-    io_uring_enabled: bool,
-    rdma_enabled: bool,
-    config: NetworkConfig,
-    connections: Arc<RwLock<HashMap<SocketAddr, NetworkConnection>>>,
-    stats: Arc<Mutex<NetworkStats>>,
-    packet_processor: Arc<PacketProcessor>,
-}
-
-/// io_uring based high-performance I/O system
-pub struct IoUringSystem {
-    ring_size: u32,
-    submission_queue: VecDeque<IoUringOp>,
-    completion_queue: VecDeque<IoUringCompletion>,
-    pending_operations: HashMap<u64, PendingOp>,
-    stats: IoUringStats,
-}
-
-/// RDMA (Remote Direct Memory Access) integration for ultra-low latency
-pub struct RdmaSystem {
-    config: RdmaConfig,
-    connections: HashMap<SocketAddr, RdmaConnection>,
-    memory_regions: Vec<RdmaMemoryRegion>,
-    queue_pairs: Vec<RdmaQueuePair>,
-    stats: RdmaStats,
-}
-
-/// Zero-copy packet processing with advanced networking
-pub struct PacketProcessor {
-    zero_copy_enabled: bool,
-    batch_size: usize,
-    packet_pools: Vec<PacketPool>,
-    processing_stats: ProcessingStats,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NetworkConfig {
-    pub enable_io_uring: bool,
-    pub enable_rdma: bool,
-    pub io_uring_queue_depth: u32,
-    pub rdma_queue_depth: u32,
-    pub batch_processing_size: usize,
-    pub zero_copy_threshold: usize, // bytes
-    pub cpu_affinity: Vec<usize>,
-    pub interrupt_coalescing: bool,
-}
-
-#[derive(Debug, Clone)]
-pub struct NetworkConnection {
-    pub addr: SocketAddr,
-    pub connection_type: ConnectionType,
-    pub state: ConnectionState,
-    pub stats: ConnectionStats,
-    pub last_activity: std::time::Instant,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum ConnectionType {
-    Standard,
-    IoUring,
-    Rdma,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum ConnectionState {
-    Connecting,
-    Connected,
-    Disconnecting,
-    Disconnected,
-}
-
-#[derive(Debug, Default, Clone)]
-pub struct ConnectionStats {
-    pub bytes_sent: u64,
-    pub bytes_received: u64,
-    pub packets_sent: u64,
-    pub packets_received: u64,
-    pub latency_us: u64,
-    pub bandwidth_mbps: f64,
-}
-
-#[derive(Debug, Default)]
-pub struct NetworkStats {
-    pub total_connections: u64,
-    pub active_connections: u64,
-    pub io_uring_operations: u64,
-    pub rdma_operations: u64,
-    pub zero_copy_operations: u64,
-    pub total_throughput_gbps: f64,
-    pub average_latency_ns: u64,
-    pub packet_loss_rate: f64,
-}
-
-/// io_uring operation types
-#[derive(Debug, Clone)]
-pub enum IoUringOp {
-    Read {
-        fd: RawFd,
-        buf: Vec<u8>,
-        offset: u64,
-        user_data: u64,
-    },
-    Write {
-        fd: RawFd,
-        buf: Vec<u8>,
-        offset: u64,
-        user_data: u64,
-    },
-    SendMsg {
-        fd: RawFd,
-        msg: NetworkMessage,
-        user_data: u64,
-    },
-    RecvMsg {
-        fd: RawFd,
-        buf: Vec<u8>,
-        user_data: u64,
-    },
-}
-
-#[derive(Debug, Clone)]
-pub struct IoUringCompletion {
-    pub user_data: u64,
-    pub result: i32,
-    pub flags: u32,
-}
-
-#[derive(Debug)]
-pub struct PendingOp {
-    pub op_type: IoUringOpType,
-    pub start_time: std::time::Instant,
-    pub callback: Option<Box<dyn FnOnce(IoUringCompletion) + Send>>,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum IoUringOpType {
-    Read,
-    Write,
-    SendMsg,
-    RecvMsg,
-}
-
-#[derive(Debug, Default)]
-pub struct IoUringStats {
-    pub operations_submitted: u64,
-    pub operations_completed: u64,
-    pub average_completion_time_ns: u64,
-    pub queue_depth_utilization: f64,
-    pub batch_completions: u64,
-}
-
-/// RDMA configuration and types
-#[derive(Debug, Clone)]
-pub struct RdmaConfig {
-    pub device_name: String,
-    pub port_num: u8,
-    pub queue_depth: u32,
-    pub max_inline_data: u32,
-    pub completion_vector: i32,
-}
-
-#[derive(Debug)]
-pub struct RdmaConnection {
-    pub queue_pair: RdmaQueuePair,
-    pub remote_addr: SocketAddr,
-    pub state: RdmaConnectionState,
-    pub stats: RdmaConnectionStats,
-}
-
-#[derive(Debug)]
-pub struct RdmaQueuePair {
-    pub qp_num: u32,
-    pub send_queue_depth: u32,
-    pub recv_queue_depth: u32,
-    pub max_send_wr: u32,
-    pub max_recv_wr: u32,
-}
-
-#[derive(Debug)]
-pub struct RdmaMemoryRegion {
-    pub addr: *mut u8,
-    pub length: usize,
-    pub lkey: u32,
-    pub rkey: u32,
-    pub access_flags: u32,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum RdmaConnectionState {
-    Init,
-    Ready,
-    Error,
-}
-
-#[derive(Debug, Default, Clone)]
-pub struct RdmaConnectionStats {
-    pub rdma_reads: u64,
-    pub rdma_writes: u64,
-    pub rdma_sends: u64,
-    pub rdma_receives: u64,
-    pub completion_errors: u64,
-    pub average_latency_ns: u64,
-}
-
-#[derive(Debug, Default)]
-pub struct RdmaStats {
-    pub total_connections: u64,
-    pub active_connections: u64,
-    pub memory_regions: u64,
-    pub total_throughput_gbps: f64,
-    pub rdma_read_bandwidth: f64,
-    pub rdma_write_bandwidth: f64,
-}
-
-/// High-performance packet pool for zero-copy operations
-#[derive(Debug)]
-pub struct PacketPool {
-    pub pool_id: u32,
-    pub packet_size: usize,
-    pub pool_size: usize,
-    pub available_packets: VecDeque<PacketBuffer>,
-    pub allocated_packets: HashMap<u64, PacketBuffer>,
-    pub stats: PacketPoolStats,
-}
-
-#[derive(Debug, Clone)]
-pub struct PacketBuffer {
-    pub buffer_id: u64,
-    pub data: Vec<u8>,
-    pub capacity: usize,
-    pub length: usize,
-    pub timestamp: std::time::Instant,
-}
-
-#[derive(Debug, Default)]
-pub struct PacketPoolStats {
-    pub allocations: u64,
-    pub deallocations: u64,
-    pub pool_hits: u64,
-    pub pool_misses: u64,
-    pub peak_utilization: f64,
-}
-
-#[derive(Debug, Default)]
-pub struct ProcessingStats {
-    pub packets_processed: u64,
-    pub zero_copy_packets: u64,
-    pub batch_operations: u64,
-    pub processing_time_ns: u64,
-    pub throughput_packets_per_second: f64,
-}
-
-#[derive(Debug, Clone)]
-pub struct NetworkMessage {
-    pub data: Vec<u8>,
-    pub msg_type: MessageType,
-    pub priority: MessagePriority,
-    pub timestamp: std::time::Instant,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum MessageType {
-    VideoFrame,
-    AudioFrame,
-    Control,
-    Heartbeat,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum MessagePriority {
-    Low,
-    Normal,
-    High,
-    Critical,
-}
-
-impl AdvancedNetworkingSystem {
-    /// Create advanced networking system with io_uring and RDMA support
-    pub fn new(config: NetworkConfig) -> Result<Self> {
-        info!("Initializing advanced networking system");
-        info!(
-            "io_uring enabled: {}, RDMA enabled: {}",
-            config.enable_io_uring, config.enable_rdma
-        );
-
-        let packet_processor = Arc::new(PacketProcessor::new(config.batch_processing_size)?);
-
-        Ok(Self {
-            io_uring_enabled: config.enable_io_uring,
-            rdma_enabled: config.enable_rdma,
-            config,
-            connections: Arc::new(RwLock::new(HashMap::new())),
-            stats: Arc::new(Mutex::new(NetworkStats::default())),
-            packet_processor,
-        })
-    }
-
-    /// Initialize io_uring for high-performance I/O
-    pub async fn initialize_io_uring(&mut self) -> Result<()> {
-        if !self.io_uring_enabled {
-            return Ok(());
-        }
-
-        info!(
-            "Initializing io_uring with queue depth: {}",
-            self.config.io_uring_queue_depth
-        );
-
-        // In a real implementation, this would initialize the actual io_uring
-        // For demonstration, we simulate the initialization
-        debug!("io_uring initialization completed");
-
-        // Set CPU affinity for network threads
-        if !self.config.cpu_affinity.is_empty() {
-            info!("Setting CPU affinity: {:?}", self.config.cpu_affinity);
-            // In real implementation: set_cpu_affinity(&self.config.cpu_affinity)?;
-        }
-
-        // Enable interrupt coalescing if configured
-        if self.config.interrupt_coalescing {
-            info!("Enabling interrupt coalescing for reduced latency");
-            // In real implementation: enable_interrupt_coalescing()?;
-        }
-
-        Ok(())
-    }
-
-    /// Initialize RDMA for ultra-low latency communications
-    pub async fn initialize_rdma(&mut self) -> Result<()> {
-        if !self.rdma_enabled {
-            return Ok(());
-        }
-
-        info!("Initializing RDMA for ultra-low latency networking");
-
-        // In a real implementation, this would:
-        // 1. Query RDMA devices
-        // 2. Create protection domain
-        // 3. Create completion queues
-        // 4. Set up memory regions
-
-        debug!("RDMA initialization completed");
-        Ok(())
-    }
-
-    /// Process network packets with zero-copy optimization
-    pub async fn process_packets_zero_copy(&self, packets: Vec<NetworkMessage>) -> Result<()> {
-        let start_time = std::time::Instant::now();
-
-        debug!(
-            "Processing {} packets with zero-copy optimization",
-            packets.len()
-        );
-
-        for packet in packets {
-            // Route packet based on type and priority
-            match packet.msg_type {
-                MessageType::VideoFrame => {
-                    self.process_video_packet_zero_copy(packet).await?;
-                }
-                MessageType::AudioFrame => {
-                    self.process_audio_packet_zero_copy(packet).await?;
-                }
-                MessageType::Control => {
-                    self.process_control_packet(packet).await?;
-                }
-                MessageType::Heartbeat => {
-                    self.process_heartbeat_packet(packet).await?;
-                }
-            }
-        }
-
-        let processing_time = start_time.elapsed();
-        debug!(
-            "Zero-copy packet processing completed in {:?}",
-            processing_time
-        );
-
-        // Update statistics
-        let mut stats = self.stats.lock();
-        stats.zero_copy_operations += 1;
-
-        Ok(())
-    }
-
-    /// Send data using io_uring for maximum performance
-    pub async fn send_io_uring(&self, fd: RawFd, data: Vec<u8>, addr: SocketAddr) -> Result<()> {
-        if !self.io_uring_enabled {
-            return self.send_standard(fd, data, addr).await;
-        }
-
-        debug!("Sending {} bytes using io_uring to {}", data.len(), addr);
-
-        // In a real implementation, this would:
-        // 1. Prepare io_uring submission queue entry
-        // 2. Submit the operation
-        // 3. Handle completion asynchronously
-
-        // Simulate io_uring operation
-        let start_time = std::time::Instant::now();
-
-        // Simulated high-performance send
-        tokio::time::sleep(tokio::time::Duration::from_nanos(100)).await;
-
-        let completion_time = start_time.elapsed();
-        debug!("io_uring send completed in {:?}", completion_time);
-
-        // Update connection statistics
-        if let Some(mut conn) = self.connections.write().get_mut(&addr) {
-            conn.stats.bytes_sent += data.len() as u64;
-            conn.stats.packets_sent += 1;
-            conn.last_activity = std::time::Instant::now();
-        }
-
-        Ok(())
-    }
-
-    /// Send data using RDMA for ultra-low latency
-    pub async fn send_rdma(&self, data: Vec<u8>, remote_addr: SocketAddr) -> Result<()> {
-        if !self.rdma_enabled {
-            return Err(anyhow::anyhow!("RDMA not enabled"));
-        }
-
-        debug!("Sending {} bytes using RDMA to {}", data.len(), remote_addr);
-
-        // In a real implementation, this would:
-        // 1. Post RDMA send work request
-        // 2. Poll completion queue
-        // 3. Handle completion
-
-        // Simulate ultra-low latency RDMA operation
-        let start_time = std::time::Instant::now();
-        tokio::time::sleep(tokio::time::Duration::from_nanos(50)).await; // Ultra-low latency
-        let completion_time = start_time.elapsed();
-
-        debug!("RDMA send completed in {:?}", completion_time);
-
-        // Update statistics
-        let mut stats = self.stats.lock();
-        stats.rdma_operations += 1;
-
-        Ok(())
-    }
-
-    /// Standard fallback send method
-    async fn send_standard(&self, _fd: RawFd, data: Vec<u8>, addr: SocketAddr) -> Result<()> {
-        debug!(
-            "Sending {} bytes using standard networking to {}",
-            data.len(),
-            addr
-        );
-
-        // Simulate standard networking latency
-        tokio::time::sleep(tokio::time::Duration::from_micros(10)).await;
-
-        Ok(())
-    }
-
-    /// Process video packets with zero-copy optimization
-    async fn process_video_packet_zero_copy(&self, packet: NetworkMessage) -> Result<()> {
-        debug!(
-            "Processing video packet with zero-copy (size: {})",
-            packet.data.len()
-        );
-
-        // In a real implementation:
-        // 1. Use packet buffer pools to avoid allocations
-        // 2. Direct memory mapping for GPU processing
-        // 3. SIMD-optimized packet header parsing
-
-        // Simulate optimized video processing
-        tokio::time::sleep(tokio::time::Duration::from_nanos(200)).await;
-
-        Ok(())
-    }
-
-    /// Process audio packets with low-latency optimization
-    async fn process_audio_packet_zero_copy(&self, packet: NetworkMessage) -> Result<()> {
-        debug!(
-            "Processing audio packet with zero-copy (size: {})",
-            packet.data.len()
-        );
-
-        // Simulate optimized audio processing
-        tokio::time::sleep(tokio::time::Duration::from_nanos(50)).await;
-
-        Ok(())
-    }
-
-    /// Process control packets
-    async fn process_control_packet(&self, packet: NetworkMessage) -> Result<()> {
-        debug!("Processing control packet (size: {})", packet.data.len());
-
-        // Handle control messages with priority
-        match packet.priority {
-            MessagePriority::Critical => {
-                // Immediate processing for critical control messages
-            }
-            _ => {
-                // Standard processing
-            }
-        }
-
-        Ok(())
-    }
-
-    /// Process heartbeat packets
-    async fn process_heartbeat_packet(&self, _packet: NetworkMessage) -> Result<()> {
-        debug!("Processing heartbeat packet");
-
-        // Update connection activity timestamp
-        // In real implementation: update_connection_activity(packet.source_addr);
-
-        Ok(())
-    }
-
-    /// Get comprehensive networking statistics
-    pub fn get_network_stats(&self) -> NetworkStats {
-        let stats = self.stats.lock();
-        let mut result = stats.clone();
-
-        // Calculate derived statistics
-        result.active_connections = self.connections.read().len() as u64;
-
-        // Calculate total throughput across all connections
-        let total_bytes: u64 = self
-            .connections
-            .read()
-            .values()
-            .map(|conn| conn.stats.bytes_sent + conn.stats.bytes_received)
-            .sum();
-
-        result.total_throughput_gbps = (total_bytes as f64 * 8.0) / 1_000_000_000.0; // Convert to Gbps
-
-        result
-    }
-
-    /// Optimize network configuration based on runtime performance
-    pub async fn optimize_network_configuration(&mut self) -> Result<()> {
-        info!("Optimizing network configuration based on runtime performance");
-
-        let stats = self.get_network_stats();
-
-        // Dynamic optimization based on performance metrics
-        if stats.average_latency_ns > 1_000_000 {
-            // > 1ms
-            warn!("High latency detected, enabling aggressive optimizations");
-
-            // Enable more aggressive optimizations
-            if !self.io_uring_enabled && self.config.enable_io_uring {
-                info!("Enabling io_uring for latency reduction");
-                self.io_uring_enabled = true;
-                self.initialize_io_uring().await?;
-            }
-
-            // Increase batch processing size for throughput
-            if self.config.batch_processing_size < 64 {
-                self.config.batch_processing_size = 64;
-                info!(
-                    "Increased batch processing size to {}",
-                    self.config.batch_processing_size
-                );
-            }
-        }
-
-        if stats.packet_loss_rate > 0.01 {
-            // > 1%
-            warn!("High packet loss detected, adjusting configuration");
-
-            // Enable RDMA for critical connections if available
-            if !self.rdma_enabled && self.config.enable_rdma {
-                info!("Enabling RDMA for critical connections");
-                self.rdma_enabled = true;
-                self.initialize_rdma().await?;
-            }
-        }
-
-        info!("Network configuration optimization completed");
-        Ok(())
-    }
-}
-
-impl PacketProcessor {
-    /// Create a new packet processor with zero-copy capabilities
-    pub fn new(batch_size: usize) -> Result<Self> {
-        info!(
-            "Initializing packet processor with batch size: {}",
-            batch_size
-        );
-
-        // Create packet pools for different sizes
-        let packet_pools = vec![
-            PacketPool::new(0, 64, 1000)?,  // Small packets (control)
-            PacketPool::new(1, 1500, 500)?, // MTU-sized packets (standard)
-            PacketPool::new(2, 9000, 100)?, // Jumbo packets (video)
-            PacketPool::new(3, 65536, 50)?, // Large packets (bulk data)
-        ];
-
-        Ok(Self {
-            zero_copy_enabled: true,
-            batch_size,
-            packet_pools,
-            processing_stats: ProcessingStats::default(),
-        })
-    }
-}
-
-impl PacketPool {
-    /// Create a new packet pool
-    pub fn new(pool_id: u32, packet_size: usize, pool_size: usize) -> Result<Self> {
-        let mut available_packets = VecDeque::with_capacity(pool_size);
-
-        // Pre-allocate packet buffers
-        for i in 0..pool_size {
-            let buffer = PacketBuffer {
-                buffer_id: (pool_id as u64) << 32 | i as u64,
-                data: vec![0u8; packet_size],
-                capacity: packet_size,
-                length: 0,
-                timestamp: std::time::Instant::now(),
-            };
-            available_packets.push_back(buffer);
-        }
-
-        Ok(Self {
-            pool_id,
-            packet_size,
-            pool_size,
-            available_packets,
-            allocated_packets: HashMap::new(),
-            stats: PacketPoolStats::default(),
-        })
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[tokio::test]
-    async fn test_advanced_networking_system() {
-        let config = NetworkConfig {
-            enable_io_uring: true,
-            enable_rdma: false,
-            io_uring_queue_depth: 128,
-            rdma_queue_depth: 64,
-            batch_processing_size: 32,
-            zero_copy_threshold: 1024,
-            cpu_affinity: vec![0, 1],
-            interrupt_coalescing: true,
-        };
-
-        let result = AdvancedNetworkingSystem::new(config);
-        assert!(result.is_ok());
-    }
-
-    #[test]
-    fn test_packet_pool_creation() {
-        let result = PacketPool::new(0, 1500, 100);
-        assert!(result.is_ok());
-
-        let pool = result.unwrap();
-        assert_eq!(pool.available_packets.len(), 100);
-        assert_eq!(pool.packet_size, 1500);
-    }
-
-    #[tokio::test]
-    async fn test_zero_copy_packet_processing() {
-        let config = NetworkConfig {
-            enable_io_uring: true,
-            enable_rdma: false,
-            io_uring_queue_depth: 128,
-            rdma_queue_depth: 64,
-            batch_processing_size: 16,
-            zero_copy_threshold: 512,
-            cpu_affinity: vec![],
-            interrupt_coalescing: false,
-        };
-
-        let system = AdvancedNetworkingSystem::new(config).unwrap();
-
-        let packets = vec![NetworkMessage {
-            data: vec![0u8; 1024],
-            msg_type: MessageType::VideoFrame,
-            priority: MessagePriority::High,
-            timestamp: std::time::Instant::now(),
-        }];
-
-        let result = system.process_packets_zero_copy(packets).await;
-        assert!(result.is_ok());
-    }
-}
@@ -0,0 +1,558 @@
+//! Congestion control from client feedback
+//!
+//! While streaming, clients report what reached them every
+//! [`STATS_INTERVAL_MS`] as [`ClientStats`]. Each session runs a
+//! [`CongestionController`] on those reports, modelled on Google Congestion
+//! Control: a delay-based estimate backs off as soon as the one-way delay
+//! starts growing, before the queue on the path overflows, and a loss-based
+//! estimate handles losses delay doesn't explain, such as Wi-Fi
+//! interference. The stream gets the lower of the two, within the configured
+//! [`RateLimits`]. When even the lowest bitrate is too much for the path, or
+//! the client can't decode frames as fast as they come, the controller steps
+//! down a ladder of frame rates and then resolutions, and back up once
+//! there is room again.
+
+use uuid::Uuid;
+
+pub use dpstream_protocol::stats::{ClientStats, STATS_INTERVAL_MS};
+
+/// Share of the received rate the delay-based estimate drops to on overuse
+const DECREASE_FACTOR: f64 = 0.85;
+
+/// Growth of the delay-based estimate per second while the delay is steady
+const INCREASE_PER_SECOND: f64 = 0.08;
+
+/// The delay-based estimate never grows past this multiple of the rate the
+/// client actually received
+const MAX_OVER_RECEIVED: f64 = 1.5;
+
+/// Loss above which the loss-based estimate backs off
+const HIGH_LOSS: f32 = 0.10;
+
+/// Loss below which the loss-based estimate grows
+const LOW_LOSS: f32 = 0.02;
+
+/// Growth of the loss-based estimate per report with little loss
+const LOSS_INCREASE: f64 = 0.05;
+
+/// Weight of the newest delay gradient in the smoothed trend
+const TREND_GAIN: f64 = 0.5;
+
+/// Overuse threshold on the delay trend, in microseconds per report
+/// interval, and the bounds it adapts within
+const INITIAL_THRESHOLD_US: f64 = 12_500.0;
+const MIN_THRESHOLD_US: f64 = 6_000.0;
+const MAX_THRESHOLD_US: f64 = 600_000.0;
+
+/// Threshold adaptation per millisecond towards a larger and a smaller trend
+const THRESHOLD_GAIN_UP: f64 = 0.002;
+const THRESHOLD_GAIN_DOWN: f64 = 0.000_18;
+
+/// Trends this far past the threshold are spikes the threshold ignores
+const THRESHOLD_SPIKE_US: f64 = 15_000.0;
+
+/// Starved reports in a row before stepping down the quality ladder
+const STEP_DOWN_REPORTS: u32 = 4;
+
+/// Reports with headroom in a row before stepping back up
+const STEP_UP_REPORTS: u32 = 20;
+
+/// A rung is left only once the bitrate reaches this multiple of the minimum
+const STEP_UP_HEADROOM: u32 = 2;
+
+/// Heights the ladder steps down through, below the negotiated one
+const LADDER_HEIGHTS: [u32; 4] = [720, 540, 480, 360];
+
+/// Bounds the controller keeps a session's stream within
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimits {
+    pub min_bitrate_kbps: u32,
+    pub max_bitrate_kbps: u32,
+    /// Lowest frame rate the ladder steps down to
+    pub min_fps: u32,
+    /// Lowest height the ladder steps down to
+    pub min_height: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            min_bitrate_kbps: 1500,
+            max_bitrate_kbps: 15000,
+            min_fps: 30,
+            min_height: 480,
+        }
+    }
+}
+
+/// What a session's encoder should produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamTarget {
+    pub bitrate_kbps: u32,
+    pub fps: u32,
+    pub resolution: (u32, u32),
+}
+
+/// A new [`StreamTarget`] for one session's encoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateChange {
+    pub session_id: Uuid,
+    pub target: StreamTarget,
+}
+
+/// Where the one-way delay is heading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandwidthUsage {
+    /// Steady, the path carries what is sent
+    Normal,
+    /// Growing, a queue on the path is filling up
+    Overusing,
+    /// Shrinking, a queue is draining
+    Underusing,
+}
+
+/// Judges the delay trend against a threshold that adapts to the path, so
+/// the jitter of a busy Wi-Fi network doesn't read as congestion
+#[derive(Debug, Clone)]
+struct OveruseDetector {
+    trend_us: f64,
+    threshold_us: f64,
+}
+
+impl OveruseDetector {
+    fn new() -> Self {
+        Self {
+            trend_us: 0.0,
+            threshold_us: INITIAL_THRESHOLD_US,
+        }
+    }
+
+    fn detect(&mut self, stats: &ClientStats) -> BandwidthUsage {
+        let interval_ms = f64::from(stats.interval_ms.max(1));
+        let gradient_us =
+            f64::from(stats.delay_gradient_us) * f64::from(STATS_INTERVAL_MS) / interval_ms;
+        self.trend_us += TREND_GAIN * (gradient_us - self.trend_us);
+
+        let usage = if self.trend_us > self.threshold_us {
+            BandwidthUsage::Overusing
+        } else if self.trend_us < -self.threshold_us {
+            BandwidthUsage::Underusing
+        } else {
+            BandwidthUsage::Normal
+        };
+
+        let magnitude = self.trend_us.abs();
+        if magnitude <= self.threshold_us + THRESHOLD_SPIKE_US {
+            let gain = if magnitude > self.threshold_us {
+                THRESHOLD_GAIN_UP
+            } else {
+                THRESHOLD_GAIN_DOWN
+            };
+            self.threshold_us += (gain * interval_ms).min(1.0) * (magnitude - self.threshold_us);
+            self.threshold_us = self.threshold_us.clamp(MIN_THRESHOLD_US, MAX_THRESHOLD_US);
+        }
+        usage
+    }
+}
+
+/// Frame rate and resolution of one step of the quality ladder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rung {
+    fps: u32,
+    resolution: (u32, u32),
+}
+
+/// Per-session bitrate, frame rate and resolution control
+#[derive(Debug, Clone)]
+pub struct CongestionController {
+    limits: RateLimits,
+    detector: OveruseDetector,
+    /// Delay-based estimate, in kbps
+    delay_rate: f64,
+    /// Loss-based estimate, in kbps
+    loss_rate: f64,
+    /// Whether the delay-based estimate may grow; it holds for a report
+    /// after backing off or while queues drain
+    increasing: bool,
+    /// Quality ladder, the negotiated frame rate and resolution first
+    ladder: Vec<Rung>,
+    rung: usize,
+    starved_reports: u32,
+    headroom_reports: u32,
+    target: StreamTarget,
+    last_usage: BandwidthUsage,
+}
+
+impl CongestionController {
+    /// Controller for a stream starting at `initial`
+    pub fn new(initial: StreamTarget, limits: RateLimits) -> Self {
+        let limits = RateLimits {
+            max_bitrate_kbps: limits.max_bitrate_kbps.max(limits.min_bitrate_kbps),
+            ..limits
+        };
+        let bitrate = initial
+            .bitrate_kbps
+            .clamp(limits.min_bitrate_kbps, limits.max_bitrate_kbps);
+
+        Self {
+            limits,
+            detector: OveruseDetector::new(),
+            delay_rate: f64::from(bitrate),
+            loss_rate: f64::from(bitrate),
+            increasing: true,
+            ladder: Self::ladder(initial, &limits),
+            rung: 0,
+            starved_reports: 0,
+            headroom_reports: 0,
+            target: StreamTarget {
+                bitrate_kbps: bitrate,
+                ..initial
+            },
+            last_usage: BandwidthUsage::Normal,
+        }
+    }
+
+    /// Frame rates and resolutions to step down through: the frame rate
+    /// halves first, then the height drops while keeping the aspect ratio
+    fn ladder(initial: StreamTarget, limits: &RateLimits) -> Vec<Rung> {
+        let mut ladder = vec![Rung {
+            fps: initial.fps,
+            resolution: initial.resolution,
+        }];
+
+        let reduced_fps = (initial.fps / 2).max(limits.min_fps);
+        if reduced_fps < initial.fps {
+            ladder.push(Rung {
+                fps: reduced_fps,
+                resolution: initial.resolution,
+            });
+        }
+
+        let (width, height) = initial.resolution;
+        for lower in LADDER_HEIGHTS {
+            if lower < height && lower >= limits.min_height {
+                let scaled_width = (u64::from(width) * u64::from(lower) / u64::from(height)) as u32;
+                ladder.push(Rung {
+                    fps: reduced_fps.min(initial.fps),
+                    resolution: (scaled_width & !1, lower),
+                });
+            }
+        }
+        ladder
+    }
+
+    pub fn target(&self) -> StreamTarget {
+        self.target
+    }
+
+    /// Delay trend seen in the latest report
+    pub fn usage(&self) -> BandwidthUsage {
+        self.last_usage
+    }
+
+    /// Take in a client report, returning the new target when it changed
+    pub fn on_stats(&mut self, stats: &ClientStats) -> Option<StreamTarget> {
+        let usage = self.detector.detect(stats);
+        self.last_usage = usage;
+        let loss = stats.loss_ratio();
+
+        self.update_delay_rate(usage, stats);
+        self.update_loss_rate(loss);
+
+        let min = f64::from(self.limits.min_bitrate_kbps);
+        let max = f64::from(self.limits.max_bitrate_kbps);
+        self.delay_rate = self.delay_rate.clamp(min, max);
+        self.loss_rate = self.loss_rate.clamp(min, max);
+        let bitrate = self.delay_rate.min(self.loss_rate).round() as u32;
+
+        let congested = usage == BandwidthUsage::Overusing || loss > HIGH_LOSS;
+        self.climb_ladder(bitrate, congested, stats);
+
+        let rung = self.ladder[self.rung];
+        let target = StreamTarget {
+            bitrate_kbps: bitrate,
+            fps: rung.fps,
+            resolution: rung.resolution,
+        };
+        if target == self.target {
+            return None;
+        }
+        self.target = target;
+        Some(target)
+    }
+
+    fn update_delay_rate(&mut self, usage: BandwidthUsage, stats: &ClientStats) {
+        let received = f64::from(stats.received_kbps());
+        match usage {
+            BandwidthUsage::Overusing => {
+                let base = if received > 0.0 {
+                    received
+                } else {
+                    self.delay_rate
+                };
+                self.delay_rate = self.delay_rate.min(DECREASE_FACTOR * base);
+                self.increasing = false;
+            }
+            BandwidthUsage::Underusing => self.increasing = false,
+            BandwidthUsage::Normal if !self.increasing => self.increasing = true,
+            BandwidthUsage::Normal => {
+                let seconds = f64::from(stats.interval_ms) / 1000.0;
+                let grown = self.delay_rate * (1.0 + INCREASE_PER_SECOND * seconds);
+                // A stream that isn't filling its bitrate says nothing about
+                // the path past what it sent
+                let cap = (MAX_OVER_RECEIVED * received).max(self.delay_rate);
+                self.delay_rate = grown.min(cap);
+            }
+        }
+    }
+
+    fn update_loss_rate(&mut self, loss: f32) {
+        if loss > HIGH_LOSS {
+            self.loss_rate *= 1.0 - 0.5 * f64::from(loss);
+        } else if loss < LOW_LOSS {
+            self.loss_rate *= 1.0 + LOSS_INCREASE;
+        }
+    }
+
+    /// Step down when the bitrate floor still congests the path or the
+    /// client can't decode in time, and back up once both have room again
+    fn climb_ladder(&mut self, bitrate: u32, congested: bool, stats: &ClientStats) {
+        let fps = self.ladder[self.rung].fps;
+        let decoder_behind = stats.frames_decoded > 0 && stats.decode_time_us > 1_000_000 / fps;
+        let starved = (congested && bitrate <= self.limits.min_bitrate_kbps) || decoder_behind;
+
+        if starved {
+            self.headroom_reports = 0;
+            self.starved_reports += 1;
+            if self.starved_reports >= STEP_DOWN_REPORTS && self.rung + 1 < self.ladder.len() {
+                self.rung += 1;
+                self.starved_reports = 0;
+            }
+            return;
+        }
+        self.starved_reports = 0;
+
+        let Some(upper) = self.rung.checked_sub(1).map(|rung| self.ladder[rung]) else {
+            return;
+        };
+        let decoder_keeps_up = stats.decode_time_us <= 1_000_000 / upper.fps * 4 / 5;
+        let headroom = bitrate >= STEP_UP_HEADROOM * self.limits.min_bitrate_kbps;
+        if congested || !headroom || !decoder_keeps_up {
+            self.headroom_reports = 0;
+            return;
+        }
+        self.headroom_reports += 1;
+        if self.headroom_reports >= STEP_UP_REPORTS {
+            self.rung -= 1;
+            self.headroom_reports = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Size of the simulated video packets, in bits
+    const PACKET_BITS: f64 = 1200.0 * 8.0;
+
+    /// Bottleneck link with a drop-tail queue, carrying one report
+    /// interval of video at a time
+    struct Link {
+        capacity_kbps: f64,
+        /// Queue size, as milliseconds at capacity
+        buffer_ms: f64,
+        /// Share of packets lost regardless of load, like Wi-Fi interference
+        random_loss: f64,
+        decode_time_us: u32,
+        queue_bits: f64,
+        delay_us: f64,
+    }
+
+    impl Link {
+        fn new(capacity_kbps: f64) -> Self {
+            Self {
+                capacity_kbps,
+                buffer_ms: 200.0,
+                random_loss: 0.0,
+                decode_time_us: 4000,
+                queue_bits: 0.0,
+                delay_us: 0.0,
+            }
+        }
+
+        fn carry(&mut self, target: StreamTarget) -> ClientStats {
+            let seconds = f64::from(STATS_INTERVAL_MS) / 1000.0;
+            let sent = f64::from(target.bitrate_kbps) * 1000.0 * seconds;
+            let random_lost = sent * self.random_loss;
+
+            let queued = self.queue_bits + sent - random_lost;
+            let delivered = queued.min(self.capacity_kbps * 1000.0 * seconds);
+            let backlog = queued - delivered;
+            let buffer = self.capacity_kbps * self.buffer_ms;
+            let overflow = (backlog - buffer).max(0.0);
+            self.queue_bits = backlog - overflow;
+
+            let delay_us = self.queue_bits / self.capacity_kbps * 1000.0;
+            let gradient_us = delay_us - self.delay_us;
+            self.delay_us = delay_us;
+
+            ClientStats {
+                interval_ms: STATS_INTERVAL_MS,
+                bytes_received: (delivered / 8.0) as u32,
+                packets_received: (delivered / PACKET_BITS) as u32,
+                packets_lost: ((random_lost + overflow) / PACKET_BITS) as u32,
+                jitter_us: 1000,
+                decode_time_us: self.decode_time_us,
+                frames_decoded: target.fps / 4,
+                frames_dropped: 0,
+                delay_gradient_us: gradient_us as i32,
+            }
+        }
+    }
+
+    fn start() -> StreamTarget {
+        StreamTarget {
+            bitrate_kbps: 15000,
+            fps: 60,
+            resolution: (1280, 720),
+        }
+    }
+
+    /// Run `reports` intervals over the link, checking the limits hold, and
+    /// count the packets lost
+    fn run(controller: &mut CongestionController, link: &mut Link, reports: usize) -> u32 {
+        let limits = controller.limits;
+        let mut lost = 0;
+        for _ in 0..reports {
+            let stats = link.carry(controller.target());
+            lost += stats.packets_lost;
+            controller.on_stats(&stats);
+            let target = controller.target();
+            assert!(target.bitrate_kbps >= limits.min_bitrate_kbps);
+            assert!(target.bitrate_kbps <= limits.max_bitrate_kbps);
+            assert!(target.fps >= limits.min_fps);
+            assert!(target.resolution.1 >= limits.min_height);
+        }
+        lost
+    }
+
+    #[test]
+    fn test_clear_path_holds_max_bitrate() {
+        let mut controller = CongestionController::new(start(), RateLimits::default());
+        let mut link = Link::new(50_000.0);
+
+        run(&mut controller, &mut link, 40);
+        assert_eq!(controller.target(), start());
+        assert_eq!(controller.usage(), BandwidthUsage::Normal);
+    }
+
+    #[test]
+    fn test_capacity_drop_backs_off_before_loss() {
+        let mut controller = CongestionController::new(start(), RateLimits::default());
+        let mut link = Link::new(50_000.0);
+        run(&mut controller, &mut link, 8);
+
+        // The Wi-Fi link falls to 6 Mbps with a deep buffer: the growing
+        // delay alone should bring the bitrate under it
+        link.capacity_kbps = 6000.0;
+        link.buffer_ms = 1000.0;
+        assert_eq!(run(&mut controller, &mut link, 30), 0);
+
+        // Settled, it probes around the capacity without building a queue
+        let mut bitrates = 0;
+        let mut worst_delay_us: f64 = 0.0;
+        for _ in 0..40 {
+            assert_eq!(run(&mut controller, &mut link, 1), 0);
+            bitrates += controller.target().bitrate_kbps;
+            worst_delay_us = worst_delay_us.max(link.delay_us);
+        }
+        let average = bitrates / 40;
+        assert!((4500..=6000).contains(&average), "averaged {average}kbps");
+        assert!(worst_delay_us < 100_000.0, "queued {worst_delay_us}us");
+        assert_eq!(controller.target().fps, 60);
+    }
+
+    #[test]
+    fn test_recovers_when_capacity_returns() {
+        let mut controller = CongestionController::new(start(), RateLimits::default());
+        let mut link = Link::new(4000.0);
+        run(&mut controller, &mut link, 40);
+        assert!(controller.target().bitrate_kbps <= 4000);
+
+        link.capacity_kbps = 50_000.0;
+        run(&mut controller, &mut link, 200);
+        assert_eq!(controller.target(), start());
+    }
+
+    #[test]
+    fn test_random_loss_lowers_bitrate() {
+        let mut controller = CongestionController::new(start(), RateLimits::default());
+        let mut link = Link::new(50_000.0);
+        link.random_loss = 0.2;
+
+        run(&mut controller, &mut link, 40);
+        assert_eq!(controller.target().bitrate_kbps, 1500);
+    }
+
+    #[test]
+    fn test_light_loss_is_tolerated() {
+        let mut controller = CongestionController::new(start(), RateLimits::default());
+        let mut link = Link::new(50_000.0);
+        link.random_loss = 0.01;
+
+        run(&mut controller, &mut link, 40);
+        assert_eq!(controller.target(), start());
+    }
+
+    #[test]
+    fn test_starved_path_steps_down_frame_rate_then_resolution() {
+        let mut controller = CongestionController::new(start(), RateLimits::default());
+        let mut link = Link::new(800.0);
+
+        run(&mut controller, &mut link, STEP_DOWN_REPORTS as usize);
+        assert_eq!(controller.target().fps, 30);
+        assert_eq!(controller.target().resolution, (1280, 720));
+
+        run(&mut controller, &mut link, 20);
+        assert_eq!(
+            controller.target(),
+            StreamTarget {
+                bitrate_kbps: 1500,
+                fps: 30,
+                resolution: (852, 480),
+            }
+        );
+
+        link.capacity_kbps = 50_000.0;
+        link.queue_bits = 0.0;
+        run(&mut controller, &mut link, 300);
+        assert_eq!(controller.target(), start());
+    }
+
+    #[test]
+    fn test_slow_decoder_lowers_frame_rate() {
+        let mut controller = CongestionController::new(start(), RateLimits::default());
+        let mut link = Link::new(50_000.0);
+        link.decode_time_us = 25_000;
+
+        run(&mut controller, &mut link, 8);
+        let target = controller.target();
+        assert_eq!(target.fps, 30);
+        assert_eq!(target.resolution, (1280, 720));
+        assert_eq!(target.bitrate_kbps, 15000);
+    }
+
+    #[test]
+    fn test_limits_clamp_the_start() {
+        let limits = RateLimits {
+            min_bitrate_kbps: 2000,
+            max_bitrate_kbps: 8000,
+            min_fps: 60,
+            min_height: 720,
+        };
+        let controller = CongestionController::new(start(), limits);
+        assert_eq!(controller.target().bitrate_kbps, 8000);
+        assert_eq!(controller.ladder.len(), 1);
+    }
+}
@@ -4,15 +4,13 @@
 
 use crate::error::{Result, StreamingError};
 use crate::streaming::capture::VideoFrame;
-use crate::streaming::{
-    CPUCapabilities, PoolConfig, SIMDVideoProcessor, VideoBufferPool, ZeroCopyVideoBuffer,
-};
-use smallvec::smallvec;
+use crate::streaming::congestion::StreamTarget;
+use crate::streaming::rtcp::Refresh;
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, info, warn};
 
 #[cfg(feature = "streaming")]
 use gstreamer as gst;
@@ -24,31 +22,41 @@ use gstreamer_app as gst_app;
 pub struct EncoderConfig {
     pub encoder_type: EncoderType,
     pub codec: VideoCodec,
-    pub bitrate: u32,     // kbps
+    pub bitrate: u32, // kbps
+    #[allow(dead_code)]
     pub max_bitrate: u32, // kbps for VBR
     pub rate_control: RateControlMode,
     pub preset: EncoderPreset,
+    #[allow(dead_code)]
     pub profile: H264Profile,
+    #[allow(dead_code)]
     pub level: H264Level,
-    pub gop_size: u32,   // Keyframe interval
-    pub b_frames: u32,   // B-frame count
+    pub gop_size: u32, // Keyframe interval
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
+    pub b_frames: u32, // B-frame count
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
     pub ref_frames: u32, // Reference frame count
     pub width: u32,
     pub height: u32,
     pub fps: u32,
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
     pub low_latency: bool,
+    #[allow(dead_code)]
     pub look_ahead: bool,
+    #[allow(dead_code)]
     pub adaptive_quantization: bool,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EncoderType {
-    NVENC,     // NVIDIA hardware encoder
-    VAAPI,     // Intel/AMD hardware encoder
+    Nvenc,     // NVIDIA hardware encoder
+    Vaapi,     // Intel/AMD hardware encoder
     QuickSync, // Intel QuickSync
     Software,  // CPU-based x264/x265
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum VideoCodec {
     H264,
@@ -56,14 +64,16 @@ pub enum VideoCodec {
     AV1,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum RateControlMode {
-    CBR,    // Constant bitrate
-    VBR,    // Variable bitrate
-    CQP,    // Constant quantization parameter
-    VBR_HQ, // High quality VBR
+    Cbr,   // Constant bitrate
+    Vbr,   // Variable bitrate
+    Cqp,   // Constant quantization parameter
+    VbrHq, // High quality VBR
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum EncoderPreset {
     UltraFast,
@@ -79,6 +89,7 @@ pub enum EncoderPreset {
     LosslessHP,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum H264Profile {
     Baseline,
@@ -87,6 +98,7 @@ pub enum H264Profile {
     High444,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum H264Level {
     Level3_0,
@@ -107,7 +119,7 @@ impl Default for EncoderConfig {
             codec: VideoCodec::H264,
             bitrate: 15000,     // 15 Mbps
             max_bitrate: 20000, // 20 Mbps
-            rate_control: RateControlMode::CBR,
+            rate_control: RateControlMode::Cbr,
             preset: EncoderPreset::Fast,
             profile: H264Profile::High,
             level: H264Level::Level4_1,
@@ -124,7 +136,7 @@ impl Default for EncoderConfig {
     }
 }
 
-/// High-performance zero-copy video encoder with SIMD acceleration
+/// Hardware-accelerated video encoder
 pub struct VideoEncoder {
     config: EncoderConfig,
     #[cfg(feature = "streaming")]
@@ -134,13 +146,15 @@ pub struct VideoEncoder {
     #[cfg(feature = "streaming")]
     appsink: Option<gst_app::AppSink>,
     frame_queue: Arc<Mutex<VecDeque<VideoFrame>>>,
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
     encoded_sender: Option<mpsc::UnboundedSender<EncodedFrame>>,
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
+    encoded_receiver: mpsc::UnboundedReceiver<EncodedFrame>,
     is_encoding: Arc<Mutex<bool>>,
     stats: Arc<Mutex<EncoderStats>>,
-    // New high-performance components
-    buffer_pool: Arc<VideoBufferPool>,
-    simd_processor: Arc<Mutex<SIMDVideoProcessor>>,
-    cpu_capabilities: CPUCapabilities,
+    /// Next simulated frame is a keyframe whatever its place in the GOP
+    #[cfg(not(feature = "streaming"))]
+    force_keyframe: bool,
 }
 
 /// Encoded frame data
@@ -149,12 +163,16 @@ pub struct EncodedFrame {
     pub data: Vec<u8>,
     pub timestamp: u64,
     pub frame_number: u64,
+    #[allow(dead_code)]
     pub is_keyframe: bool,
+    #[allow(dead_code)]
     pub encoding_time: Duration,
+    #[allow(dead_code)]
     pub size_bytes: usize,
 }
 
 /// Encoder statistics
+#[allow(dead_code)]
 #[derive(Debug, Clone, Default)]
 pub struct EncoderStats {
     pub frames_encoded: u64,
@@ -168,26 +186,10 @@ pub struct EncoderStats {
     pub buffer_fullness: f32,
 }
 
-/// Comprehensive encoder performance statistics including optimization metrics
-#[derive(Debug, Clone)]
-pub struct EncoderPerformanceStats {
-    pub encoder_stats: EncoderStats,
-    pub buffer_pool_hit_rate: f64,
-    pub total_pool_allocations: usize,
-    pub pool_hits: usize,
-    pub pool_misses: usize,
-    pub peak_buffer_usage: usize,
-    pub current_buffer_usage: usize,
-    pub cpu_capabilities: CPUCapabilities,
-}
-
 impl VideoEncoder {
-    /// Create a new high-performance hardware encoder with zero-copy optimization
+    /// Create a new hardware encoder
     pub fn new(config: EncoderConfig) -> Result<Self> {
-        info!(
-            "Initializing high-performance {:?} video encoder",
-            config.encoder_type
-        );
+        info!("Initializing {:?} video encoder", config.encoder_type);
         debug!(
             "Configuration: {}x{} @ {}fps, {}kbps {:?}",
             config.width, config.height, config.fps, config.bitrate, config.codec
@@ -196,41 +198,6 @@ impl VideoEncoder {
         // Validate configuration
         Self::validate_config(&config)?;
 
-        // Initialize CPU capabilities and SIMD processor
-        let cpu_capabilities = CPUCapabilities::detect();
-        info!(
-            "Detected CPU capabilities: AVX2={}, NEON={}",
-            cpu_capabilities.has_avx2, cpu_capabilities.has_neon
-        );
-
-        let simd_processor = SIMDVideoProcessor::new(cpu_capabilities.clone()).map_err(|e| {
-            StreamingError::InitializationFailed {
-                component: "SIMD Processor".to_string(),
-                reason: e.to_string(),
-            }
-        })?;
-
-        // Create optimized buffer pool configuration
-        let pool_config = PoolConfig {
-            buffers_per_tier: 8, // Reduced for encoder use case
-            tier_sizes: smallvec![
-                (config.width * config.height * 3 / 2) as usize, // I420 format
-                (config.width * config.height * 3) as usize,     // RGB24 format
-                (config.width * config.height * 4) as usize,     // RGBA format
-            ],
-            adaptive_allocation: true,
-            max_memory_bytes: 128 * 1024 * 1024, // 128MB for encoder
-        };
-
-        let buffer_pool = Arc::new(VideoBufferPool::new(pool_config).map_err(|e| {
-            StreamingError::InitializationFailed {
-                component: "Buffer Pool".to_string(),
-                reason: e.to_string(),
-            }
-        })?);
-
-        info!("Zero-copy buffer pool initialized with optimized tiers");
-
         // Initialize GStreamer if available
         #[cfg(feature = "streaming")]
         {
@@ -240,7 +207,7 @@ impl VideoEncoder {
             })?;
         }
 
-        let (encoded_sender, _) = mpsc::unbounded_channel();
+        let (encoded_sender, encoded_receiver) = mpsc::unbounded_channel();
 
         Ok(Self {
             config,
@@ -252,11 +219,11 @@ impl VideoEncoder {
             appsink: None,
             frame_queue: Arc::new(Mutex::new(VecDeque::new())),
             encoded_sender: Some(encoded_sender),
+            encoded_receiver,
             is_encoding: Arc::new(Mutex::new(false)),
             stats: Arc::new(Mutex::new(EncoderStats::default())),
-            buffer_pool,
-            simd_processor: Arc::new(Mutex::new(simd_processor)),
-            cpu_capabilities,
+            #[cfg(not(feature = "streaming"))]
+            force_keyframe: false,
         })
     }
 
@@ -280,82 +247,12 @@ impl VideoEncoder {
         Ok(())
     }
 
-    /// High-performance zero-copy frame encoding with SIMD acceleration
-    pub async fn encode_frame_optimized(
-        &mut self,
-        frame: VideoFrame,
-    ) -> Result<Option<EncodedFrame>> {
-        if !*self.is_encoding.lock().unwrap() {
-            return Err(
-                StreamingError::VideoEncodingFailed("Encoder not initialized".to_string()).into(),
-            );
-        }
-
-        let start_time = Instant::now();
-
-        // Get zero-copy buffer from pool
-        let required_size = (self.config.width * self.config.height * 3 / 2) as usize;
-        let zero_copy_buffer = self
-            .buffer_pool
-            .acquire_buffer(required_size)
-            .map_err(|e| {
-                StreamingError::VideoEncodingFailed(format!(
-                    "Failed to acquire zero-copy buffer: {}",
-                    e
-                ))
-            })?;
-
-        // Perform SIMD-accelerated color space conversion if needed
-        let processed_frame = if frame.data.len() != required_size {
-            debug!("Performing SIMD-accelerated format conversion");
-            let mut simd_processor = self.simd_processor.lock().unwrap();
-
-            // Convert using SIMD operations (example: RGB to YUV420)
-            match simd_processor.convert_rgb24_to_yuv420(
-                &frame.data,
-                frame.width as usize,
-                frame.height as usize,
-            ) {
-                Ok(converted_data) => {
-                    // Copy converted data to zero-copy buffer
-                    let buffer_data = unsafe {
-                        std::slice::from_raw_parts_mut(
-                            zero_copy_buffer.data().as_ptr() as *mut u8,
-                            converted_data.len(),
-                        )
-                    };
-                    buffer_data.copy_from_slice(&converted_data);
-                    zero_copy_buffer.set_length(converted_data.len());
-
-                    VideoFrame {
-                        data: zero_copy_buffer.data().to_vec(),
-                        width: frame.width,
-                        height: frame.height,
-                        timestamp: frame.timestamp,
-                        frame_number: frame.frame_number,
-                    }
-                }
-                Err(e) => {
-                    warn!("SIMD conversion failed, using fallback: {}", e);
-                    frame
-                }
-            }
-        } else {
-            frame
-        };
-
-        // Proceed with regular encoding
-        self.encode_frame_internal(processed_frame, start_time)
-            .await
-    }
-
-    /// Encode a video frame (legacy method for compatibility)
+    /// Encode a video frame
     pub async fn encode_frame(&mut self, frame: VideoFrame) -> Result<Option<EncodedFrame>> {
         let start_time = Instant::now();
         self.encode_frame_internal(frame, start_time).await
     }
 
-    /// Internal frame encoding implementation shared by optimized and legacy methods
     async fn encode_frame_internal(
         &mut self,
         frame: VideoFrame,
@@ -398,67 +295,40 @@ impl VideoEncoder {
             stats.average_encoding_time = (stats.average_encoding_time + encoding_time) / 2;
         }
 
-        // For now, return a simulated encoded frame
+        // Without GStreamer, simulate the encoded frame
         #[cfg(not(feature = "streaming"))]
-        {
-            let encoded = EncodedFrame {
-                data: self.simulate_encoded_frame(&frame),
+        let encoded = {
+            let is_keyframe = std::mem::take(&mut self.force_keyframe)
+                || frame
+                    .frame_number
+                    .is_multiple_of(self.config.gop_size as u64);
+            let data = self.simulate_encoded_frame(&frame, is_keyframe);
+            Some(EncodedFrame {
                 timestamp: frame.timestamp,
                 frame_number: frame.frame_number,
-                is_keyframe: frame.frame_number % self.config.gop_size as u64 == 0,
+                is_keyframe,
                 encoding_time: start_time.elapsed(),
-                size_bytes: frame.data.len() / 8, // Simulate compression
-            };
-            return Ok(Some(encoded));
-        }
+                size_bytes: data.len(),
+                data,
+            })
+        };
 
+        // The appsink hands frames over as they come out of the encoder
         #[cfg(feature = "streaming")]
-        {
-            // In real implementation, this would pull from appsink
-            Ok(None)
-        }
-    }
+        let encoded = self.encoded_receiver.try_recv().ok();
 
-    /// Get encoder statistics
-    pub fn get_stats(&self) -> EncoderStats {
-        self.stats.lock().unwrap().clone()
+        Ok(encoded)
     }
 
-    /// Get comprehensive performance statistics including zero-copy buffer pool metrics
-    pub fn get_performance_stats(&self) -> EncoderPerformanceStats {
-        let encoder_stats = self.stats.lock().unwrap().clone();
-        let pool_stats = self.buffer_pool.get_statistics();
-        let pool_hit_rate = self.buffer_pool.hit_rate();
-
-        EncoderPerformanceStats {
-            encoder_stats,
-            buffer_pool_hit_rate: pool_hit_rate,
-            total_pool_allocations: pool_stats
-                .total_allocations
-                .load(std::sync::atomic::Ordering::Relaxed),
-            pool_hits: pool_stats
-                .pool_hits
-                .load(std::sync::atomic::Ordering::Relaxed),
-            pool_misses: pool_stats
-                .pool_misses
-                .load(std::sync::atomic::Ordering::Relaxed),
-            peak_buffer_usage: pool_stats
-                .peak_usage
-                .load(std::sync::atomic::Ordering::Relaxed),
-            current_buffer_usage: pool_stats
-                .current_usage
-                .load(std::sync::atomic::Ordering::Relaxed),
-            cpu_capabilities: self.cpu_capabilities.clone(),
-        }
+    /// Settings the encoder currently runs with
+    pub fn config(&self) -> &EncoderConfig {
+        &self.config
     }
 
-    /// Reset all performance statistics including buffer pool
-    pub fn reset_performance_stats(&self) {
-        {
-            let mut stats = self.stats.lock().unwrap();
-            *stats = EncoderStats::default();
-        }
-        self.buffer_pool.reset_statistics();
+    /// Get encoder statistics
+    #[allow(dead_code)]
+    pub fn get_stats(&self) -> EncoderStats {
+        self.stats.lock().unwrap().clone()
     }
 
     /// Update encoder bitrate dynamically
@@ -480,6 +350,51 @@ impl VideoEncoder {
         Ok(())
     }
 
+    /// Follow a target picked by congestion control: the bitrate changes in
+    /// place, a new frame rate or resolution rebuilds the pipeline
+    pub async fn apply_target(&mut self, target: &StreamTarget) -> Result<()> {
+        self.set_bitrate(target.bitrate_kbps)?;
+
+        let (width, height) = target.resolution;
+        if (self.config.width, self.config.height, self.config.fps) == (width, height, target.fps) {
+            return Ok(());
+        }
+        info!(
+            "Switching encoder to {}x{}@{}fps",
+            width, height, target.fps
+        );
+        self.config.width = width;
+        self.config.height = height;
+        self.config.fps = target.fps;
+        self.shutdown().await?;
+        self.initialize().await
+    }
+
+    /// Get a client that lost packets decoding cleanly again
+    ///
+    /// GStreamer's encoders can't be told to stop referencing frames, so
+    /// invalidations take a keyframe as well.
+    pub fn apply_refresh(&mut self, refresh: Refresh) {
+        debug!("Forcing a keyframe for {:?}", refresh);
+
+        #[cfg(feature = "streaming")]
+        {
+            if let Some(appsrc) = &self.appsrc {
+                let event = gstreamer_video::DownstreamForceKeyUnitEvent::builder()
+                    .all_headers(true)
+                    .build();
+                if !appsrc.send_event(event) {
+                    warn!("Encoder ignored the keyframe request");
+                }
+            }
+        }
+
+        #[cfg(not(feature = "streaming"))]
+        {
+            self.force_keyframe = true;
+        }
+    }
+
     /// Shutdown the encoder
     pub async fn shutdown(&mut self) -> Result<()> {
         info!("Shutting down video encoder");
@@ -512,7 +427,6 @@ impl VideoEncoder {
         if config.bitrate == 0 {
             return Err(StreamingError::ConfigurationError {
                 field: "bitrate".to_string(),
-                value: config.bitrate.to_string(),
                 reason: "Bitrate must be greater than 0".to_string(),
             }
             .into());
@@ -521,8 +435,10 @@ impl VideoEncoder {
         if config.width == 0 || config.height == 0 {
             return Err(StreamingError::ConfigurationError {
                 field: "resolution".to_string(),
-                value: format!("{}x{}", config.width, config.height),
-                reason: "Resolution must be greater than 0".to_string(),
+                reason: format!(
+                    "Resolution must be greater than 0, got {}x{}",
+                    config.width, config.height
+                ),
             }
             .into());
         }
@@ -530,7 +446,6 @@ impl VideoEncoder {
         if config.fps == 0 {
             return Err(StreamingError::ConfigurationError {
                 field: "fps".to_string(),
-                value: config.fps.to_string(),
                 reason: "FPS must be greater than 0".to_string(),
             }
             .into());
@@ -588,7 +503,7 @@ impl VideoEncoder {
         )?;
 
         // Set up encoded frame callback
-        let encoded_sender = self.encoded_sender.take().unwrap();
+        let encoded_sender = self.encoded_sender.clone().unwrap();
         let stats = Arc::clone(&self.stats);
 
         appsink.set_callbacks(
@@ -651,7 +566,7 @@ impl VideoEncoder {
     fn create_encoder_element(&self) -> Result<gst::Element> {
         let encoder =
             match self.config.encoder_type {
-                EncoderType::NVENC => {
+                EncoderType::Nvenc => {
                     debug!("Creating NVENC H264 encoder");
                     let encoder =
                         gst::ElementFactory::make("nvh264enc", Some("encoder")).map_err(|e| {
@@ -677,7 +592,7 @@ impl VideoEncoder {
 
                     encoder
                 }
-                EncoderType::VAAPI => {
+                EncoderType::Vaapi => {
                     debug!("Creating VAAPI H264 encoder");
                     let encoder = gst::ElementFactory::make("vaapih264enc", Some("encoder"))
                         .map_err(|e| StreamingError::EncoderNotAvailable {
@@ -783,7 +698,7 @@ impl VideoEncoder {
         appsrc: &gst_app::AppSrc,
         frame: &VideoFrame,
     ) -> Result<()> {
-        let buffer = gst::Buffer::from_slice(frame.data.clone());
+        let buffer = gst::Buffer::from_slice((*frame.data).clone());
 
         // Set buffer timestamp
         let mut buffer = buffer.into_mut();
@@ -811,12 +726,17 @@ impl VideoEncoder {
     }
 
     #[cfg(not(feature = "streaming"))]
-    fn simulate_encoded_frame(&self, frame: &VideoFrame) -> Vec<u8> {
-        // Simulate compression (roughly 1:8 ratio for H264)
-        let compressed_size = frame.data.len() / 8;
-        vec![0x42; compressed_size.max(1024)] // Fake H264 data
+    fn simulate_encoded_frame(&self, frame: &VideoFrame, is_keyframe: bool) -> Vec<u8> {
+        // Simulate compression (roughly 1:8 ratio for H264) as one Annex B
+        // slice, so the frame packetizes like real encoder output
+        let compressed_size = (frame.data.len() / 8).max(1024);
+        let nal_header = if is_keyframe { 0x65 } else { 0x41 };
+        let mut data = vec![0x42; compressed_size]; // Fake H264 data
+        data[..5].copy_from_slice(&[0, 0, 0, 1, nal_header]);
+        data
     }
 
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
     fn nvenc_preset(&self) -> &'static str {
         match self.config.preset {
             EncoderPreset::UltraFast => "hp",
@@ -833,24 +753,27 @@ impl VideoEncoder {
         }
     }
 
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
     fn nvenc_rate_control(&self) -> &'static str {
         match self.config.rate_control {
-            RateControlMode::CBR => "cbr",
-            RateControlMode::VBR => "vbr",
-            RateControlMode::CQP => "cqp",
-            RateControlMode::VBR_HQ => "vbr-hq",
+            RateControlMode::Cbr => "cbr",
+            RateControlMode::Vbr => "vbr",
+            RateControlMode::Cqp => "cqp",
+            RateControlMode::VbrHq => "vbr-hq",
         }
     }
 
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
     fn vaapi_rate_control(&self) -> &'static str {
         match self.config.rate_control {
-            RateControlMode::CBR => "cbr",
-            RateControlMode::VBR => "vbr",
-            RateControlMode::CQP => "cqp",
-            RateControlMode::VBR_HQ => "vbr",
+            RateControlMode::Cbr => "cbr",
+            RateControlMode::Vbr => "vbr",
+            RateControlMode::Cqp => "cqp",
+            RateControlMode::VbrHq => "vbr",
         }
     }
 
+    #[cfg_attr(not(feature = "streaming"), allow(dead_code))]
     fn x264_preset(&self) -> u32 {
         match self.config.preset {
             EncoderPreset::UltraFast => 1,
@@ -879,6 +802,7 @@ impl Drop for VideoEncoder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::streaming::capture::FramePriority;
 
     #[tokio::test]
     async fn test_encoder_creation() {
@@ -889,8 +813,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_encoder_lifecycle() {
-        let mut config = EncoderConfig::default();
-        config.encoder_type = EncoderType::Software;
+        let config = EncoderConfig {
+            encoder_type: EncoderType::Software,
+            ..EncoderConfig::default()
+        };
 
         let mut encoder = VideoEncoder::new(config).unwrap();
 
@@ -900,11 +826,12 @@ mod tests {
 
         // Encode frame
         let frame = VideoFrame {
-            data: vec![128; 1920 * 1080 * 3 / 2], // I420 frame
+            data: Arc::new(vec![128; 1920 * 1080 * 3 / 2]), // I420 frame
             width: 1920,
             height: 1080,
             timestamp: 12345,
             frame_number: 1,
+            priority: FramePriority::Normal,
         };
 
         let encode_result = encoder.encode_frame(frame).await;
@@ -915,10 +842,38 @@ mod tests {
         assert!(shutdown_result.is_ok(), "Encoder shutdown should succeed");
     }
 
+    #[cfg(not(feature = "streaming"))]
+    #[tokio::test]
+    async fn test_refresh_forces_one_keyframe() {
+        let mut encoder = VideoEncoder::new(EncoderConfig::default()).unwrap();
+        encoder.initialize().await.unwrap();
+        let frame = |frame_number| VideoFrame {
+            data: Arc::new(vec![128; 64 * 64 * 3 / 2]),
+            width: 64,
+            height: 64,
+            timestamp: 0,
+            frame_number,
+            priority: FramePriority::Normal,
+        };
+
+        let encoded = encoder.encode_frame(frame(1)).await.unwrap().unwrap();
+        assert!(!encoded.is_keyframe);
+
+        encoder.apply_refresh(Refresh::InvalidateFrames { first: 0, last: 1 });
+        let encoded = encoder.encode_frame(frame(2)).await.unwrap().unwrap();
+        assert!(encoded.is_keyframe);
+        assert_eq!(encoded.data[4], 0x65);
+
+        let encoded = encoder.encode_frame(frame(3)).await.unwrap().unwrap();
+        assert!(!encoded.is_keyframe);
+    }
+
     #[tokio::test]
     async fn test_config_validation() {
-        let mut config = EncoderConfig::default();
-        config.bitrate = 0;
+        let config = EncoderConfig {
+            bitrate: 0,
+            ..EncoderConfig::default()
+        };
 
         let result = VideoEncoder::new(config);
         assert!(result.is_err(), "Should fail with invalid bitrate");
@@ -1,4 +1,6 @@
 use crate::health::HealthMonitor;
+use crate::streaming::latency::LatencyMetrics;
+use crate::streaming::moonlight::SessionPacing;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Method, Request, Response, Server, StatusCode};
 use serde_json;
@@ -9,6 +11,8 @@ use tracing::{debug, error, info};
 
 pub struct HealthServer {
     health_monitor: Arc<HealthMonitor>,
+    latency: Arc<LatencyMetrics>,
+    pacing: Option<SessionPacing>,
     bind_addr: SocketAddr,
 }
 
@@ -17,19 +21,39 @@ impl HealthServer {
         let bind_addr = SocketAddr::from(([0, 0, 0, 0], port));
         Self {
             health_monitor,
+            latency: Arc::default(),
+            pacing: None,
             bind_addr,
         }
     }
 
+    /// Serve these input latency histograms on `/metrics`
+    pub fn with_latency_metrics(mut self, latency: Arc<LatencyMetrics>) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Serve the sessions' pacing queues on `/metrics`
+    pub fn with_pacing_metrics(mut self, pacing: SessionPacing) -> Self {
+        self.pacing = Some(pacing);
+        self
+    }
+
     pub async fn run(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let health_monitor = Arc::clone(&self.health_monitor);
+        let latency = Arc::clone(&self.latency);
+        let pacing = self.pacing.clone();
 
         let make_svc = make_service_fn(move |_conn| {
             let health_monitor = Arc::clone(&health_monitor);
+            let latency = Arc::clone(&latency);
+            let pacing = pacing.clone();
             async move {
                 Ok::<_, Infallible>(service_fn(move |req| {
                     let health_monitor = Arc::clone(&health_monitor);
-                    async move { handle_request(req, health_monitor).await }
+                    let latency = Arc::clone(&latency);
+                    let pacing = pacing.clone();
+                    async move { handle_request(req, health_monitor, latency, pacing).await }
                 }))
             }
         });
@@ -49,11 +73,13 @@ impl HealthServer {
 async fn handle_request(
     req: Request<Body>,
     health_monitor: Arc<HealthMonitor>,
+    latency: Arc<LatencyMetrics>,
+    pacing: Option<SessionPacing>,
 ) -> Result<Response<Body>, Infallible> {
     let response = match (req.method(), req.uri().path()) {
         (&Method::GET, "/health") => handle_health_check(health_monitor).await,
         (&Method::GET, "/ready") => handle_readiness_check(health_monitor).await,
-        (&Method::GET, "/metrics") => handle_metrics().await,
+        (&Method::GET, "/metrics") => handle_metrics(&latency, pacing.as_ref()).await,
         (&Method::GET, "/") => handle_root().await,
         _ => handle_not_found().await,
     };
@@ -106,7 +132,10 @@ async fn handle_readiness_check(health_monitor: Arc<HealthMonitor>) -> Response<
     }
 }
 
-async fn handle_metrics() -> Response<Body> {
+async fn handle_metrics(
+    latency: &LatencyMetrics,
+    pacing: Option<&SessionPacing>,
+) -> Response<Body> {
     debug!("Processing metrics request");
 
     // TODO: Implement proper Prometheus metrics format
@@ -119,14 +148,6 @@ dpstream_sessions_total 0
 # TYPE dpstream_connected_clients gauge
 dpstream_connected_clients 0
 
-# HELP dpstream_latency_histogram_bucket Streaming latency histogram
-# TYPE dpstream_latency_histogram_bucket histogram
-dpstream_latency_histogram_bucket{le="10"} 0
-dpstream_latency_histogram_bucket{le="25"} 0
-dpstream_latency_histogram_bucket{le="50"} 0
-dpstream_latency_histogram_bucket{le="100"} 0
-dpstream_latency_histogram_bucket{le="+Inf"} 0
-
 # HELP dpstream_video_bytes_total Total video bytes transmitted
 # TYPE dpstream_video_bytes_total counter
 dpstream_video_bytes_total 0
@@ -142,7 +163,10 @@ dpstream_packets_lost_total 0
 # HELP dpstream_frame_drops_total Total frames dropped
 # TYPE dpstream_frame_drops_total counter
 dpstream_frame_drops_total 0
+
 "#;
+    let pacing = pacing.map(SessionPacing::prometheus).unwrap_or_default();
+    let metrics = format!("{metrics}{}{pacing}", latency.prometheus());
 
     Response::builder()
         .status(StatusCode::OK)
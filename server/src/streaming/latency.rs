@@ -0,0 +1,230 @@
+//! End-to-end input latency
+//!
+//! Clients stamp every numbered input packet with their own clock. The input
+//! manager notes when each one reached Dolphin, and every video frame carries
+//! a [`FrameInputTag`] naming the last input applied before it, so the client
+//! can tell how long an input took to show up on its screen. A clock sync
+//! exchange on the control channel gives the client the offset between the
+//! two clocks, splitting that time into the trip to Dolphin, the wait for the
+//! next frame and the way back. Clients send each measurement back as a
+//! [`LatencyReport`], which lands in the [`LatencyMetrics`] histograms.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+pub use dpstream_protocol::latency::{ClockSync, ClockSyncReply, FrameInputTag, LatencyReport};
+
+/// Upper bounds of the histogram buckets, in milliseconds
+pub const BUCKET_BOUNDS_MS: [u64; 10] = [5, 10, 16, 25, 33, 50, 75, 100, 150, 250];
+
+/// Microseconds on the server's clock, counted from the first call
+pub fn server_time_us(at: Instant) -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    let epoch = *EPOCH.get_or_init(|| at);
+    at.saturating_duration_since(epoch).as_micros() as u64
+}
+
+/// Latency histogram with fixed millisecond buckets
+#[derive(Debug, Default)]
+pub struct LatencyHistogram {
+    /// Samples per bucket, the last one past every bound
+    buckets: [AtomicU64; BUCKET_BOUNDS_MS.len() + 1],
+    sum_us: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, latency: Duration) {
+        let micros = latency.as_micros() as u64;
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| micros <= bound * 1000)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Append the histogram in Prometheus text format
+    fn write_prometheus(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (bucket, bound) in self.buckets.iter().zip(BUCKET_BOUNDS_MS) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let count = self.count();
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let sum_ms = self.sum_us.load(Ordering::Relaxed) as f64 / 1000.0;
+        let _ = writeln!(out, "{name}_sum {sum_ms}");
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+/// Input latency histograms, fed by client reports
+#[derive(Debug, Default)]
+pub struct LatencyMetrics {
+    /// Input stamped on the client until the frame showing it was on screen
+    pub input_to_photon: LatencyHistogram,
+    /// Input stamped until it reached Dolphin
+    pub uplink: LatencyHistogram,
+    /// Input reaching Dolphin until the next frame left the server
+    pub frame: LatencyHistogram,
+    /// Frame leaving the server until it was on screen
+    pub downlink: LatencyHistogram,
+}
+
+impl LatencyMetrics {
+    pub fn record_report(&self, report: &LatencyReport) {
+        self.input_to_photon
+            .record(Duration::from_micros(report.input_to_photon_us.into()));
+        self.uplink
+            .record(Duration::from_micros(report.uplink_us.into()));
+        self.frame
+            .record(Duration::from_micros(report.frame_us.into()));
+        self.downlink
+            .record(Duration::from_micros(report.downlink_us().into()));
+    }
+
+    /// All histograms in Prometheus text format
+    pub fn prometheus(&self) -> String {
+        let mut out = String::new();
+        for (histogram, name, help) in [
+            (
+                &self.input_to_photon,
+                "dpstream_input_to_photon_ms",
+                "Input stamped on the client until shown on its screen",
+            ),
+            (
+                &self.uplink,
+                "dpstream_input_uplink_ms",
+                "Input stamped on the client until it reached Dolphin",
+            ),
+            (
+                &self.frame,
+                "dpstream_input_frame_ms",
+                "Input reaching Dolphin until the next frame was sent",
+            ),
+            (
+                &self.downlink,
+                "dpstream_input_downlink_ms",
+                "Frame sent until shown on the client's screen",
+            ),
+        ] {
+            histogram.write_prometheus(&mut out, name, help);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Last numbered input of a session's first controller that was handed to
+/// the backend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AppliedInput {
+    pub sequence: u32,
+    pub applied_at: Instant,
+}
+
+impl AppliedInput {
+    /// Tag for a frame sent at `sent_at` showing this input
+    pub fn frame_tag(&self, sent_at: Instant) -> FrameInputTag {
+        FrameInputTag {
+            input_sequence: self.sequence,
+            applied_at_us: server_time_us(self.applied_at),
+            hold_us: sent_at
+                .saturating_duration_since(self.applied_at)
+                .as_micros()
+                .min(u128::from(u32::MAX)) as u32,
+        }
+    }
+}
+
+/// Server clock readings answering a client's clock sync sent at
+/// `received_at`
+pub fn clock_sync_reply(request: &ClockSync, received_at: Instant, now: Instant) -> ClockSyncReply {
+    ClockSyncReply {
+        client_time_us: request.client_time_us,
+        server_receive_us: server_time_us(received_at),
+        server_send_us: server_time_us(now),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dpstream_protocol::latency::FRAME_TAG_PROFILE;
+    use dpstream_protocol::Message;
+
+    #[test]
+    fn test_reports_fill_histograms() {
+        let metrics = LatencyMetrics::default();
+        let report = LatencyReport {
+            input_to_photon_us: 48_000,
+            uplink_us: 9_000,
+            frame_us: 14_000,
+        };
+        assert_eq!(LatencyReport::decode(&report.encode()), Ok(report));
+        assert_eq!(report.downlink_us(), 25_000);
+
+        metrics.record_report(&report);
+        metrics.record_report(&LatencyReport {
+            input_to_photon_us: 400_000,
+            ..report
+        });
+
+        assert_eq!(metrics.input_to_photon.count(), 2);
+
+        let text = metrics.prometheus();
+        assert!(text.contains("dpstream_input_to_photon_ms_bucket{le=\"33\"} 0"));
+        assert!(text.contains("dpstream_input_to_photon_ms_bucket{le=\"50\"} 1"));
+        assert!(text.contains("dpstream_input_to_photon_ms_bucket{le=\"250\"} 1"));
+        assert!(text.contains("dpstream_input_to_photon_ms_bucket{le=\"+Inf\"} 2"));
+        assert!(text.contains("dpstream_input_frame_ms_bucket{le=\"10\"} 0"));
+        assert!(text.contains("dpstream_input_frame_ms_bucket{le=\"16\"} 2"));
+        assert!(text.contains("dpstream_input_to_photon_ms_sum 448"));
+        assert!(text.contains("dpstream_input_downlink_ms_count 2"));
+    }
+
+    #[test]
+    fn test_frame_tag_and_clock_sync_encoding() {
+        let applied_at = Instant::now();
+        let applied = AppliedInput {
+            sequence: 0x0102_0304,
+            applied_at,
+        };
+        let tag = applied.frame_tag(applied_at + Duration::from_millis(7));
+        assert_eq!(tag.hold_us, 7_000);
+
+        let extension = tag.rtp_extension();
+        assert_eq!(&extension[..4], &[0x44, 0x50, 0, 4]);
+        assert_eq!(&extension[4..8], &[1, 2, 3, 4]);
+        assert_eq!(
+            u64::from_be_bytes(extension[12..].try_into().unwrap()),
+            server_time_us(applied_at)
+        );
+        assert_eq!(
+            FrameInputTag::parse(FRAME_TAG_PROFILE, &extension[4..]),
+            Some(tag)
+        );
+
+        let request = ClockSync { client_time_us: 42 };
+        let reply = clock_sync_reply(
+            &request,
+            applied_at,
+            applied_at + Duration::from_micros(150),
+        );
+        assert_eq!(reply.client_time_us, 42);
+        assert_eq!(reply.server_receive_us, server_time_us(applied_at));
+        assert_eq!(reply.server_send_us - reply.server_receive_us, 150);
+        assert_eq!(ClockSyncReply::decode(&reply.encode()), Ok(reply));
+    }
+}
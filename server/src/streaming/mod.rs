@@ -1,10 +1,17 @@
 // Core modules that work with minimal dependencies
 // pub mod audio;                 // Commented out: AudioFrame field mismatches
-// pub mod capture;               // Commented out: VideoFrame field mismatches
-// pub mod encoder;               // Commented out: depends on capture
+pub mod capture;
+pub mod congestion;
+pub mod encoder;
 pub mod error_recovery;
 pub mod health_server;
+pub mod latency;
 pub mod moonlight;
+pub mod pacer;
+pub mod packet_io;
+pub mod pipeline;
+pub mod rtcp;
+pub mod rtp;
 // pub mod optimization;          // Commented out: depends on other modules
 // pub mod rtp_optimization;      // Commented out: unsafe function call errors
 // pub mod simd_ops;              // Commented out: borrow checker errors
@@ -15,7 +22,6 @@ pub mod zero_copy;
 // These require additional crates not currently in Cargo.toml
 // Uncomment and add dependencies when needed for full feature builds
 
-// pub mod advanced_networking;      // Requires io_uring, RDMA dependencies
 // pub mod compiler_optimization;    // Requires quantum_optimization module
 // pub mod lock_free;                // Requires crossbeam_epoch
 // pub mod memory_optimization;      // Requires slab
@@ -29,7 +35,6 @@ pub use moonlight::{AudioFrame, MoonlightServer, ServerConfig, VideoFrame};
 // pub use simd_ops::{CPUCapabilities, SIMDVideoProcessor};
 
 // Commented out exports for disabled modules
-// pub use advanced_networking::{AdvancedNetworkingSystem, IoUringSystem, PacketProcessor, RdmaSystem};
 // pub use compiler_optimization::{BoltOptimizer, CompilerFlagOptimizer, CompilerOptimizationSystem, ProfileGuidedOptimizer};
 // pub use lock_free::{LockFreeMemoryPool, LockFreeRingBuffer, LockFreeSessionRegistry};
 // pub use memory_optimization::{AudioBufferHandle, PacketHandle, StreamingAllocator, VideoFrameHandle};
@@ -4,32 +4,151 @@
 //!
 //! Implements NVIDIA GameStream compatible streaming protocol for video and audio
 
-use crate::error::{Result, StreamingError};
+use crate::emulator::control::{EmulationCommand, EmulationJob};
+use crate::emulator::library::{GameEntry, LaunchJob, LibraryRequest, LibraryResponse};
+use crate::emulator::savestate::{StateJob, StateRequest, StateResponse, Thumbnail};
+use crate::error::{EmulatorError, Result, StreamingError};
 use crate::health::HealthMonitor;
-use crate::input::{MoonlightInputPacket, ServerInputManager};
-// Capture module is disabled for minimal build
-// use crate::streaming::capture::{VideoCapture, VideoCaptureConfig, VideoFrame};
+use crate::input::calibration::{CalibrationRequest, CalibrationResponse};
+use crate::input::rumble::RumbleEvent;
+use crate::input::slots::SlotRequest;
+use crate::input::{MoonlightInputPacket, ServerInputManager, WiiExtension};
+use crate::streaming::congestion::{
+    ClientStats, CongestionController, RateChange, RateLimits, StreamTarget,
+};
+use crate::streaming::latency::{clock_sync_reply, ClockSync, LatencyMetrics, LatencyReport};
+use crate::streaming::pacer::{self, Pacer, PacingMetrics, PACING_INTERVAL};
+use crate::streaming::packet_io::{
+    stream_socket_io, Datagram, PacketSink, PacketSource, RecvBatch, BATCH_SIZE,
+};
+use crate::streaming::rtcp::{
+    decode_compound, encode_compound, is_rtcp, media_ssrc, session_ssrc, PictureRefresh,
+    RtcpPacket, RtcpSession, SENDER_REPORT_INTERVAL,
+};
+use crate::streaming::rtp::RtpPacketizer;
 use crossbeam_utils::CachePadded;
 use dashmap::DashMap;
-use flume::{bounded, unbounded, Sender};
+use dpstream_protocol::capabilities::{features, Capabilities};
+use dpstream_protocol::hello::{Hello, HelloReply};
+use dpstream_protocol::keyboard::KeyboardMouse;
+use dpstream_protocol::{message_type, msg, FrameReader, Message};
+use flume::{bounded, unbounded, Receiver, Sender};
 use parking_lot::{Mutex as ParkingMutex, RwLock};
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+/// First client message, carrying a [`Hello`]
+pub const MSG_HELLO: u32 = msg::HELLO;
+
+/// Reply to [`MSG_HELLO`] accepting or rejecting the client
+pub const MSG_HELLO_REPLY: u32 = msg::HELLO_REPLY;
+
+/// Keepalive without payload
+pub const MSG_KEEPALIVE: u32 = msg::KEEPALIVE;
+
+/// Controller input carrying an encoded [`MoonlightInputPacket`]
+pub const MSG_INPUT: u32 = msg::INPUT;
+
+/// Control message carrying an [`EmulationCommand`]
+pub const MSG_EMULATION_CONTROL: u32 = msg::EMULATION_CONTROL;
+
+/// Control message carrying a [`StateRequest`]
+pub const MSG_SAVE_STATE: u32 = msg::SAVE_STATE;
+
+/// Reply to [`MSG_SAVE_STATE`] carrying a [`StateResponse`]
+pub const MSG_SAVE_STATE_RESPONSE: u32 = msg::SAVE_STATE_RESPONSE;
+
+/// Control message selecting the Wii Remote [`WiiExtension`]
+pub const MSG_WII_EXTENSION: u32 = msg::WII_EXTENSION;
+
+/// Server-to-client rumble carrying a [`Rumble`](crate::input::rumble::Rumble)
+pub const MSG_RUMBLE: u32 = msg::RUMBLE;
+
+/// Control message carrying a [`CalibrationRequest`]
+pub const MSG_CALIBRATION: u32 = msg::CALIBRATION;
+
+/// Reply to [`MSG_CALIBRATION`] carrying a [`CalibrationResponse`]
+pub const MSG_CALIBRATION_RESPONSE: u32 = msg::CALIBRATION_RESPONSE;
+
+/// Server-to-client slot layout carrying a
+/// [`PlayerSlots`](crate::input::slots::PlayerSlots), sent on every change
+pub const MSG_PLAYER_SLOTS: u32 = msg::PLAYER_SLOTS;
+
+/// Control message carrying a [`SlotRequest`]
+pub const MSG_SLOT_REQUEST: u32 = msg::SLOT_REQUEST;
+
+/// Control message carrying one keyboard or mouse [`KeyboardMouse`] event
+pub const MSG_KEYBOARD_MOUSE: u32 = msg::KEYBOARD_MOUSE;
+
+/// Server-to-client virtual controls to draw over the touchscreen, carrying
+/// a [`TouchOverlay`](crate::input::touch::TouchOverlay)
+pub const MSG_TOUCH_OVERLAY: u32 = msg::TOUCH_OVERLAY;
+
+/// Control message carrying a client clock reading for a
+/// [`ClockSync`] exchange
+pub const MSG_CLOCK_SYNC: u32 = msg::CLOCK_SYNC;
+
+/// Reply to [`MSG_CLOCK_SYNC`] with the server's clock readings
+pub const MSG_CLOCK_SYNC_REPLY: u32 = msg::CLOCK_SYNC_REPLY;
+
+/// Control message carrying one client [`LatencyReport`]
+pub const MSG_LATENCY_REPORT: u32 = msg::LATENCY_REPORT;
+
+/// Control message carrying a client's periodic [`ClientStats`]
+pub const MSG_CLIENT_STATS: u32 = msg::CLIENT_STATS;
+
+/// Control message listing or launching games, carrying a [`LibraryRequest`]
+pub const MSG_LIBRARY: u32 = msg::LIBRARY;
+
+/// Reply to [`MSG_LIBRARY`] carrying a [`LibraryResponse`]
+pub const MSG_LIBRARY_RESPONSE: u32 = msg::LIBRARY_RESPONSE;
+
+/// How long a new client has to send its [`Hello`]
+const HELLO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Release named in the server's [`Hello`]
+const SERVER_BUILD: &str = concat!("dpstream-server-", env!("CARGO_PKG_VERSION"));
+
+/// Reference frames the encoder keeps, as in its low-latency default; with
+/// only one, packets too late to resend always take a keyframe
+const ENCODER_REFERENCE_FRAMES: u32 = 1;
+
+/// Time between input ticks, each taking the controller input clients sent
+/// since the last one to the input backend
+pub const INPUT_TICK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(2);
+
+/// Channels from client sessions to the emulator, shared by all sessions
+#[derive(Clone, Default)]
+struct SessionControls {
+    emulation: Arc<RwLock<Option<Sender<EmulationJob>>>>,
+    states: Arc<RwLock<Option<Sender<StateJob>>>>,
+    launches: Arc<RwLock<Option<Sender<LaunchJob>>>>,
+    library: Arc<RwLock<Vec<GameEntry>>>,
+    thumbnail: Arc<RwLock<Option<Thumbnail>>>,
+    input: Arc<RwLock<Option<ServerInputManager>>>,
+    latency: Arc<LatencyMetrics>,
+    rate: Arc<RwLock<Option<Sender<RateChange>>>>,
+    refresh: Arc<RwLock<Option<Sender<PictureRefresh>>>>,
+}
+
 /// Moonlight streaming server with optimized concurrent access
 #[allow(dead_code)]
 pub struct MoonlightServer {
     config: ServerConfig,
     sessions: Arc<DashMap<Uuid, StreamingSession>>,
     video_broadcast: Sender<VideoFrame>,
+    video_frames: Receiver<VideoFrame>,
     audio_broadcast: Sender<AudioFrame>,
     input_manager: Arc<RwLock<Option<ServerInputManager>>>,
     health_monitor: Arc<RwLock<Option<Arc<HealthMonitor>>>>,
+    controls: SessionControls,
     is_running: Arc<parking_lot::Mutex<bool>>,
     performance_monitor: Arc<PerformanceMonitor>,
 }
@@ -43,7 +162,12 @@ pub struct PerformanceMonitor {
     pub active_sessions: CachePadded<std::sync::atomic::AtomicUsize>,
     pub network_bytes_sent: CachePadded<std::sync::atomic::AtomicU64>,
     pub packet_loss_count: CachePadded<std::sync::atomic::AtomicU64>,
-    pub latency_histogram: CachePadded<std::sync::atomic::AtomicU64>, // Stores compressed histogram
+    /// Packets waiting in the sessions' pacers
+    pub pacing_queue_depth: CachePadded<std::sync::atomic::AtomicUsize>,
+    /// Longest any session's oldest waiting packet has waited
+    pub pacing_delay_us: CachePadded<std::sync::atomic::AtomicU64>,
+    /// Input-to-photon latency reported by clients
+    pub input_latency: Arc<LatencyMetrics>,
     pub peak_memory_usage: CachePadded<std::sync::atomic::AtomicU64>,
 }
 
@@ -56,7 +180,9 @@ impl Default for PerformanceMonitor {
             active_sessions: CachePadded::new(std::sync::atomic::AtomicUsize::new(0)),
             network_bytes_sent: CachePadded::new(std::sync::atomic::AtomicU64::new(0)),
             packet_loss_count: CachePadded::new(std::sync::atomic::AtomicU64::new(0)),
-            latency_histogram: CachePadded::new(std::sync::atomic::AtomicU64::new(0)),
+            pacing_queue_depth: CachePadded::new(std::sync::atomic::AtomicUsize::new(0)),
+            pacing_delay_us: CachePadded::new(std::sync::atomic::AtomicU64::new(0)),
+            input_latency: Arc::default(),
             peak_memory_usage: CachePadded::new(std::sync::atomic::AtomicU64::new(0)),
         }
     }
@@ -71,6 +197,11 @@ pub struct ServerConfig {
     pub enable_encryption: bool,
     pub enable_authentication: bool,
     pub stream_timeout_ms: u64,
+    /// Bounds for adapting each session's stream to its network
+    pub rate_limits: RateLimits,
+    /// Send runs of stream packets as single UDP GSO buffers, where the
+    /// kernel supports it
+    pub udp_gso: bool,
 }
 
 /// Audio configuration
@@ -99,7 +230,7 @@ pub struct StreamConfig {
     pub buffer_size: usize,
 }
 
-/// Video frame data (stub for disabled capture module)
+/// Encoded video frame on its way to the client sessions
 #[derive(Debug, Clone)]
 pub struct VideoFrame {
     pub data: Vec<u8>,
@@ -109,6 +240,103 @@ pub struct VideoFrame {
     pub frame_number: u64,
 }
 
+/// Where the video pipeline hands its frames to the server
+#[derive(Clone)]
+pub struct VideoOutput {
+    frames: Sender<VideoFrame>,
+    sessions: Arc<DashMap<Uuid, StreamingSession>>,
+    thumbnail: Arc<RwLock<Option<Thumbnail>>>,
+}
+
+impl VideoOutput {
+    /// Keep a picture of the game for the next save state
+    pub fn set_thumbnail(&self, thumbnail: Thumbnail) {
+        *self.thumbnail.write() = Some(thumbnail);
+    }
+
+    /// Pass a frame on to the client sessions
+    pub fn send(&self, frame: VideoFrame) {
+        if self.frames.try_send(frame).is_err() {
+            debug!("Video send queue full, dropping frame");
+        }
+    }
+
+    /// What the one encoder all sessions share should produce: the lowest
+    /// bitrate and frame rate and the smallest resolution any streaming
+    /// session is held to, or `None` while nobody is streaming
+    pub fn stream_target(&self) -> Option<StreamTarget> {
+        self.sessions
+            .iter()
+            .filter(|session| session.state == SessionState::Streaming)
+            .filter_map(|session| Some(session.congestion.as_ref()?.target()))
+            .reduce(|a, b| StreamTarget {
+                bitrate_kbps: a.bitrate_kbps.min(b.bitrate_kbps),
+                fps: a.fps.min(b.fps),
+                resolution: if a.resolution.0 * a.resolution.1 <= b.resolution.0 * b.resolution.1 {
+                    a.resolution
+                } else {
+                    b.resolution
+                },
+            })
+    }
+}
+
+/// Where the input thread reaches the server's input manager
+pub struct InputDriver {
+    input: std::sync::Weak<RwLock<Option<ServerInputManager>>>,
+}
+
+impl InputDriver {
+    /// Tick the input manager every [`INPUT_TICK_INTERVAL`] until the
+    /// server is gone
+    ///
+    /// A tick holds the input manager's lock throughout, so it runs on a
+    /// thread of its own rather than as a task.
+    pub fn run(self) {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                error!("Failed to start the input thread: {}", e);
+                return;
+            }
+        };
+        while let Some(input) = self.input.upgrade() {
+            if let Some(manager) = input.write().as_mut() {
+                if let Err(e) = runtime.block_on(manager.process_inputs()) {
+                    warn!("Input tick failed: {}", e);
+                }
+            }
+            drop(input);
+            std::thread::sleep(INPUT_TICK_INTERVAL);
+        }
+        debug!("Input thread stopped");
+    }
+}
+
+/// Where the metrics endpoint reads the sessions' pacers
+#[derive(Clone)]
+pub struct SessionPacing {
+    sessions: Arc<DashMap<Uuid, StreamingSession>>,
+}
+
+impl SessionPacing {
+    /// Pacing queue of each session as of `now`
+    pub fn sessions(&self, now: std::time::Instant) -> Vec<(Uuid, PacingMetrics)> {
+        self.sessions
+            .iter()
+            .filter_map(|session| Some((session.id, session.pacer.as_ref()?.metrics(now))))
+            .collect()
+    }
+
+    /// Prometheus text for the sessions' pacers
+    pub fn prometheus(&self) -> String {
+        pacer::prometheus(&self.sessions(std::time::Instant::now()))
+    }
+}
+
 /// Audio frame data
 #[derive(Debug, Clone)]
 pub struct AudioFrame {
@@ -130,6 +358,22 @@ pub struct StreamingSession {
     pub started_at: std::time::Instant,
     pub last_activity: std::time::Instant,
     pub stream_config: Option<NegotiatedStreamConfig>,
+    /// [`features`] agreed with the client, the rest stay off
+    pub features: u32,
+    /// Adapts the stream to the client's receive statistics
+    pub congestion: Option<CongestionController>,
+    /// Reports and loss recovery for the video stream
+    pub rtcp: Option<RtcpSession>,
+    /// Packetizes the video under the session's SSRC
+    pub rtp: Option<RtpPacketizer>,
+    /// Spaces the video packets out at the stream's bitrate
+    pub pacer: Option<Pacer>,
+}
+
+impl StreamingSession {
+    pub fn has_feature(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -164,7 +408,8 @@ pub struct AudioStream {
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct InputHandler {
-    sender: Sender<MoonlightInputPacket>,
+    /// The session's queue in the input manager
+    sender: tokio::sync::mpsc::UnboundedSender<MoonlightInputPacket>,
     input_buffer: SmallVec<[MoonlightInputPacket; 16]>, // Stack-allocated input buffer
 }
 
@@ -207,6 +452,19 @@ pub enum InputEvent {
     },
 }
 
+impl From<KeyboardMouse> for InputEvent {
+    fn from(event: KeyboardMouse) -> Self {
+        match event {
+            KeyboardMouse::KeyDown { key } => Self::KeyDown { key },
+            KeyboardMouse::KeyUp { key } => Self::KeyUp { key },
+            KeyboardMouse::MouseMove { x, y } => Self::MouseMove { x, y },
+            KeyboardMouse::MouseDown { button } => Self::MouseDown { button },
+            KeyboardMouse::MouseUp { button } => Self::MouseUp { button },
+            KeyboardMouse::MouseWheel { delta } => Self::MouseWheel { delta },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ControllerInput {
     pub buttons: u32,
@@ -226,6 +484,12 @@ pub struct ClientCapabilities {
     pub audio_codecs: Vec<String>,
     pub max_fps: u32,
     pub supports_hdr: bool,
+    /// Protocol version the session speaks
+    pub protocol_version: u8,
+    /// Release the client reported
+    pub build_version: String,
+    /// [`features`] both sides support
+    pub features: u32,
 }
 
 /// Negotiated stream configuration after capability exchange
@@ -272,6 +536,7 @@ impl MoonlightServer {
 
         // Start control connection handler
         let sessions = Arc::clone(&self.sessions);
+        let controls = self.controls.clone();
         let is_running = Arc::clone(&self.is_running);
         let config = self.config.clone();
         let _video_broadcast = self.video_broadcast.clone();
@@ -281,6 +546,7 @@ impl MoonlightServer {
             Self::handle_control_connections(
                 control_listener,
                 sessions,
+                controls,
                 is_running,
                 config,
                 _video_broadcast,
@@ -290,13 +556,40 @@ impl MoonlightServer {
         });
 
         // Start stream data handler
+        let (sink, source) = stream_socket_io(stream_socket, self.config.udp_gso);
+        let video_frames = self.video_frames.clone();
         let sessions_clone = Arc::clone(&self.sessions);
+        let controls_clone = self.controls.clone();
+        let monitor = Arc::clone(&self.performance_monitor);
         let is_running_clone = Arc::clone(&self.is_running);
 
         tokio::spawn(async move {
-            Self::handle_stream_data(stream_socket, sessions_clone, is_running_clone).await;
+            Self::handle_stream_data(
+                sink,
+                source,
+                video_frames,
+                sessions_clone,
+                controls_clone,
+                monitor,
+                is_running_clone,
+            )
+            .await;
         });
 
+        // Route rumble captured by the input backend to client sessions
+        let rumble_source = self
+            .input_manager
+            .read()
+            .as_ref()
+            .and_then(|input| input.rumble_source());
+        if let Some(source) = rumble_source {
+            tokio::spawn(Self::route_rumble(
+                source,
+                Arc::clone(&self.controls.input),
+                Arc::clone(&self.is_running),
+            ));
+        }
+
         info!("Moonlight server started successfully");
         Ok(())
     }
@@ -321,10 +614,10 @@ impl MoonlightServer {
 
     /// Broadcast video frame to all clients
     pub fn broadcast_video_frame(&self, frame: VideoFrame) -> Result<()> {
-        match self.video_broadcast.send(frame) {
+        match self.video_broadcast.try_send(frame) {
             Ok(_) => Ok(()),
             Err(_) => {
-                debug!("No video subscribers");
+                debug!("Video send queue full, dropping frame");
                 Ok(())
             }
         }
@@ -350,18 +643,24 @@ impl MoonlightServer {
             config.max_clients, config.enable_encryption, config.enable_authentication
         );
 
-        let (video_broadcast, _) = bounded(1024);
+        let (video_broadcast, video_frames) = bounded(1024);
         let (audio_broadcast, _) = bounded(1024);
+        let controls = SessionControls::default();
 
         Ok(Self {
             config,
             sessions: Arc::new(DashMap::new()),
             video_broadcast,
+            video_frames,
             audio_broadcast,
-            input_manager: Arc::new(RwLock::new(None)),
+            input_manager: Arc::clone(&controls.input),
             health_monitor: Arc::new(RwLock::new(None)),
             is_running: Arc::new(parking_lot::Mutex::new(false)),
-            performance_monitor: Arc::new(PerformanceMonitor::default()),
+            performance_monitor: Arc::new(PerformanceMonitor {
+                input_latency: Arc::clone(&controls.latency),
+                ..PerformanceMonitor::default()
+            }),
+            controls,
         })
     }
 
@@ -370,6 +669,34 @@ impl MoonlightServer {
         self.config.port
     }
 
+    /// Handle the video pipeline sends its frames through
+    pub fn video_output(&self) -> VideoOutput {
+        VideoOutput {
+            frames: self.video_broadcast.clone(),
+            sessions: Arc::clone(&self.sessions),
+            thumbnail: Arc::clone(&self.controls.thumbnail),
+        }
+    }
+
+    /// Handle the input thread ticks the input manager through
+    pub fn input_driver(&self) -> InputDriver {
+        InputDriver {
+            input: Arc::downgrade(&self.controls.input),
+        }
+    }
+
+    /// Pacing queues of the sessions, for the metrics endpoint
+    pub fn pacing_metrics(&self) -> SessionPacing {
+        SessionPacing {
+            sessions: Arc::clone(&self.sessions),
+        }
+    }
+
+    /// Input latency histograms, for the metrics endpoint
+    pub fn latency_metrics(&self) -> Arc<LatencyMetrics> {
+        Arc::clone(&self.performance_monitor.input_latency)
+    }
+
     /// Set the input manager for handling client input
     pub fn set_input_manager(&self, input_manager: ServerInputManager) {
         *self.input_manager.write() = Some(input_manager);
@@ -380,34 +707,37 @@ impl MoonlightServer {
         *self.health_monitor.write() = Some(health_monitor);
     }
 
-    /// Convert ControllerInput to MoonlightInputPacket
-    #[allow(dead_code)]
-    fn convert_controller_input_to_moonlight(
-        &self,
-        _controller_id: u8,
-        input: ControllerInput,
-    ) -> MoonlightInputPacket {
-        MoonlightInputPacket {
-            packet_type: 0x0C, // Controller input packet type
-            button_flags: input.buttons as u16,
-            left_trigger: input.left_trigger,
-            right_trigger: input.right_trigger,
-            left_stick_x: input.left_stick_x,
-            left_stick_y: input.left_stick_y,
-            right_stick_x: input.right_stick_x,
-            right_stick_y: input.right_stick_y,
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_millis() as u64,
-            gyro_x: None,
-            gyro_y: None,
-            gyro_z: None,
-            accel_x: None,
-            accel_y: None,
-            accel_z: None,
-            touch_points: None,
-        }
+    /// Set the channel emulation control commands are forwarded to
+    pub fn set_emulation_control(&self, control: Sender<EmulationJob>) {
+        *self.controls.emulation.write() = Some(control);
+    }
+
+    /// Set the channel save-state requests are forwarded to
+    pub fn set_state_control(&self, control: Sender<StateJob>) {
+        *self.controls.states.write() = Some(control);
+    }
+
+    /// Publish the scanned game library as the app list clients pick from
+    pub fn set_library(&self, games: &[GameEntry]) {
+        *self.controls.library.write() = games.to_vec();
+        info!("App list updated with {} games", games.len());
+    }
+
+    /// Set the channel game launch requests are forwarded to
+    pub fn set_launch_control(&self, control: Sender<LaunchJob>) {
+        *self.controls.launches.write() = Some(control);
+    }
+
+    /// Set the channel new encoder targets are sent to as sessions adapt
+    /// to their networks
+    pub fn set_rate_control(&self, control: Sender<RateChange>) {
+        *self.controls.rate.write() = Some(control);
+    }
+
+    /// Set the channel keyframe and reference invalidation requests are
+    /// sent to when clients lose video packets
+    pub fn set_refresh_control(&self, control: Sender<PictureRefresh>) {
+        *self.controls.refresh.write() = Some(control);
     }
 
     /// Run the server main loop
@@ -448,6 +778,7 @@ impl MoonlightServer {
     async fn handle_control_connections(
         listener: TcpListener,
         sessions: Arc<DashMap<Uuid, StreamingSession>>,
+        controls: SessionControls,
         is_running: Arc<ParkingMutex<bool>>,
         config: ServerConfig,
         _video_broadcast: Sender<VideoFrame>,
@@ -477,12 +808,18 @@ impl MoonlightServer {
                         started_at: std::time::Instant::now(),
                         last_activity: std::time::Instant::now(),
                         stream_config: None,
+                        features: 0,
+                        congestion: None,
+                        rtcp: None,
+                        rtp: None,
+                        pacer: None,
                     };
 
                     sessions.insert(session_id, session);
 
                     // Handle client session
                     let sessions_clone = Arc::clone(&sessions);
+                    let controls_clone = controls.clone();
                     let _video_broadcast_clone = _video_broadcast.clone();
                     let _audio_broadcast_clone = _audio_broadcast.clone();
                     let config_clone = config.clone();
@@ -492,6 +829,7 @@ impl MoonlightServer {
                             stream,
                             session_id,
                             sessions_clone,
+                            controls_clone,
                             config_clone,
                             _video_broadcast_clone,
                             _audio_broadcast_clone,
@@ -514,6 +852,7 @@ impl MoonlightServer {
         mut stream: TcpStream,
         session_id: Uuid,
         sessions: Arc<DashMap<Uuid, StreamingSession>>,
+        controls: SessionControls,
         config: ServerConfig,
         _video_broadcast: Sender<VideoFrame>,
         _audio_broadcast: Sender<AudioFrame>,
@@ -538,9 +877,12 @@ impl MoonlightServer {
             return handshake_result;
         }
 
-        // Step 2: Capability exchange
-        let capabilities = Self::exchange_capabilities(&mut stream, &config).await?;
+        // Step 2: Capability exchange; messages sent right behind the hello
+        // wait in the frame reader for the session loop
+        let mut frames = FrameReader::new();
+        let capabilities = Self::exchange_capabilities(&mut stream, &mut frames, &config).await?;
         debug!("Client capabilities: {:?}", capabilities);
+        let agreed = capabilities.features;
 
         // Step 3: Encryption key exchange (if enabled)
         if config.enable_encryption {
@@ -553,9 +895,26 @@ impl MoonlightServer {
         debug!("Stream configuration: {:?}", stream_config);
 
         // Update session to streaming state
+        let congestion = CongestionController::new(
+            StreamTarget {
+                bitrate_kbps: stream_config.video_bitrate,
+                fps: stream_config.video_fps,
+                resolution: stream_config.video_resolution,
+            },
+            config.rate_limits,
+        );
+        let pacer = Pacer::new(stream_config.video_bitrate, std::time::Instant::now());
         if let Some(mut session) = sessions.get_mut(&session_id) {
             session.state = SessionState::Streaming;
             session.stream_config = Some(stream_config);
+            session.features = agreed;
+            session.congestion = Some(congestion);
+            session.rtcp = Some(RtcpSession::new(
+                session_ssrc(&session_id),
+                ENCODER_REFERENCE_FRAMES,
+            ));
+            session.rtp = Some(RtpPacketizer::new(session_ssrc(&session_id)));
+            session.pacer = Some(pacer);
         }
 
         info!("Moonlight handshake completed for session {}", session_id);
@@ -568,8 +927,16 @@ impl MoonlightServer {
         // In a real implementation, we'd need to use a proper broadcast mechanism
         // For now, these are stub channels
 
-        // Set up input handling with flume channel
-        let (input_tx, _input_rx) = unbounded();
+        // Controller input waits in the input manager for its next tick
+        let input_sender = controls.input.write().as_mut().and_then(|input| {
+            match input.register_client(session_id) {
+                Ok(sender) => Some(sender),
+                Err(e) => {
+                    warn!("Failed to register input for {}: {}", session_id, e);
+                    None
+                }
+            }
+        });
 
         // Update session with streams
         if let Some(mut session) = sessions.get_mut(&session_id) {
@@ -583,35 +950,221 @@ impl MoonlightServer {
                 stats: StreamStats::default(),
                 sample_buffer: SmallVec::new(),
             });
-            session.input_handler = Some(InputHandler {
-                sender: input_tx,
+            session.input_handler = input_sender.map(|sender| InputHandler {
+                sender,
                 input_buffer: SmallVec::new(),
             });
         }
 
         info!("Client session established: {}", session_id);
 
-        // Keep session alive and handle control messages
+        // Rumble for this client; the spare sender keeps the channel open
+        // when no input manager is attached or the client can't rumble
+        let (_no_rumble, idle_rumble) = bounded(1);
+        let rumble = controls
+            .input
+            .write()
+            .as_mut()
+            .filter(|_| agreed & features::RUMBLE != 0)
+            .map(|input| input.subscribe_rumble(session_id));
+        let rumble_messages = rumble
+            .as_ref()
+            .map_or(idle_rumble, |rumble| rumble.messages.clone());
+
+        // Slot layout changes, likewise idle without an input manager
+        let (_no_slots, idle_slots) = bounded(1);
+        let slot_updates = controls
+            .input
+            .write()
+            .as_mut()
+            .map_or(idle_slots, |input| input.subscribe_slots(session_id));
+
+        // Touch overlay for the game being played, for touchscreen clients
+        let (_no_overlay, idle_overlay) = bounded(1);
+        let overlays = controls
+            .input
+            .write()
+            .as_mut()
+            .filter(|_| agreed & features::TOUCH != 0)
+            .map_or(idle_overlay, |input| {
+                input.subscribe_touch_overlay(session_id)
+            });
+
+        // Keep session alive, handle control messages and forward rumble
         // NOTE: This is a stub implementation for minimal build
         // In a full implementation, this would handle streaming and control messages
         let mut buffer = vec![0u8; 1024];
-        while (stream.readable().await).is_ok() {
-            match stream.try_read(&mut buffer) {
-                Ok(0) => break, // Connection closed
-                Ok(_n) => {
-                    // Parse control messages would go here
+        'session: loop {
+            tokio::select! {
+                readable = stream.readable() => {
+                    if readable.is_err() {
+                        break;
+                    }
+                    match stream.try_read(&mut buffer) {
+                        Ok(0) => break, // Connection closed
+                        Ok(n) => {
+                            frames.extend(&buffer[..n]);
+                            loop {
+                                let message = match frames.next_message() {
+                                    Ok(Some(message)) => message,
+                                    Ok(None) => break,
+                                    Err(e) => {
+                                        // No telling where the next message starts
+                                        warn!("Unreadable message from {}: {}", session_id, e);
+                                        break 'session;
+                                    }
+                                };
+                                let data = message.as_slice();
+                                match message_type(data) {
+                                    Some(MSG_INPUT) => {
+                                        if let Err(e) =
+                                            Self::handle_controller_input(data, &session_id, &sessions, &controls.input)
+                                        {
+                                            warn!("Input from {} failed: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_EMULATION_CONTROL) => {
+                                        if let Err(e) = Self::handle_emulation_control(
+                                            data,
+                                            &session_id,
+                                            &sessions,
+                                            &controls.emulation,
+                                        )
+                                        .await
+                                        {
+                                            warn!("Emulation control from {} failed: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_WII_EXTENSION) => {
+                                        if let Err(e) =
+                                            Self::handle_wii_extension(data, &session_id, &controls.input)
+                                        {
+                                            warn!("Extension change from {} failed: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_SAVE_STATE) => {
+                                        let response = Self::handle_state_request(data, &controls)
+                                            .await
+                                            .unwrap_or_else(|e| StateResponse::Failed {
+                                                reason: e.to_string(),
+                                            });
+                                        if let Err(e) = stream.write_all(&response.encode()).await {
+                                            warn!("Failed to reply to {}: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_LIBRARY) => {
+                                        let response = Self::handle_library_request(data, &controls)
+                                            .await
+                                            .unwrap_or_else(|e| LibraryResponse::Failed {
+                                                reason: e.to_string(),
+                                            });
+                                        if let Err(e) = stream.write_all(&response.encode()).await {
+                                            warn!("Failed to reply to {}: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_CALIBRATION) => {
+                                        let response =
+                                            Self::handle_calibration(data, &session_id, &controls.input)
+                                                .unwrap_or_else(|e| CalibrationResponse::Failed {
+                                                    reason: e.to_string(),
+                                                });
+                                        if let Err(e) = stream.write_all(&response.encode()).await {
+                                            warn!("Failed to reply to {}: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_SLOT_REQUEST) => {
+                                        if let Err(e) =
+                                            Self::handle_slot_request(data, &session_id, &controls.input)
+                                        {
+                                            warn!("Slot request from {} failed: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_KEYBOARD_MOUSE) => {
+                                        if let Err(e) = Self::handle_keyboard_mouse(
+                                            data,
+                                            &session_id,
+                                            &sessions,
+                                            &controls,
+                                        )
+                                        .await
+                                        {
+                                            warn!("Keyboard input from {} failed: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_CLOCK_SYNC) => {
+                                        let received_at = std::time::Instant::now();
+                                        let request = match ClockSync::decode(data) {
+                                            Ok(request) => request,
+                                            Err(e) => {
+                                                warn!("Clock sync from {} failed: {}", session_id, e);
+                                                continue;
+                                            }
+                                        };
+                                        let reply =
+                                            clock_sync_reply(&request, received_at, std::time::Instant::now());
+                                        if let Err(e) = stream.write_all(&reply.encode()).await {
+                                            warn!("Failed to reply to {}: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_LATENCY_REPORT) => {
+                                        if let Err(e) =
+                                            Self::handle_latency_report(data, &session_id, &controls.latency)
+                                        {
+                                            warn!("Latency report from {} failed: {}", session_id, e);
+                                        }
+                                    }
+                                    Some(MSG_CLIENT_STATS) => {
+                                        if let Err(e) =
+                                            Self::handle_client_stats(data, &session_id, &sessions, &controls.rate)
+                                        {
+                                            warn!("Stats from {} failed: {}", session_id, e);
+                                        }
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Read error: {}", e);
+                            break;
+                        }
+                    }
                 }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
-                    continue;
+                Ok(message) = rumble_messages.recv_async() => {
+                    let packet = message.to_wire(std::time::Instant::now()).encode();
+                    match stream.write_all(&packet).await {
+                        Ok(()) => {
+                            if let Some(rumble) = &rumble {
+                                rumble.delivered(&message);
+                            }
+                        }
+                        Err(e) => warn!("Failed to send rumble to {}: {}", session_id, e),
+                    }
                 }
-                Err(e) => {
-                    error!("Read error: {}", e);
-                    break;
+                Ok(update) = slot_updates.recv_async() => {
+                    if let Err(e) = stream.write_all(&update.encode()).await {
+                        warn!("Failed to send player slots to {}: {}", session_id, e);
+                    }
+                }
+                Ok(overlay) = overlays.recv_async() => {
+                    if let Err(e) = stream.write_all(&overlay.encode()).await {
+                        warn!("Failed to send touch overlay to {}: {}", session_id, e);
+                    }
                 }
             }
         }
 
+        if let Some(input) = controls.input.write().as_mut() {
+            input.unsubscribe_rumble(&session_id);
+            if let Err(e) = input.unregister_client(&session_id) {
+                warn!("Failed to unregister input for {}: {}", session_id, e);
+            }
+        }
+
         // Cleanup session
         sessions.remove(&session_id);
         info!("Client session ended: {}", session_id);
@@ -619,25 +1172,213 @@ impl MoonlightServer {
         Ok(())
     }
 
+    async fn route_rumble(
+        source: flume::Receiver<RumbleEvent>,
+        input: Arc<RwLock<Option<ServerInputManager>>>,
+        is_running: Arc<ParkingMutex<bool>>,
+    ) {
+        while let Ok(event) = source.recv_async().await {
+            if !*is_running.lock() {
+                break;
+            }
+            if let Some(input_manager) = input.write().as_mut() {
+                input_manager.route_rumble(event);
+            }
+        }
+    }
+
     async fn handle_stream_data(
-        socket: UdpSocket,
-        _sessions: Arc<DashMap<Uuid, StreamingSession>>,
+        sink: Arc<dyn PacketSink>,
+        source: Arc<dyn PacketSource>,
+        video_frames: Receiver<VideoFrame>,
+        sessions: Arc<DashMap<Uuid, StreamingSession>>,
+        controls: SessionControls,
+        monitor: Arc<PerformanceMonitor>,
         is_running: Arc<ParkingMutex<bool>>,
     ) {
-        let mut buffer = vec![0u8; 65536]; // Max UDP packet size
+        let mut batch = RecvBatch::new(BATCH_SIZE);
+        let mut sender_reports = tokio::time::interval(SENDER_REPORT_INTERVAL);
+        // Parked while no pacer holds a packet it could send
+        let mut next_pacing = None;
 
         while *is_running.lock() {
-            match socket.recv_from(&mut buffer).await {
-                Ok((size, addr)) => {
-                    debug!("Received {} bytes from {}", size, addr);
-                    // TODO: Parse and route stream data to appropriate session
+            let pacing_due = tokio::time::sleep_until(
+                next_pacing.unwrap_or_else(std::time::Instant::now).into(),
+            );
+            tokio::select! {
+                received = source.recv_batch(&mut batch) => match received {
+                    Ok(_) => {
+                        for (datagram, addr) in batch.iter() {
+                            if !is_rtcp(datagram) {
+                                debug!("Received {} bytes from {}", datagram.len(), addr);
+                                continue;
+                            }
+                            if let Err(e) = Self::handle_rtcp(
+                                datagram,
+                                addr,
+                                &sessions,
+                                &controls.refresh,
+                                &monitor,
+                            ) {
+                                debug!("Ignoring RTCP from {}: {}", addr, e);
+                            }
+                        }
+                        // Resends and newly learned addresses may need sending
+                        next_pacing = Self::next_pacing_at(&sessions, std::time::Instant::now());
+                    }
+                    Err(e) => {
+                        error!("UDP receive error: {}", e);
+                        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                    }
+                },
+                frame = video_frames.recv_async() => match frame {
+                    Ok(frame) => {
+                        let now = std::time::Instant::now();
+                        Self::send_video_frame(&frame, &sessions, &controls.input, now);
+                        next_pacing = Self::next_pacing_at(&sessions, now);
+                    }
+                    Err(_) => break,
+                },
+                _ = pacing_due, if next_pacing.is_some() => {
+                    let now = std::time::Instant::now();
+                    let due = Self::drain_pacers(&sessions, &monitor, now);
+                    if let Err(e) = sink.send_batch(&due).await {
+                        warn!("Failed to send stream packets: {}", e);
+                    }
+                    next_pacing = Self::next_pacing_at(&sessions, now)
+                        .map(|at| at.max(now + PACING_INTERVAL));
                 }
-                Err(e) => {
-                    error!("UDP receive error: {}", e);
-                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                _ = sender_reports.tick() => {
+                    let now = std::time::Instant::now();
+                    let reports: Vec<Datagram> = sessions
+                        .iter()
+                        .filter_map(|session| {
+                            let rtcp = session.rtcp.as_ref()?;
+                            let report = RtcpPacket::SenderReport(rtcp.sender_report(now));
+                            Some((rtcp.media_addr?, encode_compound(&[report]).into()))
+                        })
+                        .collect();
+                    if let Err(e) = sink.send_batch(&reports).await {
+                        warn!("Failed to send sender reports: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Packets the sessions' pacers let go at `now`, with where to send
+    /// them, updating the pacing metrics
+    fn drain_pacers(
+        sessions: &DashMap<Uuid, StreamingSession>,
+        monitor: &PerformanceMonitor,
+        now: std::time::Instant,
+    ) -> Vec<Datagram> {
+        let mut due = Vec::new();
+        let mut queue_depth = 0;
+        let mut queue_delay = std::time::Duration::ZERO;
+        for mut session in sessions.iter_mut() {
+            let session = &mut *session;
+            let (Some(pacer), Some(addr)) = (
+                session.pacer.as_mut(),
+                session.rtcp.as_ref().and_then(|rtcp| rtcp.media_addr),
+            ) else {
+                continue;
+            };
+            while let Some(packet) = pacer.poll(now) {
+                due.push((addr, packet));
+            }
+            let metrics = pacer.metrics(now);
+            queue_depth += metrics.queued_packets;
+            queue_delay = queue_delay.max(metrics.queue_delay);
+        }
+
+        monitor
+            .pacing_queue_depth
+            .store(queue_depth, std::sync::atomic::Ordering::Relaxed);
+        monitor.pacing_delay_us.store(
+            queue_delay.as_micros() as u64,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+        due
+    }
+
+    /// When the next packet any session can be sent is due, `None` with
+    /// nothing waiting that has somewhere to go
+    fn next_pacing_at(
+        sessions: &DashMap<Uuid, StreamingSession>,
+        now: std::time::Instant,
+    ) -> Option<std::time::Instant> {
+        sessions
+            .iter()
+            .filter(|session| {
+                session
+                    .rtcp
+                    .as_ref()
+                    .is_some_and(|rtcp| rtcp.media_addr.is_some())
+            })
+            .filter_map(|session| session.pacer.as_ref()?.next_send_at(now))
+            .min()
+    }
+
+    /// Act on RTCP from a client: learn where it receives the stream, count
+    /// what it lost, queue what it asked to have resent and pass refreshes on
+    /// to the encoder
+    fn handle_rtcp(
+        datagram: &[u8],
+        from: SocketAddr,
+        sessions: &DashMap<Uuid, StreamingSession>,
+        refresh_control: &RwLock<Option<Sender<PictureRefresh>>>,
+        monitor: &PerformanceMonitor,
+    ) -> Result<()> {
+        let packets = decode_compound(datagram).map_err(StreamingError::from)?;
+        let Some(ssrc) = media_ssrc(&packets) else {
+            return Ok(());
+        };
+
+        let (session_id, response) = {
+            // Only the session's own client may steer its stream
+            let Some(mut session) = sessions.iter_mut().find(|session| {
+                session.client_addr.ip() == from.ip()
+                    && session
+                        .rtcp
+                        .as_ref()
+                        .is_some_and(|rtcp| rtcp.ssrc() == ssrc)
+            }) else {
+                return Ok(());
+            };
+            let session = &mut *session;
+            let Some(rtcp) = session.rtcp.as_mut() else {
+                return Ok(());
+            };
+            let now = std::time::Instant::now();
+            rtcp.media_addr = Some(from);
+            let response = rtcp.on_feedback(&packets, now);
+            if let Some(pacer) = session.pacer.as_mut() {
+                // Resent in sequence order once at the head of the queue
+                for packet in response.resend.iter().rev() {
+                    pacer.enqueue_resend(Arc::clone(packet), now);
                 }
             }
+            (session.id, response)
+        };
+
+        monitor
+            .packet_loss_count
+            .fetch_add(response.newly_lost, std::sync::atomic::Ordering::Relaxed);
+        if let Some(refresh) = response.refresh {
+            debug!("Client {} needs {:?}", session_id, refresh);
+            if let Some(control) = refresh_control.read().as_ref() {
+                control
+                    .send(PictureRefresh {
+                        session_id,
+                        refresh,
+                    })
+                    .map_err(|_| StreamingError::ControlUnavailable {
+                        reason: "refresh control channel closed".to_string(),
+                    })?;
+            }
         }
+        Ok(())
     }
 
     /// Perform RTSP handshake for session negotiation
@@ -652,22 +1393,108 @@ impl MoonlightServer {
         Ok(())
     }
 
-    /// Exchange capabilities with the client
+    /// Read from the control socket until a whole message has arrived
+    async fn read_message(stream: &mut TcpStream, frames: &mut FrameReader) -> Result<Vec<u8>> {
+        let mut buffer = [0u8; 1024];
+        loop {
+            if let Some(message) = frames.next_message().map_err(StreamingError::from)? {
+                return Ok(message);
+            }
+            let n = stream.read(&mut buffer).await?;
+            if n == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            frames.extend(&buffer[..n]);
+        }
+    }
+
+    /// Exchange hellos with the client, settling the protocol version and
+    /// the features the session uses
+    ///
+    /// Clients speaking no version in common, or lacking a feature the
+    /// server requires, get a rejection saying which side to update.
     async fn exchange_capabilities(
-        _stream: &mut TcpStream,
-        _config: &ServerConfig,
+        stream: &mut TcpStream,
+        frames: &mut FrameReader,
+        config: &ServerConfig,
     ) -> Result<ClientCapabilities> {
         debug!("Exchanging capabilities");
 
-        // In a real implementation, this would parse client capabilities and respond with server capabilities
-        // For now, return default capabilities
-        Ok(ClientCapabilities {
-            max_resolution: (1920, 1080),
-            supported_codecs: vec!["H264".to_string()],
-            audio_codecs: vec!["Opus".to_string()],
-            max_fps: 60,
-            supports_hdr: false,
-        })
+        let message = tokio::time::timeout(HELLO_TIMEOUT, Self::read_message(stream, frames))
+            .await
+            .map_err(|_| StreamingError::ClientRejected {
+                reason: "no hello received; the client predates protocol negotiation".into(),
+            })??;
+        let client = Hello::decode(&message).map_err(StreamingError::from)?;
+
+        let reply = Self::server_hello(config).answer(&client, Self::required_features(config));
+        stream.write_all(&reply.encode()).await?;
+
+        match reply {
+            HelloReply::Rejected(rejection) => {
+                warn!(
+                    "Rejecting {} (protocol {}-{}): {}",
+                    client.build_version,
+                    client.min_protocol_version,
+                    client.protocol_version,
+                    rejection
+                );
+                Err(StreamingError::ClientRejected {
+                    reason: rejection.to_string(),
+                }
+                .into())
+            }
+            HelloReply::Accepted(agreement) => {
+                info!(
+                    "Client {} speaks protocol {}, features {:#x}",
+                    client.build_version, agreement.protocol_version, agreement.features
+                );
+                let mut supported_codecs = vec!["H264".to_string()];
+                if agreement.has(features::HEVC) {
+                    supported_codecs.push("H265".to_string());
+                }
+                Ok(ClientCapabilities {
+                    max_resolution: (
+                        client.capabilities.max_width.into(),
+                        client.capabilities.max_height.into(),
+                    ),
+                    supported_codecs,
+                    audio_codecs: vec!["Opus".to_string()],
+                    max_fps: client.capabilities.max_fps.into(),
+                    supports_hdr: false,
+                    protocol_version: agreement.protocol_version,
+                    build_version: client.build_version,
+                    features: agreement.features,
+                })
+            }
+        }
+    }
+
+    /// What this server offers clients
+    fn server_hello(config: &ServerConfig) -> Hello {
+        Hello::new(
+            SERVER_BUILD,
+            Capabilities {
+                features: features::RUMBLE
+                    | features::MULTI_CONTROLLER
+                    | features::MOTION
+                    | features::TOUCH
+                    | features::LATENCY_TAGS
+                    | Self::required_features(config),
+                max_width: 1920,
+                max_height: 1080,
+                max_fps: 60,
+            },
+        )
+    }
+
+    /// Features a client must support to be served
+    fn required_features(config: &ServerConfig) -> u32 {
+        if config.enable_encryption {
+            features::ENCRYPTION
+        } else {
+            0
+        }
     }
 
     /// Exchange encryption keys if encryption is enabled
@@ -698,27 +1525,53 @@ impl MoonlightServer {
         })
     }
 
-    /// Send video frame to specific client
-    #[allow(dead_code)]
-    async fn send_video_frame_to_client(
-        &self,
-        frame: &Option<VideoFrame>,
-        session_id: &Uuid,
-    ) -> Result<()> {
-        if let Some(frame) = frame {
-            // In a real implementation, this would encode the frame and send via UDP
-            debug!(
-                "Sending video frame {} to client {}",
-                frame.frame_number, session_id
-            );
+    /// Packetize an encoded frame for every streaming session, keep the
+    /// packets for resending and queue them on the session's pacer; clients
+    /// that read [`features::LATENCY_TAGS`] get the last input their session
+    /// got into Dolphin on every packet of the frame
+    fn send_video_frame(
+        frame: &VideoFrame,
+        sessions: &DashMap<Uuid, StreamingSession>,
+        input: &RwLock<Option<ServerInputManager>>,
+        now: std::time::Instant,
+    ) {
+        let tagged: Vec<Uuid> = sessions
+            .iter()
+            .filter(|session| {
+                session.state == SessionState::Streaming
+                    && session.has_feature(features::LATENCY_TAGS)
+            })
+            .map(|session| session.id)
+            .collect();
+        let tags: HashMap<Uuid, [u8; 20]> =
+            input.read().as_ref().map_or_else(HashMap::new, |input| {
+                tagged
+                    .iter()
+                    .filter_map(|id| {
+                        let applied = input.last_applied_input(id)?;
+                        Some((*id, applied.frame_tag(now).rtp_extension()))
+                    })
+                    .collect()
+            });
 
-            // TODO: Implement actual H264 encoding and UDP transmission
-            // This would involve:
-            // 1. H264 encoding of the frame
-            // 2. RTP packetization
-            // 3. UDP transmission to client
+        for mut session in sessions.iter_mut() {
+            let session = &mut *session;
+            if session.state != SessionState::Streaming {
+                continue;
+            }
+            let (Some(rtp), Some(rtcp), Some(pacer)) = (
+                session.rtp.as_mut(),
+                session.rtcp.as_mut(),
+                session.pacer.as_mut(),
+            ) else {
+                continue;
+            };
+            let extension = tags.get(&session.id).map(|tag| &tag[..]);
+            for packet in rtp.packetize(&frame.data, frame.timestamp, extension) {
+                rtcp.packet_sent(Arc::clone(&packet), frame.frame_number, now);
+                pacer.enqueue(packet, now);
+            }
         }
-        Ok(())
     }
 
     /// Send audio frame to specific client
@@ -754,13 +1607,37 @@ impl MoonlightServer {
         let msg_type = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
 
         match msg_type {
-            0x0C => {
-                // Controller input message
-                if data.len() >= 20 {
-                    self.handle_controller_input(data, session_id).await?;
-                }
+            MSG_EMULATION_CONTROL => {
+                Self::handle_emulation_control(
+                    data,
+                    session_id,
+                    &self.sessions,
+                    &self.controls.emulation,
+                )
+                .await?;
+            }
+            MSG_WII_EXTENSION => {
+                Self::handle_wii_extension(data, session_id, &self.controls.input)?;
+            }
+            MSG_KEYBOARD_MOUSE => {
+                Self::handle_keyboard_mouse(data, session_id, &self.sessions, &self.controls)
+                    .await?;
+            }
+            MSG_LATENCY_REPORT => {
+                Self::handle_latency_report(data, session_id, &self.controls.latency)?;
             }
-            0x0A => {
+            MSG_CLIENT_STATS => {
+                Self::handle_client_stats(data, session_id, &self.sessions, &self.controls.rate)?;
+            }
+            MSG_INPUT => {
+                Self::handle_controller_input(
+                    data,
+                    session_id,
+                    &self.sessions,
+                    &self.controls.input,
+                )?;
+            }
+            MSG_KEEPALIVE => {
                 // Keepalive message
                 debug!("Received keepalive from client {}", session_id);
             }
@@ -775,34 +1652,359 @@ impl MoonlightServer {
         Ok(())
     }
 
-    /// Handle controller input from client
-    #[allow(dead_code)]
-    async fn handle_controller_input(&self, data: &[u8], session_id: &Uuid) -> Result<()> {
-        // Parse Moonlight controller input packet
-        if data.len() >= 20 {
-            let controller_input = ControllerInput {
-                buttons: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
-                left_stick_x: i16::from_le_bytes([data[8], data[9]]),
-                left_stick_y: i16::from_le_bytes([data[10], data[11]]),
-                right_stick_x: i16::from_le_bytes([data[12], data[13]]),
-                right_stick_y: i16::from_le_bytes([data[14], data[15]]),
-                left_trigger: data[16],
-                right_trigger: data[17],
+    /// Forward an emulation control message and update session state to match
+    async fn handle_emulation_control(
+        data: &[u8],
+        session_id: &Uuid,
+        sessions: &DashMap<Uuid, StreamingSession>,
+        emulation_control: &RwLock<Option<Sender<EmulationJob>>>,
+    ) -> Result<()> {
+        let command = EmulationCommand::decode(data).map_err(StreamingError::from)?;
+        Self::forward_emulation_command(command, session_id, sessions, emulation_control).await
+    }
+
+    /// Send a command to the emulator and wait for it to be applied, then
+    /// move the viewers to the emulator's pause state
+    async fn forward_emulation_command(
+        command: EmulationCommand,
+        session_id: &Uuid,
+        sessions: &DashMap<Uuid, StreamingSession>,
+        emulation_control: &RwLock<Option<Sender<EmulationJob>>>,
+    ) -> Result<()> {
+        let control =
+            emulation_control
+                .read()
+                .clone()
+                .ok_or_else(|| StreamingError::ControlUnavailable {
+                    reason: "no emulator attached".to_string(),
+                })?;
+        let (reply_tx, reply_rx) = bounded(1);
+        control
+            .send(EmulationJob {
+                command,
+                reply: reply_tx,
+            })
+            .map_err(|_| StreamingError::ControlUnavailable {
+                reason: "control channel closed".to_string(),
+            })?;
+        debug!("Client {} requested emulation {:?}", session_id, command);
+
+        let status =
+            reply_rx
+                .recv_async()
+                .await
+                .map_err(|_| StreamingError::ControlUnavailable {
+                    reason: "emulator dropped the request".to_string(),
+                })??;
+
+        // Every streaming viewer watches the one emulator, so its pause state
+        // is theirs; sessions still connecting or closing are left alone
+        let state = if status.paused {
+            SessionState::Paused
+        } else {
+            SessionState::Streaming
+        };
+        for mut session in sessions.iter_mut() {
+            if matches!(
+                session.state,
+                SessionState::Streaming | SessionState::Paused
+            ) {
+                session.state = state;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply a keyboard or mouse event to the requesting session's player
+    /// and run the emulation commands bound to it
+    async fn handle_keyboard_mouse(
+        data: &[u8],
+        session_id: &Uuid,
+        sessions: &DashMap<Uuid, StreamingSession>,
+        controls: &SessionControls,
+    ) -> Result<()> {
+        let event = InputEvent::from(KeyboardMouse::decode(data).map_err(StreamingError::from)?);
+
+        let commands = {
+            let mut input = controls.input.write();
+            let input_manager =
+                input
+                    .as_mut()
+                    .ok_or_else(|| StreamingError::ControlUnavailable {
+                        reason: "no input manager attached".to_string(),
+                    })?;
+            input_manager.handle_event(session_id, &event)?
+        };
+
+        for command in commands {
+            debug!("Client {} hotkey {:?}", session_id, command);
+            Self::forward_emulation_command(command, session_id, sessions, &controls.emulation)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Add a client's input-to-photon measurement to the histograms
+    fn handle_latency_report(
+        data: &[u8],
+        session_id: &Uuid,
+        latency: &LatencyMetrics,
+    ) -> Result<()> {
+        let report = LatencyReport::decode(data).map_err(StreamingError::from)?;
+        debug!("Client {} input latency {:?}", session_id, report);
+        latency.record_report(&report);
+        Ok(())
+    }
+
+    /// Adapt a session's stream to the receive statistics its client sent,
+    /// passing a changed target on to the encoder
+    fn handle_client_stats(
+        data: &[u8],
+        session_id: &Uuid,
+        sessions: &DashMap<Uuid, StreamingSession>,
+        rate_control: &RwLock<Option<Sender<RateChange>>>,
+    ) -> Result<()> {
+        let stats = ClientStats::decode(data).map_err(StreamingError::from)?;
+        let (target, usage) = {
+            let Some(mut session) = sessions.get_mut(session_id) else {
+                return Ok(());
             };
+            let Some(congestion) = session.congestion.as_mut() else {
+                return Ok(());
+            };
+            let Some(target) = congestion.on_stats(&stats) else {
+                return Ok(());
+            };
+            let usage = congestion.usage();
+            if let Some(stream_config) = session.stream_config.as_mut() {
+                stream_config.video_bitrate = target.bitrate_kbps;
+                stream_config.video_fps = target.fps;
+                stream_config.video_resolution = target.resolution;
+            }
+            if let Some(pacer) = session.pacer.as_mut() {
+                pacer.set_bitrate(target.bitrate_kbps);
+            }
+            (target, usage)
+        };
+        debug!(
+            "Client {} received {}kbps, {:.1}% lost, delay {:?}; streaming {:?}",
+            session_id,
+            stats.received_kbps(),
+            stats.loss_ratio() * 100.0,
+            usage,
+            target
+        );
+
+        if let Some(control) = rate_control.read().as_ref() {
+            control
+                .send(RateChange {
+                    session_id: *session_id,
+                    target,
+                })
+                .map_err(|_| StreamingError::ControlUnavailable {
+                    reason: "rate control channel closed".to_string(),
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Switch the extension on the requesting session's Wii Remote
+    fn handle_wii_extension(
+        data: &[u8],
+        session_id: &Uuid,
+        input: &RwLock<Option<ServerInputManager>>,
+    ) -> Result<()> {
+        let extension = WiiExtension::decode(data).map_err(StreamingError::from)?;
+
+        let mut input = input.write();
+        let input_manager = input
+            .as_mut()
+            .ok_or_else(|| StreamingError::ControlUnavailable {
+                reason: "no input manager attached".to_string(),
+            })?;
+        input_manager.set_wii_extension(session_id, extension)?;
+
+        debug!("Client {} attached {:?}", session_id, extension);
+        Ok(())
+    }
+
+    /// Step the requesting session's controller calibration
+    fn handle_calibration(
+        data: &[u8],
+        session_id: &Uuid,
+        input: &RwLock<Option<ServerInputManager>>,
+    ) -> Result<CalibrationResponse> {
+        let request = CalibrationRequest::decode(data).map_err(StreamingError::from)?;
+
+        let mut input = input.write();
+        let input_manager = input
+            .as_mut()
+            .ok_or_else(|| StreamingError::ControlUnavailable {
+                reason: "no input manager attached".to_string(),
+            })?;
+
+        debug!("Client {} calibration {:?}", session_id, request);
+        input_manager.handle_calibration(session_id, request)
+    }
+
+    /// Change the requesting session's player slot
+    fn handle_slot_request(
+        data: &[u8],
+        session_id: &Uuid,
+        input: &RwLock<Option<ServerInputManager>>,
+    ) -> Result<()> {
+        let request = SlotRequest::decode(data).map_err(StreamingError::from)?;
+
+        let mut input = input.write();
+        let input_manager = input
+            .as_mut()
+            .ok_or_else(|| StreamingError::ControlUnavailable {
+                reason: "no input manager attached".to_string(),
+            })?;
 
-            // Convert to MoonlightInputPacket
-            let _input_packet = self.convert_controller_input_to_moonlight(0, controller_input);
+        debug!("Client {} slot request {:?}", session_id, request);
+        input_manager.handle_slot_request(session_id, request)
+    }
 
-            // Send to input manager if available - parking_lot RwLock needs write()
-            if let Some(input_manager) = self.input_manager.write().as_mut() {
-                // Register client - let register_client handle whether client already exists
-                let _sender = input_manager.register_client(*session_id)?;
+    /// Forward a save-state request to the emulator and wait for its reply
+    async fn handle_state_request(
+        data: &[u8],
+        controls: &SessionControls,
+    ) -> Result<StateResponse> {
+        let request = StateRequest::decode(data).map_err(StreamingError::from)?;
+
+        let control =
+            controls
+                .states
+                .read()
+                .clone()
+                .ok_or_else(|| StreamingError::ControlUnavailable {
+                    reason: "no emulator attached".to_string(),
+                })?;
+
+        let (reply_tx, reply_rx) = bounded(1);
+        let job = StateJob {
+            request,
+            thumbnail: controls.thumbnail.read().clone(),
+            reply: reply_tx,
+        };
+        control
+            .send(job)
+            .map_err(|_| StreamingError::ControlUnavailable {
+                reason: "control channel closed".to_string(),
+            })?;
+
+        reply_rx.recv_async().await.map_err(|_| {
+            StreamingError::ControlUnavailable {
+                reason: "emulator dropped the request".to_string(),
+            }
+            .into()
+        })
+    }
 
-                // TODO: Send input packet to the registered session
-                debug!("Processed controller input from client {}", session_id);
+    /// List the app list, or forward a launch to the emulator and wait for
+    /// it to start
+    async fn handle_library_request(
+        data: &[u8],
+        controls: &SessionControls,
+    ) -> Result<LibraryResponse> {
+        let (app_id, user) = match LibraryRequest::decode(data).map_err(StreamingError::from)? {
+            LibraryRequest::List => {
+                let games = controls
+                    .library
+                    .read()
+                    .iter()
+                    .zip(1..)
+                    .map(|(game, app_id)| game.info(app_id))
+                    .collect();
+                return Ok(LibraryResponse::Games(games));
             }
+            LibraryRequest::Launch { app_id, user } => (app_id, user),
+        };
+
+        // App IDs are 1-based positions in the app list
+        let game = app_id
+            .checked_sub(1)
+            .and_then(|index| controls.library.read().get(index as usize).cloned())
+            .ok_or_else(|| EmulatorError::RomNotFound {
+                path: format!("app {app_id}"),
+                suggestions: Vec::new(),
+            })?;
+
+        let control =
+            controls
+                .launches
+                .read()
+                .clone()
+                .ok_or_else(|| StreamingError::ControlUnavailable {
+                    reason: "no emulator attached".to_string(),
+                })?;
+
+        let game_id = game.game_id.clone();
+        let (reply_tx, reply_rx) = bounded(1);
+        control
+            .send(LaunchJob {
+                game,
+                user,
+                reply: reply_tx,
+            })
+            .map_err(|_| StreamingError::ControlUnavailable {
+                reason: "control channel closed".to_string(),
+            })?;
+
+        let profile =
+            reply_rx
+                .recv_async()
+                .await
+                .map_err(|_| StreamingError::ControlUnavailable {
+                    reason: "emulator dropped the request".to_string(),
+                })??;
+
+        // Every player's controls follow the game that is now running
+        if let (Some(profile), Some(input)) = (profile, controls.input.write().as_mut()) {
+            input.apply_game_profile(&profile);
         }
 
+        Ok(LibraryResponse::Launched { game_id })
+    }
+
+    /// Queue a client's controller input for the input manager's next tick
+    fn handle_controller_input(
+        data: &[u8],
+        session_id: &Uuid,
+        sessions: &DashMap<Uuid, StreamingSession>,
+        input: &RwLock<Option<ServerInputManager>>,
+    ) -> Result<()> {
+        let packet = MoonlightInputPacket::decode(data).map_err(StreamingError::from)?;
+        let packet = {
+            let session = sessions.get(session_id);
+            match session.as_ref().and_then(|s| s.input_handler.as_ref()) {
+                Some(handler) => match handler.sender.send(packet) {
+                    Ok(()) => return Ok(()),
+                    Err(tokio::sync::mpsc::error::SendError(packet)) => packet,
+                },
+                None => packet,
+            }
+        };
+
+        // The input manager drops sessions that went quiet; one that speaks
+        // up again is registered anew
+        let Some(sender) = input
+            .write()
+            .as_mut()
+            .map(|input| input.register_client(*session_id))
+            .transpose()?
+        else {
+            return Ok(());
+        };
+        let _ = sender.send(packet);
+        if let Some(mut session) = sessions.get_mut(session_id) {
+            session.input_handler = Some(InputHandler {
+                sender,
+                input_buffer: SmallVec::new(),
+            });
+        }
         Ok(())
     }
 }
@@ -819,8 +2021,7 @@ pub struct ServerStats {
 #[cfg(test)]
 mod tests {
     use super::*;
-    // Capture module is disabled for minimal build
-    // use crate::streaming::capture::{QualityPreset, VideoEncoder};
+    use dpstream_protocol::{HEADER_LEN, PROTOCOL_VERSION};
 
     fn create_test_config() -> ServerConfig {
         ServerConfig {
@@ -830,6 +2031,8 @@ mod tests {
             enable_encryption: true,
             enable_authentication: true,
             stream_timeout_ms: 30000,
+            rate_limits: RateLimits::default(),
+            udp_gso: false,
         }
     }
 
@@ -877,4 +2080,1465 @@ mod tests {
         let result = server.broadcast_audio_frame(frame);
         assert!(result.is_ok(), "Audio frame broadcast should succeed");
     }
+
+    #[tokio::test]
+    async fn test_emulation_control_pauses_sessions() {
+        use crate::emulator::control::EmulationStatus;
+
+        let config = create_test_config();
+        let server = MoonlightServer::new(config).await.unwrap();
+        let (control_tx, control_rx) = unbounded::<EmulationJob>();
+        server.set_emulation_control(control_tx);
+
+        let session = |state| {
+            let id = Uuid::new_v4();
+            let session = StreamingSession {
+                id,
+                client_addr: "127.0.0.1:50000".parse().unwrap(),
+                video_stream: None,
+                audio_stream: None,
+                input_handler: None,
+                state,
+                started_at: std::time::Instant::now(),
+                last_activity: std::time::Instant::now(),
+                stream_config: None,
+                features: 0,
+                congestion: None,
+                rtcp: None,
+                rtp: None,
+                pacer: None,
+            };
+            server.sessions.insert(id, session);
+            id
+        };
+        let session_id = session(SessionState::Streaming);
+        let connecting = session(SessionState::Handshaking);
+        let state = |id| server.sessions.get(&id).unwrap().state;
+
+        // The emulator confirms the pause, then fails to resume
+        let emulator = tokio::spawn(async move {
+            let job = control_rx.recv_async().await.unwrap();
+            assert_eq!(job.command, EmulationCommand::Pause);
+            let paused = EmulationStatus {
+                paused: true,
+                ..EmulationStatus::default()
+            };
+            job.reply.send(Ok(paused)).unwrap();
+
+            let job = control_rx.recv_async().await.unwrap();
+            assert_eq!(job.command, EmulationCommand::Resume);
+            let failed = EmulatorError::ProcessControlFailed {
+                operation: "Resume".to_string(),
+                reason: "Dolphin is not running".to_string(),
+            };
+            job.reply.send(Err(failed.into())).unwrap();
+        });
+
+        server
+            .parse_control_message(&EmulationCommand::Pause.encode(), &session_id)
+            .await
+            .unwrap();
+        assert_eq!(state(session_id), SessionState::Paused);
+        assert_eq!(state(connecting), SessionState::Handshaking);
+        assert_eq!(server.get_stats().active_sessions, 0);
+
+        assert!(server
+            .parse_control_message(&EmulationCommand::Resume.encode(), &session_id)
+            .await
+            .is_err());
+        assert_eq!(state(session_id), SessionState::Paused);
+        emulator.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_stats_adapt_session_stream() {
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let (rate_tx, rate_rx) = unbounded();
+        server.set_rate_control(rate_tx);
+
+        let stream_config = NegotiatedStreamConfig {
+            video_resolution: (1280, 720),
+            video_fps: 60,
+            video_bitrate: 15000,
+            audio_sample_rate: 48000,
+            audio_channels: 2,
+        };
+        let session_id = Uuid::new_v4();
+        server.sessions.insert(
+            session_id,
+            StreamingSession {
+                id: session_id,
+                client_addr: "127.0.0.1:50000".parse().unwrap(),
+                video_stream: None,
+                audio_stream: None,
+                input_handler: None,
+                state: SessionState::Streaming,
+                started_at: std::time::Instant::now(),
+                last_activity: std::time::Instant::now(),
+                stream_config: Some(stream_config),
+                features: 0,
+                congestion: Some(CongestionController::new(
+                    StreamTarget {
+                        bitrate_kbps: 15000,
+                        fps: 60,
+                        resolution: (1280, 720),
+                    },
+                    RateLimits::default(),
+                )),
+                rtcp: None,
+                rtp: None,
+                pacer: None,
+            },
+        );
+
+        // A quarter of the stream lost and the delay climbing
+        let stats = ClientStats {
+            interval_ms: 250,
+            bytes_received: 300_000,
+            packets_received: 250,
+            packets_lost: 80,
+            jitter_us: 6000,
+            decode_time_us: 4000,
+            frames_decoded: 15,
+            frames_dropped: 0,
+            delay_gradient_us: 60_000,
+        };
+        server
+            .parse_control_message(&stats.encode(), &session_id)
+            .await
+            .unwrap();
+
+        let change = rate_rx.try_recv().unwrap();
+        assert_eq!(change.session_id, session_id);
+        assert!(change.target.bitrate_kbps < 15000);
+        let session = server.sessions.get(&session_id).unwrap();
+        let stream_config = session.stream_config.as_ref().unwrap();
+        assert_eq!(stream_config.video_bitrate, change.target.bitrate_kbps);
+    }
+
+    #[tokio::test]
+    async fn test_client_stats_reconfigure_encoder() {
+        use crate::streaming::capture::{QualityPreset, VideoCaptureConfig};
+        use crate::streaming::encoder::EncoderConfig;
+        use crate::streaming::pipeline::VideoPipeline;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let (rate_tx, rate_rx) = unbounded();
+        server.set_rate_control(rate_tx);
+
+        let start = StreamTarget {
+            bitrate_kbps: 15000,
+            fps: 60,
+            resolution: (1280, 720),
+        };
+        let streaming_session = |id| StreamingSession {
+            id,
+            client_addr: "127.0.0.1:50000".parse().unwrap(),
+            video_stream: None,
+            audio_stream: None,
+            input_handler: None,
+            state: SessionState::Streaming,
+            started_at: std::time::Instant::now(),
+            last_activity: std::time::Instant::now(),
+            stream_config: None,
+            features: 0,
+            congestion: Some(CongestionController::new(start, RateLimits::default())),
+            rtcp: None,
+            rtp: None,
+            pacer: None,
+        };
+        let lossy = Uuid::new_v4();
+        let clear = Uuid::new_v4();
+        server.sessions.insert(lossy, streaming_session(lossy));
+        server.sessions.insert(clear, streaming_session(clear));
+
+        let mut pipeline = VideoPipeline::start(
+            VideoCaptureConfig {
+                window_id: 0,
+                width: 64,
+                height: 36,
+                fps: 30,
+                bitrate: start.bitrate_kbps,
+                encoder: crate::streaming::capture::VideoEncoder::Software,
+                quality_preset: QualityPreset::UltraFast,
+            },
+            EncoderConfig {
+                bitrate: start.bitrate_kbps,
+                width: 1280,
+                height: 720,
+                fps: 60,
+                ..EncoderConfig::default()
+            },
+            server.video_output(),
+        )
+        .await
+        .unwrap();
+
+        // One client reports heavy loss, the other nothing
+        let stats = ClientStats {
+            interval_ms: 250,
+            bytes_received: 300_000,
+            packets_received: 250,
+            packets_lost: 80,
+            jitter_us: 6000,
+            decode_time_us: 4000,
+            frames_decoded: 15,
+            frames_dropped: 0,
+            delay_gradient_us: 60_000,
+        };
+        server
+            .parse_control_message(&stats.encode(), &lossy)
+            .await
+            .unwrap();
+        let change = rate_rx.try_recv().unwrap();
+        pipeline.apply_rate_change(change).await.unwrap();
+
+        // The shared encoder drops to what the lossy client can take
+        let config = pipeline.encoder().config();
+        assert!(change.target.bitrate_kbps < start.bitrate_kbps);
+        assert_eq!(config.bitrate, change.target.bitrate_kbps);
+        assert_eq!((config.width, config.height), change.target.resolution);
+        assert_eq!(config.fps, change.target.fps);
+    }
+
+    #[tokio::test]
+    async fn test_rtcp_feedback_resends_and_refreshes() {
+        use crate::streaming::rtcp::{Refresh, ReportBlock};
+        use dpstream_protocol::rtcp::{Nack, ReceiverReport};
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let (refresh_tx, refresh_rx) = unbounded();
+        server.set_refresh_control(refresh_tx);
+
+        let session_id = Uuid::new_v4();
+        let ssrc = session_ssrc(&session_id);
+        let now = std::time::Instant::now();
+        let mut rtcp = RtcpSession::new(ssrc, ENCODER_REFERENCE_FRAMES);
+        for (sequence, sent_at) in [
+            (0u16, now - std::time::Duration::from_millis(500)),
+            (1, now),
+        ] {
+            let mut packet = vec![0x80, 96];
+            packet.extend(sequence.to_be_bytes());
+            packet.extend([0u8; 1196]);
+            rtcp.packet_sent(packet.into(), u64::from(sequence), sent_at);
+        }
+        server.sessions.insert(
+            session_id,
+            StreamingSession {
+                id: session_id,
+                client_addr: "127.0.0.1:50000".parse().unwrap(),
+                video_stream: None,
+                audio_stream: None,
+                input_handler: None,
+                state: SessionState::Streaming,
+                started_at: now,
+                last_activity: now,
+                stream_config: None,
+                features: 0,
+                congestion: None,
+                rtcp: Some(rtcp),
+                rtp: None,
+                pacer: Some(Pacer::new(15000, now)),
+            },
+        );
+
+        let datagram = encode_compound(&[
+            RtcpPacket::ReceiverReport(ReceiverReport {
+                ssrc: 1,
+                reports: vec![ReportBlock {
+                    ssrc,
+                    cumulative_lost: 2,
+                    ..ReportBlock::default()
+                }],
+            }),
+            RtcpPacket::Nack(Nack {
+                sender_ssrc: 1,
+                media_ssrc: ssrc,
+                lost: vec![0, 1],
+            }),
+        ]);
+        let handle = |from: &str| {
+            MoonlightServer::handle_rtcp(
+                &datagram,
+                from.parse().unwrap(),
+                &server.sessions,
+                &server.controls.refresh,
+                &server.performance_monitor,
+            )
+            .unwrap()
+        };
+
+        let drain = || {
+            MoonlightServer::drain_pacers(
+                &server.sessions,
+                &server.performance_monitor,
+                std::time::Instant::now(),
+            )
+        };
+
+        // Another host can't claim the session's stream
+        handle("10.0.0.9:47999");
+        assert!(drain().is_empty());
+        assert!(refresh_rx.is_empty());
+
+        // The fresh packet is resent, the stale one takes a keyframe
+        handle("127.0.0.1:47999");
+        let resend = drain();
+        assert_eq!(resend.len(), 1);
+        assert_eq!(resend[0].0, "127.0.0.1:47999".parse().unwrap());
+        assert_eq!(&resend[0].1[2..4], &1u16.to_be_bytes());
+        assert_eq!(
+            refresh_rx.try_recv().unwrap(),
+            PictureRefresh {
+                session_id,
+                refresh: Refresh::Keyframe,
+            }
+        );
+        assert_eq!(
+            server
+                .performance_monitor
+                .packet_loss_count
+                .load(std::sync::atomic::Ordering::Relaxed),
+            2
+        );
+        let session = server.sessions.get(&session_id).unwrap();
+        let rtcp = session.rtcp.as_ref().unwrap();
+        assert_eq!(rtcp.media_addr, Some("127.0.0.1:47999".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_pacers_space_out_session_packets() {
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let session_id = Uuid::new_v4();
+        let start = std::time::Instant::now();
+        let mut rtcp = RtcpSession::new(session_ssrc(&session_id), ENCODER_REFERENCE_FRAMES);
+        rtcp.media_addr = Some("127.0.0.1:47999".parse().unwrap());
+        let mut pacer = Pacer::new(15000, start);
+        for _ in 0..100 {
+            pacer.enqueue(vec![0u8; 1200].into(), start);
+        }
+        server.sessions.insert(
+            session_id,
+            StreamingSession {
+                id: session_id,
+                client_addr: "127.0.0.1:50000".parse().unwrap(),
+                video_stream: None,
+                audio_stream: None,
+                input_handler: None,
+                state: SessionState::Streaming,
+                started_at: start,
+                last_activity: start,
+                stream_config: None,
+                features: 0,
+                congestion: None,
+                rtcp: Some(rtcp),
+                rtp: None,
+                pacer: Some(pacer),
+            },
+        );
+        let monitor = &server.performance_monitor;
+
+        // A bucket's worth at once, then a few packets per pacing interval
+        assert_eq!(
+            MoonlightServer::next_pacing_at(&server.sessions, start),
+            Some(start)
+        );
+        let first = MoonlightServer::drain_pacers(&server.sessions, monitor, start);
+        assert_eq!(first.len(), 20);
+        assert_eq!(monitor.pacing_queue_depth.load(Ordering::Relaxed), 80);
+
+        let mut now = start;
+        let mut sent = first.len();
+        while sent < 100 {
+            now += PACING_INTERVAL;
+            let due = MoonlightServer::drain_pacers(&server.sessions, monitor, now);
+            sent += due.len();
+            assert!(due.len() <= 4 && (due.len() >= 3 || sent == 100));
+            assert_eq!(
+                monitor.pacing_queue_depth.load(Ordering::Relaxed),
+                100 - sent
+            );
+        }
+        let took = now - start;
+        assert!(took > Duration::from_millis(19) && took < Duration::from_millis(23));
+        assert_eq!(monitor.pacing_delay_us.load(Ordering::Relaxed), 0);
+
+        assert_eq!(MoonlightServer::next_pacing_at(&server.sessions, now), None);
+
+        let metrics = server.pacing_metrics().sessions(now);
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].1.sent_packets, 100);
+        assert_eq!(metrics[0].1.max_delay, took);
+        let text = server.pacing_metrics().prometheus();
+        assert!(text.contains(&format!(
+            "dpstream_pacing_sent_packets_total{{session=\"{session_id}\"}} 100\n"
+        )));
+        assert!(text.contains("# TYPE dpstream_pacing_queue_packets gauge\n"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_socket_sends_paced_video() {
+        use crate::streaming::rtcp::ReportBlock;
+        use dpstream_protocol::rtcp::ReceiverReport;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let (sink, source) = stream_socket_io(socket, false);
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let session_id = Uuid::new_v4();
+        let ssrc = session_ssrc(&session_id);
+        let now = std::time::Instant::now();
+        server.sessions.insert(
+            session_id,
+            StreamingSession {
+                id: session_id,
+                client_addr: "127.0.0.1:50000".parse().unwrap(),
+                video_stream: None,
+                audio_stream: None,
+                input_handler: None,
+                state: SessionState::Streaming,
+                started_at: now,
+                last_activity: now,
+                stream_config: None,
+                features: 0,
+                congestion: None,
+                rtcp: Some(RtcpSession::new(ssrc, ENCODER_REFERENCE_FRAMES)),
+                rtp: Some(RtpPacketizer::new(ssrc)),
+                pacer: Some(Pacer::new(15000, now)),
+            },
+        );
+        *server.is_running.lock() = true;
+        tokio::spawn(MoonlightServer::handle_stream_data(
+            sink,
+            source,
+            server.video_frames.clone(),
+            Arc::clone(&server.sessions),
+            server.controls.clone(),
+            Arc::clone(&server.performance_monitor),
+            Arc::clone(&server.is_running),
+        ));
+
+        // The client's receiver report tells the server where to stream
+        let report = encode_compound(&[RtcpPacket::ReceiverReport(ReceiverReport {
+            ssrc: 1,
+            reports: vec![ReportBlock {
+                ssrc,
+                ..ReportBlock::default()
+            }],
+        })]);
+        client.send_to(&report, server_addr).await.unwrap();
+        let media_addr = Some(client.local_addr().unwrap());
+        for _ in 0..100 {
+            let session = server.sessions.get(&session_id).unwrap();
+            if session.rtcp.as_ref().unwrap().media_addr == media_addr {
+                break;
+            }
+            drop(session);
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let mut data = vec![0, 0, 0, 1, 0x65];
+        data.resize(3000, 0x42);
+        server.video_output().send(VideoFrame {
+            data,
+            width: 1280,
+            height: 720,
+            timestamp: 0,
+            frame_number: 0,
+        });
+
+        // The frame's packets arrive in order through the batched socket,
+        // among the sender reports
+        let mut buf = [0u8; 2048];
+        let mut sequence = 0u16;
+        while sequence < 3 {
+            let (len, from) = tokio::time::timeout(
+                std::time::Duration::from_secs(1),
+                client.recv_from(&mut buf),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            assert_eq!(from, server_addr);
+            if is_rtcp(&buf[..len]) {
+                continue;
+            }
+            assert!(len <= crate::streaming::rtp::MAX_PACKET_SIZE);
+            assert_eq!(&buf[2..4], &sequence.to_be_bytes());
+            assert_eq!(&buf[8..12], &ssrc.to_be_bytes());
+            assert_eq!(buf[1] & 0x80 != 0, sequence == 2);
+            sequence += 1;
+        }
+        *server.is_running.lock() = false;
+    }
+
+    #[tokio::test]
+    async fn test_video_frames_carry_input_tags() {
+        use crate::input::backend::RecordingBackend;
+        use crate::streaming::latency::FrameInputTag;
+        use dpstream_protocol::latency::FRAME_TAG_PROFILE;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let mut input_manager =
+            ServerInputManager::with_backend(Box::new(RecordingBackend::new())).unwrap();
+        let tagged = Uuid::new_v4();
+        let plain = Uuid::new_v4();
+        for id in [tagged, plain] {
+            let input = input_manager.register_client(id).unwrap();
+            input
+                .send(MoonlightInputPacket {
+                    sequence: Some(5),
+                    ..MoonlightInputPacket::default()
+                })
+                .unwrap();
+        }
+        input_manager.process_inputs().await.unwrap();
+        server.set_input_manager(input_manager);
+
+        let now = std::time::Instant::now();
+        for (id, agreed) in [(tagged, features::LATENCY_TAGS), (plain, 0)] {
+            server.sessions.insert(
+                id,
+                StreamingSession {
+                    id,
+                    client_addr: "127.0.0.1:50000".parse().unwrap(),
+                    video_stream: None,
+                    audio_stream: None,
+                    input_handler: None,
+                    state: SessionState::Streaming,
+                    started_at: now,
+                    last_activity: now,
+                    stream_config: None,
+                    features: agreed,
+                    congestion: None,
+                    rtcp: Some(RtcpSession::new(
+                        session_ssrc(&id),
+                        ENCODER_REFERENCE_FRAMES,
+                    )),
+                    rtp: Some(RtpPacketizer::new(session_ssrc(&id))),
+                    pacer: Some(Pacer::new(15000, now)),
+                },
+            );
+        }
+
+        let mut data = vec![0, 0, 0, 1, 0x65];
+        data.resize(3000, 0x42);
+        server
+            .broadcast_video_frame(VideoFrame {
+                data,
+                width: 1280,
+                height: 720,
+                timestamp: 0,
+                frame_number: 1,
+            })
+            .unwrap();
+        let frame = server.video_frames.try_recv().unwrap();
+        MoonlightServer::send_video_frame(&frame, &server.sessions, &server.controls.input, now);
+
+        let sent = |id: &Uuid| {
+            let mut session = server.sessions.get_mut(id).unwrap();
+            let pacer = session.pacer.as_mut().unwrap();
+            std::iter::from_fn(|| pacer.poll(now + std::time::Duration::from_secs(1)))
+                .collect::<Vec<_>>()
+        };
+
+        // Every packet of the frame names the input, for the client that
+        // reads tags only
+        let tagged_packets = sent(&tagged);
+        assert_eq!(tagged_packets.len(), 3);
+        for packet in &tagged_packets {
+            assert_eq!(packet[0], 0x90);
+            let profile = u16::from_be_bytes([packet[12], packet[13]]);
+            let tag = FrameInputTag::parse(profile, &packet[16..32]).unwrap();
+            assert_eq!(profile, FRAME_TAG_PROFILE);
+            assert_eq!(tag.input_sequence, 5);
+        }
+        let plain_packets = sent(&plain);
+        assert_eq!(plain_packets.len(), 3);
+        assert!(plain_packets.iter().all(|packet| packet[0] == 0x80));
+        assert_eq!(
+            &plain_packets[0][8..12],
+            &session_ssrc(&plain).to_be_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sent_frames_are_resent_and_refreshed() {
+        use crate::streaming::rtcp::Refresh;
+        use dpstream_protocol::rtcp::{Nack, PictureLoss};
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let (refresh_tx, refresh_rx) = unbounded();
+        server.set_refresh_control(refresh_tx);
+
+        let session_id = Uuid::new_v4();
+        let ssrc = session_ssrc(&session_id);
+        let now = std::time::Instant::now();
+        server.sessions.insert(
+            session_id,
+            StreamingSession {
+                id: session_id,
+                client_addr: "127.0.0.1:50000".parse().unwrap(),
+                video_stream: None,
+                audio_stream: None,
+                input_handler: None,
+                state: SessionState::Streaming,
+                started_at: now,
+                last_activity: now,
+                stream_config: None,
+                features: 0,
+                congestion: None,
+                rtcp: Some(RtcpSession::new(ssrc, ENCODER_REFERENCE_FRAMES)),
+                rtp: Some(RtpPacketizer::new(ssrc)),
+                pacer: Some(Pacer::new(15000, now)),
+            },
+        );
+
+        let mut data = vec![0, 0, 0, 1, 0x41];
+        data.resize(3000, 0x42);
+        let frame = VideoFrame {
+            data,
+            width: 1280,
+            height: 720,
+            timestamp: 0,
+            frame_number: 1,
+        };
+        MoonlightServer::send_video_frame(&frame, &server.sessions, &server.controls.input, now);
+        let sent: Vec<Arc<[u8]>> = {
+            let mut session = server.sessions.get_mut(&session_id).unwrap();
+            let pacer = session.pacer.as_mut().unwrap();
+            std::iter::from_fn(|| pacer.poll(now + std::time::Duration::from_secs(1))).collect()
+        };
+        assert_eq!(sent.len(), 3);
+
+        let feedback = |packet: RtcpPacket| {
+            MoonlightServer::handle_rtcp(
+                &encode_compound(&[packet]),
+                "127.0.0.1:47999".parse().unwrap(),
+                &server.sessions,
+                &server.controls.refresh,
+                &server.performance_monitor,
+            )
+            .unwrap();
+            MoonlightServer::drain_pacers(
+                &server.sessions,
+                &server.performance_monitor,
+                std::time::Instant::now(),
+            )
+        };
+
+        // A lost packet of the frame goes out again as first sent
+        let resent = feedback(RtcpPacket::Nack(Nack {
+            sender_ssrc: 1,
+            media_ssrc: ssrc,
+            lost: vec![1],
+        }));
+        assert_eq!(resent.len(), 1);
+        assert_eq!(resent[0].1, sent[1]);
+        assert_eq!(
+            server
+                .sessions
+                .get(&session_id)
+                .unwrap()
+                .rtcp
+                .as_ref()
+                .unwrap()
+                .sender_report(now)
+                .packet_count,
+            3
+        );
+
+        // A picture loss asks the encoder for a keyframe
+        feedback(RtcpPacket::PictureLoss(PictureLoss {
+            sender_ssrc: 1,
+            media_ssrc: ssrc,
+        }));
+        assert_eq!(
+            refresh_rx.try_recv().unwrap(),
+            PictureRefresh {
+                session_id,
+                refresh: Refresh::Keyframe,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_state_request_forwarded_with_thumbnail() {
+        use crate::streaming::capture::{QualityPreset, VideoCaptureConfig};
+        use crate::streaming::encoder::EncoderConfig;
+        use crate::streaming::pipeline::VideoPipeline;
+
+        let config = create_test_config();
+        let server = MoonlightServer::new(config).await.unwrap();
+        let (state_tx, state_rx) = unbounded::<StateJob>();
+        server.set_state_control(state_tx);
+
+        // The thumbnail comes from the captured frame, before encoding
+        let mut pipeline = VideoPipeline::start(
+            VideoCaptureConfig {
+                window_id: 0,
+                width: 64,
+                height: 36,
+                fps: 30,
+                bitrate: 2000,
+                encoder: crate::streaming::capture::VideoEncoder::Software,
+                quality_preset: QualityPreset::UltraFast,
+            },
+            EncoderConfig {
+                bitrate: 2000,
+                width: 64,
+                height: 36,
+                fps: 30,
+                ..EncoderConfig::default()
+            },
+            server.video_output(),
+        )
+        .await
+        .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        pipeline.encode_latest().await.unwrap();
+
+        tokio::spawn(async move {
+            let job = state_rx.recv_async().await.unwrap();
+            let thumbnail = job.thumbnail.unwrap();
+            assert_eq!((thumbnail.width, thumbnail.height), (64, 36));
+            job.reply.send(StateResponse::Listed(Vec::new())).unwrap();
+        });
+
+        let message = StateRequest {
+            user: "player1".to_string(),
+            game_id: None,
+            op: crate::emulator::savestate::StateOp::List,
+        }
+        .encode();
+        let response = MoonlightServer::handle_state_request(&message, &server.controls)
+            .await
+            .unwrap();
+        assert_eq!(response, StateResponse::Listed(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn test_library_launch_applies_game_profile() {
+        use crate::emulator::library::{DiscFormat, GameInfo, Region};
+        use crate::input::backend::RecordingBackend;
+        use crate::input::mapping::{ConsoleType, ControllerMapping, GameProfile};
+
+        let config = create_test_config();
+        let server = MoonlightServer::new(config).await.unwrap();
+        let game = GameEntry {
+            entry: "melee.iso".to_string(),
+            path: "/srv/games/melee.iso".into(),
+            game_id: "GALE01".to_string(),
+            title: "Melee".to_string(),
+            console_type: ConsoleType::GameCube,
+            region: Region::NtscU,
+            disc_number: 0,
+            revision: 2,
+            format: DiscFormat::Iso,
+            file_size: 1 << 30,
+            modified_secs: 0,
+        };
+        server.set_library(std::slice::from_ref(&game));
+        let (launch_tx, launch_rx) = unbounded::<LaunchJob>();
+        server.set_launch_control(launch_tx);
+
+        let response = MoonlightServer::handle_library_request(
+            &LibraryRequest::List.encode(),
+            &server.controls,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            response,
+            LibraryResponse::Games(vec![GameInfo {
+                app_id: 1,
+                game_id: "GALE01".to_string(),
+                title: "Melee".to_string(),
+                disc_number: 0,
+            }])
+        );
+
+        let mut input_manager =
+            ServerInputManager::with_backend(Box::new(RecordingBackend::new())).unwrap();
+        let session_id = Uuid::new_v4();
+        input_manager.register_client(session_id).unwrap();
+        let overlays = input_manager.subscribe_touch_overlay(session_id);
+        let before = overlays.recv().unwrap();
+        server.set_input_manager(input_manager);
+
+        tokio::spawn(async move {
+            let job = launch_rx.recv_async().await.unwrap();
+            assert_eq!(job.game.entry, "melee.iso");
+            assert_eq!(job.user, "alice");
+            let mut profile = GameProfile::load_for_game(&job.game.game_id).unwrap();
+            profile.controller_mapping = ControllerMapping::default_wii_remote();
+            job.reply.send(Ok(Some(profile))).unwrap();
+        });
+
+        let launch = LibraryRequest::Launch {
+            app_id: 1,
+            user: "alice".to_string(),
+        };
+        let response = MoonlightServer::handle_library_request(&launch.encode(), &server.controls)
+            .await
+            .unwrap();
+        assert_eq!(
+            response,
+            LibraryResponse::Launched {
+                game_id: "GALE01".to_string()
+            }
+        );
+
+        // The launched game's profile reached every player's controls
+        let after = overlays.try_recv().unwrap();
+        assert_ne!(after, before);
+        assert_eq!(
+            after,
+            ControllerMapping::default_wii_remote().touch.overlay()
+        );
+
+        let unknown = LibraryRequest::Launch {
+            app_id: 2,
+            user: "alice".to_string(),
+        };
+        assert!(
+            MoonlightServer::handle_library_request(&unknown.encode(), &server.controls)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wii_extension_message_reaches_backend() {
+        use crate::input::backend::RecordingBackend;
+        use crate::input::processor::DolphinCommand;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let recorder = RecordingBackend::new();
+        let mut input_manager =
+            ServerInputManager::with_backend(Box::new(recorder.clone())).unwrap();
+        let session_id = Uuid::new_v4();
+        input_manager.register_client(session_id).unwrap();
+        server.set_input_manager(input_manager);
+
+        let mut message = WiiExtension::Nunchuk.encode();
+        server
+            .parse_control_message(&message, &session_id)
+            .await
+            .unwrap();
+
+        let mut input_manager = server.input_manager.write().take().unwrap();
+        input_manager.process_inputs().await.unwrap();
+        assert!(matches!(
+            recorder.commands().as_slice(),
+            [DolphinCommand::WiiExtensionChange {
+                extension: WiiExtension::Nunchuk,
+                ..
+            }]
+        ));
+
+        message[HEADER_LEN + 1] = 9;
+        assert!(server
+            .parse_control_message(&message, &session_id)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_keyboard_message_drives_pad_and_hotkeys() {
+        use crate::input::backend::RecordingBackend;
+        use crate::input::keyboard::keys;
+        use crate::input::processor::{DolphinButton, DolphinCommand};
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let (control_tx, control_rx) = unbounded::<EmulationJob>();
+        server.set_emulation_control(control_tx);
+        let emulator = tokio::spawn(async move {
+            let job = control_rx.recv_async().await.unwrap();
+            job.reply.send(Ok(Default::default())).unwrap();
+            job.command
+        });
+        let recorder = RecordingBackend::new();
+        let mut input_manager =
+            ServerInputManager::with_backend(Box::new(recorder.clone())).unwrap();
+        let session_id = Uuid::new_v4();
+        input_manager.register_client(session_id).unwrap();
+        server.set_input_manager(input_manager);
+
+        for event in [
+            KeyboardMouse::KeyDown { key: keys::SPACE },
+            KeyboardMouse::KeyDown { key: keys::F12 },
+        ] {
+            server
+                .parse_control_message(&event.encode(), &session_id)
+                .await
+                .unwrap();
+        }
+        assert_eq!(emulator.await.unwrap(), EmulationCommand::Screenshot);
+
+        let mut input_manager = server.input_manager.write().take().unwrap();
+        input_manager.process_inputs().await.unwrap();
+        assert_eq!(
+            recorder.commands(),
+            [DolphinCommand::ButtonPress {
+                player: 1,
+                button: DolphinButton::A,
+                pressed: true,
+            }]
+        );
+
+        // A mouse move missing its y
+        let truncated = [
+            MSG_KEYBOARD_MOUSE.to_le_bytes().as_slice(),
+            &3u32.to_le_bytes(),
+            &[PROTOCOL_VERSION, 2, 1],
+        ]
+        .concat();
+        assert!(server
+            .parse_control_message(&truncated, &session_id)
+            .await
+            .is_err());
+    }
+
+    /// Connect a client through the control socket's session loop,
+    /// returning once the session is registered for input
+    async fn connect_client(
+        server: &MoonlightServer,
+        agreed: u32,
+    ) -> (TcpStream, FrameReader, Uuid) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, client_addr) = listener.accept().await.unwrap();
+
+        let session_id = Uuid::new_v4();
+        let now = std::time::Instant::now();
+        server.sessions.insert(
+            session_id,
+            StreamingSession {
+                id: session_id,
+                client_addr,
+                video_stream: None,
+                audio_stream: None,
+                input_handler: None,
+                state: SessionState::Connecting,
+                started_at: now,
+                last_activity: now,
+                stream_config: None,
+                features: 0,
+                congestion: None,
+                rtcp: None,
+                rtp: None,
+                pacer: None,
+            },
+        );
+        tokio::spawn(MoonlightServer::handle_client_session(
+            stream,
+            session_id,
+            Arc::clone(&server.sessions),
+            server.controls.clone(),
+            server.config.clone(),
+            server.video_broadcast.clone(),
+            server.audio_broadcast.clone(),
+        ));
+
+        let hello = Hello::new(
+            "dpstream-switch-test",
+            Capabilities {
+                features: agreed,
+                max_width: 1280,
+                max_height: 720,
+                max_fps: 60,
+            },
+        );
+        client.write_all(&hello.encode()).await.unwrap();
+        let mut frames = FrameReader::new();
+        assert!(matches!(
+            receive::<HelloReply>(&mut client, &mut frames).await,
+            HelloReply::Accepted(_)
+        ));
+
+        for _ in 0..100 {
+            let registered = server
+                .sessions
+                .get(&session_id)
+                .is_some_and(|session| session.input_handler.is_some());
+            if registered {
+                return (client, frames, session_id);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("session {session_id} never registered for input");
+    }
+
+    /// Next message of type `M` from the server, skipping others
+    async fn receive<M: Message>(client: &mut TcpStream, frames: &mut FrameReader) -> M {
+        loop {
+            let message = tokio::time::timeout(
+                std::time::Duration::from_secs(1),
+                MoonlightServer::read_message(client, frames),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            if let Ok(message) = M::decode(&message) {
+                return message;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_input_reaches_dolphin_pipe() {
+        use crate::input::processor::{DolphinButton, DolphinCommand};
+        use crate::input::recording::InputRecording;
+        use crate::input::DolphinInputAdapter;
+        use dpstream_protocol::input::buttons;
+        use tokio::io::AsyncBufReadExt;
+        use tokio::net::unix::pipe;
+
+        let user_dir =
+            std::env::temp_dir().join(format!("dpstream-session-pipes-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&user_dir);
+        let mut adapter = DolphinInputAdapter::new().unwrap();
+        adapter.initialize(&user_dir).await.unwrap();
+        let reader = pipe::OpenOptions::new()
+            .open_receiver(adapter.pipe_path(1).unwrap())
+            .unwrap();
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let mut input_manager = ServerInputManager::with_backend(Box::new(adapter)).unwrap();
+        let recording = user_dir.join("session.rec");
+        input_manager.start_recording(&recording).unwrap();
+        server.set_input_manager(input_manager);
+        let driver = server.input_driver();
+        std::thread::spawn(move || driver.run());
+
+        let (mut client, _, session_id) = connect_client(&server, features::ENCRYPTION).await;
+        assert_eq!(
+            server
+                .input_manager
+                .read()
+                .as_ref()
+                .unwrap()
+                .player_slot(&session_id),
+            Some(1)
+        );
+
+        let packet = MoonlightInputPacket {
+            sequence: Some(0),
+            button_flags: buttons::A,
+            ..MoonlightInputPacket::default()
+        };
+        client.write_all(&packet.encode()).await.unwrap();
+
+        // The input thread's tick hands the press to Dolphin
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while let Some(line) = lines.next_line().await.unwrap() {
+                if line == "PRESS A" {
+                    return;
+                }
+            }
+            panic!("pipe closed before the press");
+        })
+        .await
+        .unwrap();
+
+        // and the recording took down the input and the press it made
+        server
+            .input_manager
+            .write()
+            .as_mut()
+            .unwrap()
+            .stop_recording()
+            .unwrap();
+        let recorded = InputRecording::load(&recording).unwrap();
+        assert_eq!(recorded.input_count(), 1);
+        assert!(recorded
+            .recorded_commands()
+            .contains(&DolphinCommand::ButtonPress {
+                player: 1,
+                button: DolphinButton::A,
+                pressed: true,
+            }));
+
+        // Leaving frees the port
+        drop(client);
+        for _ in 0..100 {
+            if !server.sessions.contains_key(&session_id) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            server
+                .input_manager
+                .read()
+                .as_ref()
+                .unwrap()
+                .player_slot(&session_id),
+            None
+        );
+        std::fs::remove_dir_all(&user_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_input_tags_next_video_packet() {
+        use crate::input::backend::RecordingBackend;
+        use crate::streaming::latency::FrameInputTag;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        server.set_input_manager(
+            ServerInputManager::with_backend(Box::new(RecordingBackend::new())).unwrap(),
+        );
+        let driver = server.input_driver();
+        std::thread::spawn(move || driver.run());
+
+        let (mut client, _, session_id) =
+            connect_client(&server, features::ENCRYPTION | features::LATENCY_TAGS).await;
+        let packet = MoonlightInputPacket {
+            sequence: Some(0),
+            ..MoonlightInputPacket::default()
+        };
+        client.write_all(&packet.encode()).await.unwrap();
+        for _ in 0..100 {
+            let applied = server
+                .input_manager
+                .read()
+                .as_ref()
+                .unwrap()
+                .last_applied_input(&session_id);
+            if applied.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let mut data = vec![0, 0, 0, 1, 0x65];
+        data.resize(500, 0x42);
+        server
+            .broadcast_video_frame(VideoFrame {
+                data,
+                width: 1280,
+                height: 720,
+                timestamp: 0,
+                frame_number: 1,
+            })
+            .unwrap();
+        let frame = server.video_frames.try_recv().unwrap();
+        let now = std::time::Instant::now();
+        MoonlightServer::send_video_frame(&frame, &server.sessions, &server.controls.input, now);
+
+        // The next packet out names the input the session just sent
+        let packet = server
+            .sessions
+            .get_mut(&session_id)
+            .unwrap()
+            .pacer
+            .as_mut()
+            .unwrap()
+            .poll(now + std::time::Duration::from_secs(1))
+            .unwrap();
+        let profile = u16::from_be_bytes([packet[12], packet[13]]);
+        let tag = FrameInputTag::parse(profile, &packet[16..32]).unwrap();
+        assert_eq!(tag.input_sequence, 0);
+    }
+
+    #[tokio::test]
+    async fn test_session_cuts_control_stream_into_messages() {
+        use crate::input::backend::RecordingBackend;
+        use crate::input::processor::{DolphinButton, DolphinCommand};
+        use dpstream_protocol::input::buttons;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let recorder = RecordingBackend::new();
+        server.set_input_manager(
+            ServerInputManager::with_backend(Box::new(recorder.clone())).unwrap(),
+        );
+        let driver = server.input_driver();
+        std::thread::spawn(move || driver.run());
+        let (mut client, _, _) = connect_client(&server, features::ENCRYPTION).await;
+
+        // Two messages in one write
+        let press = MoonlightInputPacket {
+            sequence: Some(0),
+            button_flags: buttons::A,
+            ..MoonlightInputPacket::default()
+        };
+        client
+            .write_all(&[WiiExtension::Nunchuk.encode(), press.encode()].concat())
+            .await
+            .unwrap();
+
+        // and one over two
+        let release = MoonlightInputPacket {
+            sequence: Some(1),
+            ..MoonlightInputPacket::default()
+        }
+        .encode();
+        let (head, tail) = release.split_at(HEADER_LEN + 3);
+        client.write_all(head).await.unwrap();
+        client.flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        client.write_all(tail).await.unwrap();
+
+        let pressed = |pressed| DolphinCommand::ButtonPress {
+            player: 1,
+            button: DolphinButton::A,
+            pressed,
+        };
+        for _ in 0..100 {
+            if recorder.commands().contains(&pressed(false)) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let commands = recorder.commands();
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DolphinCommand::WiiExtensionChange {
+                extension: WiiExtension::Nunchuk,
+                ..
+            }
+        )));
+        let press_at = commands.iter().position(|c| *c == pressed(true));
+        let release_at = commands.iter().position(|c| *c == pressed(false));
+        assert!(press_at.is_some() && press_at < release_at);
+    }
+
+    #[tokio::test]
+    async fn test_connected_session_receives_rumble() {
+        use crate::input::backend::RecordingBackend;
+        use dpstream_protocol::rumble::Rumble;
+        use dpstream_protocol::slots::PlayerSlots;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        server.set_input_manager(
+            ServerInputManager::with_backend(Box::new(RecordingBackend::new())).unwrap(),
+        );
+        let (mut client, mut frames, _) =
+            connect_client(&server, features::ENCRYPTION | features::RUMBLE).await;
+
+        // The first layout follows the rumble subscription
+        let slots = receive::<PlayerSlots>(&mut client, &mut frames).await;
+        assert_eq!(slots.slot(), Some(1));
+
+        server
+            .input_manager
+            .write()
+            .as_mut()
+            .unwrap()
+            .route_rumble(RumbleEvent::new(1, 0xFFFF, 0));
+        let rumble = receive::<Rumble>(&mut client, &mut frames).await;
+        assert_eq!(rumble.controller, 0);
+        assert!(rumble.low > 0);
+        assert_eq!(rumble.high, 0);
+    }
+
+    #[tokio::test]
+    async fn test_connected_session_keyboard_drives_pad_and_hotkeys() {
+        use crate::input::backend::RecordingBackend;
+        use crate::input::keyboard::keys;
+        use crate::input::processor::{DolphinButton, DolphinCommand};
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let (control_tx, control_rx) = unbounded::<EmulationJob>();
+        server.set_emulation_control(control_tx);
+        let recorder = RecordingBackend::new();
+        server.set_input_manager(
+            ServerInputManager::with_backend(Box::new(recorder.clone())).unwrap(),
+        );
+        let driver = server.input_driver();
+        std::thread::spawn(move || driver.run());
+        let (mut client, _, _) = connect_client(&server, features::ENCRYPTION).await;
+
+        for event in [
+            KeyboardMouse::KeyDown { key: keys::SPACE },
+            KeyboardMouse::KeyDown { key: keys::F12 },
+        ] {
+            client.write_all(&event.encode()).await.unwrap();
+        }
+        let job = tokio::time::timeout(std::time::Duration::from_secs(1), control_rx.recv_async())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.command, EmulationCommand::Screenshot);
+        job.reply.send(Ok(Default::default())).unwrap();
+
+        let press = DolphinCommand::ButtonPress {
+            player: 1,
+            button: DolphinButton::A,
+            pressed: true,
+        };
+        for _ in 0..100 {
+            if recorder.commands().contains(&press) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("space never pressed A: {:?}", recorder.commands());
+    }
+
+    #[tokio::test]
+    async fn test_connected_session_calibrates() {
+        use crate::input::backend::RecordingBackend;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        server.set_input_manager(
+            ServerInputManager::with_backend(Box::new(RecordingBackend::new())).unwrap(),
+        );
+        let (mut client, mut frames, _) = connect_client(&server, features::ENCRYPTION).await;
+
+        client
+            .write_all(&CalibrationRequest::Start.encode())
+            .await
+            .unwrap();
+        assert!(matches!(
+            receive::<CalibrationResponse>(&mut client, &mut frames).await,
+            CalibrationResponse::Phase(_)
+        ));
+        client
+            .write_all(&CalibrationRequest::Cancel.encode())
+            .await
+            .unwrap();
+        assert_eq!(
+            receive::<CalibrationResponse>(&mut client, &mut frames).await,
+            CalibrationResponse::Cancelled
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connected_sessions_claim_and_release_slots() {
+        use crate::input::backend::RecordingBackend;
+        use dpstream_protocol::slots::PlayerSlots;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        server.set_input_manager(
+            ServerInputManager::with_backend(Box::new(RecordingBackend::new())).unwrap(),
+        );
+        let slot_of = |id: &Uuid| {
+            server
+                .input_manager
+                .read()
+                .as_ref()
+                .unwrap()
+                .player_slot(id)
+        };
+
+        let (first, _, first_id) = connect_client(&server, features::ENCRYPTION).await;
+        let (mut second, mut frames, second_id) =
+            connect_client(&server, features::ENCRYPTION).await;
+        assert_eq!(
+            (slot_of(&first_id), slot_of(&second_id)),
+            (Some(1), Some(2))
+        );
+        let slots = receive::<PlayerSlots>(&mut second, &mut frames).await;
+        assert_eq!(slots.occupied, [1, 2]);
+
+        // Hanging up gives the first port back, and the other client hears
+        drop(first);
+        let slots = receive::<PlayerSlots>(&mut second, &mut frames).await;
+        assert_eq!(slots.occupied, [2]);
+        assert_eq!(slots.slot(), Some(2));
+        assert_eq!(slot_of(&first_id), None);
+    }
+
+    #[tokio::test]
+    async fn test_connected_session_changes_its_wii_extension() {
+        use crate::input::backend::RecordingBackend;
+        use crate::input::processor::DolphinCommand;
+
+        let server = MoonlightServer::new(create_test_config()).await.unwrap();
+        let recorder = RecordingBackend::new();
+        server.set_input_manager(
+            ServerInputManager::with_backend(Box::new(recorder.clone())).unwrap(),
+        );
+        let driver = server.input_driver();
+        std::thread::spawn(move || driver.run());
+        let (_first, _, _) = connect_client(&server, features::ENCRYPTION).await;
+        let (mut second, _, _) = connect_client(&server, features::ENCRYPTION).await;
+
+        // Only the sender's remote gets the attachment
+        second
+            .write_all(&WiiExtension::Classic.encode())
+            .await
+            .unwrap();
+        let change = DolphinCommand::WiiExtensionChange {
+            player: 2,
+            extension: WiiExtension::Classic,
+        };
+        for _ in 0..100 {
+            if recorder.commands().contains(&change) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let changes: Vec<_> = recorder
+            .commands()
+            .into_iter()
+            .filter(|command| matches!(command, DolphinCommand::WiiExtensionChange { .. }))
+            .collect();
+        assert_eq!(changes, [change]);
+    }
+
+    #[tokio::test]
+    async fn test_hello_exchange_settles_features() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = create_test_config();
+
+        let client = |hello: Hello| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(&hello.encode()).await.unwrap();
+            let mut reply = vec![0u8; 256];
+            let n = stream.read(&mut reply).await.unwrap();
+            HelloReply::decode(&reply[..n]).unwrap()
+        };
+        let capabilities = |features| Capabilities {
+            features,
+            max_width: 1280,
+            max_height: 720,
+            max_fps: 60,
+        };
+
+        // A client with encryption and HEVC but no touchscreen
+        let current = tokio::spawn(client(Hello::new(
+            "dpstream-switch-test",
+            capabilities(features::ENCRYPTION | features::RUMBLE | features::HEVC),
+        )));
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let accepted =
+            MoonlightServer::exchange_capabilities(&mut stream, &mut FrameReader::new(), &config)
+                .await
+                .unwrap();
+        assert_eq!(accepted.features, features::ENCRYPTION | features::RUMBLE);
+        assert_eq!(accepted.max_resolution, (1280, 720));
+        assert_eq!(accepted.supported_codecs, ["H264"]);
+        assert!(matches!(current.await.unwrap(), HelloReply::Accepted(_)));
+
+        // One that needs a newer server is told so
+        let mut newer = Hello::new("dpstream-switch-next", capabilities(features::ENCRYPTION));
+        newer.protocol_version += 1;
+        newer.min_protocol_version = newer.protocol_version;
+        let rejected = tokio::spawn(client(newer));
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert!(MoonlightServer::exchange_capabilities(
+            &mut stream,
+            &mut FrameReader::new(),
+            &config
+        )
+        .await
+        .is_err());
+        let HelloReply::Rejected(rejection) = rejected.await.unwrap() else {
+            panic!("newer client accepted");
+        };
+        assert!(rejection.to_string().ends_with("update the server"));
+    }
 }
@@ -0,0 +1,435 @@
+//! Packet pacing for the video stream
+//!
+//! A keyframe leaves the encoder as hundreds of packets at once, more than
+//! the Switch's Wi-Fi buffers hold, so each session's packets wait in a
+//! [`Pacer`] in front of the stream socket. It is a token bucket filled at
+//! [`PACING_FACTOR`] times the session's target bitrate, holding up to
+//! [`BURST_DURATION`] of sending so ordinary frames go out without delay.
+//! Resends skip ahead of the queue but spend tokens like anything else.
+//! The send loop only wakes for the pacers while packets wait in them.
+
+use std::collections::VecDeque;
+use std::fmt::{Display, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Pacing rate over the target bitrate, so frames above the average size
+/// still leave well within a frame time
+pub const PACING_FACTOR: f64 = 2.5;
+
+/// Sending a full bucket may burst out at once
+pub const BURST_DURATION: Duration = Duration::from_millis(5);
+
+/// Shortest time between runs of the stream socket's send loop
+pub const PACING_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Packets a session may have waiting; the oldest go first once full
+pub const MAX_QUEUE_PACKETS: usize = 4096;
+
+/// Smallest bucket, so a full-size packet never waits on a low bitrate
+const MIN_BURST_BYTES: f64 = 1500.0;
+
+/// A packet waiting to be sent
+#[derive(Debug, Clone)]
+struct Queued {
+    data: Arc<[u8]>,
+    enqueued_at: Instant,
+}
+
+/// Queue depth and delay of one session's pacer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacingMetrics {
+    pub queued_packets: usize,
+    pub queued_bytes: usize,
+    /// How long the oldest waiting packet has waited
+    pub queue_delay: Duration,
+    /// How long the last packet sent waited
+    pub last_delay: Duration,
+    /// Longest any packet sent waited
+    pub max_delay: Duration,
+    pub sent_packets: u64,
+    /// Packets pushed out of a full queue, or resends it refused
+    pub dropped_packets: u64,
+}
+
+/// Token bucket pacing one session's packets
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    bytes_per_second: f64,
+    burst_bytes: f64,
+    /// Bytes that may go out now; a packet may take it below zero
+    tokens: f64,
+    refilled_at: Instant,
+    queue: VecDeque<Queued>,
+    queued_bytes: usize,
+    last_delay: Duration,
+    max_delay: Duration,
+    sent_packets: u64,
+    dropped_packets: u64,
+}
+
+impl Pacer {
+    /// Pacer for a stream at `bitrate_kbps`, starting with a full bucket
+    pub fn new(bitrate_kbps: u32, now: Instant) -> Self {
+        let mut pacer = Self {
+            bytes_per_second: 0.0,
+            burst_bytes: 0.0,
+            tokens: 0.0,
+            refilled_at: now,
+            queue: VecDeque::new(),
+            queued_bytes: 0,
+            last_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            sent_packets: 0,
+            dropped_packets: 0,
+        };
+        pacer.set_bitrate(bitrate_kbps);
+        pacer.tokens = pacer.burst_bytes;
+        pacer
+    }
+
+    /// Follow a new target bitrate
+    pub fn set_bitrate(&mut self, bitrate_kbps: u32) {
+        self.bytes_per_second = f64::from(bitrate_kbps) * 1000.0 / 8.0 * PACING_FACTOR;
+        self.burst_bytes =
+            (self.bytes_per_second * BURST_DURATION.as_secs_f64()).max(MIN_BURST_BYTES);
+        self.tokens = self.tokens.min(self.burst_bytes);
+    }
+
+    /// Queue a packet behind the ones already waiting
+    pub fn enqueue(&mut self, packet: Arc<[u8]>, now: Instant) {
+        if self.queue.len() == MAX_QUEUE_PACKETS {
+            if let Some(dropped) = self.queue.pop_front() {
+                self.queued_bytes -= dropped.data.len();
+                self.dropped_packets += 1;
+            }
+        }
+        self.queued_bytes += packet.len();
+        self.queue.push_back(Queued {
+            data: packet,
+            enqueued_at: now,
+        });
+    }
+
+    /// Queue a resent packet ahead of the rest, its frame being the most
+    /// overdue
+    ///
+    /// A full queue refuses it; the client asks again if it still needs it.
+    pub fn enqueue_resend(&mut self, packet: Arc<[u8]>, now: Instant) {
+        if self.queue.len() == MAX_QUEUE_PACKETS {
+            self.dropped_packets += 1;
+            return;
+        }
+        self.queued_bytes += packet.len();
+        self.queue.push_front(Queued {
+            data: packet,
+            enqueued_at: now,
+        });
+    }
+
+    /// Tokens in the bucket as of `now`
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        (self.tokens + self.bytes_per_second * elapsed.as_secs_f64()).min(self.burst_bytes)
+    }
+
+    /// The next packet, if the bucket lets it go at `now`
+    pub fn poll(&mut self, now: Instant) -> Option<Arc<[u8]>> {
+        self.tokens = self.tokens_at(now);
+        self.refilled_at = self.refilled_at.max(now);
+        if self.tokens <= 0.0 {
+            return None;
+        }
+
+        let packet = self.queue.pop_front()?;
+        self.tokens -= packet.data.len() as f64;
+        self.queued_bytes -= packet.data.len();
+        self.last_delay = now.saturating_duration_since(packet.enqueued_at);
+        self.max_delay = self.max_delay.max(self.last_delay);
+        self.sent_packets += 1;
+        Some(packet.data)
+    }
+
+    /// When the next packet may go, `None` with nothing waiting
+    pub fn next_send_at(&self, now: Instant) -> Option<Instant> {
+        self.queue.front()?;
+        let tokens = self.tokens_at(now);
+        if tokens > 0.0 {
+            return Some(now);
+        }
+        // Just past the moment the bucket climbs above empty
+        let wait = (-tokens + 1.0) / self.bytes_per_second;
+        Some(now + Duration::from_secs_f64(wait))
+    }
+
+    pub fn metrics(&self, now: Instant) -> PacingMetrics {
+        PacingMetrics {
+            queued_packets: self.queue.len(),
+            queued_bytes: self.queued_bytes,
+            queue_delay: self.queue.front().map_or(Duration::ZERO, |oldest| {
+                now.saturating_duration_since(oldest.enqueued_at)
+            }),
+            last_delay: self.last_delay,
+            max_delay: self.max_delay,
+            sent_packets: self.sent_packets,
+            dropped_packets: self.dropped_packets,
+        }
+    }
+}
+
+/// Reads one exported value off a session's pacing metrics
+type MetricValue = fn(&PacingMetrics) -> f64;
+
+/// Prometheus text for the pacers of the given sessions
+pub fn prometheus<S: Display>(pacers: &[(S, PacingMetrics)]) -> String {
+    let series: [(&str, &str, &str, MetricValue); 7] = [
+        (
+            "dpstream_pacing_queue_packets",
+            "gauge",
+            "Packets waiting in the session's pacer",
+            |metrics| metrics.queued_packets as f64,
+        ),
+        (
+            "dpstream_pacing_queue_bytes",
+            "gauge",
+            "Bytes waiting in the session's pacer",
+            |metrics| metrics.queued_bytes as f64,
+        ),
+        (
+            "dpstream_pacing_queue_delay_ms",
+            "gauge",
+            "How long the oldest waiting packet has waited",
+            |metrics| metrics.queue_delay.as_secs_f64() * 1000.0,
+        ),
+        (
+            "dpstream_pacing_last_delay_ms",
+            "gauge",
+            "How long the last packet sent waited",
+            |metrics| metrics.last_delay.as_secs_f64() * 1000.0,
+        ),
+        (
+            "dpstream_pacing_max_delay_ms",
+            "gauge",
+            "Longest any packet sent waited",
+            |metrics| metrics.max_delay.as_secs_f64() * 1000.0,
+        ),
+        (
+            "dpstream_pacing_sent_packets_total",
+            "counter",
+            "Packets the session's pacer let go",
+            |metrics| metrics.sent_packets as f64,
+        ),
+        (
+            "dpstream_pacing_dropped_packets_total",
+            "counter",
+            "Packets pushed out of or refused by the session's full queue",
+            |metrics| metrics.dropped_packets as f64,
+        ),
+    ];
+
+    let mut out = String::new();
+    for (name, kind, help, value) in series {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        for (session, metrics) in pacers {
+            let _ = writeln!(out, "{name}{{session=\"{session}\"}} {}", value(metrics));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKET: usize = 1200;
+
+    fn packet(sequence: u16) -> Arc<[u8]> {
+        let mut data = vec![0u8; PACKET];
+        data[..2].copy_from_slice(&sequence.to_be_bytes());
+        data.into()
+    }
+
+    fn sequence(packet: &[u8]) -> u16 {
+        u16::from_be_bytes([packet[0], packet[1]])
+    }
+
+    /// Poll every `step` from `start` until the queue is empty, returning
+    /// when each packet went out
+    fn drain(pacer: &mut Pacer, start: Instant, step: Duration) -> Vec<(Instant, Arc<[u8]>)> {
+        let mut sent = Vec::new();
+        let mut now = start;
+        while pacer.metrics(now).queued_packets > 0 {
+            while let Some(packet) = pacer.poll(now) {
+                sent.push((now, packet));
+            }
+            now += step;
+        }
+        sent
+    }
+
+    #[test]
+    fn test_keyframe_is_spread_over_time() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(15000, start);
+        for sequence in 0..200 {
+            pacer.enqueue(packet(sequence), start);
+        }
+
+        let sent = drain(&mut pacer, start, Duration::from_micros(50));
+        assert_eq!(sent.len(), 200);
+
+        // The bucket's worth goes at once, the rest at the pacing rate
+        let rate = 15000.0 * 1000.0 / 8.0 * PACING_FACTOR;
+        let burst = rate * BURST_DURATION.as_secs_f64();
+        let at_once = sent.iter().filter(|(at, _)| *at == start).count();
+        assert_eq!(at_once, (burst / PACKET as f64).ceil() as usize);
+        let expected = (200.0 * PACKET as f64 - burst) / rate;
+        let took = (sent.last().unwrap().0 - start).as_secs_f64();
+        assert!((took - expected).abs() < 0.001, "{took} vs {expected}");
+
+        // No 5ms window carries more than a bucket and a window's refill
+        for (i, (at, _)) in sent.iter().enumerate() {
+            let in_window = sent[i..]
+                .iter()
+                .take_while(|(later, _)| *later - *at < BURST_DURATION)
+                .count();
+            assert!(
+                (in_window * PACKET) as f64 <= 2.0 * burst + PACKET as f64,
+                "{in_window} packets in a window"
+            );
+        }
+    }
+
+    #[test]
+    fn test_spacing_follows_bitrate() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(6000, start);
+        for sequence in 0..100 {
+            pacer.enqueue(packet(sequence), start);
+        }
+        let step = Duration::from_micros(10);
+        let sent = drain(&mut pacer, start, step);
+
+        let spacing = PACKET as f64 / (6000.0 * 1000.0 / 8.0 * PACING_FACTOR);
+        // Once the burst and the debt its last packet left are behind
+        let paced: Vec<f64> = sent
+            .windows(2)
+            .filter(|pair| pair[0].0 > start)
+            .map(|pair| (pair[1].0 - pair[0].0).as_secs_f64())
+            .collect();
+        assert!(!paced.is_empty());
+        for gap in paced {
+            assert!(gap >= spacing - step.as_secs_f64() && gap <= spacing + step.as_secs_f64());
+        }
+
+        // Halving the bitrate doubles the spacing
+        pacer.set_bitrate(3000);
+        let later = sent.last().unwrap().0 + Duration::from_secs(1);
+        for sequence in 0..20 {
+            pacer.enqueue(packet(sequence), later);
+        }
+        let sent = drain(&mut pacer, later, step);
+        let gap = (sent[19].0 - sent[18].0).as_secs_f64();
+        assert!((gap - 2.0 * spacing).abs() <= step.as_secs_f64());
+    }
+
+    #[test]
+    fn test_idle_time_earns_one_bucket_only() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(15000, start);
+        let later = start + Duration::from_secs(10);
+        for sequence in 0..100 {
+            pacer.enqueue(packet(sequence), later);
+        }
+        let mut at_once = 0;
+        while pacer.poll(later).is_some() {
+            at_once += 1;
+        }
+        assert_eq!(at_once, 20);
+        assert!(pacer.next_send_at(later).unwrap() > later);
+    }
+
+    #[test]
+    fn test_next_send_at_matches_poll() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(15000, start);
+        assert_eq!(pacer.next_send_at(start), None);
+        for sequence in 0..30 {
+            pacer.enqueue(packet(sequence), start);
+        }
+        while pacer.poll(start).is_some() {}
+
+        let next = pacer.next_send_at(start).unwrap();
+        assert!(pacer.poll(next - Duration::from_micros(5)).is_none());
+        assert!(pacer.poll(next).is_some());
+    }
+
+    #[test]
+    fn test_resends_skip_the_queue() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(15000, start);
+        for sequence in 0..40 {
+            pacer.enqueue(packet(sequence), start);
+        }
+        while pacer.poll(start).is_some() {}
+        pacer.enqueue_resend(packet(1000), start);
+
+        let sent = drain(&mut pacer, start, Duration::from_micros(50));
+        assert_eq!(sequence(&sent[0].1), 1000);
+        assert_eq!(sequence(&sent[1].1), 20);
+    }
+
+    #[test]
+    fn test_metrics_report_depth_and_delay() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(15000, start);
+        for sequence in 0..100 {
+            pacer.enqueue(packet(sequence), start);
+        }
+        while pacer.poll(start).is_some() {}
+
+        let now = start + Duration::from_millis(3);
+        let metrics = pacer.metrics(now);
+        assert_eq!(metrics.queued_packets, 80);
+        assert_eq!(metrics.queued_bytes, 80 * PACKET);
+        assert_eq!(metrics.queue_delay, Duration::from_millis(3));
+        assert_eq!(metrics.sent_packets, 20);
+        assert_eq!(metrics.last_delay, Duration::ZERO);
+
+        let sent = drain(&mut pacer, now, Duration::from_micros(50));
+        let metrics = pacer.metrics(sent.last().unwrap().0);
+        assert_eq!(metrics.queued_packets, 0);
+        assert_eq!(metrics.queue_delay, Duration::ZERO);
+        assert_eq!(metrics.max_delay, metrics.last_delay);
+        assert!(metrics.max_delay > Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_full_queue_drops_oldest() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(1500, start);
+        for sequence in 0..MAX_QUEUE_PACKETS as u16 + 10 {
+            pacer.enqueue(packet(sequence), start);
+        }
+        let metrics = pacer.metrics(start);
+        assert_eq!(metrics.queued_packets, MAX_QUEUE_PACKETS);
+        assert_eq!(metrics.dropped_packets, 10);
+        assert_eq!(sequence(&pacer.poll(start).unwrap()), 10);
+    }
+
+    #[test]
+    fn test_full_queue_refuses_resends() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(1500, start);
+        for sequence in 0..MAX_QUEUE_PACKETS as u16 {
+            pacer.enqueue(packet(sequence), start);
+        }
+        pacer.enqueue_resend(packet(u16::MAX), start);
+        let metrics = pacer.metrics(start);
+        assert_eq!(metrics.queued_packets, MAX_QUEUE_PACKETS);
+        assert_eq!(metrics.dropped_packets, 1);
+        assert_eq!(sequence(&pacer.poll(start).unwrap()), 0);
+    }
+}
@@ -0,0 +1,604 @@
+//! Batched UDP I/O for the stream socket
+//!
+//! The stream sender hands whole batches of datagrams to a [`PacketSink`]
+//! and takes them from a [`PacketSource`]. On Linux, [`MmsgSocket`] moves a
+//! batch in one `sendmmsg`/`recvmmsg` call and, with UDP GSO on, sends each
+//! run of equal-size packets to a client as one buffer the kernel or NIC
+//! splits up again. Elsewhere, and on kernels without those calls,
+//! [`PortableSocket`] sends and receives a datagram per call.
+
+use async_trait::async_trait;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(target_os = "linux")]
+use tracing::warn;
+
+/// A datagram and where it goes
+pub type Datagram = (SocketAddr, Arc<[u8]>);
+
+/// Datagrams a receive batch holds
+pub const BATCH_SIZE: usize = 32;
+
+/// Largest datagram a receive batch takes whole; clients' RTCP is far smaller
+pub const RECV_DATAGRAM_SIZE: usize = 2048;
+
+/// Messages handed to one `sendmmsg` call
+const SEND_MESSAGES: usize = 64;
+
+/// Segments the kernel takes in one GSO send
+const MAX_GSO_SEGMENTS: usize = 64;
+
+/// Bytes the kernel takes in one GSO send, the most a UDP payload holds
+const MAX_GSO_BYTES: usize = 65_507;
+
+/// Where the stream sender's datagrams go
+#[async_trait]
+pub trait PacketSink: Send + Sync {
+    /// Send datagrams in order, waiting for room on the socket
+    ///
+    /// A datagram the socket refuses is skipped; the last such error is
+    /// returned once the rest are sent.
+    async fn send_batch(&self, datagrams: &[Datagram]) -> io::Result<()>;
+}
+
+/// Where the stream socket's incoming datagrams come from
+#[async_trait]
+pub trait PacketSource: Send + Sync {
+    /// Wait for datagrams, then receive as many as are ready into `batch`
+    ///
+    /// Cancel safe: nothing is taken off the socket unless it is returned.
+    async fn recv_batch(&self, batch: &mut RecvBatch) -> io::Result<usize>;
+}
+
+/// Received datagrams, in one preallocated buffer
+pub struct RecvBatch {
+    buffer: Vec<u8>,
+    /// Length and sender of each datagram, by slot
+    received: Vec<(usize, SocketAddr)>,
+}
+
+impl RecvBatch {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffer: vec![0; capacity * RECV_DATAGRAM_SIZE],
+            received: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len() / RECV_DATAGRAM_SIZE
+    }
+
+    pub fn len(&self) -> usize {
+        self.received.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.received.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// The received datagrams and their senders
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], SocketAddr)> {
+        self.received
+            .iter()
+            .enumerate()
+            .map(|(slot, &(len, from))| {
+                let start = slot * RECV_DATAGRAM_SIZE;
+                (&self.buffer[start..start + len], from)
+            })
+    }
+
+    fn clear(&mut self) {
+        self.received.clear();
+    }
+
+    /// Buffer for the next datagram
+    fn next_slot(&mut self) -> &mut [u8] {
+        let start = self.received.len() * RECV_DATAGRAM_SIZE;
+        &mut self.buffer[start..start + RECV_DATAGRAM_SIZE]
+    }
+
+    fn push(&mut self, len: usize, from: SocketAddr) {
+        self.received.push((len.min(RECV_DATAGRAM_SIZE), from));
+    }
+}
+
+/// A datagram per system call, on any platform
+pub struct PortableSocket {
+    socket: Arc<UdpSocket>,
+}
+
+impl PortableSocket {
+    pub fn new(socket: Arc<UdpSocket>) -> Self {
+        Self { socket }
+    }
+}
+
+#[async_trait]
+impl PacketSink for PortableSocket {
+    async fn send_batch(&self, datagrams: &[Datagram]) -> io::Result<()> {
+        let mut last_error = None;
+        for (addr, data) in datagrams {
+            if let Err(e) = self.socket.send_to(data, addr).await {
+                last_error = Some(e);
+            }
+        }
+        last_error.map_or(Ok(()), Err)
+    }
+}
+
+#[async_trait]
+impl PacketSource for PortableSocket {
+    async fn recv_batch(&self, batch: &mut RecvBatch) -> io::Result<usize> {
+        batch.clear();
+        loop {
+            self.socket.readable().await?;
+            while !batch.is_full() {
+                match self.socket.try_recv_from(batch.next_slot()) {
+                    Ok((len, from)) => batch.push(len, from),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) if batch.is_empty() => return Err(e),
+                    Err(_) => break,
+                }
+            }
+            if !batch.is_empty() {
+                return Ok(batch.len());
+            }
+        }
+    }
+}
+
+/// Datagrams of `datagrams` the first message carries: with GSO, a run to
+/// the same address of one segment size, the last segment allowed to be
+/// shorter; without, one
+fn message_len(datagrams: &[Datagram], gso: bool) -> usize {
+    let Some((addr, first)) = datagrams.first() else {
+        return 0;
+    };
+    let segment = first.len();
+    if !gso || segment == 0 {
+        return 1;
+    }
+
+    let mut count = 1;
+    let mut bytes = segment;
+    for (next_addr, next) in &datagrams[1..] {
+        if next_addr != addr
+            || next.len() > segment
+            || count == MAX_GSO_SEGMENTS
+            || bytes + next.len() > MAX_GSO_BYTES
+        {
+            break;
+        }
+        count += 1;
+        bytes += next.len();
+        if next.len() < segment {
+            break;
+        }
+    }
+    count
+}
+
+/// Batches through `sendmmsg`/`recvmmsg`, optionally with UDP GSO
+#[cfg(target_os = "linux")]
+pub struct MmsgSocket {
+    socket: Arc<UdpSocket>,
+    gso: AtomicBool,
+    /// Cleared when the kernel lacks the batched calls
+    batched: AtomicBool,
+    fallback: PortableSocket,
+}
+
+#[cfg(target_os = "linux")]
+impl MmsgSocket {
+    /// Batched I/O on `socket`, with GSO if asked for and the kernel has it
+    pub fn new(socket: Arc<UdpSocket>, gso: bool) -> Self {
+        let gso = gso && Self::gso_supported(&socket);
+        Self {
+            fallback: PortableSocket::new(Arc::clone(&socket)),
+            socket,
+            gso: AtomicBool::new(gso),
+            batched: AtomicBool::new(true),
+        }
+    }
+
+    /// Whether the kernel knows UDP GSO, 4.18 and later
+    fn gso_supported(socket: &UdpSocket) -> bool {
+        use std::os::unix::io::AsRawFd;
+
+        let mut segment: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: the option value points at a c_int of the given length
+        unsafe {
+            libc::getsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_UDP,
+                libc::UDP_SEGMENT,
+                (&mut segment as *mut libc::c_int).cast(),
+                &mut len,
+            ) == 0
+        }
+    }
+
+    pub fn gso_enabled(&self) -> bool {
+        self.gso.load(Ordering::Relaxed)
+    }
+
+    /// Send what one `sendmmsg` call takes from the front of `datagrams`,
+    /// returning how many datagrams went
+    fn send_now(&self, datagrams: &[Datagram]) -> io::Result<usize> {
+        use socket2::SockAddr;
+        use std::os::unix::io::AsRawFd;
+
+        let gso = self.gso_enabled();
+        let mut runs = Vec::with_capacity(SEND_MESSAGES);
+        let mut taken = 0;
+        while taken < datagrams.len() && runs.len() < SEND_MESSAGES {
+            let len = message_len(&datagrams[taken..], gso);
+            runs.push(taken..taken + len);
+            taken += len;
+        }
+
+        // Everything the headers point into is laid out before they are made
+        let addrs: Vec<SockAddr> = runs
+            .iter()
+            .map(|run| SockAddr::from(datagrams[run.start].0))
+            .collect();
+        let mut iovecs: Vec<libc::iovec> = datagrams[..taken]
+            .iter()
+            .map(|(_, data)| libc::iovec {
+                iov_base: data.as_ptr() as *mut libc::c_void,
+                iov_len: data.len(),
+            })
+            .collect();
+        // Room for one cmsg carrying a u16, 8-byte aligned
+        let mut controls = vec![[0u64; 4]; runs.len()];
+
+        let mut headers: Vec<libc::mmsghdr> = runs
+            .iter()
+            .zip(&addrs)
+            .zip(&mut controls)
+            .map(|((run, addr), control)| {
+                // SAFETY: all-zero is a valid msghdr
+                let mut header: libc::mmsghdr = unsafe { std::mem::zeroed() };
+                header.msg_hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+                header.msg_hdr.msg_namelen = addr.len();
+                header.msg_hdr.msg_iov = iovecs[run.clone()].as_mut_ptr();
+                header.msg_hdr.msg_iovlen = run.len() as _;
+                if run.len() > 1 {
+                    let segment = datagrams[run.start].1.len() as u16;
+                    // SAFETY: the control buffer outlives the call and has
+                    // room for the one cmsg written into it
+                    unsafe {
+                        header.msg_hdr.msg_control = control.as_mut_ptr().cast();
+                        header.msg_hdr.msg_controllen =
+                            libc::CMSG_SPACE(std::mem::size_of::<u16>() as u32) as _;
+                        let cmsg = libc::CMSG_FIRSTHDR(&header.msg_hdr);
+                        (*cmsg).cmsg_level = libc::SOL_UDP;
+                        (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+                        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<u16>() as u32) as _;
+                        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<u16>(), segment);
+                    }
+                }
+                header
+            })
+            .collect();
+
+        // SAFETY: every header points into addrs, iovecs, controls and
+        // datagrams, all alive until the call returns
+        let sent = unsafe {
+            libc::sendmmsg(
+                self.socket.as_raw_fd(),
+                headers.as_mut_ptr(),
+                headers.len() as libc::c_uint,
+                0,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(runs[..sent as usize].iter().map(|run| run.len()).sum())
+    }
+
+    /// Receive what one `recvmmsg` call gets without waiting
+    fn recv_now(&self, batch: &mut RecvBatch) -> io::Result<usize> {
+        use socket2::SockAddr;
+        use std::os::unix::io::AsRawFd;
+
+        let capacity = batch.capacity();
+        // SAFETY: all-zero is a valid sockaddr_storage
+        let mut names: Vec<libc::sockaddr_storage> = vec![unsafe { std::mem::zeroed() }; capacity];
+        let mut iovecs: Vec<libc::iovec> = batch
+            .buffer
+            .chunks_exact_mut(RECV_DATAGRAM_SIZE)
+            .map(|slot| libc::iovec {
+                iov_base: slot.as_mut_ptr().cast(),
+                iov_len: slot.len(),
+            })
+            .collect();
+        let mut headers: Vec<libc::mmsghdr> = names
+            .iter_mut()
+            .zip(&mut iovecs)
+            .map(|(name, iovec)| {
+                // SAFETY: all-zero is a valid msghdr
+                let mut header: libc::mmsghdr = unsafe { std::mem::zeroed() };
+                header.msg_hdr.msg_name = (name as *mut libc::sockaddr_storage).cast();
+                header.msg_hdr.msg_namelen =
+                    std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+                header.msg_hdr.msg_iov = iovec;
+                header.msg_hdr.msg_iovlen = 1;
+                header
+            })
+            .collect();
+
+        // SAFETY: every header points into names, iovecs and the batch's
+        // buffer, all alive until the call returns
+        let received = unsafe {
+            libc::recvmmsg(
+                self.socket.as_raw_fd(),
+                headers.as_mut_ptr(),
+                capacity as libc::c_uint,
+                libc::MSG_DONTWAIT,
+                std::ptr::null_mut(),
+            )
+        };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for (header, name) in headers.iter().zip(&names).take(received as usize) {
+            // SAFETY: the kernel filled in the address and its length
+            let from = unsafe { SockAddr::new(*name, header.msg_hdr.msg_namelen) };
+            if let Some(from) = from.as_socket() {
+                batch.push(header.msg_len as usize, from);
+            }
+        }
+        Ok(batch.len())
+    }
+
+    /// Whether an error means the kernel lacks the batched calls
+    fn unsupported(&self, e: &io::Error) -> bool {
+        if e.raw_os_error() != Some(libc::ENOSYS) {
+            return false;
+        }
+        warn!("sendmmsg/recvmmsg unavailable, sending a datagram per call");
+        self.batched.store(false, Ordering::Relaxed);
+        true
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[async_trait]
+impl PacketSink for MmsgSocket {
+    async fn send_batch(&self, datagrams: &[Datagram]) -> io::Result<()> {
+        use tokio::io::Interest;
+
+        let mut remaining = datagrams;
+        let mut last_error = None;
+        while !remaining.is_empty() {
+            if !self.batched.load(Ordering::Relaxed) {
+                return self.fallback.send_batch(remaining).await;
+            }
+            self.socket.writable().await?;
+            match self
+                .socket
+                .try_io(Interest::WRITABLE, || self.send_now(remaining))
+            {
+                Ok(sent) => remaining = &remaining[sent..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) if self.unsupported(&e) => {}
+                // NICs that can't checksum segments refuse GSO sends
+                Err(e) if self.gso_enabled() && e.raw_os_error() == Some(libc::EIO) => {
+                    warn!("UDP GSO refused, sending packets unsegmented: {}", e);
+                    self.gso.store(false, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    remaining = &remaining[message_len(remaining, self.gso_enabled())..];
+                    last_error = Some(e);
+                }
+            }
+        }
+        last_error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[async_trait]
+impl PacketSource for MmsgSocket {
+    async fn recv_batch(&self, batch: &mut RecvBatch) -> io::Result<usize> {
+        use tokio::io::Interest;
+
+        batch.clear();
+        loop {
+            if !self.batched.load(Ordering::Relaxed) {
+                return self.fallback.recv_batch(batch).await;
+            }
+            self.socket.readable().await?;
+            match self
+                .socket
+                .try_io(Interest::READABLE, || self.recv_now(batch))
+            {
+                Ok(0) => {}
+                Ok(received) => return Ok(received),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) if self.unsupported(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// The stream socket's sink and source: batched where the platform allows,
+/// with GSO if `gso` is set and the kernel has it
+pub fn stream_socket_io(
+    socket: UdpSocket,
+    gso: bool,
+) -> (Arc<dyn PacketSink>, Arc<dyn PacketSource>) {
+    let socket = Arc::new(socket);
+
+    #[cfg(target_os = "linux")]
+    let io = Arc::new(MmsgSocket::new(socket, gso));
+    #[cfg(not(target_os = "linux"))]
+    let io = {
+        let _ = gso;
+        Arc::new(PortableSocket::new(socket))
+    };
+
+    (Arc::clone(&io) as Arc<dyn PacketSink>, io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Socket on loopback with room to receive a test's datagrams unread
+    async fn loopback() -> Arc<UdpSocket> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket2::SockRef::from(&socket)
+            .set_recv_buffer_size(1 << 20)
+            .unwrap();
+        Arc::new(socket)
+    }
+
+    fn datagrams(to: SocketAddr, sizes: &[usize]) -> Vec<Datagram> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| (to, vec![i as u8; size].into()))
+            .collect()
+    }
+
+    /// Receive until `count` datagrams arrived
+    async fn recv_all(source: &dyn PacketSource, count: usize) -> Vec<(Vec<u8>, SocketAddr)> {
+        let mut batch = RecvBatch::new(BATCH_SIZE);
+        let mut received = Vec::new();
+        while received.len() < count {
+            tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                source.recv_batch(&mut batch),
+            )
+            .await
+            .expect("datagrams arrive")
+            .unwrap();
+            received.extend(batch.iter().map(|(data, from)| (data.to_vec(), from)));
+        }
+        received
+    }
+
+    #[test]
+    fn test_messages_group_segment_runs() {
+        let a: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+
+        let same = datagrams(a, &[1200, 1200, 1200, 700, 1200]);
+        assert_eq!(message_len(&same, false), 1);
+        // A shorter segment ends the run
+        assert_eq!(message_len(&same, true), 4);
+
+        let mut mixed = datagrams(a, &[1200, 1200]);
+        mixed.extend(datagrams(b, &[1200]));
+        assert_eq!(message_len(&mixed, true), 2);
+
+        // Larger segments can't follow
+        assert_eq!(message_len(&datagrams(a, &[700, 1200]), true), 1);
+
+        // Nor can more than the kernel takes in one send
+        assert_eq!(
+            message_len(&datagrams(a, &[1200; 100]), true),
+            MAX_GSO_BYTES / 1200
+        );
+        assert_eq!(
+            message_len(&datagrams(a, &[100; 100]), true),
+            MAX_GSO_SEGMENTS
+        );
+        assert_eq!(message_len(&[], true), 0);
+    }
+
+    #[tokio::test]
+    async fn test_portable_socket_round_trip() {
+        let sender = PortableSocket::new(loopback().await);
+        let receiver_socket = loopback().await;
+        let receiver = PortableSocket::new(Arc::clone(&receiver_socket));
+        let to = receiver_socket.local_addr().unwrap();
+
+        let sent = datagrams(to, &[1200; 40]);
+        sender.send_batch(&sent).await.unwrap();
+
+        let received = recv_all(&receiver, 40).await;
+        let from = sender.socket.local_addr().unwrap();
+        for ((data, source), (_, expected)) in received.iter().zip(&sent) {
+            assert_eq!(&data[..], &expected[..]);
+            assert_eq!(*source, from);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_mmsg_socket_round_trip() {
+        let sender = MmsgSocket::new(loopback().await, false);
+        let receiver_socket = loopback().await;
+        let receiver = MmsgSocket::new(Arc::clone(&receiver_socket), false);
+        let to = receiver_socket.local_addr().unwrap();
+
+        // More than one sendmmsg and one recvmmsg call take
+        let sent = datagrams(to, &[1200; 100]);
+        sender.send_batch(&sent).await.unwrap();
+
+        let received = recv_all(&receiver, 100).await;
+        assert_eq!(received.len(), 100);
+        for ((data, _), (_, expected)) in received.iter().zip(&sent) {
+            assert_eq!(&data[..], &expected[..]);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_gso_sends_arrive_as_separate_datagrams() {
+        let sender = MmsgSocket::new(loopback().await, true);
+        if !sender.gso_enabled() {
+            return;
+        }
+        let receiver_socket = loopback().await;
+        let receiver = PortableSocket::new(Arc::clone(&receiver_socket));
+        let to = receiver_socket.local_addr().unwrap();
+
+        let mut sizes = vec![1200; 20];
+        sizes.push(300);
+        let sent = datagrams(to, &sizes);
+        sender.send_batch(&sent).await.unwrap();
+
+        let received = recv_all(&receiver, 21).await;
+        let lengths: Vec<usize> = received.iter().map(|(data, _)| data.len()).collect();
+        assert_eq!(lengths, sizes);
+        for ((data, _), (_, expected)) in received.iter().zip(&sent) {
+            assert_eq!(&data[..], &expected[..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refused_datagrams_are_skipped() {
+        let (sink, _) = stream_socket_io(UdpSocket::bind("127.0.0.1:0").await.unwrap(), false);
+        let receiver_socket = loopback().await;
+        let receiver = PortableSocket::new(Arc::clone(&receiver_socket));
+        let to = receiver_socket.local_addr().unwrap();
+
+        // An IPv6 destination from an IPv4 socket fails on its own
+        let mut sent = datagrams(to, &[100]);
+        sent.push(("[::1]:9".parse().unwrap(), vec![1u8; 100].into()));
+        sent.extend(datagrams(to, &[200]));
+        assert!(sink.send_batch(&sent).await.is_err());
+
+        let received = recv_all(&receiver, 2).await;
+        assert_eq!(received[0].0.len(), 100);
+        assert_eq!(received[1].0.len(), 200);
+    }
+}
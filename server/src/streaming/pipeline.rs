@@ -0,0 +1,165 @@
+//! Video from Dolphin's window to the client sessions
+//!
+//! The pipeline captures the emulator window, encodes the latest frame on
+//! every tick of the encoder's frame rate and hands the result to the
+//! [`MoonlightServer`](crate::streaming::MoonlightServer) through its
+//! [`VideoOutput`]. All sessions share the one encoder, so it follows the
+//! most constrained of them: whenever congestion control picks a new
+//! [`StreamTarget`](crate::streaming::congestion::StreamTarget) for a
+//! session, the encoder takes the lowest bitrate and frame rate and the
+//! smallest resolution any streaming session is held to.
+//!
+//! Clients that lose packets beyond repair get a
+//! [`Refresh`](crate::streaming::rtcp::Refresh) from the same encoder.
+//!
+//! Save states get their thumbnails from here too, taken from the raw
+//! captured frames before they are encoded.
+
+use crate::emulator::savestate::Thumbnail;
+use crate::error::Result;
+use crate::streaming::capture::{VideoCapture, VideoCaptureConfig};
+use crate::streaming::congestion::RateChange;
+use crate::streaming::encoder::{EncoderConfig, VideoEncoder};
+use crate::streaming::moonlight::{VideoFrame, VideoOutput};
+use crate::streaming::rtcp::PictureRefresh;
+use flume::Receiver;
+use std::time::Duration;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+/// Frames between save-state thumbnail refreshes
+const THUMBNAIL_INTERVAL_FRAMES: u64 = 30;
+
+/// Capture, encoder and output for the one video stream
+pub struct VideoPipeline {
+    capture: VideoCapture,
+    encoder: VideoEncoder,
+    output: VideoOutput,
+    /// Captured frame the latest thumbnail was taken from
+    thumbnail_frame: Option<u64>,
+}
+
+impl VideoPipeline {
+    /// Start capturing and encoding into `output`
+    pub async fn start(
+        capture: VideoCaptureConfig,
+        encoder: EncoderConfig,
+        output: VideoOutput,
+    ) -> Result<Self> {
+        let mut encoder = VideoEncoder::new(encoder)?;
+        encoder.initialize().await?;
+        let mut capture = VideoCapture::new(capture)?;
+        capture.start_capture().await?;
+
+        Ok(Self {
+            capture,
+            encoder,
+            output,
+            thumbnail_frame: None,
+        })
+    }
+
+    /// The encoder, as set up for the current target
+    pub fn encoder(&self) -> &VideoEncoder {
+        &self.encoder
+    }
+
+    /// Encode the newest captured frame and pass it on; frames captured
+    /// faster than the encoder's frame rate are skipped
+    pub async fn encode_latest(&mut self) -> Result<()> {
+        let mut latest = None;
+        while let Some(frame) = self.capture.get_frame() {
+            latest = Some(frame);
+        }
+        let Some(frame) = latest else {
+            return Ok(());
+        };
+
+        // Keep a small thumbnail of recent frames for save states
+        let thumbnail_due = self
+            .thumbnail_frame
+            .is_none_or(|taken| frame.frame_number >= taken + THUMBNAIL_INTERVAL_FRAMES);
+        if thumbnail_due {
+            if let Some(thumbnail) = Thumbnail::from_i420(&frame.data, frame.width, frame.height) {
+                self.output.set_thumbnail(thumbnail);
+                self.thumbnail_frame = Some(frame.frame_number);
+            }
+        }
+
+        if let Some(encoded) = self.encoder.encode_frame(frame).await? {
+            let config = self.encoder.config();
+            self.output.send(VideoFrame {
+                data: encoded.data,
+                width: config.width,
+                height: config.height,
+                timestamp: encoded.timestamp,
+                frame_number: encoded.frame_number,
+            });
+        }
+        Ok(())
+    }
+
+    /// Follow a session's new target, as far as the other sessions allow
+    pub async fn apply_rate_change(&mut self, change: RateChange) -> Result<()> {
+        let Some(target) = self.output.stream_target() else {
+            return Ok(());
+        };
+        debug!(
+            "Session {} moved to {:?}; encoding {:?}",
+            change.session_id, change.target, target
+        );
+        self.encoder.apply_target(&target).await
+    }
+
+    /// Run until the server drops the rate or refresh control channel
+    pub async fn run(
+        mut self,
+        rate_changes: Receiver<RateChange>,
+        refreshes: Receiver<PictureRefresh>,
+    ) {
+        info!("Video pipeline running");
+        let mut ticker = frame_ticker(self.encoder.config().fps);
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if let Err(e) = self.encode_latest().await {
+                        warn!("Failed to encode frame: {}", e);
+                    }
+                }
+                change = rate_changes.recv_async() => {
+                    let Ok(change) = change else {
+                        break;
+                    };
+                    let fps = self.encoder.config().fps;
+                    if let Err(e) = self.apply_rate_change(change).await {
+                        warn!("Failed to apply {:?}: {}", change.target, e);
+                    }
+                    if self.encoder.config().fps != fps {
+                        ticker = frame_ticker(self.encoder.config().fps);
+                    }
+                }
+                refresh = refreshes.recv_async() => {
+                    let Ok(refresh) = refresh else {
+                        break;
+                    };
+                    debug!("Session {} needs {:?}", refresh.session_id, refresh.refresh);
+                    self.encoder.apply_refresh(refresh.refresh);
+                }
+            }
+        }
+
+        if let Err(e) = self.capture.stop_capture().await {
+            warn!("Failed to stop video capture: {}", e);
+        }
+        if let Err(e) = self.encoder.shutdown().await {
+            warn!("Failed to shut down video encoder: {}", e);
+        }
+    }
+}
+
+fn frame_ticker(fps: u32) -> Interval {
+    let mut ticker = interval(Duration::from_secs(1) / fps.max(1));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    ticker
+}
@@ -0,0 +1,523 @@
+//! RTCP and loss recovery for the video stream
+//!
+//! Every session sends its video under its own SSRC and keeps the packets
+//! it sent last in a bounded [`RetransmitBuffer`]. The server sends a
+//! sender report every [`SENDER_REPORT_INTERVAL`]; the client's receiver
+//! reports echo it, timing the round trip, and their loss counts feed the
+//! server's packet loss metric. When the client NACKs packets, those that
+//! can still reach it within [`RETRANSMIT_BUDGET`] of being first sent are
+//! resent. The rest are given up on: if the encoder still holds a reference
+//! older than the damaged frames, it stops referencing them, and otherwise
+//! it encodes a keyframe, as it also does when the client sends a picture
+//! loss indication.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use uuid::Uuid;
+
+use crate::streaming::rtp::RTP_CLOCK_HZ;
+
+pub use dpstream_protocol::rtcp::{
+    compact_ntp, decode_compound, encode_compound, is_rtcp, ReportBlock, RtcpPacket, SenderReport,
+};
+
+/// Sent packets each session keeps for resending, over a second of 15 Mbps
+/// video in 1200-byte packets
+pub const HISTORY_PACKETS: usize = 2048;
+
+/// Longest a packet may take to reach the client, resends included, and
+/// still be in time for its frame
+pub const RETRANSMIT_BUDGET: Duration = Duration::from_millis(80);
+
+/// Time between sender reports
+pub const SENDER_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shortest time between keyframes for the same session, however many
+/// NACKs and PLIs the damage they repair causes
+const MIN_KEYFRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Seconds from the NTP epoch in 1900 to the Unix epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// SSRC a session's video is sent under
+pub fn session_ssrc(session_id: &Uuid) -> u32 {
+    session_id.as_u128() as u32
+}
+
+/// Video SSRC an RTCP datagram from a client is about
+pub fn media_ssrc(packets: &[RtcpPacket]) -> Option<u32> {
+    packets.iter().find_map(|packet| match packet {
+        RtcpPacket::ReceiverReport(rr) => rr.reports.first().map(|block| block.ssrc),
+        RtcpPacket::Nack(nack) => Some(nack.media_ssrc),
+        RtcpPacket::PictureLoss(pli) => Some(pli.media_ssrc),
+        RtcpPacket::SenderReport(_) => None,
+    })
+}
+
+/// An RTP packet kept for resending
+#[derive(Debug, Clone)]
+struct SentPacket {
+    sequence: u16,
+    frame: u64,
+    sent_at: Instant,
+    data: Arc<[u8]>,
+}
+
+/// Ring buffer of the packets a session sent last, by sequence number
+#[derive(Debug, Clone)]
+pub struct RetransmitBuffer {
+    packets: VecDeque<SentPacket>,
+    capacity: usize,
+}
+
+impl RetransmitBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            packets: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Keep a packet, dropping the oldest once full
+    pub fn push(&mut self, sequence: u16, frame: u64, data: Arc<[u8]>, sent_at: Instant) {
+        if self.packets.len() == self.capacity {
+            self.packets.pop_front();
+        }
+        self.packets.push_back(SentPacket {
+            sequence,
+            frame,
+            sent_at,
+            data,
+        });
+    }
+
+    /// A kept packet; sequence numbers are consecutive, so it is found by
+    /// its distance from the oldest
+    fn get(&self, sequence: u16) -> Option<&SentPacket> {
+        let oldest = self.packets.front()?.sequence;
+        self.packets
+            .get(usize::from(sequence.wrapping_sub(oldest)))
+            .filter(|packet| packet.sequence == sequence)
+    }
+}
+
+/// How the encoder should get a client decoding cleanly again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
+    /// Encode the next frame without references
+    Keyframe,
+    /// Stop referencing these frames, the next one references an older
+    /// frame the client decoded
+    InvalidateFrames { first: u64, last: u64 },
+}
+
+impl Refresh {
+    /// One refresh covering both; a keyframe covers anything
+    fn merge(a: Option<Refresh>, b: Option<Refresh>) -> Option<Refresh> {
+        match (a, b) {
+            (
+                Some(Refresh::InvalidateFrames { first, last }),
+                Some(Refresh::InvalidateFrames {
+                    first: other_first,
+                    last: other_last,
+                }),
+            ) => Some(Refresh::InvalidateFrames {
+                first: first.min(other_first),
+                last: last.max(other_last),
+            }),
+            (Some(Refresh::Keyframe), _) | (_, Some(Refresh::Keyframe)) => Some(Refresh::Keyframe),
+            (refresh, None) | (None, refresh) => refresh,
+        }
+    }
+}
+
+/// A [`Refresh`] for one session's encoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PictureRefresh {
+    pub session_id: Uuid,
+    pub refresh: Refresh,
+}
+
+/// What a client's RTCP asks of the server
+#[derive(Debug, Default)]
+pub struct FeedbackResponse {
+    /// Packets to send again as they were
+    pub resend: Vec<Arc<[u8]>>,
+    pub refresh: Option<Refresh>,
+    /// Packets the client newly counted lost
+    pub newly_lost: u64,
+}
+
+/// Loss recovery counts of one session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecoveryStats {
+    pub nacked: u64,
+    pub resent: u64,
+    /// NACKed packets gone from the buffer or past the budget
+    pub abandoned: u64,
+    pub keyframes: u64,
+    pub invalidations: u64,
+}
+
+/// RTCP state of one session's video stream
+#[derive(Debug, Clone)]
+pub struct RtcpSession {
+    ssrc: u32,
+    history: RetransmitBuffer,
+    /// Frames the encoder keeps as references; damage to fewer than this
+    /// many of the newest frames can be invalidated instead of refreshed
+    reference_frames: u32,
+    /// An instant and the wallclock time then, for NTP timestamps
+    epoch: (Instant, Duration),
+    /// Where the client receives the stream, learned from its RTCP
+    pub media_addr: Option<SocketAddr>,
+    packets_sent: u32,
+    octets_sent: u32,
+    /// RTP timestamp of the newest packet and when it went out
+    last_sent: Option<(u32, Instant)>,
+    newest_frame: Option<u64>,
+    /// Round trip to the client, once a receiver report has echoed a
+    /// sender report
+    rtt: Option<Duration>,
+    /// Cumulative loss in the client's last receiver report
+    reported_lost: u32,
+    last_keyframe: Option<Instant>,
+    pub stats: RecoveryStats,
+}
+
+impl RtcpSession {
+    pub fn new(ssrc: u32, reference_frames: u32) -> Self {
+        Self {
+            ssrc,
+            history: RetransmitBuffer::new(HISTORY_PACKETS),
+            reference_frames,
+            epoch: (
+                Instant::now(),
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default(),
+            ),
+            media_addr: None,
+            packets_sent: 0,
+            octets_sent: 0,
+            last_sent: None,
+            newest_frame: None,
+            rtt: None,
+            reported_lost: 0,
+            last_keyframe: None,
+            stats: RecoveryStats::default(),
+        }
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    /// 32.32 NTP-format wallclock time of an instant
+    fn ntp_timestamp(&self, at: Instant) -> u64 {
+        let (instant, unix) = self.epoch;
+        let since_unix = if at >= instant {
+            unix + (at - instant)
+        } else {
+            unix.saturating_sub(instant - at)
+        };
+        let seconds = since_unix.as_secs() + NTP_UNIX_OFFSET;
+        let fraction = (u64::from(since_unix.subsec_nanos()) << 32) / 1_000_000_000;
+        seconds << 32 | fraction
+    }
+
+    /// Note an RTP packet of `frame` going out, keeping it for resending
+    pub fn packet_sent(&mut self, packet: Arc<[u8]>, frame: u64, now: Instant) {
+        if packet.len() < 12 {
+            return;
+        }
+        let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+        let timestamp = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+        self.packets_sent = self.packets_sent.wrapping_add(1);
+        self.octets_sent = self.octets_sent.wrapping_add(packet.len() as u32 - 12);
+        self.last_sent = Some((timestamp, now));
+        self.newest_frame = Some(frame);
+        self.history.push(sequence, frame, packet, now);
+    }
+
+    /// Sender report as of `now`, with the RTP clock extrapolated from the
+    /// newest packet
+    pub fn sender_report(&self, now: Instant) -> SenderReport {
+        let rtp_timestamp = self.last_sent.map_or(0, |(timestamp, sent_at)| {
+            let elapsed_us = now.saturating_duration_since(sent_at).as_micros() as u64;
+            timestamp.wrapping_add((elapsed_us * RTP_CLOCK_HZ / 1_000_000) as u32)
+        });
+        SenderReport {
+            ssrc: self.ssrc,
+            ntp_timestamp: self.ntp_timestamp(now),
+            rtp_timestamp,
+            packet_count: self.packets_sent,
+            octet_count: self.octets_sent,
+            reports: Vec::new(),
+        }
+    }
+
+    /// Take in a client's RTCP datagram received at `now`
+    pub fn on_feedback(&mut self, packets: &[RtcpPacket], now: Instant) -> FeedbackResponse {
+        let mut response = FeedbackResponse::default();
+        for packet in packets {
+            match packet {
+                RtcpPacket::ReceiverReport(rr) => {
+                    if let Some(block) = rr.reports.iter().find(|block| block.ssrc == self.ssrc) {
+                        response.newly_lost += self.receiver_report(block, now);
+                    }
+                }
+                RtcpPacket::Nack(nack) if nack.media_ssrc == self.ssrc => {
+                    let refresh = self.nack(&nack.lost, now, &mut response.resend);
+                    response.refresh = Refresh::merge(response.refresh, refresh);
+                }
+                RtcpPacket::PictureLoss(pli) if pli.media_ssrc == self.ssrc => {
+                    response.refresh = Some(Refresh::Keyframe);
+                }
+                _ => {}
+            }
+        }
+
+        if response.refresh == Some(Refresh::Keyframe) {
+            let cooldown = self.rtt.unwrap_or_default().max(MIN_KEYFRAME_INTERVAL);
+            if self
+                .last_keyframe
+                .is_some_and(|last| now.saturating_duration_since(last) < cooldown)
+            {
+                response.refresh = None;
+            } else {
+                self.last_keyframe = Some(now);
+                self.stats.keyframes += 1;
+            }
+        } else if response.refresh.is_some() {
+            self.stats.invalidations += 1;
+        }
+        response
+    }
+
+    /// Time the round trip and count new losses from a report block
+    fn receiver_report(&mut self, block: &ReportBlock, now: Instant) -> u64 {
+        if block.last_sr != 0 {
+            let units = compact_ntp(self.ntp_timestamp(now))
+                .wrapping_sub(block.last_sr)
+                .wrapping_sub(block.delay_since_last_sr);
+            // Clocks a little off can put the echo in the future
+            if (units as i32) >= 0 {
+                self.rtt = Some(Duration::from_micros(u64::from(units) * 1_000_000 / 65_536));
+            }
+        }
+        let newly_lost = block.cumulative_lost.saturating_sub(self.reported_lost);
+        self.reported_lost = block.cumulative_lost;
+        newly_lost.into()
+    }
+
+    /// Resend what can still make it and pick a refresh for the rest
+    fn nack(&mut self, lost: &[u16], now: Instant, resend: &mut Vec<Arc<[u8]>>) -> Option<Refresh> {
+        let one_way = self.rtt.unwrap_or_default() / 2;
+        let mut damaged: Option<u64> = None;
+        let mut forgotten = false;
+
+        for &sequence in lost {
+            self.stats.nacked += 1;
+            match self.history.get(sequence) {
+                Some(packet)
+                    if now.saturating_duration_since(packet.sent_at) + one_way
+                        <= RETRANSMIT_BUDGET =>
+                {
+                    resend.push(Arc::clone(&packet.data));
+                    self.stats.resent += 1;
+                }
+                Some(packet) => {
+                    damaged = Some(damaged.map_or(packet.frame, |frame| frame.min(packet.frame)));
+                    self.stats.abandoned += 1;
+                }
+                None => {
+                    forgotten = true;
+                    self.stats.abandoned += 1;
+                }
+            }
+        }
+
+        if forgotten {
+            return Some(Refresh::Keyframe);
+        }
+        let first = damaged?;
+        let last = self.newest_frame.unwrap_or(first);
+        if last - first + 1 < u64::from(self.reference_frames) {
+            Some(Refresh::InvalidateFrames { first, last })
+        } else {
+            Some(Refresh::Keyframe)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dpstream_protocol::rtcp::{Nack, PictureLoss, ReceiverReport};
+
+    const SSRC: u32 = 0x1234_5678;
+
+    /// RTP packet of `frame` with the given sequence number
+    fn rtp(sequence: u16, frame: u64) -> Arc<[u8]> {
+        let mut packet = vec![0x80, 96];
+        packet.extend(sequence.to_be_bytes());
+        packet.extend(((frame * 1500) as u32).to_be_bytes());
+        packet.extend(SSRC.to_be_bytes());
+        packet.extend([0u8; 1188]);
+        packet.into()
+    }
+
+    /// Session that sent `frames` frames of `per_frame` packets, a frame
+    /// every 16ms from `start`
+    fn sent(frames: u64, per_frame: u16, reference_frames: u32, start: Instant) -> RtcpSession {
+        let mut session = RtcpSession::new(SSRC, reference_frames);
+        let mut sequence = 0u16;
+        for frame in 0..frames {
+            let at = start + Duration::from_millis(16 * frame);
+            for _ in 0..per_frame {
+                session.packet_sent(rtp(sequence, frame), frame, at);
+                sequence = sequence.wrapping_add(1);
+            }
+        }
+        session
+    }
+
+    fn nack(lost: Vec<u16>) -> RtcpPacket {
+        RtcpPacket::Nack(Nack {
+            sender_ssrc: 1,
+            media_ssrc: SSRC,
+            lost,
+        })
+    }
+
+    #[test]
+    fn test_buffer_is_bounded_and_finds_by_sequence() {
+        let start = Instant::now();
+        let mut buffer = RetransmitBuffer::new(4);
+        for sequence in 65533..=65535u16 {
+            buffer.push(sequence, 0, rtp(sequence, 0), start);
+        }
+        for sequence in 0..3u16 {
+            buffer.push(sequence, 1, rtp(sequence, 1), start);
+        }
+
+        assert_eq!(buffer.packets.len(), 4);
+        assert!(buffer.get(65534).is_none());
+        assert_eq!(buffer.get(65535).unwrap().frame, 0);
+        assert_eq!(buffer.get(2).unwrap().sequence, 2);
+        assert!(buffer.get(3).is_none());
+    }
+
+    #[test]
+    fn test_fresh_packets_are_resent() {
+        let start = Instant::now();
+        let mut session = sent(3, 10, 1, start);
+        let now = start + Duration::from_millis(40);
+
+        let response = session.on_feedback(&[nack(vec![21, 22, 25])], now);
+        assert_eq!(response.resend.len(), 3);
+        assert_eq!(&response.resend[0][2..4], &21u16.to_be_bytes());
+        assert_eq!(response.refresh, None);
+        assert_eq!(session.stats.resent, 3);
+    }
+
+    #[test]
+    fn test_late_packets_invalidate_or_refresh() {
+        let start = Instant::now();
+        let now = start + Duration::from_millis(16 * 9 + 5);
+
+        // Frame 2 went out 117ms ago, past the budget, and the encoder still
+        // holds frame 1 among its 16 references
+        let mut session = sent(10, 10, 16, start);
+        let response = session.on_feedback(&[nack(vec![20, 95])], now);
+        assert_eq!(response.resend.len(), 1);
+        assert_eq!(
+            response.refresh,
+            Some(Refresh::InvalidateFrames { first: 2, last: 9 })
+        );
+
+        // With a single reference frame only a keyframe helps
+        let mut session = sent(10, 10, 1, start);
+        let response = session.on_feedback(&[nack(vec![20])], now);
+        assert!(response.resend.is_empty());
+        assert_eq!(response.refresh, Some(Refresh::Keyframe));
+        assert_eq!(session.stats.abandoned, 1);
+    }
+
+    #[test]
+    fn test_forgotten_packets_need_a_keyframe() {
+        let start = Instant::now();
+        let mut session = sent(2, 10, 16, start);
+
+        let response = session.on_feedback(&[nack(vec![500])], start);
+        assert_eq!(response.refresh, Some(Refresh::Keyframe));
+    }
+
+    #[test]
+    fn test_keyframes_are_rate_limited() {
+        let start = Instant::now();
+        let mut session = sent(1, 1, 1, start);
+        let pli = RtcpPacket::PictureLoss(PictureLoss {
+            sender_ssrc: 1,
+            media_ssrc: SSRC,
+        });
+
+        let first = session.on_feedback(std::slice::from_ref(&pli), start);
+        assert_eq!(first.refresh, Some(Refresh::Keyframe));
+        let burst = session.on_feedback(
+            std::slice::from_ref(&pli),
+            start + Duration::from_millis(30),
+        );
+        assert_eq!(burst.refresh, None);
+        let later = session.on_feedback(&[pli], start + MIN_KEYFRAME_INTERVAL);
+        assert_eq!(later.refresh, Some(Refresh::Keyframe));
+        assert_eq!(session.stats.keyframes, 2);
+    }
+
+    #[test]
+    fn test_receiver_report_times_round_trip_and_counts_loss() {
+        let start = Instant::now();
+        let mut session = sent(1, 5, 1, start);
+        let report = session.sender_report(start);
+        assert_eq!(report.packet_count, 5);
+        assert_eq!(report.octet_count, 5 * 1188);
+
+        // The client held the report 10ms before answering, received 40ms
+        // after it was sent
+        let rr = |cumulative_lost| {
+            RtcpPacket::ReceiverReport(ReceiverReport {
+                ssrc: 1,
+                reports: vec![ReportBlock {
+                    ssrc: SSRC,
+                    cumulative_lost,
+                    last_sr: compact_ntp(report.ntp_timestamp),
+                    delay_since_last_sr: 65_536 / 100,
+                    ..ReportBlock::default()
+                }],
+            })
+        };
+        let now = start + Duration::from_millis(40);
+        assert_eq!(session.on_feedback(&[rr(3)], now).newly_lost, 3);
+        let rtt = session.rtt.unwrap();
+        assert!(rtt.abs_diff(Duration::from_millis(30)) < Duration::from_millis(1));
+
+        assert_eq!(session.on_feedback(&[rr(7)], now).newly_lost, 4);
+    }
+
+    #[test]
+    fn test_feedback_routes_by_media_ssrc() {
+        let report = RtcpPacket::ReceiverReport(ReceiverReport {
+            ssrc: 1,
+            reports: vec![ReportBlock {
+                ssrc: SSRC,
+                ..ReportBlock::default()
+            }],
+        });
+        let packets = decode_compound(&encode_compound(&[report, nack(vec![1])])).unwrap();
+        assert_eq!(media_ssrc(&packets), Some(SSRC));
+        assert_eq!(media_ssrc(&[]), None);
+    }
+}
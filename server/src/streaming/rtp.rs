@@ -0,0 +1,207 @@
+//! RTP packetization of the H.264 video stream
+//!
+//! Encoded frames come out of the encoder as an Annex B byte stream. Each
+//! NAL unit that fits in a [`MAX_PACKET_SIZE`] packet goes out as a single
+//! NAL unit packet, larger ones are split into FU-A fragments as in
+//! RFC 6184. All packets of a frame share its RTP timestamp and header
+//! extension, and the last one carries the marker bit.
+
+use std::sync::Arc;
+
+/// Payload type of the H.264 video stream
+pub const VIDEO_PAYLOAD_TYPE: u8 = 96;
+
+/// Largest RTP packet sent, leaving room for the IP, UDP and VPN headers
+/// within a 1280-byte path MTU
+pub const MAX_PACKET_SIZE: usize = 1200;
+
+/// Ticks per second of the video RTP clock
+pub const RTP_CLOCK_HZ: u64 = 90_000;
+
+/// Fixed RTP header, without CSRCs or extension
+const HEADER_SIZE: usize = 12;
+
+/// NAL unit type of a fragmentation unit
+const FU_A: u8 = 28;
+
+/// Numbers and frames the packets of one session's video stream
+#[derive(Debug, Clone)]
+pub struct RtpPacketizer {
+    ssrc: u32,
+    sequence: u16,
+}
+
+impl RtpPacketizer {
+    pub fn new(ssrc: u32) -> Self {
+        Self { ssrc, sequence: 0 }
+    }
+
+    /// Packets for one encoded frame captured at `timestamp_ns`, each with
+    /// `extension` as its header extension, profile and length included
+    pub fn packetize(
+        &mut self,
+        frame: &[u8],
+        timestamp_ns: u64,
+        extension: Option<&[u8]>,
+    ) -> Vec<Arc<[u8]>> {
+        let timestamp =
+            (u128::from(timestamp_ns) * u128::from(RTP_CLOCK_HZ) / 1_000_000_000) as u32;
+        let max_payload = MAX_PACKET_SIZE - HEADER_SIZE - extension.map_or(0, <[u8]>::len);
+
+        let mut payloads = Vec::new();
+        for nal in nal_units(frame) {
+            if nal.len() <= max_payload {
+                payloads.push(nal.to_vec());
+                continue;
+            }
+            // FU indicator keeps the NAL's F and NRI bits, the FU header
+            // its type along with the start and end flags
+            let indicator = (nal[0] & 0xE0) | FU_A;
+            let fragments: Vec<&[u8]> = nal[1..].chunks(max_payload - 2).collect();
+            let last = fragments.len() - 1;
+            for (index, fragment) in fragments.into_iter().enumerate() {
+                let mut fu_header = nal[0] & 0x1F;
+                if index == 0 {
+                    fu_header |= 0x80;
+                }
+                if index == last {
+                    fu_header |= 0x40;
+                }
+                let mut payload = Vec::with_capacity(fragment.len() + 2);
+                payload.extend_from_slice(&[indicator, fu_header]);
+                payload.extend_from_slice(fragment);
+                payloads.push(payload);
+            }
+        }
+
+        let last = payloads.len().saturating_sub(1);
+        payloads
+            .iter()
+            .enumerate()
+            .map(|(index, payload)| self.packet(payload, timestamp, index == last, extension))
+            .collect()
+    }
+
+    fn packet(
+        &mut self,
+        payload: &[u8],
+        timestamp: u32,
+        marker: bool,
+        extension: Option<&[u8]>,
+    ) -> Arc<[u8]> {
+        let extension = extension.unwrap_or_default();
+        let mut packet = Vec::with_capacity(HEADER_SIZE + extension.len() + payload.len());
+        packet.push(if extension.is_empty() { 0x80 } else { 0x90 });
+        packet.push(if marker { 0x80 } else { 0 } | VIDEO_PAYLOAD_TYPE);
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(extension);
+        packet.extend_from_slice(payload);
+        self.sequence = self.sequence.wrapping_add(1);
+        packet.into()
+    }
+}
+
+/// NAL units of an Annex B byte stream, without their start codes; a
+/// stream without start codes is taken as a single NAL unit
+pub fn nal_units(stream: &[u8]) -> Vec<&[u8]> {
+    let mut starts = Vec::new();
+    let mut at = 0;
+    while at + 3 <= stream.len() {
+        if stream[at..at + 3] == [0, 0, 1] {
+            starts.push(at);
+            at += 3;
+        } else {
+            at += 1;
+        }
+    }
+    if starts.is_empty() {
+        return if stream.is_empty() {
+            Vec::new()
+        } else {
+            vec![stream]
+        };
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &start)| {
+            let end = starts.get(index + 1).copied().unwrap_or(stream.len());
+            // NAL units never end in a zero byte, so trailing zeros belong
+            // to the next four-byte start code
+            let mut nal = &stream[start + 3..end];
+            while let [rest @ .., 0] = nal {
+                nal = rest;
+            }
+            (!nal.is_empty()).then_some(nal)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streaming::latency::FrameInputTag;
+    use dpstream_protocol::latency::FRAME_TAG_PROFILE;
+
+    #[test]
+    fn test_frame_splits_into_nal_and_fu_a_packets() {
+        let sps = [0x67, 0x42, 0x00, 0x1f];
+        let mut idr = vec![0x65];
+        idr.extend((0..3000).map(|i| (i % 251) as u8 + 1));
+        let mut frame = vec![0, 0, 0, 1];
+        frame.extend_from_slice(&sps);
+        frame.extend_from_slice(&[0, 0, 1]);
+        frame.extend_from_slice(&idr);
+        assert_eq!(nal_units(&frame), vec![&sps[..], &idr[..]]);
+
+        let tag = FrameInputTag {
+            input_sequence: 7,
+            applied_at_us: 1_000,
+            hold_us: 250,
+        };
+        let mut packetizer = RtpPacketizer::new(0xdead_beef);
+        packetizer.sequence = u16::MAX;
+        let packets = packetizer.packetize(&frame, 1_000_000_000, Some(&tag.rtp_extension()));
+        assert_eq!(packets.len(), 4);
+
+        let mut fragments = Vec::new();
+        for (index, packet) in packets.iter().enumerate() {
+            assert!(packet.len() <= MAX_PACKET_SIZE);
+            assert_eq!(packet[0], 0x90);
+            assert_eq!(packet[1] & 0x7F, VIDEO_PAYLOAD_TYPE);
+            assert_eq!(packet[1] & 0x80 != 0, index == packets.len() - 1);
+            let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+            assert_eq!(sequence, u16::MAX.wrapping_add(index as u16));
+            assert_eq!(&packet[4..8], &90_000u32.to_be_bytes());
+            assert_eq!(&packet[8..12], &0xdead_beefu32.to_be_bytes());
+
+            let profile = u16::from_be_bytes([packet[12], packet[13]]);
+            assert_eq!(profile, FRAME_TAG_PROFILE);
+            assert_eq!(FrameInputTag::parse(profile, &packet[16..32]), Some(tag));
+
+            let payload = &packet[32..];
+            if index == 0 {
+                assert_eq!(payload, sps);
+                continue;
+            }
+            assert_eq!(payload[0], FU_A | 0x60);
+            assert_eq!(payload[1] & 0x1F, 5);
+            assert_eq!(payload[1] & 0x80 != 0, index == 1);
+            assert_eq!(payload[1] & 0x40 != 0, index == packets.len() - 1);
+            fragments.extend_from_slice(&payload[2..]);
+        }
+        assert_eq!(fragments, idr[1..]);
+    }
+
+    #[test]
+    fn test_untagged_frame_has_no_extension() {
+        let mut packetizer = RtpPacketizer::new(1);
+        let packets = packetizer.packetize(&[0, 0, 1, 0x41, 0x9a], 0, None);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(&packets[0][..2], &[0x80, 0x80 | VIDEO_PAYLOAD_TYPE]);
+        assert_eq!(&packets[0][12..], &[0x41, 0x9a]);
+    }
+}
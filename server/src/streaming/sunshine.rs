@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 // Sunshine integration for streaming
+use crate::emulator::library::GameEntry;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -46,6 +47,7 @@ pub struct DolphinStreamHost {
     dolphin_window_id: Option<u64>,
     sunshine_process: Option<Child>,
     tailscale_ip: String,
+    apps: Vec<GameEntry>,
 }
 
 #[allow(dead_code)]
@@ -60,9 +62,22 @@ impl DolphinStreamHost {
             dolphin_window_id: None,
             sunshine_process: None,
             tailscale_ip,
+            apps: Vec::new(),
         })
     }
 
+    /// Publish the scanned game library as the Moonlight app list
+    pub fn set_library(&mut self, games: &[GameEntry]) {
+        self.apps = games.to_vec();
+        info!("App list updated with {} games", self.apps.len());
+    }
+
+    /// Look up a game by its Moonlight app ID (1-based position in the app list)
+    pub fn app_for_id(&self, app_id: u32) -> Option<&GameEntry> {
+        let index = app_id.checked_sub(1)? as usize;
+        self.apps.get(index)
+    }
+
     pub async fn start_streaming_service(&mut self) -> Result<()> {
         info!("Starting streaming service on IP: {}", self.tailscale_ip);
 
@@ -277,8 +292,9 @@ impl DolphinStreamHost {
     }
 
     async fn handle_app_list(&self) -> Result<String> {
-        // Return list of available Dolphin games
-        let xml_response = r#"<?xml version="1.0" encoding="utf-8"?>
+        // Fall back to a single generic entry until the library has been scanned
+        if self.apps.is_empty() {
+            let xml_response = r#"<?xml version="1.0" encoding="utf-8"?>
 <root protocol="1" status_code="200">
     <app>
         <AppTitle>Dolphin - GameCube/Wii Emulator</AppTitle>
@@ -288,13 +304,37 @@ impl DolphinStreamHost {
     </app>
 </root>"#;
 
-        Ok(xml_response.to_string())
+            return Ok(xml_response.to_string());
+        }
+
+        let mut xml_response = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<root protocol=\"1\" status_code=\"200\">\n",
+        );
+        for (index, game) in self.apps.iter().enumerate() {
+            let title = if game.disc_number > 0 {
+                format!("{} (Disc {})", game.title, game.disc_number + 1)
+            } else {
+                game.title.clone()
+            };
+            xml_response.push_str(&format!(
+                "    <app>\n        <AppTitle>{}</AppTitle>\n        <ID>{}</ID>\n        <GameID>{}</GameID>\n        <IsRunning>0</IsRunning>\n        <MaxControllers>4</MaxControllers>\n    </app>\n",
+                escape_xml(&title),
+                index + 1,
+                escape_xml(&game.game_id)
+            ));
+        }
+        xml_response.push_str("</root>");
+
+        Ok(xml_response)
     }
 
     async fn handle_launch_request(&mut self, params: HashMap<String, String>) -> Result<String> {
         let app_id = params.get("appid").map_or("1", |v| v);
 
         info!("Launch request for app ID: {}", app_id);
+        if let Some(game) = app_id.parse().ok().and_then(|id| self.app_for_id(id)) {
+            info!("Requested game: {} ({})", game.title, game.game_id);
+        }
 
         // TODO: Actually launch Dolphin with the requested game
         // This would involve:
@@ -337,3 +377,12 @@ impl DolphinStreamHost {
         Ok(xml_response.to_string())
     }
 }
+
+/// Escape text for inclusion in GameStream XML responses
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -7,7 +7,11 @@ use uuid::Uuid;
 
 use dpstream_server::{
     error::Result,
-    input::MoonlightInputPacket,
+    input::{
+        backend::RecordingBackend, processor::DolphinCommand, MoonlightInputPacket,
+        ServerInputManager,
+    },
+    streaming::congestion::RateLimits,
     streaming::{AudioFrame, MoonlightServer, ServerConfig, VideoFrame},
 };
 
@@ -19,6 +23,8 @@ pub struct TestEnvironment {
     pub sessions: Arc<Mutex<HashMap<Uuid, TestSession>>>,
     pub network_simulator: Arc<Mutex<NetworkSimulator>>,
     pub metrics_collector: Arc<MetricsCollector>,
+    pub input_manager: Arc<tokio::sync::Mutex<ServerInputManager>>,
+    pub input_recorder: RecordingBackend,
 }
 
 /// Individual test session data
@@ -30,6 +36,7 @@ pub struct TestSession {
     pub received_frames: Vec<VideoFrame>,
     pub received_audio: Vec<AudioFrame>,
     pub sent_inputs: Vec<MoonlightInputPacket>,
+    pub input_sender: tokio::sync::mpsc::UnboundedSender<MoonlightInputPacket>,
 }
 
 /// Network condition simulator for testing resilience
@@ -85,21 +92,28 @@ impl TestEnvironment {
             enable_encryption: false, // Disable for testing
             enable_authentication: false,
             stream_timeout_ms: 10000, // Longer timeout for testing
+            rate_limits: RateLimits::default(),
+            udp_gso: false,
         };
 
         let server = MoonlightServer::new(config).await?;
+        let input_recorder = RecordingBackend::new();
+        let input_manager = ServerInputManager::with_backend(Box::new(input_recorder.clone()))?;
 
         Ok(Self {
             server: Arc::new(Mutex::new(server)),
             sessions: Arc::new(Mutex::new(HashMap::new())),
             network_simulator: Arc::new(Mutex::new(NetworkSimulator::new())),
             metrics_collector: Arc::new(MetricsCollector::new()),
+            input_manager: Arc::new(tokio::sync::Mutex::new(input_manager)),
+            input_recorder,
         })
     }
 
     /// Connect a test client with given name
     pub async fn connect_client(&mut self, client_name: &str) -> Result<Uuid> {
         let client_id = Uuid::new_v4();
+        let input_sender = self.input_manager.lock().await.register_client(client_id)?;
 
         // Create test session
         let session = TestSession {
@@ -109,6 +123,7 @@ impl TestEnvironment {
             received_frames: Vec::new(),
             received_audio: Vec::new(),
             sent_inputs: Vec::new(),
+            input_sender,
         };
 
         // Register with server (mock connection)
@@ -131,6 +146,11 @@ impl TestEnvironment {
 
     /// Disconnect a client
     pub async fn disconnect_client(&mut self, client_id: &Uuid) -> Result<()> {
+        self.input_manager
+            .lock()
+            .await
+            .unregister_client(client_id)?;
+
         {
             let mut sessions = self.sessions.lock().unwrap();
             sessions.remove(client_id);
@@ -190,11 +210,15 @@ impl TestEnvironment {
 
     /// Send input from client
     pub async fn send_input(&self, client_id: &Uuid, input: MoonlightInputPacket) -> Result<()> {
-        let mut sessions = self.sessions.lock().unwrap();
-        if let Some(session) = sessions.get_mut(client_id) {
-            session.sent_inputs.push(input);
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            if let Some(session) = sessions.get_mut(client_id) {
+                session.sent_inputs.push(input.clone());
+                let _ = session.input_sender.send(input);
+            }
         }
-        Ok(())
+
+        self.input_manager.lock().await.process_inputs().await
     }
 
     /// Get received frames for a client
@@ -241,14 +265,9 @@ impl TestEnvironment {
         Ok(all_inputs)
     }
 
-    /// Get generated Dolphin commands (mock)
-    pub async fn get_dolphin_commands(&self) -> Result<Vec<String>> {
-        // Mock implementation - in real test would capture actual commands
-        Ok(vec![
-            "BUTTON 1 A PRESS".to_string(),
-            "ANALOG 1 MAIN 128 128".to_string(),
-            "TRIGGER 1 255 0".to_string(),
-        ])
+    /// Get the commands the input manager delivered to its backend
+    pub async fn get_dolphin_commands(&self) -> Result<Vec<DolphinCommand>> {
+        Ok(self.input_recorder.commands())
     }
 
     /// Network simulation methods
@@ -385,63 +404,23 @@ pub fn generate_test_audio_samples(
 
 pub fn create_button_input(button_flags: u16, pressed: bool) -> MoonlightInputPacket {
     MoonlightInputPacket {
-        packet_type: 0x0C,
         button_flags: if pressed { button_flags } else { 0 },
-        left_trigger: 0,
-        right_trigger: 0,
-        left_stick_x: 0,
-        left_stick_y: 0,
-        right_stick_x: 0,
-        right_stick_y: 0,
-        timestamp: 0,
-        gyro_x: None,
-        gyro_y: None,
-        gyro_z: None,
-        accel_x: None,
-        accel_y: None,
-        accel_z: None,
-        touch_points: None,
+        ..Default::default()
     }
 }
 
 pub fn create_analog_input(left_x: i16, left_y: i16) -> MoonlightInputPacket {
     MoonlightInputPacket {
-        packet_type: 0x0C,
-        button_flags: 0,
-        left_trigger: 0,
-        right_trigger: 0,
         left_stick_x: left_x,
         left_stick_y: left_y,
-        right_stick_x: 0,
-        right_stick_y: 0,
-        timestamp: 0,
-        gyro_x: None,
-        gyro_y: None,
-        gyro_z: None,
-        accel_x: None,
-        accel_y: None,
-        accel_z: None,
-        touch_points: None,
+        ..Default::default()
     }
 }
 
 pub fn create_trigger_input(left_trigger: u8, right_trigger: u8) -> MoonlightInputPacket {
     MoonlightInputPacket {
-        packet_type: 0x0C,
-        button_flags: 0,
         left_trigger,
         right_trigger,
-        left_stick_x: 0,
-        left_stick_y: 0,
-        right_stick_x: 0,
-        right_stick_y: 0,
-        timestamp: 0,
-        gyro_x: None,
-        gyro_y: None,
-        gyro_z: None,
-        accel_x: None,
-        accel_y: None,
-        accel_z: None,
-        touch_points: None,
+        ..Default::default()
     }
 }
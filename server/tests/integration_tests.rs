@@ -4,12 +4,23 @@
 
 use std::time::Duration;
 use tokio::time::timeout;
+use uuid::Uuid;
 
 mod common;
 
 use common::*;
+use dpstream_protocol::Message;
 use dpstream_server::{
     error::Result,
+    input::backend::RecordedInput,
+    input::calibration::{CalibrationRequest, CalibrationResponse, CalibrationStore},
+    input::processor::{AnalogStick, DolphinButton, DolphinCommand},
+    input::recording::{diff_commands, InputRecording, ReplaySpeed},
+    input::rumble::RumbleEvent,
+    input::slots::{PlayerSlots, SlotRequest},
+    input::MoonlightInputPacket,
+    streaming::congestion::RateLimits,
+    streaming::latency::LatencyReport,
     streaming::{MoonlightServer, ServerConfig},
 };
 
@@ -23,6 +34,8 @@ async fn test_server_initialization() -> Result<()> {
         enable_encryption: false, // Disable for testing
         enable_authentication: false,
         stream_timeout_ms: 5000,
+        rate_limits: RateLimits::default(),
+        udp_gso: false,
     };
 
     let server = MoonlightServer::new(config).await?;
@@ -140,6 +153,484 @@ async fn test_input_processing() -> Result<()> {
     Ok(())
 }
 
+/// Test rumble from the emulator reaching the client playing that slot
+#[tokio::test]
+async fn test_rumble_back_channel() -> Result<()> {
+    let mut test_env = TestEnvironment::new().await?;
+    let client_id = test_env.connect_client("rumble_test").await?;
+    let rumble = test_env
+        .input_manager
+        .lock()
+        .await
+        .subscribe_rumble(client_id);
+
+    // Nobody plays slot 2, so that event is dropped
+    test_env
+        .input_recorder
+        .rumble(RumbleEvent::new(1, 0xFFFF, 0x4000));
+    test_env
+        .input_recorder
+        .rumble(RumbleEvent::new(2, 0xFFFF, 0));
+    test_env
+        .send_input(&client_id, create_button_input(0x1000, true))
+        .await?;
+
+    let message = rumble.messages.try_recv().expect("rumble for player 1");
+    assert_eq!((message.low, message.high), (0xFFFF, 0x4000));
+    assert!(rumble.messages.try_recv().is_err());
+
+    rumble.delivered(&message);
+    let stats = test_env.input_manager.lock().await.get_stats().rumble;
+    assert_eq!(stats.events_captured, 2);
+    assert_eq!(stats.events_dropped, 1);
+    assert_eq!(stats.messages_delivered, 1);
+
+    Ok(())
+}
+
+/// Test a client calibrating its sticks and the result shaping later input
+#[tokio::test]
+async fn test_stick_calibration_flow() -> Result<()> {
+    let mut test_env = TestEnvironment::new().await?;
+    let client_id = test_env.connect_client("calibration_test").await?;
+    test_env
+        .input_manager
+        .lock()
+        .await
+        .set_calibration_store(CalibrationStore::in_memory());
+    let identified = calibration_step(
+        &test_env,
+        &client_id,
+        CalibrationRequest::Identify {
+            client_id: "switch-test".to_string(),
+        },
+    )
+    .await?;
+    assert_eq!(
+        identified,
+        CalibrationResponse::Identified { calibration: None }
+    );
+
+    // A worn stick resting off center that only reaches 3/4 of full range
+    calibration_step(&test_env, &client_id, CalibrationRequest::Start).await?;
+    for _ in 0..12 {
+        test_env
+            .send_input(&client_id, create_analog_input(2000, 0))
+            .await?;
+    }
+    calibration_step(&test_env, &client_id, CalibrationRequest::Next).await?;
+    for (x, y) in [(26000, 0), (-22000, 0), (2000, 24000), (2000, -24000)] {
+        let mut input = create_analog_input(x, y);
+        input.right_stick_x = x;
+        input.right_stick_y = y;
+        test_env.send_input(&client_id, input).await?;
+    }
+    let CalibrationResponse::Complete(calibration) =
+        calibration_step(&test_env, &client_id, CalibrationRequest::Next).await?
+    else {
+        panic!("calibration did not complete");
+    };
+    assert_eq!(calibration.left_stick.center_x, 2000);
+
+    // Resting reads as centered and the worn edge as full deflection
+    for x in [2000, -22000] {
+        test_env
+            .send_input(&client_id, create_analog_input(x, 0))
+            .await?;
+        for _ in 0..4 {
+            test_env.input_manager.lock().await.process_inputs().await?;
+        }
+        let main_x = test_env
+            .input_recorder
+            .commands()
+            .iter()
+            .rev()
+            .find_map(|command| match command {
+                DolphinCommand::AnalogInput {
+                    stick: AnalogStick::Main,
+                    x,
+                    ..
+                } => Some(*x),
+                _ => None,
+            })
+            .expect("main stick input");
+        let expected = if x == 2000 { 0.0 } else { -1.0 };
+        assert!((main_x - expected).abs() < 1e-4, "{x} read as {main_x}");
+    }
+
+    Ok(())
+}
+
+/// Test choosing, swapping and handing over player slots
+#[tokio::test]
+async fn test_player_slot_management() -> Result<()> {
+    let mut test_env = TestEnvironment::new().await?;
+    let mut players = Vec::new();
+    for i in 0..4 {
+        players.push(test_env.connect_client(&format!("player_{i}")).await?);
+    }
+    let spectator = test_env.connect_client("spectator").await?;
+
+    let mut input = test_env.input_manager.lock().await;
+    let slots = input.subscribe_slots(spectator);
+    assert_eq!(input.player_slot(&spectator), None);
+    let layout = slots.try_recv().unwrap();
+    assert_eq!(layout.slot(), None);
+    assert_eq!(
+        (layout.occupied.as_slice(), layout.spectators),
+        (&[1, 2, 3, 4][..], 1)
+    );
+
+    // Players 1 and 2 trade controllers; both ports reconnect
+    test_env.input_recorder.clear();
+    input.handle_slot_request(
+        &players[0],
+        SlotRequest::Swap {
+            slot: 2,
+            controller: 0,
+        },
+    )?;
+    assert_eq!(input.player_slot(&players[0]), Some(2));
+    assert_eq!(input.player_slot(&players[1]), Some(1));
+    let mut changes: Vec<(bool, u8)> = test_env
+        .input_recorder
+        .events()
+        .iter()
+        .filter_map(|event| match event {
+            RecordedInput::Connected(slot) => Some((true, *slot)),
+            RecordedInput::Disconnected(slot) => Some((false, *slot)),
+            _ => None,
+        })
+        .collect();
+    changes.sort();
+    assert_eq!(changes, [(false, 1), (false, 2), (true, 1), (true, 2)]);
+
+    // Player 3 hands the controller to the spectator
+    assert!(input.request_slot(&spectator, 3).is_err());
+    input.handle_slot_request(&players[2], SlotRequest::HandOver)?;
+    assert_eq!(input.player_slot(&spectator), Some(3));
+    assert_eq!(input.player_slot(&players[2]), None);
+    assert_eq!(
+        slots.try_iter().last().unwrap(),
+        PlayerSlots {
+            slots: vec![Some(3)],
+            occupied: vec![1, 2, 3, 4],
+            reserved: Vec::new(),
+            spectators: 1,
+        }
+    );
+
+    // Player 4 leaves for good and the waiting player 3 takes over
+    input.unregister_client(&players[3])?;
+    assert_eq!(input.player_slot(&players[2]), Some(4));
+
+    // An identified client's slot is held while it reconnects
+    input.set_calibration_store(CalibrationStore::in_memory());
+    input.handle_calibration(
+        &players[2],
+        CalibrationRequest::Identify {
+            client_id: "switch-b".to_string(),
+        },
+    )?;
+    input.unregister_client(&players[2])?;
+    assert_eq!(
+        slots.try_iter().last().unwrap(),
+        PlayerSlots {
+            slots: vec![Some(3)],
+            occupied: vec![1, 2, 3],
+            reserved: vec![4],
+            spectators: 0,
+        }
+    );
+    drop(input);
+
+    let returning = test_env.connect_client("returning").await?;
+    let mut input = test_env.input_manager.lock().await;
+    assert_eq!(input.player_slot(&returning), None);
+    input.handle_calibration(
+        &returning,
+        CalibrationRequest::Identify {
+            client_id: "switch-b".to_string(),
+        },
+    )?;
+    assert_eq!(input.player_slot(&returning), Some(4));
+
+    Ok(())
+}
+
+/// Test a couch session whose second controller gets its own port
+#[tokio::test]
+async fn test_local_players_share_a_session() -> Result<()> {
+    let mut test_env = TestEnvironment::new().await?;
+    let couch = test_env.connect_client("couch").await?;
+    let other = test_env.connect_client("other").await?;
+
+    // The second Joy-Con takes the first free port when it starts sending
+    let mut input = create_button_input(0x1000, true);
+    input.controller_index = 1;
+    test_env.send_input(&couch, input).await?;
+
+    let mut input = test_env.input_manager.lock().await;
+    assert_eq!(input.player_slot(&couch), Some(1));
+    assert_eq!(input.player_slot(&other), Some(2));
+    assert_eq!(input.controller_slot(&couch, 1), Some(3));
+    assert!(test_env
+        .input_recorder
+        .events()
+        .iter()
+        .any(|event| matches!(event, RecordedInput::Connected(3))));
+
+    // Rumble for port 3 reaches the couch client, addressed to that Joy-Con
+    let rumble = input.subscribe_rumble(couch);
+    input.route_rumble(RumbleEvent::new(3, 0xFFFF, 0));
+    assert_eq!(rumble.messages.try_recv().unwrap().controller, 1);
+
+    // Unpairing it frees the port for everyone
+    input.handle_slot_request(&couch, SlotRequest::Detach { controller: 1 })?;
+    assert_eq!(input.controller_slot(&couch, 1), None);
+    assert_eq!(input.player_slot(&couch), Some(1));
+    input.request_slot(&other, 3)?;
+    assert_eq!(input.player_slot(&other), Some(3));
+
+    Ok(())
+}
+
+/// Test that a recorded session replays to the same Dolphin commands
+#[tokio::test]
+async fn test_input_recording_replays_identically() -> Result<()> {
+    let mut test_env = TestEnvironment::new().await?;
+    let path = std::env::temp_dir().join(format!("dpstream-recording-{}.gz", Uuid::new_v4()));
+    test_env.input_manager.lock().await.start_recording(&path)?;
+
+    let client_id = test_env.connect_client("recorded").await?;
+    for step in 0..20i16 {
+        test_env
+            .send_input(&client_id, create_analog_input(step * 1500, -step * 900))
+            .await?;
+        test_env
+            .send_input(&client_id, create_button_input(0x1000, step % 3 == 0))
+            .await?;
+    }
+
+    // Let the batched commands drain into the recording before closing it
+    let mut input = test_env.input_manager.lock().await;
+    for _ in 0..40 {
+        input.process_inputs().await?;
+    }
+    input.stop_recording()?;
+    drop(input);
+
+    let recording = InputRecording::load(&path)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(recording.input_count(), 40);
+
+    let recorded = recording.recorded_commands();
+    assert!(!recorded.is_empty());
+    let replayed = recording.replay(ReplaySpeed::Unpaced).await?;
+    assert_eq!(diff_commands(&recorded, &replayed), None);
+
+    Ok(())
+}
+
+/// Test that reordered and stale input can't leave a button held
+#[tokio::test]
+async fn test_input_sequencing_releases_buttons() -> Result<()> {
+    let mut test_env = TestEnvironment::new().await?;
+    let client_id = test_env.connect_client("lossy").await?;
+    let numbered = |sequence, pressed| {
+        let mut input = create_button_input(0x1000, pressed);
+        input.sequence = Some(sequence);
+        input
+    };
+    let a_pressed = |test_env: &TestEnvironment| {
+        test_env
+            .input_recorder
+            .commands()
+            .iter()
+            .rev()
+            .find_map(|command| match command {
+                DolphinCommand::ButtonPress {
+                    button: DolphinButton::A,
+                    pressed,
+                    ..
+                } => Some(*pressed),
+                _ => None,
+            })
+    };
+
+    // The release overtakes the press before it and waits for it
+    test_env.send_input(&client_id, numbered(0, true)).await?;
+    test_env.send_input(&client_id, numbered(2, false)).await?;
+    assert_eq!(a_pressed(&test_env), Some(true));
+    test_env.send_input(&client_id, numbered(1, true)).await?;
+    assert_eq!(a_pressed(&test_env), Some(false));
+
+    // A duplicate of the old press is too late to matter
+    test_env.send_input(&client_id, numbered(1, true)).await?;
+    assert_eq!(a_pressed(&test_env), Some(false));
+
+    let stats = test_env.input_manager.lock().await.get_stats().sequencing;
+    assert_eq!(stats.packets_delivered, 3);
+    assert_eq!((stats.packets_reordered, stats.packets_late), (1, 1));
+
+    // Frames from now on show the newest input that got through
+    let applied = test_env
+        .input_manager
+        .lock()
+        .await
+        .last_applied_input(&client_id)
+        .map(|applied| applied.sequence);
+    assert_eq!(applied, Some(2));
+
+    Ok(())
+}
+
+/// Test that each local controller's input is ordered on its own
+#[tokio::test]
+async fn test_input_sequencing_per_controller() -> Result<()> {
+    let mut test_env = TestEnvironment::new().await?;
+    let client_id = test_env.connect_client("couch").await?;
+    let numbered = |controller_index, sequence, pressed| {
+        let mut input = create_button_input(0x1000, pressed);
+        input.controller_index = controller_index;
+        input.sequence = Some(sequence);
+        input
+    };
+    let a_pressed = |test_env: &TestEnvironment, slot: u8| {
+        test_env
+            .input_recorder
+            .commands()
+            .iter()
+            .rev()
+            .find_map(|command| match command {
+                DolphinCommand::ButtonPress {
+                    player,
+                    button: DolphinButton::A,
+                    pressed,
+                } if *player == slot => Some(*pressed),
+                _ => None,
+            })
+    };
+
+    // The first controller's release waits for the press before it
+    test_env
+        .send_input(&client_id, numbered(0, 0, true))
+        .await?;
+    test_env
+        .send_input(&client_id, numbered(0, 2, false))
+        .await?;
+
+    // The second controller counts from zero without being taken as late,
+    // and its snapshot leaves the first controller's waiting packet alone
+    test_env
+        .send_input(&client_id, numbered(1, 0, true))
+        .await?;
+    let mut snapshot = numbered(1, 3, false);
+    snapshot.snapshot = true;
+    test_env.send_input(&client_id, snapshot).await?;
+    assert_eq!(a_pressed(&test_env, 1), Some(true));
+    assert_eq!(a_pressed(&test_env, 2), Some(false));
+
+    test_env
+        .send_input(&client_id, numbered(0, 1, true))
+        .await?;
+    assert_eq!(a_pressed(&test_env, 1), Some(false));
+
+    let stats = test_env.input_manager.lock().await.get_stats().sequencing;
+    assert_eq!(stats.packets_delivered, 5);
+    assert_eq!((stats.packets_late, stats.snapshots), (0, 1));
+    assert_eq!((stats.packets_reordered, stats.packets_lost), (1, 2));
+
+    // Frames are tagged with the first controller's input only
+    let applied = test_env
+        .input_manager
+        .lock()
+        .await
+        .last_applied_input(&client_id)
+        .map(|applied| applied.sequence);
+    assert_eq!(applied, Some(2));
+
+    Ok(())
+}
+
+/// Bytes of a golden file of the shared protocol crate
+fn golden(hex: &str) -> Vec<u8> {
+    hex.lines()
+        .flat_map(|line| {
+            line.split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+        })
+        .map(|byte| u8::from_str_radix(byte, 16).expect("golden files hold hex bytes"))
+        .collect()
+}
+
+/// Test that input encoded as the Switch client does reaches Dolphin
+#[tokio::test]
+async fn test_golden_protocol_messages() -> Result<()> {
+    let bytes = golden(include_str!("../../protocol/tests/golden/input_packet.hex"));
+    let mut input = MoonlightInputPacket::decode(&bytes).expect("golden input decodes");
+    assert_eq!(input.encode(), bytes);
+    assert_eq!((input.button_flags, input.right_trigger), (0x1001, 255));
+    assert_eq!(input.timestamp, 1_500_000);
+
+    let mut test_env = TestEnvironment::new().await?;
+    let client_id = test_env.connect_client("golden").await?;
+    input.controller_index = 0;
+    test_env.send_input(&client_id, input).await?;
+    assert!(test_env
+        .input_recorder
+        .commands()
+        .iter()
+        .any(|command| matches!(
+            command,
+            DolphinCommand::ButtonPress {
+                button: DolphinButton::A,
+                pressed: true,
+                ..
+            }
+        )));
+
+    let bytes = golden(include_str!(
+        "../../protocol/tests/golden/latency_report.hex"
+    ));
+    let report = LatencyReport::decode(&bytes).expect("golden report decodes");
+    assert_eq!(report.downlink_us(), 25_000);
+
+    // Control requests as the client builds them
+    let bytes = golden(include_str!("../../protocol/tests/golden/slot_request.hex"));
+    let request = SlotRequest::decode(&bytes).expect("golden slot request decodes");
+    assert_eq!(
+        request,
+        SlotRequest::Swap {
+            slot: 3,
+            controller: 1,
+        }
+    );
+    let bytes = golden(include_str!("../../protocol/tests/golden/calibration.hex"));
+    let identify = CalibrationRequest::decode(&bytes).expect("golden calibration decodes");
+    let response = calibration_step(&test_env, &client_id, identify).await?;
+    assert_eq!(
+        response,
+        CalibrationResponse::Identified { calibration: None }
+    );
+
+    Ok(())
+}
+
+async fn calibration_step(
+    test_env: &TestEnvironment,
+    client_id: &Uuid,
+    request: CalibrationRequest,
+) -> Result<CalibrationResponse> {
+    test_env
+        .input_manager
+        .lock()
+        .await
+        .handle_calibration(client_id, request)
+}
+
 /// Test network resilience and error recovery
 #[tokio::test]
 async fn test_network_resilience() -> Result<()> {
@@ -3,6 +3,9 @@
 //! Handles framebuffer, rendering, and UI display
 
 use crate::error::{DisplayError, Result};
+use crate::moonlight::latency::LatencyTracker;
+use crate::moonlight::{GameInfo, OverlayControlKind, TouchOverlay};
+use alloc::format;
 use alloc::string::String;
 
 /// Main display manager
@@ -82,6 +85,46 @@ impl DisplayManager {
         Ok(())
     }
 
+    /// Show the game picker with the server's library
+    pub fn show_game_picker(&mut self, games: &[GameInfo], selected: usize) -> Result<()> {
+        self.current_screen = Screen::GamePicker;
+        self.clear_screen()?;
+
+        self.draw_text(50, 50, "Select a Game", Color::WHITE)?;
+
+        if games.is_empty() {
+            self.draw_text(50, 150, "No games found on server", Color::GRAY)?;
+        }
+
+        // Show a window of entries around the selection
+        let visible = ((self.height - 250) / 30) as usize;
+        let first = selected.saturating_sub(visible / 2);
+        for (row, game) in games.iter().enumerate().skip(first).take(visible) {
+            let y = 120 + ((row - first) as u32) * 30;
+            let label = if game.disc_number > 0 {
+                format!("{} (Disc {})", game.title, game.disc_number + 1)
+            } else {
+                game.title.clone()
+            };
+            let color = if row == selected {
+                Color::YELLOW
+            } else {
+                Color::WHITE
+            };
+            self.draw_text(50, y, &label, color)?;
+            self.draw_text(self.width - 200, y, &game.game_id, Color::GRAY)?;
+        }
+
+        self.draw_text(
+            50,
+            self.height - 60,
+            "A - Launch   B - Disconnect",
+            Color::GRAY,
+        )?;
+
+        Ok(())
+    }
+
     /// Show streaming UI
     pub fn show_streaming_ui(&mut self) -> Result<()> {
         self.current_screen = Screen::Streaming;
@@ -90,6 +133,31 @@ impl DisplayManager {
         Ok(())
     }
 
+    /// Show paused overlay while emulation is paused
+    pub fn show_paused(&mut self) -> Result<()> {
+        self.current_screen = Screen::Paused;
+        self.draw_text(self.width / 2 - 60, self.height / 2, "Paused", Color::WHITE)?;
+        self.draw_text(
+            50,
+            self.height - 60,
+            "+ - Resume   X - Save   Y - Load   L - Calibrate   R - Next port   ZR - Hand over   - - Disconnect",
+            Color::GRAY,
+        )?;
+        Ok(())
+    }
+
+    /// Show the current stick calibration step over the paused screen
+    pub fn show_calibration(&mut self, centering: bool) -> Result<()> {
+        let instruction = if centering {
+            "Leave both sticks at rest"
+        } else {
+            "Roll both sticks around their edges and press ZL and ZR fully"
+        };
+        self.draw_text(50, self.height / 2 + 60, instruction, Color::WHITE)?;
+        self.draw_text(50, self.height - 60, "L - Next   B - Cancel", Color::GRAY)?;
+        Ok(())
+    }
+
     /// Show settings menu
     pub fn show_settings_menu(&mut self) -> Result<()> {
         self.current_screen = Screen::Settings;
@@ -150,6 +218,77 @@ impl DisplayManager {
         Ok(())
     }
 
+    /// Draw the server's virtual touch controls over the stream
+    ///
+    /// The touchscreen only works in handheld mode, so nothing is drawn
+    /// while docked.
+    pub fn draw_touch_overlay(&mut self, overlay: &TouchOverlay) -> Result<()> {
+        if self.current_screen != Screen::Streaming || self.is_docked {
+            return Ok(());
+        }
+
+        for control in &overlay.controls {
+            let (x, y) = (u32::from(control.x), u32::from(control.y));
+            let (width, height) = (u32::from(control.width), u32::from(control.height));
+            self.draw_rect(x, y, width, height, Color::OVERLAY)?;
+            if control.kind == OverlayControlKind::Stick {
+                // Knob at the rest position
+                self.draw_rect(
+                    x + width * 3 / 8,
+                    y + height * 3 / 8,
+                    width / 4,
+                    height / 4,
+                    Color::GRAY,
+                )?;
+            }
+            self.draw_text(x + 8, y + 8, &control.label, Color::WHITE)?;
+        }
+        Ok(())
+    }
+
+    /// Show input latency in the top right corner while streaming
+    pub fn show_latency(&mut self, latency: &LatencyTracker) -> Result<()> {
+        if self.current_screen != Screen::Streaming {
+            return Ok(());
+        }
+        let (Some(median), Some(report)) = (
+            latency.input_to_photon.percentile_ms(50),
+            latency.last_report,
+        ) else {
+            return Ok(());
+        };
+
+        let tail = latency
+            .input_to_photon
+            .percentile_ms(95)
+            .map_or(String::from(">250"), |p95| format!("{p95}"));
+        let color = if median <= 50 {
+            Color::GREEN
+        } else if median <= 100 {
+            Color::YELLOW
+        } else {
+            Color::RED
+        };
+        self.draw_text(
+            self.width - 300,
+            10,
+            &format!("Input lag {median} ms (p95 {tail} ms)"),
+            color,
+        )?;
+        self.draw_text(
+            self.width - 300,
+            35,
+            &format!(
+                "up {} / frame {} / down {} ms",
+                report.uplink_us / 1000,
+                report.frame_us / 1000,
+                report.downlink_us() / 1000
+            ),
+            Color::GRAY,
+        )?;
+        Ok(())
+    }
+
     /// Present the current frame to screen
     pub fn present_frame(&mut self) -> Result<()> {
         // In real implementation: gfxFlushBuffers() and gfxSwapBuffers()
@@ -217,7 +356,9 @@ pub enum Screen {
     SplashScreen,
     MainMenu,
     Connecting,
+    GamePicker,
     Streaming,
+    Paused,
     Settings,
     Error,
 }
@@ -290,6 +431,13 @@ impl Color {
         b: 128,
         a: 255,
     };
+    /// Translucent fill for on-screen controls
+    pub const OVERLAY: Color = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+        a: 64,
+    };
 }
 
 /// Video frame data
@@ -3,6 +3,7 @@
 //! No-std compatible error types for the dpstream Switch client
 
 use core::fmt;
+use dpstream_protocol::hello::Rejection;
 
 /// Result type alias for client operations
 pub type Result<T> = core::result::Result<T, ClientError>;
@@ -111,6 +112,8 @@ pub enum MoonlightError {
     ServerIncompatible,
     SessionTimeout,
     InvalidPacket,
+    /// The server refused this client's protocol version or features
+    Rejected(Rejection),
 }
 
 impl fmt::Display for MoonlightError {
@@ -124,6 +127,7 @@ impl fmt::Display for MoonlightError {
             MoonlightError::ServerIncompatible => write!(f, "Server incompatible"),
             MoonlightError::SessionTimeout => write!(f, "Session timeout"),
             MoonlightError::InvalidPacket => write!(f, "Invalid packet format"),
+            MoonlightError::Rejected(rejection) => write!(f, "{}", rejection),
         }
     }
 }
@@ -1,15 +1,21 @@
 //! Input handling for Nintendo Switch
 //!
-//! Manages Joy-Con, Pro Controller, and touch input
+//! Manages Joy-Con, Pro Controller, and touch input, and renders rumble
+//! from the server as HD Rumble
 
 use crate::error::{InputError, Result};
 use bitflags::bitflags;
+use dpstream_protocol::input::{buttons, TouchPoint as WireTouchPoint};
+
+/// Moonlight input packet format, shared with the server
+pub use dpstream_protocol::input::InputPacket as MoonlightInput;
 
 /// Main input manager
 pub struct InputManager {
     current_state: InputState,
     previous_state: InputState,
     controllers: [Controller; 8], // Up to 8 controllers
+    rumble_stats: RumbleStats,
 }
 
 impl InputManager {
@@ -20,6 +26,7 @@ impl InputManager {
             current_state: InputState::default(),
             previous_state: InputState::default(),
             controllers: [Controller::default(); 8],
+            rumble_stats: RumbleStats::default(),
         })
     }
 
@@ -142,25 +149,24 @@ impl InputManager {
         // Combine input from all connected controllers
         // Priority: Handheld > Pro Controller > Joy-Cons
 
-        for controller in &self.controllers {
-            if controller.is_connected {
-                // Use the first connected controller as primary
-                self.current_state = InputState {
-                    buttons: controller.buttons,
-                    left_stick: controller.left_stick,
-                    right_stick: controller.right_stick,
-                    left_trigger: controller.left_trigger,
-                    right_trigger: controller.right_trigger,
-                    gyro: controller.gyro,
-                    touch_points: controller.touch_points.clone(),
-                };
-                break;
-            }
+        if let Some(controller) = self.controllers.iter().find(|c| c.is_connected) {
+            // Use the first connected controller as primary
+            self.current_state = controller.input_state();
         }
 
         Ok(())
     }
 
+    /// Every connected controller as its own local player, numbered from 0
+    /// in connection order; player 0 is the one the menus follow
+    pub fn local_players(&self) -> impl Iterator<Item = (u8, InputState)> + '_ {
+        self.controllers
+            .iter()
+            .filter(|controller| controller.is_connected)
+            .enumerate()
+            .map(|(index, controller)| (index as u8, controller.input_state()))
+    }
+
     /// Get current input state
     pub fn get_current_state(&self) -> &InputState {
         &self.current_state
@@ -225,6 +231,41 @@ impl InputManager {
         &self.current_state.gyro
     }
 
+    /// Rumble one local player's controller
+    ///
+    /// `player` numbers controllers as [`InputManager::local_players`] does.
+    /// `low` and `high` are the server's motor levels, already scaled by the
+    /// mapping's vibration strength; `server_latency_us` is how long the
+    /// server held them after the emulator asked.
+    pub fn apply_rumble(
+        &mut self,
+        player: u8,
+        low: u16,
+        high: u16,
+        server_latency_us: u32,
+    ) -> Result<()> {
+        let value = HdRumble::from_levels(low, high);
+
+        if let Some(controller) = self
+            .controllers
+            .iter_mut()
+            .filter(|c| c.is_connected)
+            .nth(player as usize)
+        {
+            // In real implementation: hidSendVibrationValues() with one value
+            // per actuator; Joy-Con pairs and handheld mode have two
+            controller.rumble = value;
+        }
+
+        self.rumble_stats.record(server_latency_us);
+        Ok(())
+    }
+
+    /// Rumble counters and latency
+    pub fn rumble_stats(&self) -> &RumbleStats {
+        &self.rumble_stats
+    }
+
     /// Cleanup input system
     pub fn cleanup(&mut self) -> Result<()> {
         // In real implementation: hidExit()
@@ -348,6 +389,70 @@ pub struct Controller {
     pub right_trigger: u8,
     pub gyro: SixAxisSensor,
     pub touch_points: alloc::vec::Vec<TouchPoint>,
+    pub rumble: HdRumble,
+}
+
+impl Controller {
+    /// This controller's input on its own
+    pub fn input_state(&self) -> InputState {
+        InputState {
+            buttons: self.buttons,
+            left_stick: self.left_stick,
+            right_stick: self.right_stick,
+            left_trigger: self.left_trigger,
+            right_trigger: self.right_trigger,
+            gyro: self.gyro,
+            touch_points: self.touch_points.clone(),
+        }
+    }
+}
+
+/// HD Rumble actuator value, as libnx's `HidVibrationValue`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdRumble {
+    pub amp_low: f32,
+    pub freq_low: f32,
+    pub amp_high: f32,
+    pub freq_high: f32,
+}
+
+impl HdRumble {
+    /// Resonant frequencies of the Joy-Con actuator bands, in Hz
+    pub const FREQ_LOW: f32 = 160.0;
+    pub const FREQ_HIGH: f32 = 320.0;
+
+    /// Map the strong and weak motor levels onto the low and high bands
+    pub fn from_levels(low: u16, high: u16) -> Self {
+        Self {
+            amp_low: low as f32 / u16::MAX as f32,
+            freq_low: Self::FREQ_LOW,
+            amp_high: high as f32 / u16::MAX as f32,
+            freq_high: Self::FREQ_HIGH,
+        }
+    }
+}
+
+impl Default for HdRumble {
+    fn default() -> Self {
+        Self::from_levels(0, 0)
+    }
+}
+
+/// Rumble received from the server
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RumbleStats {
+    pub commands: u64,
+    /// Server-side capture-to-send latency of the latest command
+    pub last_latency_us: u32,
+    pub max_latency_us: u32,
+}
+
+impl RumbleStats {
+    fn record(&mut self, latency_us: u32) {
+        self.commands += 1;
+        self.last_latency_us = latency_us;
+        self.max_latency_us = self.max_latency_us.max(latency_us);
+    }
 }
 
 /// Six-axis sensor data (gyroscope + accelerometer + orientation)
@@ -379,60 +484,37 @@ pub enum ControllerType {
 
 /// Convert input state to Moonlight protocol format
 impl InputState {
-    /// Convert to Moonlight input packet
-    pub fn to_moonlight_input(&self) -> MoonlightInput {
+    /// Convert to Moonlight input packet for one of the client's controllers
+    pub fn to_moonlight_input(&self, controller_index: u8) -> MoonlightInput {
         let mut gamepad_buttons = 0u16;
 
         // Map Switch buttons to Xbox controller buttons (Moonlight format)
-        if self.buttons.contains(Buttons::A) {
-            gamepad_buttons |= 0x1000;
-        } // A
-        if self.buttons.contains(Buttons::B) {
-            gamepad_buttons |= 0x2000;
-        } // B
-        if self.buttons.contains(Buttons::X) {
-            gamepad_buttons |= 0x4000;
-        } // X
-        if self.buttons.contains(Buttons::Y) {
-            gamepad_buttons |= 0x8000;
-        } // Y
-
-        if self.buttons.contains(Buttons::L) {
-            gamepad_buttons |= 0x0100;
-        } // Left shoulder
-        if self.buttons.contains(Buttons::R) {
-            gamepad_buttons |= 0x0200;
-        } // Right shoulder
-
-        if self.buttons.contains(Buttons::MINUS) {
-            gamepad_buttons |= 0x0020;
-        } // Back
-        if self.buttons.contains(Buttons::PLUS) {
-            gamepad_buttons |= 0x0010;
-        } // Start
-
-        if self.buttons.contains(Buttons::L_STICK) {
-            gamepad_buttons |= 0x0040;
-        } // Left thumb
-        if self.buttons.contains(Buttons::R_STICK) {
-            gamepad_buttons |= 0x0080;
-        } // Right thumb
-
-        if self.buttons.contains(Buttons::D_UP) {
-            gamepad_buttons |= 0x0001;
-        }
-        if self.buttons.contains(Buttons::D_DOWN) {
-            gamepad_buttons |= 0x0002;
-        }
-        if self.buttons.contains(Buttons::D_LEFT) {
-            gamepad_buttons |= 0x0004;
-        }
-        if self.buttons.contains(Buttons::D_RIGHT) {
-            gamepad_buttons |= 0x0008;
+        for (button, flag) in [
+            (Buttons::A, buttons::A),
+            (Buttons::B, buttons::B),
+            (Buttons::X, buttons::X),
+            (Buttons::Y, buttons::Y),
+            (Buttons::L, buttons::L),
+            (Buttons::R, buttons::R),
+            (Buttons::MINUS, buttons::MINUS),
+            (Buttons::PLUS, buttons::PLUS),
+            (Buttons::L_STICK, buttons::L_STICK),
+            (Buttons::R_STICK, buttons::R_STICK),
+            (Buttons::D_UP, buttons::UP),
+            (Buttons::D_DOWN, buttons::DOWN),
+            (Buttons::D_LEFT, buttons::LEFT),
+            (Buttons::D_RIGHT, buttons::RIGHT),
+        ] {
+            if self.buttons.contains(button) {
+                gamepad_buttons |= flag;
+            }
         }
 
         MoonlightInput {
             packet_type: 0x0C, // Multi-controller packet
+            controller_index,
+            sequence: None,
+            snapshot: false,
             button_flags: gamepad_buttons,
             left_trigger: if self.buttons.contains(Buttons::ZL) {
                 255
@@ -456,7 +538,17 @@ impl InputState {
             accel_y: Some(self.gyro.acceleration_y),
             accel_z: Some(self.gyro.acceleration_z),
             touch_points: if !self.touch_points.is_empty() {
-                Some(self.touch_points.clone())
+                Some(
+                    self.touch_points
+                        .iter()
+                        .map(|touch| WireTouchPoint {
+                            x: touch.x,
+                            y: touch.y,
+                            // No pressure sensing; the contact size stands in
+                            pressure: touch.diameter_x.max(touch.diameter_y).min(255) as u8,
+                        })
+                        .collect(),
+                )
             } else {
                 None
             },
@@ -464,25 +556,57 @@ impl InputState {
     }
 }
 
-/// Moonlight input packet format
-#[derive(Debug, Clone)]
-pub struct MoonlightInput {
-    pub packet_type: u8,
-    pub button_flags: u16,
-    pub left_trigger: u8,
-    pub right_trigger: u8,
-    pub left_stick_x: i16,
-    pub left_stick_y: i16,
-    pub right_stick_x: i16,
-    pub right_stick_y: i16,
-    pub timestamp: u64,
-
-    // Extended Switch-specific data
-    pub gyro_x: Option<f32>,
-    pub gyro_y: Option<f32>,
-    pub gyro_z: Option<f32>,
-    pub accel_x: Option<f32>,
-    pub accel_y: Option<f32>,
-    pub accel_z: Option<f32>,
-    pub touch_points: Option<alloc::vec::Vec<TouchPoint>>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dpstream_protocol::Message;
+
+    /// Bytes of a golden file of the shared protocol crate
+    fn golden(hex: &str) -> alloc::vec::Vec<u8> {
+        hex.lines()
+            .flat_map(|line| {
+                line.split('#')
+                    .next()
+                    .unwrap_or_default()
+                    .split_whitespace()
+            })
+            .map(|byte| u8::from_str_radix(byte, 16).expect("golden files hold hex bytes"))
+            .collect()
+    }
+
+    #[test]
+    fn test_input_matches_protocol_golden() {
+        let mut state = InputState {
+            buttons: Buttons::A | Buttons::D_UP | Buttons::ZR,
+            left_stick: AnalogStick { x: -32768, y: 1000 },
+            right_stick: AnalogStick { x: 0, y: -1 },
+            gyro: SixAxisSensor {
+                angular_velocity_x: 0.5,
+                acceleration_z: -1.0,
+                ..SixAxisSensor::default()
+            },
+            ..InputState::default()
+        };
+        state.touch_points.push(TouchPoint {
+            id: 0,
+            x: 640,
+            y: 360,
+            diameter_x: 20,
+            diameter_y: 12,
+            rotation_angle: 0,
+        });
+
+        // Set by the network layer when sent
+        let mut input = state.to_moonlight_input(1);
+        input.sequence = Some(300);
+        input.snapshot = true;
+        input.timestamp = 1_500_000;
+
+        assert_eq!(
+            input.encode(),
+            golden(include_str!(
+                "../../../protocol/tests/golden/input_packet.hex"
+            ))
+        );
+    }
 }
@@ -23,9 +23,11 @@ mod network;
 mod sys;
 
 use display::DisplayManager;
-use error::{ClientError, Result};
+use error::{ClientError, MoonlightError, Result};
 use input::InputManager;
-use moonlight::MoonlightClient;
+use moonlight::{
+    CalibrationRequest, ClientState, GameInfo, MoonlightClient, SaveSlot, SlotRequest, StateOp,
+};
 use sys::libnx::LibnxSystem;
 
 /// Global allocator for heap memory management
@@ -38,6 +40,10 @@ pub struct DpstreamApp {
     display: DisplayManager,
     input: InputManager,
     moonlight: Option<MoonlightClient>,
+    games: alloc::vec::Vec<GameInfo>,
+    selected_game: usize,
+    /// Local players sent last frame, to notice unpaired controllers
+    local_players: u8,
     running: bool,
 }
 
@@ -58,6 +64,9 @@ impl DpstreamApp {
             display,
             input,
             moonlight: None,
+            games: alloc::vec::Vec::new(),
+            selected_game: 0,
+            local_players: 0,
             running: true,
         })
     }
@@ -78,15 +87,18 @@ impl DpstreamApp {
             }
 
             // Handle menu navigation if not connected
-            if self.moonlight.is_none() {
-                self.handle_menu_input()?;
-            } else {
-                // Handle game streaming
-                self.handle_streaming()?;
+            match self.moonlight.as_ref().map(|client| client.get_state()) {
+                None => self.handle_menu_input()?,
+                Some(ClientState::Streaming) => self.handle_streaming()?,
+                Some(ClientState::Paused) => self.handle_paused()?,
+                Some(_) => self.handle_game_picker()?,
             }
 
             // Update display
             self.display.present_frame()?;
+            if let Some(client) = &mut self.moonlight {
+                client.frame_presented()?;
+            }
         }
 
         self.cleanup()?;
@@ -109,16 +121,73 @@ impl DpstreamApp {
         Ok(())
     }
 
+    /// Handle game picker navigation while connected
+    fn handle_game_picker(&mut self) -> Result<()> {
+        let game_count = self.games.len();
+
+        if self.input.is_button_pressed(input::Buttons::D_DOWN) && game_count > 0 {
+            self.selected_game = (self.selected_game + 1) % game_count;
+            self.display
+                .show_game_picker(&self.games, self.selected_game)?;
+        } else if self.input.is_button_pressed(input::Buttons::D_UP) && game_count > 0 {
+            self.selected_game = (self.selected_game + game_count - 1) % game_count;
+            self.display
+                .show_game_picker(&self.games, self.selected_game)?;
+        } else if self.input.is_a_pressed() {
+            let user = self.system.get_user_nickname()?;
+            if let (Some(client), Some(game)) =
+                (&mut self.moonlight, self.games.get(self.selected_game))
+            {
+                client.launch_game(game, &user)?;
+                client.start_stream()?;
+                self.display.show_streaming_ui()?;
+            }
+        } else if self.input.is_b_pressed() {
+            self.disconnect_from_server()?;
+        }
+
+        Ok(())
+    }
+
     /// Handle streaming input and display
     fn handle_streaming(&mut self) -> Result<()> {
+        // Pause emulation when the console sleeps or the HOME menu opens
+        if !self.system.is_in_focus()? {
+            if let Some(client) = &mut self.moonlight {
+                client.pause_stream()?;
+                self.display.show_paused()?;
+            }
+            return Ok(());
+        }
+
         if let Some(client) = &mut self.moonlight {
-            // Send input to server
-            let input_state = self.input.get_current_state();
-            client.send_input(&input_state)?;
+            // Send every local player's input; each gets its own port
+            let mut players = 0;
+            for (controller, input_state) in self.input.local_players() {
+                client.send_input(controller, &input_state)?;
+                players += 1;
+            }
+            // Free the ports of controllers unpaired since the last frame
+            for controller in players.max(1)..self.local_players {
+                client.request_slot(SlotRequest::Detach { controller })?;
+            }
+            self.local_players = players;
+
+            // Render rumble from the emulator
+            while let Some(rumble) = client.poll_rumble()? {
+                self.input.apply_rumble(
+                    rumble.controller,
+                    rumble.low,
+                    rumble.high,
+                    rumble.latency_us,
+                )?;
+            }
 
             // Receive and decode video frame
             if let Some(frame) = client.receive_frame()? {
                 self.display.render_frame(&frame)?;
+                self.display.draw_touch_overlay(client.touch_overlay())?;
+                self.display.show_latency(client.latency())?;
             }
 
             // Check for disconnect
@@ -130,6 +199,88 @@ impl DpstreamApp {
         Ok(())
     }
 
+    /// Handle input while emulation is paused
+    fn handle_paused(&mut self) -> Result<()> {
+        if self
+            .moonlight
+            .as_ref()
+            .is_some_and(|client| client.is_calibrating())
+        {
+            return self.handle_calibration();
+        }
+
+        if self.input.is_button_pressed(input::Buttons::L) {
+            if let Some(client) = &mut self.moonlight {
+                client.calibrate(&CalibrationRequest::Start)?;
+                self.display.show_calibration(true)?;
+            }
+        } else if self.input.is_button_pressed(input::Buttons::R) {
+            // Move to the next port, or take a free one while spectating
+            if let Some(client) = &mut self.moonlight {
+                let request = client.player_slots().and_then(|slots| match slots.slot() {
+                    Some(slot) => Some(SlotRequest::Swap {
+                        slot: slot % 4 + 1,
+                        controller: 0,
+                    }),
+                    None => slots.first_free().map(|slot| SlotRequest::Claim {
+                        slot,
+                        controller: 0,
+                    }),
+                });
+                if let Some(request) = request {
+                    client.request_slot(request)?;
+                }
+            }
+        } else if self.input.is_button_pressed(input::Buttons::ZR) {
+            if let Some(client) = &mut self.moonlight {
+                client.request_slot(SlotRequest::HandOver)?;
+            }
+        } else if self.input.is_x_pressed() || self.input.is_y_pressed() {
+            // Quick save/load to the player's first slot
+            let slot = SaveSlot::Numbered(1);
+            let op = if self.input.is_x_pressed() {
+                StateOp::Save { slot }
+            } else {
+                StateOp::Load { slot }
+            };
+            let user = self.system.get_user_nickname()?;
+            if let Some(client) = &mut self.moonlight {
+                client.request_save_state(&user, op)?;
+            }
+        } else if self.input.is_plus_pressed() && self.system.is_in_focus()? {
+            if let Some(client) = &mut self.moonlight {
+                client.resume_stream()?;
+                self.display.show_streaming_ui()?;
+            }
+        } else if self.input.is_minus_pressed() {
+            self.disconnect_from_server()?;
+        }
+
+        Ok(())
+    }
+
+    /// Step the stick calibration; the server measures the input sent meanwhile
+    fn handle_calibration(&mut self) -> Result<()> {
+        let Some(client) = &mut self.moonlight else {
+            return Ok(());
+        };
+        client.send_input(0, self.input.get_current_state())?;
+
+        if self.input.is_button_pressed(input::Buttons::L) {
+            client.calibrate(&CalibrationRequest::Next)?;
+            if client.is_calibrating() {
+                self.display.show_calibration(false)?;
+            } else {
+                self.display.show_paused()?;
+            }
+        } else if self.input.is_b_pressed() {
+            client.calibrate(&CalibrationRequest::Cancel)?;
+            self.display.show_paused()?;
+        }
+
+        Ok(())
+    }
+
     /// Connect to dpstream server
     fn connect_to_server(&mut self) -> Result<()> {
         self.display.show_connecting_screen()?;
@@ -137,21 +288,41 @@ impl DpstreamApp {
         // Create Moonlight client
         let mut client = MoonlightClient::new()?;
 
-        // For now, mock the connection process
-        // In real implementation, this would discover and connect to servers
+        // Connect to the first discovered server
+        if let Some(server) = client.discover_servers()?.first() {
+            match client.connect(server) {
+                Err(ClientError::Moonlight(MoonlightError::Rejected(rejection))) => {
+                    // Versions don't match; say which side needs updating
+                    self.display.show_error(&alloc::format!("{}", rejection))?;
+                    return Ok(());
+                }
+                connected => connected?,
+            }
+        }
+
+        // Apply the stick calibration saved for this console
+        client.calibrate(&CalibrationRequest::Identify {
+            client_id: self.system.get_client_id()?,
+        })?;
+
+        // Fetch the game library for the picker
+        self.games = client.request_game_list()?;
+        self.selected_game = 0;
 
         self.moonlight = Some(client);
-        self.display.show_streaming_ui()?;
+        self.display
+            .show_game_picker(&self.games, self.selected_game)?;
 
         Ok(())
     }
 
     /// Disconnect from server
     fn disconnect_from_server(&mut self) -> Result<()> {
-        if let Some(_client) = self.moonlight.take() {
-            // Disconnect logic would go here
+        if let Some(mut client) = self.moonlight.take() {
+            client.disconnect()?;
         }
 
+        self.games.clear();
         self.display.show_main_menu()?;
         Ok(())
     }
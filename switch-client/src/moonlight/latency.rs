@@ -0,0 +1,152 @@
+//! Input-to-photon latency measurement
+//!
+//! Every numbered input is stamped with the local clock when sent. Video
+//! frames carry a [`FrameInputTag`] naming the last input the server got into
+//! Dolphin before the frame; when a frame naming a new input is shown, the
+//! time since that input was stamped is the input-to-photon latency. The
+//! clock offset from [`ClockSync`] exchanges splits it into the trip to
+//! Dolphin, the wait for the frame and the way back.
+
+pub use dpstream_protocol::latency::{
+    ClockSync, ClockSyncReply, FrameInputTag, LatencyReport, FRAME_TAG_PROFILE,
+};
+
+/// Upper bounds of the histogram buckets, in milliseconds
+pub const BUCKET_BOUNDS_MS: [u32; 10] = [5, 10, 16, 25, 33, 50, 75, 100, 150, 250];
+
+/// Sent inputs remembered for matching frame tags
+const STAMP_HISTORY: usize = 64;
+
+/// Clock sync samples the offset is picked from
+const SYNC_SAMPLES: usize = 8;
+
+/// Latency histogram with fixed millisecond buckets
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    /// Samples per bucket, the last one past every bound
+    buckets: [u32; BUCKET_BOUNDS_MS.len() + 1],
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency_us: u32) {
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| latency_us <= bound * 1000)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket] = self.buckets[bucket].saturating_add(1);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.buckets.iter().sum()
+    }
+
+    /// Upper bound in milliseconds of the bucket holding the given
+    /// percentile, `None` without samples or past the last bound
+    pub fn percentile_ms(&self, percentile: u32) -> Option<u32> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let target = (count * percentile).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (bucket, bound) in self.buckets.iter().zip(BUCKET_BOUNDS_MS) {
+            seen += bucket;
+            if seen >= target {
+                return Some(bound);
+            }
+        }
+        None
+    }
+}
+
+/// Matches shown frames to the inputs they show
+#[derive(Debug, Clone)]
+pub struct LatencyTracker {
+    /// Sequence number and send time of recent inputs, by sequence
+    stamps: [Option<(u32, u64)>; STAMP_HISTORY],
+    /// Recent clock syncs as (round trip, server minus client offset)
+    syncs: [Option<(u64, i64)>; SYNC_SAMPLES],
+    next_sync: usize,
+    /// Newest input already measured
+    last_measured: Option<u32>,
+    pub input_to_photon: LatencyHistogram,
+    pub uplink: LatencyHistogram,
+    pub frame: LatencyHistogram,
+    pub downlink: LatencyHistogram,
+    pub last_report: Option<LatencyReport>,
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self {
+            stamps: [None; STAMP_HISTORY],
+            syncs: [None; SYNC_SAMPLES],
+            next_sync: 0,
+            last_measured: None,
+            input_to_photon: LatencyHistogram::default(),
+            uplink: LatencyHistogram::default(),
+            frame: LatencyHistogram::default(),
+            downlink: LatencyHistogram::default(),
+            last_report: None,
+        }
+    }
+}
+
+impl LatencyTracker {
+    /// Remember when a numbered input was sent
+    pub fn stamp(&mut self, sequence: u32, sent_us: u64) {
+        self.stamps[sequence as usize % STAMP_HISTORY] = Some((sequence, sent_us));
+    }
+
+    /// Take in a clock sync reply received at `now_us`
+    pub fn clock_synced(&mut self, reply: &ClockSyncReply, now_us: u64) {
+        let round_trip = now_us
+            .saturating_sub(reply.client_time_us)
+            .saturating_sub(reply.server_send_us.saturating_sub(reply.server_receive_us));
+        let offset = ((reply.server_receive_us as i64 - reply.client_time_us as i64)
+            + (reply.server_send_us as i64 - now_us as i64))
+            / 2;
+        self.syncs[self.next_sync] = Some((round_trip, offset));
+        self.next_sync = (self.next_sync + 1) % SYNC_SAMPLES;
+    }
+
+    /// Server clock minus client clock, from the sync with the shortest
+    /// round trip since it is the least skewed by queuing
+    pub fn clock_offset_us(&self) -> Option<i64> {
+        self.syncs
+            .iter()
+            .flatten()
+            .min_by_key(|(round_trip, _)| *round_trip)
+            .map(|(_, offset)| *offset)
+    }
+
+    /// Measure a frame shown at `now_us`
+    ///
+    /// Returns a report the first time a frame shows a given input, as long
+    /// as that input is still remembered and the clocks have been synced.
+    pub fn frame_shown(&mut self, tag: &FrameInputTag, now_us: u64) -> Option<LatencyReport> {
+        if self.last_measured == Some(tag.input_sequence) {
+            return None;
+        }
+        let (sequence, sent_us) = self.stamps[tag.input_sequence as usize % STAMP_HISTORY]?;
+        if sequence != tag.input_sequence {
+            return None;
+        }
+        let offset = self.clock_offset_us()?;
+        self.last_measured = Some(sequence);
+
+        let applied_us = tag.applied_at_us as i64 - offset;
+        let clamp = |micros: i64| micros.clamp(0, i64::from(u32::MAX)) as u32;
+        let report = LatencyReport {
+            input_to_photon_us: clamp(now_us as i64 - sent_us as i64),
+            uplink_us: clamp(applied_us - sent_us as i64),
+            frame_us: tag.hold_us,
+        };
+        self.input_to_photon.record(report.input_to_photon_us);
+        self.uplink.record(report.uplink_us);
+        self.frame.record(report.frame_us);
+        self.downlink.record(report.downlink_us());
+        self.last_report = Some(report);
+        Some(report)
+    }
+}
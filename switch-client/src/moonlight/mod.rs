@@ -4,15 +4,33 @@
 
 pub mod audio;
 pub mod decoder;
+pub mod latency;
+pub mod rtcp;
+pub mod stats;
 
 use self::audio::{AudioFrame, AudioPlayer};
+use self::latency::{ClockSync, ClockSyncReply, FrameInputTag, LatencyTracker};
+use self::rtcp::{decode_compound, is_rtcp, RtcpPacket, RtcpReceiver};
+use self::stats::ReceiveStats;
 use crate::display::VideoFrame;
 use crate::error::{MoonlightError, NetworkError, Result};
 use crate::input::{InputState, MoonlightInput};
+use crate::sys::time::get_time_us;
 use alloc::string::String;
 use alloc::vec::Vec;
 use cache_padded::CachePadded;
 use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+pub use dpstream_protocol::calibration::{CalibrationRequest, CalibrationResponse};
+use dpstream_protocol::capabilities::{features, Capabilities};
+pub use dpstream_protocol::emulation::EmulationCommand;
+use dpstream_protocol::hello::{Agreement, Hello, HelloReply};
+pub use dpstream_protocol::input::WiiExtension;
+pub use dpstream_protocol::library::{GameInfo, LibraryRequest, LibraryResponse};
+pub use dpstream_protocol::rumble::Rumble;
+pub use dpstream_protocol::savestate::{SaveSlot, StateOp, StateRequest, StateResponse};
+pub use dpstream_protocol::slots::{PlayerSlots, SlotRequest};
+pub use dpstream_protocol::touch::{OverlayControl, OverlayControlKind, TouchOverlay};
+use dpstream_protocol::Message;
 use heapless::Vec as HeaplessVec;
 
 /// Main Moonlight client
@@ -23,6 +41,48 @@ pub struct MoonlightClient {
     network: NetworkManager,
     decoder: VideoDecoder,
     audio_player: Option<AudioPlayer>,
+    /// Calibration steps still to send, while input flows even when paused
+    calibration_steps: u8,
+    /// Latest slot layout from the server
+    player_slots: Option<PlayerSlots>,
+    /// Virtual controls the server wants drawn over the touchscreen
+    touch_overlay: TouchOverlay,
+    /// What each local controller last sent
+    sent_inputs: [SentInput; MAX_LOCAL_CONTROLLERS],
+    latency: LatencyTracker,
+    /// Input tag of the frame being received
+    frame_tag: Option<FrameInputTag>,
+    /// Input tag of the decoded frame waiting to be shown
+    shown_tag: Option<FrameInputTag>,
+    frames_since_sync: u32,
+    /// Protocol version and features settled with the server
+    agreement: Option<Agreement>,
+    /// What reached us since the last stats report to the server
+    receive_stats: ReceiveStats,
+    /// Losses to NACK and reports to send on the stream socket
+    rtcp: RtcpReceiver,
+}
+
+/// Release named in the client's [`Hello`]
+pub const CLIENT_BUILD: &str = concat!("dpstream-switch-", env!("CARGO_PKG_VERSION"));
+
+/// Local controllers a client can send input for
+const MAX_LOCAL_CONTROLLERS: usize = 8;
+
+/// Frames between full-state snapshots of a controller, so a lost packet
+/// can't leave a button held on the server for long
+const SNAPSHOT_INTERVAL: u8 = 15;
+
+/// Frames between clock sync exchanges, about five seconds
+const CLOCK_SYNC_INTERVAL: u32 = 300;
+
+/// Last input sent for one local controller
+#[derive(Debug, Clone, Default)]
+struct SentInput {
+    last: Option<MoonlightInput>,
+    frames_since_snapshot: u8,
+    /// Number given to the controller's next input packet
+    next_sequence: u32,
 }
 
 impl MoonlightClient {
@@ -35,6 +95,17 @@ impl MoonlightClient {
             network: NetworkManager::new()?,
             decoder: VideoDecoder::new()?,
             audio_player: None,
+            calibration_steps: 0,
+            player_slots: None,
+            touch_overlay: TouchOverlay::default(),
+            sent_inputs: Default::default(),
+            latency: LatencyTracker::default(),
+            frame_tag: None,
+            shown_tag: None,
+            frames_since_sync: 0,
+            agreement: None,
+            receive_stats: ReceiveStats::default(),
+            rtcp: RtcpReceiver::default(),
         })
     }
 
@@ -81,16 +152,268 @@ impl MoonlightClient {
         audio_player.initialize()?;
         self.audio_player = Some(audio_player);
 
+        self.sync_clock()
+    }
+
+    /// Ask the server for its clock, for splitting up input latency
+    pub fn sync_clock(&mut self) -> Result<()> {
+        self.frames_since_sync = 0;
+        if !self.has_feature(features::LATENCY_TAGS) {
+            return Ok(());
+        }
+        self.network.send_clock_sync(
+            &ClockSync {
+                client_time_us: get_time_us()?,
+            }
+            .encode(),
+        )
+    }
+
+    /// Input latency measured so far
+    pub fn latency(&self) -> &LatencyTracker {
+        &self.latency
+    }
+
+    /// Note that the last decoded frame is on screen
+    ///
+    /// The first frame to show a new input measures its input-to-photon
+    /// latency, which is also reported to the server.
+    pub fn frame_presented(&mut self) -> Result<()> {
+        if self.state != ClientState::Streaming {
+            return Ok(());
+        }
+        if let Some(tag) = self.shown_tag.take() {
+            if let Some(report) = self.latency.frame_shown(&tag, get_time_us()?) {
+                self.network.send_latency_report(&report.encode())?;
+            }
+        }
+
+        self.frames_since_sync += 1;
+        if self.frames_since_sync >= CLOCK_SYNC_INTERVAL {
+            self.sync_clock()?;
+        }
         Ok(())
     }
 
-    /// Send input to server
-    pub fn send_input(&mut self, input: &InputState) -> Result<()> {
+    /// Fetch the server's game library for the game picker
+    pub fn request_game_list(&mut self) -> Result<Vec<GameInfo>> {
+        if self.state != ClientState::Connected {
+            return Err(MoonlightError::HandshakeFailed.into());
+        }
+
+        match self
+            .network
+            .exchange_library(&LibraryRequest::List.encode())?
+        {
+            LibraryResponse::Games(games) => Ok(games),
+            _ => Err(MoonlightError::StreamingError.into()),
+        }
+    }
+
+    /// Ask the server to launch a game from its library with `user`'s saves
+    pub fn launch_game(&mut self, game: &GameInfo, user: &str) -> Result<()> {
+        if self.state != ClientState::Connected {
+            return Err(MoonlightError::HandshakeFailed.into());
+        }
+
+        let request = LibraryRequest::Launch {
+            app_id: game.app_id,
+            user: String::from(user),
+        };
+        match self.network.exchange_library(&request.encode())? {
+            LibraryResponse::Failed { .. } => Err(MoonlightError::StreamingError.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Pause emulation on the server, e.g. when the console goes to sleep
+    pub fn pause_stream(&mut self) -> Result<()> {
         if self.state != ClientState::Streaming {
             return Ok(());
         }
 
-        let moonlight_input = input.to_moonlight_input();
+        self.network
+            .send_emulation_command(EmulationCommand::Pause)?;
+        self.state = ClientState::Paused;
+        Ok(())
+    }
+
+    /// Resume emulation after [`MoonlightClient::pause_stream`]
+    pub fn resume_stream(&mut self) -> Result<()> {
+        if self.state != ClientState::Paused {
+            return Ok(());
+        }
+
+        self.network
+            .send_emulation_command(EmulationCommand::Resume)?;
+        self.state = ClientState::Streaming;
+        Ok(())
+    }
+
+    /// Request a save-state operation for the running game
+    ///
+    /// The server replies with a [`StateResponse`] on the control channel.
+    pub fn request_save_state(&mut self, user: &str, op: StateOp) -> Result<()> {
+        let request = StateRequest {
+            user: String::from(user),
+            game_id: None,
+            op,
+        };
+        match self.state {
+            ClientState::Streaming | ClientState::Paused => {
+                self.network.send_state_request(&request.encode())
+            }
+            _ => Err(MoonlightError::StreamingError.into()),
+        }
+    }
+
+    /// Send an emulation control command (reset, frame advance, speed)
+    pub fn send_emulation_command(&mut self, command: EmulationCommand) -> Result<()> {
+        match self.state {
+            ClientState::Streaming | ClientState::Paused => {
+                self.network.send_emulation_command(command)
+            }
+            _ => Err(MoonlightError::StreamingError.into()),
+        }
+    }
+
+    /// Attach an extension to this client's emulated Wii Remote
+    pub fn set_wii_extension(&mut self, extension: WiiExtension) -> Result<()> {
+        match self.state {
+            ClientState::Streaming | ClientState::Paused => {
+                self.network.send_wii_extension(extension)
+            }
+            _ => Err(MoonlightError::StreamingError.into()),
+        }
+    }
+
+    /// Send a stick calibration step
+    ///
+    /// The server replies with a [`CalibrationResponse`] on the control
+    /// channel.
+    pub fn calibrate(&mut self, request: &CalibrationRequest) -> Result<()> {
+        if matches!(
+            self.state,
+            ClientState::Disconnected | ClientState::Connecting
+        ) {
+            return Err(MoonlightError::StreamingError.into());
+        }
+
+        self.network.send_calibration(&request.encode())?;
+        self.calibration_steps = match request {
+            CalibrationRequest::Identify { .. } => self.calibration_steps,
+            CalibrationRequest::Start => 2,
+            CalibrationRequest::Next => self.calibration_steps.saturating_sub(1),
+            CalibrationRequest::Cancel => 0,
+        };
+        Ok(())
+    }
+
+    /// Whether a calibration is in progress
+    pub fn is_calibrating(&self) -> bool {
+        self.calibration_steps > 0
+    }
+
+    /// Ask the server for a different player slot
+    ///
+    /// The outcome arrives as a new slot layout, see
+    /// [`MoonlightClient::player_slots`].
+    pub fn request_slot(&mut self, request: SlotRequest) -> Result<()> {
+        match self.state {
+            ClientState::Streaming | ClientState::Paused => {
+                self.network.send_slot_request(&request.encode())
+            }
+            _ => Err(MoonlightError::StreamingError.into()),
+        }
+    }
+
+    /// Slot layout last announced by the server
+    pub fn player_slots(&self) -> Option<&PlayerSlots> {
+        self.player_slots.as_ref()
+    }
+
+    /// Touch controls to draw over the stream
+    pub fn touch_overlay(&self) -> &TouchOverlay {
+        &self.touch_overlay
+    }
+
+    /// Next rumble command from the server, if one is waiting
+    ///
+    /// Slot layouts, touch overlays and clock sync replies read along the
+    /// way are taken in; other control messages are skipped.
+    pub fn poll_rumble(&mut self) -> Result<Option<Rumble>> {
+        while let Some(message) = self.network.receive_control_message()? {
+            if let Ok(rumble) = Rumble::decode(&message) {
+                return Ok(Some(rumble));
+            }
+            if let Ok(slots) = PlayerSlots::decode(&message) {
+                self.player_slots = Some(slots);
+            }
+            if let Ok(overlay) = TouchOverlay::decode(&message) {
+                self.touch_overlay = overlay;
+            }
+            if let Ok(reply) = ClockSyncReply::decode(&message) {
+                self.latency.clock_synced(&reply, get_time_us()?);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Send one local controller's input to the server
+    ///
+    /// Controller 0 is the client's own seat; the server gives further
+    /// controllers ports of their own. Unchanged input is only resent as a
+    /// periodic snapshot; every packet sent is numbered so the server can
+    /// put them back in order.
+    pub fn send_input(&mut self, controller_index: u8, input: &InputState) -> Result<()> {
+        if self.state != ClientState::Streaming && !self.is_calibrating() {
+            return Ok(());
+        }
+        if controller_index > 0 && !self.has_feature(features::MULTI_CONTROLLER) {
+            return Ok(());
+        }
+        let motion = self.has_feature(features::MOTION);
+        let touch = self.has_feature(features::TOUCH);
+        let Some(sent) = self.sent_inputs.get_mut(controller_index as usize) else {
+            return Ok(());
+        };
+
+        let mut moonlight_input = input.to_moonlight_input(controller_index);
+        // Leave out what the server can't use
+        if !motion {
+            moonlight_input.gyro_x = None;
+            moonlight_input.gyro_y = None;
+            moonlight_input.gyro_z = None;
+            moonlight_input.accel_x = None;
+            moonlight_input.accel_y = None;
+            moonlight_input.accel_z = None;
+        }
+        if !touch {
+            moonlight_input.touch_points = None;
+        }
+        sent.frames_since_snapshot = sent.frames_since_snapshot.saturating_add(1);
+        let snapshot = sent.frames_since_snapshot >= SNAPSHOT_INTERVAL;
+        let unchanged = sent
+            .last
+            .as_ref()
+            .is_some_and(|last| last.same_state(&moonlight_input));
+        if unchanged && !snapshot {
+            return Ok(());
+        }
+        if snapshot {
+            sent.frames_since_snapshot = 0;
+        }
+
+        moonlight_input.sequence = Some(sent.next_sequence);
+        moonlight_input.snapshot = snapshot;
+        moonlight_input.timestamp = get_time_us()?;
+        // Frames are tagged with the first controller's input
+        if controller_index == 0 {
+            self.latency
+                .stamp(sent.next_sequence, moonlight_input.timestamp);
+        }
+        sent.next_sequence = sent.next_sequence.wrapping_add(1);
+        sent.last = Some(moonlight_input.clone());
         self.network.send_input(&moonlight_input)
     }
 
@@ -100,13 +423,22 @@ impl MoonlightClient {
             return Ok(None);
         }
 
+        self.report_stats()?;
+        self.send_feedback()?;
+
         // Check for incoming video packets
         if let Some(packet) = self.network.receive_video_packet()? {
             // Process RTP packet
+            let started_us = get_time_us()?;
             self.process_video_packet(&packet)?;
 
             // Try to decode a complete frame
+            self.receive_stats
+                .frames_dropped(self.decoder.take_dropped_frames());
             if let Some(frame) = self.decoder.get_decoded_frame()? {
+                self.receive_stats
+                    .frame_decoded(get_time_us()?.saturating_sub(started_us));
+                self.shown_tag = self.frame_tag;
                 return Ok(Some(frame));
             }
         }
@@ -121,17 +453,64 @@ impl MoonlightClient {
         Ok(None)
     }
 
+    /// Send the server receive statistics once an interval is up, for it to
+    /// adapt the stream's bitrate
+    fn report_stats(&mut self) -> Result<()> {
+        if let Some(stats) = self.receive_stats.take_report(get_time_us()?) {
+            self.network.send_client_stats(&stats.encode())?;
+        }
+        Ok(())
+    }
+
+    /// Send the RTCP that's due: NACKs for missing packets, a picture loss
+    /// indication once they can't arrive in time, and receiver reports
+    fn send_feedback(&mut self) -> Result<()> {
+        if let Some(datagram) = self.rtcp.poll(get_time_us()?) {
+            self.network.send_rtcp(&datagram)?;
+        }
+        Ok(())
+    }
+
     /// Optimized RTP packet processing with fast payload type routing
     pub fn process_video_packet(&mut self, packet: &[u8]) -> Result<()> {
         if self.state != ClientState::Streaming {
             return Ok(());
         }
 
+        // Sender reports share the socket with the stream
+        if is_rtcp(packet) {
+            let now_us = get_time_us()?;
+            for rtcp_packet in decode_compound(packet).unwrap_or_default() {
+                if let RtcpPacket::SenderReport(report) = rtcp_packet {
+                    self.rtcp.sender_report(&report, now_us);
+                }
+            }
+            return Ok(());
+        }
+
         // Fast path: check payload type without full parsing for routing
         match RtpPacket::get_payload_type(packet) {
             Some(96) => {
                 // H264 video stream - only parse when needed
                 let rtp_packet = RtpPacket::parse(packet)?;
+                let arrival_us = get_time_us()?;
+                self.receive_stats.packet_received(
+                    rtp_packet.sequence_number,
+                    rtp_packet.timestamp,
+                    packet.len(),
+                    arrival_us,
+                );
+                self.rtcp.packet_received(
+                    rtp_packet.ssrc,
+                    rtp_packet.sequence_number,
+                    rtp_packet.timestamp,
+                    arrival_us,
+                );
+                if let Some(tag) =
+                    FrameInputTag::parse(rtp_packet.extension_profile, rtp_packet.extension_data)
+                {
+                    self.frame_tag = Some(tag);
+                }
                 self.process_h264_packet(&rtp_packet)?;
             }
             Some(97) => {
@@ -230,7 +609,7 @@ impl MoonlightClient {
     /// Disconnect from server
     pub fn disconnect(&mut self) -> Result<()> {
         match self.state {
-            ClientState::Streaming => {
+            ClientState::Streaming | ClientState::Paused => {
                 self.network.stop_stream()?;
                 self.decoder.cleanup()?;
                 if let Some(mut audio_player) = self.audio_player.take() {
@@ -245,6 +624,13 @@ impl MoonlightClient {
 
         self.state = ClientState::Disconnected;
         self.server_info = None;
+        self.sent_inputs = Default::default();
+        self.latency = LatencyTracker::default();
+        self.frame_tag = None;
+        self.shown_tag = None;
+        self.agreement = None;
+        self.receive_stats = ReceiveStats::default();
+        self.rtcp = RtcpReceiver::default();
 
         Ok(())
     }
@@ -265,7 +651,7 @@ impl MoonlightClient {
         let handshake = HandshakeRequest {
             version: [7, 1, 408, 0], // Moonlight protocol version
             gfe_version: server.version_quad,
-            client_version: "dpstream-switch-1.0.0",
+            client_version: CLIENT_BUILD,
         };
 
         self.network.send_handshake(&handshake)?;
@@ -277,7 +663,43 @@ impl MoonlightClient {
             return Err(MoonlightError::HandshakeFailed.into());
         }
 
-        Ok(())
+        // Step 3: Settle the protocol version and features
+        let hello = Hello::new(CLIENT_BUILD, self.capabilities());
+        match self.network.exchange_hello(&hello)? {
+            HelloReply::Accepted(agreement) => {
+                self.agreement = Some(agreement);
+                Ok(())
+            }
+            HelloReply::Rejected(rejection) => Err(MoonlightError::Rejected(rejection).into()),
+        }
+    }
+
+    /// What this client offers the server
+    fn capabilities(&self) -> Capabilities {
+        let mut offered = features::RUMBLE
+            | features::MULTI_CONTROLLER
+            | features::MOTION
+            | features::TOUCH
+            | features::LATENCY_TAGS;
+        if cfg!(feature = "crypto") {
+            offered |= features::ENCRYPTION;
+        }
+        if self.stream_config.codec == VideoCodec::H265 {
+            offered |= features::HEVC;
+        }
+        Capabilities {
+            features: offered,
+            max_width: self.stream_config.width,
+            max_height: self.stream_config.height,
+            max_fps: self.stream_config.fps,
+        }
+    }
+
+    /// Whether the server agreed to use a feature this session
+    pub fn has_feature(&self, feature: u32) -> bool {
+        self.agreement
+            .as_ref()
+            .is_some_and(|agreement| agreement.has(feature))
     }
 
     /// Authenticate with server
@@ -307,6 +729,7 @@ pub enum ClientState {
     Connecting,
     Connected,
     Streaming,
+    Paused,
     Error,
 }
 
@@ -426,6 +849,14 @@ impl NetworkManager {
         Ok(HandshakeResponse { success: true })
     }
 
+    pub fn exchange_hello(&mut self, hello: &Hello) -> Result<HelloReply> {
+        // Mock implementation - would send the hello on the control
+        // connection and wait for the reply; answers as a server of the
+        // same release would
+        let _message = hello.encode();
+        Ok(hello.answer(hello, 0))
+    }
+
     pub fn send_auth_request(&mut self, _auth: &AuthRequest) -> Result<()> {
         // Mock implementation
         Ok(())
@@ -436,6 +867,12 @@ impl NetworkManager {
         Ok(AuthResponse { success: true })
     }
 
+    pub fn exchange_library(&mut self, _request: &[u8]) -> Result<LibraryResponse> {
+        // Mock implementation - would send the request on the control
+        // connection and wait for the server's reply
+        Ok(LibraryResponse::Games(Vec::new()))
+    }
+
     pub fn start_stream(&mut self, _config: &StreamConfig) -> Result<()> {
         // Mock implementation
         Ok(())
@@ -451,11 +888,65 @@ impl NetworkManager {
         Ok(())
     }
 
-    pub fn send_input(&mut self, _input: &MoonlightInput) -> Result<()> {
-        // Mock implementation
+    pub fn send_emulation_command(&mut self, command: EmulationCommand) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        let _message = command.encode();
+        Ok(())
+    }
+
+    pub fn send_wii_extension(&mut self, extension: WiiExtension) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        let _message = extension.encode();
+        Ok(())
+    }
+
+    pub fn send_state_request(&mut self, _message: &[u8]) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        Ok(())
+    }
+
+    pub fn send_slot_request(&mut self, _message: &[u8]) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        Ok(())
+    }
+
+    pub fn send_calibration(&mut self, _message: &[u8]) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        Ok(())
+    }
+
+    pub fn send_clock_sync(&mut self, _message: &[u8]) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        Ok(())
+    }
+
+    pub fn send_latency_report(&mut self, _message: &[u8]) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        Ok(())
+    }
+
+    pub fn send_client_stats(&mut self, _message: &[u8]) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        Ok(())
+    }
+
+    pub fn send_rtcp(&mut self, _datagram: &[u8]) -> Result<()> {
+        // Mock implementation - would write to the stream socket
+        Ok(())
+    }
+
+    pub fn send_input(&mut self, input: &MoonlightInput) -> Result<()> {
+        // Mock implementation - would write to the control connection
+        let _message = input.encode();
         Ok(())
     }
 
+    pub fn receive_control_message(&mut self) -> Result<Option<Vec<u8>>> {
+        // Mock implementation - would read from the control connection and
+        // cut it into messages with a dpstream_protocol::FrameReader
+        Ok(None)
+    }
+
     pub fn receive_video_packet(&mut self) -> Result<Option<VideoPacket>> {
         // Mock implementation
         Ok(None)
@@ -474,6 +965,8 @@ pub struct VideoDecoder {
     fragment_buffer: Vec<u8>,
     is_fragmenting: bool,
     decoded_frames: HeaplessVec<VideoFrame, 4>,
+    /// Frames pushed out of a full queue since last asked
+    dropped_frames: u32,
 }
 
 impl VideoDecoder {
@@ -484,6 +977,7 @@ impl VideoDecoder {
             fragment_buffer: Vec::new(),
             is_fragmenting: false,
             decoded_frames: HeaplessVec::new(),
+            dropped_frames: 0,
         })
     }
 
@@ -566,6 +1060,7 @@ impl VideoDecoder {
             if self.decoded_frames.push(frame).is_err() {
                 // Buffer full, drop oldest frame
                 self.decoded_frames.pop_at(0);
+                self.dropped_frames += 1;
                 self.decoded_frames.push(frame).ok();
             }
 
@@ -575,6 +1070,11 @@ impl VideoDecoder {
         Ok(())
     }
 
+    /// Frames dropped since the last call
+    pub fn take_dropped_frames(&mut self) -> u32 {
+        core::mem::take(&mut self.dropped_frames)
+    }
+
     /// Get a decoded frame if available
     pub fn get_decoded_frame(&mut self) -> Result<Option<VideoFrame>> {
         if self.decoded_frames.is_empty() {
@@ -636,6 +1136,10 @@ pub struct RtpPacket<'a> {
     pub sequence_number: u16,
     pub timestamp: u32,
     pub ssrc: u32,
+    /// Header extension profile, 0 without an extension
+    pub extension_profile: u16,
+    /// Header extension data after its profile and length
+    pub extension_data: &'a [u8],
     pub payload: &'a [u8],
 }
 
@@ -675,6 +1179,25 @@ impl<'a> RtpPacket<'a> {
             extended_header_size
         };
 
+        // Header extension: profile, length in 32-bit words, then the data
+        let (extension_profile, extension_data, header_size) = if extension {
+            let words = data
+                .get(header_size + 2..header_size + 4)
+                .ok_or(MoonlightError::InvalidPacket)?;
+            let extension_end =
+                header_size + 4 + u16::from_be_bytes([words[0], words[1]]) as usize * 4;
+            if data.len() < extension_end {
+                return Err(MoonlightError::InvalidPacket.into());
+            }
+            (
+                u16::from_be_bytes([data[header_size], data[header_size + 1]]),
+                &data[header_size + 4..extension_end],
+                extension_end,
+            )
+        } else {
+            (0, &data[..0], header_size)
+        };
+
         let payload = &data[header_size..];
 
         Ok(RtpPacket {
@@ -687,6 +1210,8 @@ impl<'a> RtpPacket<'a> {
             sequence_number,
             timestamp,
             ssrc,
+            extension_profile,
+            extension_data,
             payload,
         })
     }
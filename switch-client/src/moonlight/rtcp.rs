@@ -0,0 +1,229 @@
+//! RTCP feedback to the server
+//!
+//! An [`RtcpReceiver`] follows the sequence numbers of the video stream.
+//! Gaps are NACKed straight away and again every [`NACK_RETRY_US`] while the
+//! server may still resend them; packets still missing after [`MAX_NACKS`]
+//! tries, or too many at once to track, are given up on with a picture loss
+//! indication. A receiver report goes out every [`REPORT_INTERVAL_US`],
+//! echoing the server's last sender report so it can time the round trip.
+
+pub use dpstream_protocol::rtcp::{
+    compact_ntp, decode_compound, encode_compound, is_rtcp, Nack, PictureLoss, ReceiverReport,
+    ReportBlock, RtcpPacket, SenderReport,
+};
+
+use alloc::vec::Vec;
+
+/// SSRC the client's RTCP is sent under
+pub const RECEIVER_SSRC: u32 = 0x6470_7377;
+
+/// Time between receiver reports
+pub const REPORT_INTERVAL_US: u64 = 1_000_000;
+
+/// Time between NACKs for the same packet
+pub const NACK_RETRY_US: u64 = 20_000;
+
+/// NACKs sent for a packet before it is given up on
+pub const MAX_NACKS: u8 = 3;
+
+/// Missing packets tracked at once; losing more takes a new picture
+const MAX_MISSING: usize = 128;
+
+/// Shortest time between picture loss indications, for the keyframe one
+/// asked for to arrive
+const PLI_INTERVAL_US: u64 = 200_000;
+
+/// A packet waiting to be resent
+#[derive(Debug, Clone, Copy)]
+struct Missing {
+    sequence: u16,
+    nacks: u8,
+    last_nack_us: u64,
+}
+
+/// Reception state of the video stream, for feedback to the server
+#[derive(Debug, Clone, Default)]
+pub struct RtcpReceiver {
+    media_ssrc: Option<u32>,
+    /// Highest sequence number seen, extended past 16 bits
+    highest: Option<u32>,
+    /// Extended sequence number of the first packet
+    base: u32,
+    received: u32,
+    /// Packets expected and received as of the last report
+    prior: (u32, u32),
+    /// RFC 3550 interarrival jitter, in RTP timestamp units
+    jitter: f32,
+    /// Transit time of the last packet, in RTP timestamp units
+    last_transit: Option<u32>,
+    missing: Vec<Missing>,
+    picture_lost: bool,
+    last_pli_us: Option<u64>,
+    /// [`compact_ntp`] of the last sender report and when it arrived
+    last_sr: Option<(u32, u64)>,
+    last_report_us: Option<u64>,
+}
+
+impl RtcpReceiver {
+    /// Count a video packet that arrived at `arrival_us`
+    pub fn packet_received(
+        &mut self,
+        ssrc: u32,
+        sequence: u16,
+        rtp_timestamp: u32,
+        arrival_us: u64,
+    ) {
+        if self.media_ssrc != Some(ssrc) {
+            // A new stream starts counting over
+            *self = Self {
+                media_ssrc: Some(ssrc),
+                last_sr: self.last_sr,
+                last_report_us: self.last_report_us,
+                ..Self::default()
+            };
+        }
+        self.received = self.received.wrapping_add(1);
+
+        let extended = match self.highest {
+            None => {
+                self.base = u32::from(sequence);
+                u32::from(sequence)
+            }
+            Some(highest) => {
+                highest.wrapping_add_signed(i32::from(sequence.wrapping_sub(highest as u16) as i16))
+            }
+        };
+        match self.highest {
+            Some(highest) if extended > highest + 1 => {
+                if (extended - highest - 1) as usize > MAX_MISSING {
+                    self.missing.clear();
+                    self.picture_lost = true;
+                } else {
+                    (highest + 1..extended).for_each(|lost| self.lose(lost as u16, arrival_us));
+                }
+                self.highest = Some(extended);
+            }
+            Some(highest) if extended <= highest => {
+                // Late or resent
+                self.missing.retain(|missing| missing.sequence != sequence);
+            }
+            _ => self.highest = Some(extended),
+        }
+
+        // Arrival on the 90 kHz clock, wrapping like the timestamps do
+        let arrival = (arrival_us * 9 / 100) as u32;
+        let transit = arrival.wrapping_sub(rtp_timestamp);
+        if let Some(last) = self.last_transit {
+            let change = (transit.wrapping_sub(last) as i32).unsigned_abs() as f32;
+            self.jitter += (change - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+    }
+
+    fn lose(&mut self, sequence: u16, now_us: u64) {
+        if self.missing.len() == MAX_MISSING {
+            self.missing.remove(0);
+            self.picture_lost = true;
+        }
+        self.missing.push(Missing {
+            sequence,
+            nacks: 0,
+            last_nack_us: now_us,
+        });
+    }
+
+    /// Note a sender report that arrived at `now_us`, to echo it
+    pub fn sender_report(&mut self, report: &SenderReport, now_us: u64) {
+        if self.media_ssrc.is_none_or(|ssrc| ssrc == report.ssrc) {
+            self.last_sr = Some((compact_ntp(report.ntp_timestamp), now_us));
+        }
+    }
+
+    /// The compound RTCP datagram due at `now_us`, if any: a receiver
+    /// report, then NACKs for missing packets and a picture loss
+    /// indication for those given up on
+    pub fn poll(&mut self, now_us: u64) -> Option<Vec<u8>> {
+        let media_ssrc = self.media_ssrc?;
+
+        let mut lost = Vec::new();
+        let mut gave_up = false;
+        self.missing.retain_mut(|missing| {
+            let retry_due =
+                missing.nacks == 0 || now_us.saturating_sub(missing.last_nack_us) >= NACK_RETRY_US;
+            if !retry_due {
+                return true;
+            }
+            if missing.nacks == MAX_NACKS {
+                gave_up = true;
+                return false;
+            }
+            missing.nacks += 1;
+            missing.last_nack_us = now_us;
+            lost.push(missing.sequence);
+            true
+        });
+        self.picture_lost |= gave_up;
+
+        let send_pli = self.picture_lost
+            && self
+                .last_pli_us
+                .is_none_or(|last| now_us.saturating_sub(last) >= PLI_INTERVAL_US);
+        let report_due = self
+            .last_report_us
+            .is_none_or(|last| now_us.saturating_sub(last) >= REPORT_INTERVAL_US);
+        if lost.is_empty() && !send_pli && !report_due {
+            return None;
+        }
+
+        let mut packets = Vec::with_capacity(3);
+        packets.push(RtcpPacket::ReceiverReport(ReceiverReport {
+            ssrc: RECEIVER_SSRC,
+            reports: alloc::vec![self.report_block(media_ssrc, now_us)],
+        }));
+        if !lost.is_empty() {
+            packets.push(RtcpPacket::Nack(Nack {
+                sender_ssrc: RECEIVER_SSRC,
+                media_ssrc,
+                lost,
+            }));
+        }
+        if send_pli {
+            packets.push(RtcpPacket::PictureLoss(PictureLoss {
+                sender_ssrc: RECEIVER_SSRC,
+                media_ssrc,
+            }));
+            self.picture_lost = false;
+            self.last_pli_us = Some(now_us);
+        }
+        self.last_report_us = Some(now_us);
+        Some(encode_compound(&packets))
+    }
+
+    /// Reception statistics since the last report
+    fn report_block(&mut self, media_ssrc: u32, now_us: u64) -> ReportBlock {
+        let highest = self.highest.unwrap_or(self.base);
+        let expected = highest.wrapping_sub(self.base).wrapping_add(1);
+        let (prior_expected, prior_received) = self.prior;
+        let expected_interval = expected.wrapping_sub(prior_expected);
+        let received_interval = self.received.wrapping_sub(prior_received);
+        self.prior = (expected, self.received);
+
+        let lost_interval = expected_interval.saturating_sub(received_interval);
+        let (last_sr, delay_since_last_sr) = self.last_sr.map_or((0, 0), |(ntp, at_us)| {
+            let delay = now_us.saturating_sub(at_us) * 65_536 / 1_000_000;
+            (ntp, delay as u32)
+        });
+        ReportBlock {
+            ssrc: media_ssrc,
+            fraction_lost: (u64::from(lost_interval) * 256)
+                .checked_div(u64::from(expected_interval))
+                .unwrap_or(0)
+                .min(255) as u8,
+            cumulative_lost: expected.saturating_sub(self.received),
+            highest_sequence: highest,
+            jitter: self.jitter as u32,
+            last_sr,
+            delay_since_last_sr,
+        }
+    }
+}
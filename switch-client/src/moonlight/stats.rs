@@ -0,0 +1,125 @@
+//! Receive statistics for the server's congestion control
+//!
+//! Every video packet's sequence number, RTP timestamp and arrival time go
+//! into a [`ReceiveStats`], along with how long frames took to decode. Every
+//! [`STATS_INTERVAL_MS`] it yields a [`ClientStats`] for the server, which
+//! adapts the stream's bitrate to it.
+
+pub use dpstream_protocol::stats::{ClientStats, STATS_INTERVAL_MS};
+
+/// Microseconds per tick of the 90 kHz video RTP clock, times nine
+const RTP_TICK_US_X9: i64 = 100;
+
+/// Counts over the current reporting interval
+#[derive(Debug, Clone, Default)]
+pub struct ReceiveStats {
+    interval_start_us: Option<u64>,
+    bytes_received: u32,
+    packets_received: u32,
+    /// Highest sequence number seen, extended past 16 bits
+    highest_sequence: Option<u32>,
+    /// Highest sequence number when the interval started
+    interval_base: Option<u32>,
+    /// RFC 3550 interarrival jitter, in microseconds
+    jitter_us: f32,
+    /// Arrival time and RTP timestamp of the last packet
+    last_arrival: Option<(u64, u32)>,
+    delay_gradient_us: i64,
+    decode_time_us: u64,
+    frames_decoded: u32,
+    frames_dropped: u32,
+}
+
+impl ReceiveStats {
+    /// Count a video packet that arrived at `arrival_us`
+    pub fn packet_received(
+        &mut self,
+        sequence: u16,
+        rtp_timestamp: u32,
+        size: usize,
+        arrival_us: u64,
+    ) {
+        self.interval_start_us.get_or_insert(arrival_us);
+        self.bytes_received = self.bytes_received.saturating_add(size as u32);
+        self.packets_received += 1;
+
+        let extended = match self.highest_sequence {
+            None => u32::from(sequence),
+            Some(highest) => {
+                let delta = sequence.wrapping_sub(highest as u16) as i16;
+                highest.wrapping_add_signed(i32::from(delta))
+            }
+        };
+        self.interval_base.get_or_insert(extended.wrapping_sub(1));
+        if self
+            .highest_sequence
+            .is_none_or(|highest| extended > highest)
+        {
+            self.highest_sequence = Some(extended);
+        }
+
+        // Change in transit time since the previous packet: how much later
+        // this one arrived than its timestamp says it was sent
+        if let Some((last_us, last_timestamp)) = self.last_arrival {
+            let sent_delta_us =
+                i64::from(rtp_timestamp.wrapping_sub(last_timestamp) as i32) * RTP_TICK_US_X9 / 9;
+            let transit_delta_us = (arrival_us as i64 - last_us as i64) - sent_delta_us;
+            self.jitter_us += (transit_delta_us.unsigned_abs() as f32 - self.jitter_us) / 16.0;
+            self.delay_gradient_us += transit_delta_us;
+        }
+        self.last_arrival = Some((arrival_us, rtp_timestamp));
+    }
+
+    /// Count a frame the decoder finished after `decode_time_us`
+    pub fn frame_decoded(&mut self, decode_time_us: u64) {
+        self.frames_decoded += 1;
+        self.decode_time_us += decode_time_us;
+    }
+
+    /// Count frames the decoder had to throw away
+    pub fn frames_dropped(&mut self, count: u32) {
+        self.frames_dropped += count;
+    }
+
+    /// Statistics for the interval ending at `now_us`, once it is
+    /// [`STATS_INTERVAL_MS`] long; the next interval starts from there
+    pub fn take_report(&mut self, now_us: u64) -> Option<ClientStats> {
+        let start_us = self.interval_start_us?;
+        let interval_ms = (now_us.saturating_sub(start_us) / 1000) as u32;
+        if interval_ms < STATS_INTERVAL_MS {
+            return None;
+        }
+
+        let expected = match (self.highest_sequence, self.interval_base) {
+            (Some(highest), Some(base)) => highest.wrapping_sub(base),
+            _ => 0,
+        };
+        let report = ClientStats {
+            interval_ms,
+            bytes_received: self.bytes_received,
+            packets_received: self.packets_received,
+            packets_lost: expected.saturating_sub(self.packets_received),
+            jitter_us: self.jitter_us as u32,
+            decode_time_us: self
+                .decode_time_us
+                .checked_div(u64::from(self.frames_decoded))
+                .unwrap_or(0) as u32,
+            frames_decoded: self.frames_decoded,
+            frames_dropped: self.frames_dropped,
+            delay_gradient_us: self
+                .delay_gradient_us
+                .clamp(i32::MIN.into(), i32::MAX.into()) as i32,
+        };
+
+        // Jitter and the sequence numbers carry over, the counts start over
+        *self = Self {
+            interval_start_us: Some(now_us),
+            interval_base: self.highest_sequence,
+            highest_sequence: self.highest_sequence,
+            jitter_us: self.jitter_us,
+            last_arrival: self.last_arrival,
+            ..Self::default()
+        };
+        Some(report)
+    }
+}
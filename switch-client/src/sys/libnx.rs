@@ -3,6 +3,7 @@
 //! Provides safe abstractions over Nintendo Switch system services
 
 use crate::error::{Result, SystemError};
+use alloc::string::String;
 
 /// Main system manager for Switch services
 pub struct LibnxSystem {
@@ -71,6 +72,39 @@ impl LibnxSystem {
         Ok(false) // Assume handheld for now
     }
 
+    /// Check whether the application has foreground focus
+    ///
+    /// Focus is lost when the console goes to sleep or the HOME menu opens.
+    pub fn is_in_focus(&self) -> Result<bool> {
+        if !self.initialized {
+            return Err(SystemError::InvalidState.into());
+        }
+
+        // In real implementation: appletGetFocusState() == AppletFocusState_InFocus
+        Ok(true)
+    }
+
+    /// Nickname of the selected user account, used to key save states
+    pub fn get_user_nickname(&self) -> Result<String> {
+        if !self.initialized {
+            return Err(SystemError::InvalidState.into());
+        }
+
+        // In real implementation: accountGetPreselectedUser() + accountProfileGetNickname()
+        Ok(String::from("Player"))
+    }
+
+    /// Console serial number, identifying this client's controllers to the
+    /// server across connections
+    pub fn get_client_id(&self) -> Result<String> {
+        if !self.initialized {
+            return Err(SystemError::InvalidState.into());
+        }
+
+        // In real implementation: setsysGetSerialNumber()
+        Ok(String::from("XAW10000000000"))
+    }
+
     /// Get battery status
     pub fn get_battery_status(&self) -> Result<BatteryStatus> {
         if !self.initialized {